@@ -0,0 +1,281 @@
+//! Crash-safe file replacement.
+//!
+//! Content is written to a temporary file in the destination directory,
+//! fsynced, and renamed over the target so readers only ever observe the old
+//! file or the complete new one.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static TEMP_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteOptions {
+    /// Keep the previous version as `<name>.bak.<timestamp>`, with `-N`
+    /// appended to later backups of the same second.
+    pub backup: bool,
+}
+
+#[derive(Debug)]
+pub struct WriteOutcome {
+    pub bytes: u64,
+    pub backup: Option<PathBuf>,
+}
+
+pub fn write_atomic(
+    path: &Path,
+    contents: &[u8],
+    options: WriteOptions,
+) -> io::Result<WriteOutcome> {
+    let file_name = path.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "target path has no file name")
+    })?;
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let existing = match fs::metadata(path) {
+        Ok(meta) if meta.is_file() => Some(meta),
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} exists and is not a regular file", path.display()),
+            ));
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+
+    let (temp_path, mut temp_file) = create_temp(&dir, &file_name.to_string_lossy())?;
+    let result = (|| {
+        temp_file.write_all(contents)?;
+        if let Some(meta) = &existing {
+            temp_file.set_permissions(meta.permissions())?;
+        }
+        temp_file.sync_all()?;
+        drop(temp_file);
+
+        let backup = match &existing {
+            Some(meta) if options.backup => Some(create_backup(path, meta)?),
+            _ => None,
+        };
+
+        fs::rename(&temp_path, path)?;
+        sync_dir(&dir)?;
+        Ok(WriteOutcome {
+            bytes: contents.len() as u64,
+            backup,
+        })
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn create_temp(dir: &Path, file_name: &str) -> io::Result<(PathBuf, File)> {
+    loop {
+        let n = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let candidate = dir.join(format!(".{}.tmp.{}.{}", file_name, process::id(), n));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(file) => return Ok((candidate, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Copy `path` to `<name>.bak.<timestamp>`, or `<name>.bak.<timestamp>-N`
+/// when an earlier backup of the same second exists; never overwrites one.
+fn create_backup(path: &Path, meta: &fs::Metadata) -> io::Result<PathBuf> {
+    let base = format!(
+        "{}.bak.{}",
+        path.file_name().unwrap_or_default().to_string_lossy(),
+        timestamp()
+    );
+    let mut n = 0;
+    let (backup_path, mut backup) = loop {
+        let candidate = path.with_file_name(match n {
+            0 => base.clone(),
+            n => format!("{}-{}", base, n),
+        });
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(file) => break (candidate, file),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e),
+        }
+    };
+    let copied = (|| {
+        io::copy(&mut File::open(path)?, &mut backup)?;
+        backup.set_permissions(meta.permissions())?;
+        backup.sync_all()
+    })();
+    if let Err(e) = copied {
+        let _ = fs::remove_file(&backup_path);
+        return Err(e);
+    }
+    Ok(backup_path)
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// UTC timestamp in the compact `YYYYMMDDTHHMMSSZ` form used for backup names.
+fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+// Howard Hinnant's days-to-civil conversion.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("atomic-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Every file in `dir` but `keep`, sorted.
+    fn others(dir: &Path, keep: &str) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name != keep)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn creates_and_replaces() {
+        let dir = temp_dir("replace");
+        let path = dir.join("a.ts");
+        let backup = WriteOptions { backup: true };
+
+        // No previous version, so no backup even when asked for one.
+        let outcome = write_atomic(&path, b"one", backup).unwrap();
+        assert_eq!((outcome.bytes, outcome.backup), (3, None));
+        assert_eq!(fs::read(&path).unwrap(), b"one");
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        let outcome = write_atomic(&path, b"two!", WriteOptions::default()).unwrap();
+        assert_eq!((outcome.bytes, outcome.backup), (4, None));
+        assert_eq!(fs::read(&path).unwrap(), b"two!");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        assert_eq!(others(&dir, "a.ts"), Vec::<String>::new());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn backups_are_never_overwritten() {
+        let dir = temp_dir("backup");
+        let path = dir.join("a.ts");
+        let backup = WriteOptions { backup: true };
+        fs::write(&path, "v1").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        // Well within a second, so the timestamps collide.
+        let mut backups = Vec::new();
+        for next in ["v2", "v3", "v4"] {
+            let outcome = write_atomic(&path, next.as_bytes(), backup).unwrap();
+            backups.push(outcome.backup.unwrap());
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "v4");
+        let kept: Vec<String> = backups
+            .iter()
+            .map(|b| fs::read_to_string(b).unwrap())
+            .collect();
+        assert_eq!(kept, ["v1", "v2", "v3"]);
+        for b in &backups {
+            assert_eq!(fs::metadata(b).unwrap().permissions().mode() & 0o777, 0o600);
+            let name = b.file_name().unwrap().to_string_lossy().into_owned();
+            assert!(name.starts_with("a.ts.bak."), "{}", name);
+        }
+        let mut names: Vec<String> = backups
+            .iter()
+            .map(|b| b.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(others(&dir, "a.ts"), names);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_non_files() {
+        let dir = temp_dir("refuse");
+        fs::create_dir(dir.join("sub")).unwrap();
+        let error = write_atomic(&dir.join("sub"), b"x", WriteOptions::default()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        let error = write_atomic(&dir.join("missing/a.ts"), b"x", WriteOptions::default());
+        assert_eq!(error.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(others(&dir, ""), ["sub"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn civil_from_days() {
+        let cases = [
+            (0, (1970, 1, 1)),
+            (-1, (1969, 12, 31)),
+            (11_016, (2000, 2, 29)),
+            (19_723, (2024, 1, 1)),
+            (20_741, (2026, 10, 15)),
+        ];
+        for (days, expected) in cases {
+            assert_eq!(super::civil_from_days(days), expected, "{}", days);
+        }
+    }
+}
@@ -0,0 +1,565 @@
+//! Referential integrity of the target, replacing one-off scripts such as
+//! `fix-orders-doctor-references.ts`, `fix-doctor-office-relationships.ts`
+//! and `fix-international-patient-relationships.ts`.
+//!
+//! The reference graph is assembled from three places:
+//!
+//! - the single-column FK constraints of the target catalog;
+//! - the FK lookups of the entity specs, declared by the target or not;
+//! - a refs file of logical FKs the target has no constraint for, such as
+//!   `doctors.profile_id → profiles.id` (see `refs.example.toml`).
+//!
+//! Every reference is checked for orphans, values pointing at no row. Where
+//! the legacy ID of the referenced row is known, from a target column or from
+//! the source row of a spec lookup, NULL references that could be resolved,
+//! and legacy IDs that `migration_mappings` cannot resolve (dangling), are
+//! counted too; a legacy column also reveals references to the wrong row.
+//! References marked `one_to_one` are checked for several rows pointing at
+//! the same row. [`repair`] turns the findings into SQL.
+
+pub mod repair;
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use postgres::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{self, Side, describe};
+use crate::spec::{EntitySpec, is_identifier};
+
+/// Offending values or legacy IDs kept per reference.
+const EXAMPLES: usize = 5;
+
+/// Where a reference was found.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", content = "name", rename_all = "snake_case")]
+pub enum Origin {
+    Constraint(String),
+    Spec(String),
+    File,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Constraint(name) => write!(f, "constraint {}", name),
+            Origin::Spec(name) => write!(f, "spec {}", name),
+            Origin::File => f.write_str("refs file"),
+        }
+    }
+}
+
+/// Where the legacy ID of the referenced row comes from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "from", rename_all = "snake_case")]
+pub enum Legacy {
+    /// A column of the referencing table.
+    Column { column: String, entity: String },
+    /// The source row of a spec's FK lookup, found through the legacy ID
+    /// column of the referencing row.
+    Lookup {
+        spec: String,
+        source: String,
+        entity: String,
+        legacy_id_column: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Reference {
+    pub table: String,
+    pub column: String,
+    pub referenced_table: String,
+    pub referenced_column: String,
+    pub origins: Vec<Origin>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub legacy: Option<Legacy>,
+    pub one_to_one: bool,
+}
+
+impl Reference {
+    pub fn label(&self) -> String {
+        format!(
+            "{}.{} → {}.{}",
+            self.table, self.column, self.referenced_table, self.referenced_column
+        )
+    }
+
+    /// `NOT EXISTS` test of an orphaned `t.column`.
+    fn orphaned(&self) -> String {
+        format!(
+            "NOT EXISTS (SELECT 1 FROM {} x WHERE x.{} = t.{})",
+            self.referenced_table, self.referenced_column, self.column
+        )
+    }
+}
+
+/// A logical FK of a refs file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RefEntry {
+    pub table: String,
+    pub column: String,
+    /// `table` or `table.column`; the column defaults to `id`.
+    pub references: String,
+    /// Column of `table` holding the legacy ID of the referenced row.
+    #[serde(default)]
+    pub legacy: Option<String>,
+    /// `migration_mappings.entity_type` the `legacy` IDs are mapped under.
+    #[serde(default)]
+    pub entity: Option<String>,
+    /// At most one row of `table` may point at each referenced row.
+    #[serde(default)]
+    pub one_to_one: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RefFile {
+    #[serde(rename = "ref", default)]
+    refs: Vec<RefEntry>,
+}
+
+impl RefEntry {
+    fn referenced(&self) -> (&str, &str) {
+        self.references
+            .split_once('.')
+            .unwrap_or((&self.references, "id"))
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let (table, column) = self.referenced();
+        let names = [
+            Some(self.table.as_str()),
+            Some(self.column.as_str()),
+            Some(table),
+            Some(column),
+            self.legacy.as_deref(),
+        ];
+        if let Some(bad) = names.into_iter().flatten().find(|n| !is_identifier(n)) {
+            return Err(format!("'{}' is not a valid table or column name", bad));
+        }
+        match (&self.legacy, &self.entity) {
+            (Some(_), None) => Err("`legacy` needs the `entity` its IDs are mapped under".into()),
+            (None, Some(_)) => Err("`entity` needs the `legacy` column".into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The logical FKs of a `.toml`, `.yaml` or `.yml` refs file.
+pub fn load(path: &Path) -> Result<Vec<RefEntry>, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let invalid = |e: String| format!("invalid refs file {}: {}", path.display(), e);
+    let file: RefFile = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str(&text).map_err(|e| invalid(e.to_string()))?,
+        Some("yaml" | "yml") => serde_yaml::from_str(&text).map_err(|e| invalid(e.to_string()))?,
+        _ => return Err(invalid("expected a .toml, .yaml or .yml file".into())),
+    };
+    for (i, entry) in file.refs.iter().enumerate() {
+        entry.validate().map_err(|e| {
+            invalid(format!(
+                "ref {} ({}.{}): {}",
+                i + 1,
+                entry.table,
+                entry.column,
+                e
+            ))
+        })?;
+    }
+    Ok(file.refs)
+}
+
+/// The reference graph of the target: its FK constraints, the specs' lookups
+/// and the logical FKs of `entries`, merged per referencing column.
+pub fn graph(
+    target: &mut Client,
+    specs: &[EntitySpec],
+    entries: &[RefEntry],
+) -> Result<Vec<Reference>, String> {
+    let rows = target
+        .query(
+            "SELECT c.conname::text, c.conrelid::regclass::text, a.attname::text,
+                    c.confrelid::regclass::text, ra.attname::text
+             FROM pg_constraint c
+             JOIN pg_namespace n ON n.oid = c.connamespace
+             JOIN pg_attribute a ON a.attrelid = c.conrelid AND a.attnum = c.conkey[1]
+             JOIN pg_attribute ra ON ra.attrelid = c.confrelid AND ra.attnum = c.confkey[1]
+             WHERE c.contype = 'f'
+               AND cardinality(c.conkey) = 1
+               AND n.nspname = ANY(current_schemas(false))
+             ORDER BY 2, 3",
+            &[],
+        )
+        .map_err(|e| format!("cannot read the target's FK constraints: {}", describe(&e)))?;
+    let mut graph: Vec<Reference> = rows
+        .iter()
+        .map(|row| Reference {
+            table: row.get(1),
+            column: row.get(2),
+            referenced_table: row.get(3),
+            referenced_column: row.get(4),
+            origins: vec![Origin::Constraint(row.get(0))],
+            legacy: None,
+            one_to_one: false,
+        })
+        .collect();
+
+    for spec in specs {
+        for lookup in &spec.lookups {
+            let Some(referenced) = specs
+                .iter()
+                .find(|s| s.name == lookup.entity || s.entity_type() == lookup.entity)
+            else {
+                continue;
+            };
+            let reference = merge(
+                &mut graph,
+                &spec.target_table,
+                &lookup.target,
+                &referenced.target_table,
+                &referenced.target_key,
+                Origin::Spec(spec.name.clone()),
+            );
+            reference.legacy.get_or_insert_with(|| Legacy::Lookup {
+                spec: spec.name.clone(),
+                source: lookup.source.clone(),
+                entity: lookup.entity.clone(),
+                legacy_id_column: spec.legacy_id_column.clone(),
+            });
+        }
+    }
+
+    for entry in entries {
+        let (table, column) = entry.referenced();
+        let reference = merge(
+            &mut graph,
+            &entry.table,
+            &entry.column,
+            table,
+            column,
+            Origin::File,
+        );
+        reference.one_to_one |= entry.one_to_one;
+        if let (Some(column), Some(entity)) = (&entry.legacy, &entry.entity) {
+            reference.legacy = Some(Legacy::Column {
+                column: column.clone(),
+                entity: entity.clone(),
+            });
+        }
+    }
+    Ok(graph)
+}
+
+/// The reference of `table.column`, added when the graph lacks it.
+fn merge<'a>(
+    graph: &'a mut Vec<Reference>,
+    table: &str,
+    column: &str,
+    referenced_table: &str,
+    referenced_column: &str,
+    origin: Origin,
+) -> &'a mut Reference {
+    let index = match graph
+        .iter()
+        .position(|r| r.table == table && r.column == column)
+    {
+        Some(i) => i,
+        None => {
+            graph.push(Reference {
+                table: table.to_string(),
+                column: column.to_string(),
+                referenced_table: referenced_table.to_string(),
+                referenced_column: referenced_column.to_string(),
+                origins: Vec::new(),
+                legacy: None,
+                one_to_one: false,
+            });
+            graph.len() - 1
+        }
+    };
+    let reference = &mut graph[index];
+    if !reference.origins.contains(&origin) {
+        reference.origins.push(origin);
+    }
+    reference
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Findings {
+    /// Rows with a non-NULL reference.
+    pub referencing_rows: u64,
+    /// References to no row.
+    pub orphans: u64,
+    pub orphan_values: Vec<String>,
+    /// NULL references whose legacy ID resolves.
+    pub unset: u64,
+    /// References to another row than the legacy ID maps to; only known for
+    /// a legacy column.
+    pub misdirected: u64,
+    /// Rows with a NULL or orphaned reference whose legacy ID has no mapping,
+    /// or maps to a row that is gone.
+    pub dangling: u64,
+    pub dangling_ids: Vec<i64>,
+    /// Rows the repair SQL re-resolves through `migration_mappings`.
+    pub repairable: u64,
+    /// Rows beyond the first pointing at the same row of a one-to-one
+    /// reference.
+    pub duplicates: u64,
+    pub duplicate_values: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Audit {
+    #[serde(flatten)]
+    pub reference: Reference,
+    #[serde(flatten)]
+    pub findings: Findings,
+    /// `(legacy ID of the row, legacy ID it references)` of the lookup rows
+    /// the repair SQL re-resolves.
+    #[serde(skip)]
+    pub resolved: Vec<(i64, i64)>,
+}
+
+impl Audit {
+    pub fn is_clean(&self) -> bool {
+        let f = &self.findings;
+        f.error.is_none() && f.orphans + f.unset + f.misdirected + f.dangling + f.duplicates == 0
+    }
+}
+
+/// Audits references, connecting to the source only when a spec lookup
+/// needs the legacy IDs of broken rows.
+pub struct Auditor<'a> {
+    pub specs: &'a [EntitySpec],
+    pub target: &'a mut Client,
+    pub source_url: Option<&'a str>,
+    source: Option<Client>,
+}
+
+impl<'a> Auditor<'a> {
+    pub fn new(
+        specs: &'a [EntitySpec],
+        target: &'a mut Client,
+        source_url: Option<&'a str>,
+    ) -> Self {
+        Auditor {
+            specs,
+            target,
+            source_url,
+            source: None,
+        }
+    }
+
+    pub fn audit(&mut self, reference: &Reference) -> Audit {
+        let mut audit = Audit {
+            reference: reference.clone(),
+            findings: Findings::default(),
+            resolved: Vec::new(),
+        };
+        if let Err(e) = self.inspect(&mut audit) {
+            audit.findings.error = Some(e);
+        }
+        audit
+    }
+
+    fn inspect(&mut self, audit: &mut Audit) -> Result<(), String> {
+        let reference = &audit.reference;
+        let findings = &mut audit.findings;
+        let orphaned = reference.orphaned();
+        let row = self
+            .target
+            .query_one(
+                &format!(
+                    "SELECT count(t.{column}),
+                            count(*) FILTER (WHERE t.{column} IS NOT NULL AND {orphaned}),
+                            (array_agg(DISTINCT t.{column}::text)
+                                FILTER (WHERE t.{column} IS NOT NULL AND {orphaned}))[1:{n}]
+                     FROM {table} t",
+                    column = reference.column,
+                    table = reference.table,
+                    orphaned = orphaned,
+                    n = EXAMPLES,
+                ),
+                &[],
+            )
+            .map_err(|e| describe(&e))?;
+        findings.referencing_rows = row.get::<_, i64>(0) as u64;
+        findings.orphans = row.get::<_, i64>(1) as u64;
+        findings.orphan_values = row.get::<_, Option<Vec<String>>>(2).unwrap_or_default();
+
+        if reference.one_to_one {
+            let row = self
+                .target
+                .query_one(
+                    &format!(
+                        "SELECT coalesce(sum(n - 1), 0)::bigint,
+                                (array_agg(v ORDER BY n DESC, v))[1:{n}]
+                         FROM (
+                             SELECT t.{column}::text AS v, count(*) AS n
+                             FROM {table} t
+                             WHERE t.{column} IS NOT NULL
+                             GROUP BY t.{column}
+                             HAVING count(*) > 1
+                         ) d",
+                        column = reference.column,
+                        table = reference.table,
+                        n = EXAMPLES,
+                    ),
+                    &[],
+                )
+                .map_err(|e| describe(&e))?;
+            findings.duplicates = row.get::<_, i64>(0) as u64;
+            findings.duplicate_values = row.get::<_, Option<Vec<String>>>(1).unwrap_or_default();
+        }
+
+        match &reference.legacy {
+            None => Ok(()),
+            Some(Legacy::Column { column, entity }) => {
+                let row = self
+                    .target
+                    .query_one(
+                        &format!(
+                            "SELECT count(*) FILTER (WHERE r.{key} IS NULL AND {broken}),
+                                    (array_agg(DISTINCT t.{legacy}::bigint)
+                                        FILTER (WHERE r.{key} IS NULL AND {broken}))[1:{n}],
+                                    count(*) FILTER (WHERE r.{key} IS NOT NULL AND t.{column} IS NULL),
+                                    count(*) FILTER (WHERE r.{key} IS NOT NULL AND t.{column} <> r.{key}
+                                                       AND NOT {orphaned}),
+                                    count(*) FILTER (WHERE r.{key} IS NOT NULL
+                                                       AND t.{column} IS DISTINCT FROM r.{key})
+                             FROM {table} t
+                             LEFT JOIN migration_mappings m
+                                    ON m.entity_type = $1 AND m.legacy_id = t.{legacy}::bigint
+                             LEFT JOIN {referenced} r ON r.{key} = m.new_id
+                             WHERE t.{legacy} IS NOT NULL",
+                            key = reference.referenced_column,
+                            broken = format!("(t.{} IS NULL OR {})", reference.column, orphaned),
+                            legacy = column,
+                            column = reference.column,
+                            orphaned = orphaned,
+                            table = reference.table,
+                            referenced = reference.referenced_table,
+                            n = EXAMPLES,
+                        ),
+                        &[entity],
+                    )
+                    .map_err(|e| describe(&e))?;
+                findings.dangling = row.get::<_, i64>(0) as u64;
+                findings.dangling_ids = row.get::<_, Option<Vec<i64>>>(1).unwrap_or_default();
+                findings.unset = row.get::<_, i64>(2) as u64;
+                findings.misdirected = row.get::<_, i64>(3) as u64;
+                findings.repairable = row.get::<_, i64>(4) as u64;
+                Ok(())
+            }
+            Some(Legacy::Lookup {
+                spec,
+                source,
+                entity,
+                ..
+            }) => {
+                let specs = self.specs;
+                let spec = specs
+                    .iter()
+                    .find(|s| &s.name == spec)
+                    .ok_or_else(|| format!("no entity spec named '{}'", spec))?;
+                // Rows with a NULL or orphaned reference, by legacy ID.
+                let broken: Vec<(i64, bool)> = self
+                    .target
+                    .query(
+                        &format!(
+                            "SELECT t.{legacy}::bigint, t.{column} IS NULL
+                             FROM {table} t
+                             WHERE t.{legacy} IS NOT NULL AND (t.{column} IS NULL OR {orphaned})",
+                            legacy = spec.legacy_id_column,
+                            column = reference.column,
+                            table = reference.table,
+                            orphaned = orphaned,
+                        ),
+                        &[],
+                    )
+                    .map_err(|e| describe(&e))?
+                    .iter()
+                    .map(|row| (row.get(0), row.get(1)))
+                    .collect();
+                if broken.is_empty() {
+                    return Ok(());
+                }
+                let ids: Vec<i64> = broken.iter().map(|(id, _)| *id).collect();
+                let pairs: Vec<(i64, i64)> = self
+                    .source()?
+                    .query(
+                        &format!(
+                            "SELECT s.{key}::bigint, s.{source}::bigint
+                             FROM {table} s
+                             WHERE s.{key} = ANY($1::bigint[]) AND s.{source} IS NOT NULL",
+                            key = spec.source_key,
+                            source = source,
+                            table = spec.source_table,
+                        ),
+                        &[&ids],
+                    )
+                    .map_err(|e| format!("cannot read {}: {}", spec.source_table, describe(&e)))?
+                    .iter()
+                    .map(|row| (row.get(0), row.get(1)))
+                    .collect();
+                let mut references: Vec<i64> = pairs.iter().map(|(_, r)| *r).collect();
+                references.sort_unstable();
+                references.dedup();
+                let resolvable: HashSet<i64> = self
+                    .target
+                    .query(
+                        &format!(
+                            "SELECT v.reference
+                             FROM unnest($1::bigint[]) v(reference)
+                             JOIN migration_mappings m
+                               ON m.entity_type = $2 AND m.legacy_id = v.reference
+                             JOIN {referenced} r ON r.{key} = m.new_id",
+                            referenced = reference.referenced_table,
+                            key = reference.referenced_column,
+                        ),
+                        &[&references, entity],
+                    )
+                    .map_err(|e| describe(&e))?
+                    .iter()
+                    .map(|row| row.get(0))
+                    .collect();
+                let unset: HashSet<i64> = broken
+                    .iter()
+                    .filter(|(_, null)| *null)
+                    .map(|(id, _)| *id)
+                    .collect();
+                for (id, referenced) in pairs {
+                    if resolvable.contains(&referenced) {
+                        if unset.contains(&id) {
+                            findings.unset += 1;
+                        }
+                        audit.resolved.push((id, referenced));
+                    } else {
+                        findings.dangling += 1;
+                        if findings.dangling_ids.len() < EXAMPLES
+                            && !findings.dangling_ids.contains(&referenced)
+                        {
+                            findings.dangling_ids.push(referenced);
+                        }
+                    }
+                }
+                findings.repairable = audit.resolved.len() as u64;
+                Ok(())
+            }
+        }
+    }
+
+    fn source(&mut self) -> Result<&mut Client, String> {
+        if self.source.is_none() {
+            self.source = Some(engine::connect(Side::Source, self.source_url)?);
+        }
+        Ok(self.source.as_mut().expect("connected above"))
+    }
+}
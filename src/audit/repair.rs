@@ -0,0 +1,334 @@
+//! Repair SQL for the findings of a reference audit.
+//!
+//! References with a known legacy ID are re-resolved through
+//! `migration_mappings`, joined at run time so a mapping changed since the
+//! audit is still honored, and only to rows that exist. What cannot be
+//! resolved is left to a reviewer: orphans get a commented-out statement
+//! clearing them and one-to-one violations a commented-out query listing
+//! them.
+
+use std::fmt::Write;
+
+use chrono::Utc;
+
+use super::{Audit, Legacy};
+use crate::spec::literal;
+
+/// Rows per `VALUES` list of a lookup repair.
+const CHUNK: usize = 1000;
+
+/// A script repairing `audits` in one transaction, or None when there is
+/// nothing to repair.
+pub fn script(audits: &[Audit]) -> Option<String> {
+    let broken: Vec<&Audit> = audits
+        .iter()
+        .filter(|a| !a.is_clean() && a.findings.error.is_none())
+        .collect();
+    if broken.is_empty() {
+        return None;
+    }
+    let mut sql = String::new();
+    let _ = writeln!(
+        sql,
+        "-- Reference repairs generated by migration_generator audit refs at {}.",
+        Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
+    );
+    let _ = writeln!(
+        sql,
+        "-- Review before running: references are re-resolved through migration_mappings;"
+    );
+    let _ = writeln!(
+        sql,
+        "-- whatever cannot be resolved is only suggested in comments."
+    );
+    let _ = writeln!(sql, "\nBEGIN;");
+    for audit in broken {
+        section(&mut sql, audit);
+    }
+    let _ = writeln!(sql, "\nCOMMIT;");
+    Some(sql)
+}
+
+fn section(sql: &mut String, audit: &Audit) {
+    let reference = &audit.reference;
+    let findings = &audit.findings;
+    let origins: Vec<String> = reference.origins.iter().map(|o| o.to_string()).collect();
+    let _ = writeln!(
+        sql,
+        "\n-- {} ({})\n-- {} orphan(s), {} unset, {} misdirected, {} dangling, {} duplicate(s)",
+        reference.label(),
+        origins.join(", "),
+        findings.orphans,
+        findings.unset,
+        findings.misdirected,
+        findings.dangling,
+        findings.duplicates
+    );
+
+    if findings.repairable > 0 {
+        match &reference.legacy {
+            Some(Legacy::Column { column, entity }) => {
+                let _ = writeln!(
+                    sql,
+                    "UPDATE {table} t
+SET {column} = r.{key}
+FROM migration_mappings m
+JOIN {referenced} r ON r.{key} = m.new_id
+WHERE m.entity_type = {entity}
+  AND m.legacy_id = t.{legacy}::bigint
+  AND t.{column} IS DISTINCT FROM r.{key};",
+                    table = reference.table,
+                    column = reference.column,
+                    key = reference.referenced_column,
+                    referenced = reference.referenced_table,
+                    entity = literal(entity),
+                    legacy = column,
+                );
+            }
+            Some(Legacy::Lookup {
+                spec,
+                entity,
+                legacy_id_column,
+                ..
+            }) => {
+                let _ = writeln!(
+                    sql,
+                    "-- Legacy references read from the source rows of spec {}.",
+                    spec
+                );
+                for chunk in audit.resolved.chunks(CHUNK) {
+                    let values: Vec<String> = chunk
+                        .iter()
+                        .map(|(id, referenced)| format!("({}, {})", id, referenced))
+                        .collect();
+                    let _ = writeln!(
+                        sql,
+                        "UPDATE {table} t
+SET {column} = r.{key}
+FROM (VALUES {values}) v(legacy_id, reference)
+JOIN migration_mappings m ON m.entity_type = {entity} AND m.legacy_id = v.reference
+JOIN {referenced} r ON r.{key} = m.new_id
+WHERE t.{legacy} = v.legacy_id
+  AND t.{column} IS DISTINCT FROM r.{key};",
+                        table = reference.table,
+                        column = reference.column,
+                        key = reference.referenced_column,
+                        values = values.join(", "),
+                        entity = literal(entity),
+                        referenced = reference.referenced_table,
+                        legacy = legacy_id_column,
+                    );
+                }
+            }
+            None => {}
+        }
+    }
+
+    if findings.orphans > 0 {
+        let _ = writeln!(
+            sql,
+            "-- Orphans left after the above have no resolvable legacy ID; to clear them:
+-- UPDATE {table} t SET {column} = NULL
+-- WHERE t.{column} IS NOT NULL AND {orphaned};",
+            table = reference.table,
+            column = reference.column,
+            orphaned = reference.orphaned(),
+        );
+    }
+    if findings.duplicates > 0 {
+        let _ = writeln!(
+            sql,
+            "-- {n} row(s) share a {referenced} row with another; decide which to keep:
+-- SELECT {column}, count(*) FROM {table} WHERE {column} IS NOT NULL
+-- GROUP BY {column} HAVING count(*) > 1;",
+            n = findings.duplicates,
+            referenced = reference.referenced_table,
+            column = reference.column,
+            table = reference.table,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Findings, Origin, Reference};
+    use super::*;
+
+    fn audit(legacy: Option<Legacy>, findings: Findings, resolved: Vec<(i64, i64)>) -> Audit {
+        Audit {
+            reference: Reference {
+                table: "patients".into(),
+                column: "office_id".into(),
+                referenced_table: "offices".into(),
+                referenced_column: "id".into(),
+                origins: vec![
+                    Origin::Constraint("patients_office_id_fkey".into()),
+                    Origin::Spec("patients".into()),
+                ],
+                legacy,
+                one_to_one: false,
+            },
+            findings,
+            resolved,
+        }
+    }
+
+    fn column() -> Legacy {
+        Legacy::Column {
+            column: "legacy_office_id".into(),
+            entity: "office's".into(),
+        }
+    }
+
+    fn lookup() -> Legacy {
+        Legacy::Lookup {
+            spec: "patients".into(),
+            source: "office_id".into(),
+            entity: "office".into(),
+            legacy_id_column: "legacy_patient_id".into(),
+        }
+    }
+
+    fn rendered(audit: &Audit) -> String {
+        let mut sql = String::new();
+        section(&mut sql, audit);
+        sql
+    }
+
+    #[test]
+    fn legacy_columns_are_re_resolved() {
+        let findings = Findings {
+            orphans: 2,
+            misdirected: 1,
+            repairable: 3,
+            duplicates: 1,
+            ..Findings::default()
+        };
+        let expected = "
+-- patients.office_id → offices.id (constraint patients_office_id_fkey, spec patients)
+-- 2 orphan(s), 0 unset, 1 misdirected, 0 dangling, 1 duplicate(s)
+UPDATE patients t
+SET office_id = r.id
+FROM migration_mappings m
+JOIN offices r ON r.id = m.new_id
+WHERE m.entity_type = 'office''s'
+  AND m.legacy_id = t.legacy_office_id::bigint
+  AND t.office_id IS DISTINCT FROM r.id;
+-- Orphans left after the above have no resolvable legacy ID; to clear them:
+-- UPDATE patients t SET office_id = NULL
+-- WHERE t.office_id IS NOT NULL AND NOT EXISTS (SELECT 1 FROM offices x WHERE x.id = t.office_id);
+-- 1 row(s) share a offices row with another; decide which to keep:
+-- SELECT office_id, count(*) FROM patients WHERE office_id IS NOT NULL
+-- GROUP BY office_id HAVING count(*) > 1;
+";
+        assert_eq!(
+            rendered(&audit(Some(column()), findings, Vec::new())),
+            expected
+        );
+    }
+
+    #[test]
+    fn lookups_are_re_resolved_from_the_source_ids() {
+        let findings = Findings {
+            unset: 2,
+            repairable: 2,
+            ..Findings::default()
+        };
+        let expected = "
+-- patients.office_id → offices.id (constraint patients_office_id_fkey, spec patients)
+-- 0 orphan(s), 2 unset, 0 misdirected, 0 dangling, 0 duplicate(s)
+-- Legacy references read from the source rows of spec patients.
+UPDATE patients t
+SET office_id = r.id
+FROM (VALUES (10, 1), (11, 2)) v(legacy_id, reference)
+JOIN migration_mappings m ON m.entity_type = 'office' AND m.legacy_id = v.reference
+JOIN offices r ON r.id = m.new_id
+WHERE t.legacy_patient_id = v.legacy_id
+  AND t.office_id IS DISTINCT FROM r.id;
+";
+        let sql = rendered(&audit(Some(lookup()), findings, vec![(10, 1), (11, 2)]));
+        assert_eq!(sql, expected);
+    }
+
+    #[test]
+    fn lookups_are_chunked() {
+        let findings = Findings {
+            unset: CHUNK as u64 * 2 + 1,
+            repairable: CHUNK as u64 * 2 + 1,
+            ..Findings::default()
+        };
+        let resolved: Vec<(i64, i64)> = (1..=CHUNK as i64 * 2 + 1).map(|i| (i, -i)).collect();
+        let sql = rendered(&audit(Some(lookup()), findings, resolved));
+        let statements: Vec<&str> = sql.split("UPDATE patients t\n").skip(1).collect();
+        let rows: Vec<usize> = statements
+            .iter()
+            .map(|s| s.matches("), (").count() + 1)
+            .collect();
+        assert_eq!(rows, [CHUNK, CHUNK, 1]);
+        assert!(statements[0].contains("VALUES (1, -1), (2, -2), "));
+        assert!(statements[1].contains(&format!("VALUES ({0}, -{0}), ", CHUNK + 1)));
+        assert!(statements[2].contains(&format!("(VALUES ({0}, -{0})) v(", CHUNK * 2 + 1)));
+    }
+
+    #[test]
+    fn unresolvable_findings_are_only_suggested() {
+        // Without a legacy ID, and for a legacy column with nothing
+        // repairable, only the hints are written.
+        let findings = Findings {
+            orphans: 1,
+            dangling: 1,
+            ..Findings::default()
+        };
+        for legacy in [None, Some(column()), Some(lookup())] {
+            let sql = rendered(&audit(legacy, findings.clone(), vec![(1, 2)]));
+            assert!(!sql.contains("\nUPDATE"), "{}", sql);
+            assert!(sql.contains("-- Orphans left after the above"), "{}", sql);
+            assert!(!sql.contains("share a"), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn scripts_cover_the_broken_audits_in_one_transaction() {
+        let clean = audit(Some(column()), Findings::default(), Vec::new());
+        let failed = audit(
+            None,
+            Findings {
+                orphans: 1,
+                error: Some("permission denied".into()),
+                ..Findings::default()
+            },
+            Vec::new(),
+        );
+        assert_eq!(script(&[]), None);
+        assert_eq!(script(&[clean.clone(), failed.clone()]), None);
+
+        let broken = audit(
+            None,
+            Findings {
+                duplicates: 1,
+                ..Findings::default()
+            },
+            Vec::new(),
+        );
+        let sql = script(&[clean, broken.clone(), failed]).unwrap();
+        let (stamp, rest) = sql.split_once('\n').unwrap();
+        assert!(
+            stamp.starts_with(
+                "-- Reference repairs generated by migration_generator audit refs at "
+            ),
+            "{}",
+            stamp
+        );
+        let expected = format!(
+            "-- Review before running: references are re-resolved through migration_mappings;
+-- whatever cannot be resolved is only suggested in comments.
+
+BEGIN;
+{}
+COMMIT;
+",
+            rendered(&broken)
+        );
+        assert_eq!(rest, expected);
+    }
+}
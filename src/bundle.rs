@@ -0,0 +1,298 @@
+//! Bundle mode: one stdin stream carrying several files.
+//!
+//! Each file starts with a header line of the form `=== path/to/file ===`
+//! and runs until the next header or the end of input.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+const HEADER_OPEN: &str = "=== ";
+const HEADER_CLOSE: &str = " ===";
+
+#[derive(Debug)]
+pub struct BundleEntry {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+#[derive(Debug)]
+pub enum BundleError {
+    ContentBeforeHeader { line: usize },
+    EmptyPath { line: usize },
+    UnsafePath { line: usize, path: String },
+    DuplicatePath { line: usize, path: String },
+    NoFiles,
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleError::ContentBeforeHeader { line } => {
+                write!(
+                    f,
+                    "line {}: content before the first '=== path ===' header",
+                    line
+                )
+            }
+            BundleError::EmptyPath { line } => write!(f, "line {}: header has an empty path", line),
+            BundleError::UnsafePath { line, path } => {
+                write!(
+                    f,
+                    "line {}: path '{}' escapes the working directory",
+                    line, path
+                )
+            }
+            BundleError::DuplicatePath { line, path } => {
+                write!(f, "line {}: path '{}' appears more than once", line, path)
+            }
+            BundleError::NoFiles => write!(f, "bundle contains no '=== path ===' headers"),
+        }
+    }
+}
+
+impl std::error::Error for BundleError {}
+
+/// Split a bundle stream into its files, validating every path up front so a
+/// bad entry never leaves a half-written family of scripts behind.
+pub fn parse(input: &str) -> Result<Vec<BundleEntry>, BundleError> {
+    let mut entries: Vec<BundleEntry> = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (idx, line) in input.split_inclusive('\n').enumerate() {
+        let line_no = idx + 1;
+        if let Some(raw) = header_path(line) {
+            if raw.is_empty() {
+                return Err(BundleError::EmptyPath { line: line_no });
+            }
+            let path = normalize(raw).ok_or_else(|| BundleError::UnsafePath {
+                line: line_no,
+                path: raw.to_string(),
+            })?;
+            if !seen.insert(path.clone()) {
+                return Err(BundleError::DuplicatePath {
+                    line: line_no,
+                    path: raw.to_string(),
+                });
+            }
+            entries.push(BundleEntry {
+                path,
+                contents: String::new(),
+            });
+            continue;
+        }
+        match entries.last_mut() {
+            Some(entry) => entry.contents.push_str(line),
+            None if line.trim().is_empty() => {}
+            None => return Err(BundleError::ContentBeforeHeader { line: line_no }),
+        }
+    }
+
+    if entries.is_empty() {
+        return Err(BundleError::NoFiles);
+    }
+    Ok(entries)
+}
+
+fn header_path(line: &str) -> Option<&str> {
+    let line = line.trim_end_matches(['\n', '\r']);
+    line.strip_prefix(HEADER_OPEN)
+        .and_then(|rest| rest.strip_suffix(HEADER_CLOSE))
+        .map(str::trim)
+}
+
+/// Lexically normalize a relative path, returning `None` if it is absolute or
+/// climbs above the directory it is resolved against.
+fn normalize(raw: &str) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+    for component in Path::new(raw).components() {
+        match component {
+            Component::Normal(part) => out.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    if out.as_os_str().is_empty() {
+        None
+    } else {
+        Some(out)
+    }
+}
+
+/// The destination of `relative` under `root`, provided its deepest existing
+/// ancestor resolves, through any symlinks, to a directory inside `root`.
+/// Nothing is created, so every entry can be checked before any is written.
+pub fn check_destination(root: &Path, relative: &Path) -> io::Result<PathBuf> {
+    let dest = root.join(relative);
+    let canonical_root = fs::canonicalize(root)?;
+    let mut existing = dest.parent().unwrap_or(root);
+    while !existing.exists() {
+        existing = existing.parent().unwrap_or(root);
+    }
+    ensure_within(&canonical_root, existing, relative)?;
+    Ok(dest)
+}
+
+/// [`check_destination`], then create the parent directories and confirm
+/// they did not end up outside `root` either.
+pub fn prepare_destination(root: &Path, relative: &Path) -> io::Result<PathBuf> {
+    let dest = check_destination(root, relative)?;
+    let parent = dest.parent().unwrap_or(root);
+    fs::create_dir_all(parent)?;
+    ensure_within(&fs::canonicalize(root)?, parent, relative)?;
+    Ok(dest)
+}
+
+fn ensure_within(canonical_root: &Path, dir: &Path, relative: &Path) -> io::Result<()> {
+    if fs::canonicalize(dir)?.starts_with(canonical_root) {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!(
+            "{} resolves outside the working directory",
+            relative.display()
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+
+    /// A fresh `root/` and `outside/` pair under the temp directory.
+    fn dirs(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let base = std::env::temp_dir().join(format!("bundle-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let (root, outside) = (base.join("root"), base.join("outside"));
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        (base, root, outside)
+    }
+
+    #[test]
+    fn normalize() {
+        let cases = [
+            ("a.ts", Some("a.ts")),
+            ("scripts/a.ts", Some("scripts/a.ts")),
+            ("./scripts//a.ts", Some("scripts/a.ts")),
+            ("scripts/../a.ts", Some("a.ts")),
+            ("a/b/../../c.ts", Some("c.ts")),
+            ("../a.ts", None),
+            ("a/../../b.ts", None),
+            ("/etc/passwd", None),
+            (".", None),
+            ("a/..", None),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(
+                super::normalize(raw),
+                expected.map(PathBuf::from),
+                "{}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn parse_rejects() {
+        let cases = [
+            (
+                "=== a.ts ===\nx\n=== ./a.ts ===\ny\n",
+                "line 3: path './a.ts' appears more than once",
+            ),
+            (
+                "=== a/b.ts ===\n=== a/x/../b.ts ===\n",
+                "line 2: path 'a/x/../b.ts' appears more than once",
+            ),
+            (
+                "=== ../a.ts ===\n",
+                "line 1: path '../a.ts' escapes the working directory",
+            ),
+            (
+                "=== a.ts ===\n=== /tmp/a.ts ===\n",
+                "line 2: path '/tmp/a.ts' escapes the working directory",
+            ),
+            ("===  ===\n", "line 1: header has an empty path"),
+            (
+                "\nstray\n=== a.ts ===\n",
+                "line 2: content before the first '=== path ===' header",
+            ),
+            (
+                "no headers\n",
+                "line 1: content before the first '=== path ===' header",
+            ),
+            ("\n", "bundle contains no '=== path ===' headers"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                parse(input).unwrap_err().to_string(),
+                expected,
+                "{:?}",
+                input
+            );
+        }
+
+        let entries = parse("\n=== a.ts ===\none\n=== b/c.ts ===\n=== not a header\n").unwrap();
+        let parsed: Vec<(&Path, &str)> = entries
+            .iter()
+            .map(|e| (e.path.as_path(), e.contents.as_str()))
+            .collect();
+        assert_eq!(
+            parsed,
+            [
+                (Path::new("a.ts"), "one\n"),
+                (Path::new("b/c.ts"), "=== not a header\n")
+            ]
+        );
+    }
+
+    #[test]
+    fn destinations_stay_inside_the_root() {
+        let (base, root, outside) = dirs("destinations");
+        symlink(&outside, root.join("link")).unwrap();
+        fs::create_dir_all(root.join("real")).unwrap();
+        symlink(root.join("real"), root.join("inner")).unwrap();
+
+        // Checking creates nothing.
+        let dest = check_destination(&root, Path::new("new/dir/a.ts")).unwrap();
+        assert_eq!(dest, root.join("new/dir/a.ts"));
+        assert!(!root.join("new").exists());
+
+        let dest = prepare_destination(&root, Path::new("new/dir/a.ts")).unwrap();
+        assert!(dest.parent().unwrap().is_dir());
+        // A symlink that stays inside the root is fine.
+        prepare_destination(&root, Path::new("inner/sub/a.ts")).unwrap();
+        assert!(root.join("real/sub").is_dir());
+
+        for relative in ["link/a.ts", "link/sub/deeper/a.ts"] {
+            for result in [
+                check_destination(&root, Path::new(relative)),
+                prepare_destination(&root, Path::new(relative)),
+            ] {
+                let error = result.unwrap_err();
+                assert_eq!(
+                    error.kind(),
+                    io::ErrorKind::PermissionDenied,
+                    "{}",
+                    relative
+                );
+                assert_eq!(
+                    error.to_string(),
+                    format!("{} resolves outside the working directory", relative)
+                );
+            }
+        }
+        assert_eq!(fs::read_dir(&outside).unwrap().count(), 0);
+        fs::remove_dir_all(base).unwrap();
+    }
+}
@@ -0,0 +1,850 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use uuid::Uuid;
+
+use crate::config::Profile;
+use crate::differential::DetectionMode;
+use crate::engine::LoadMode;
+use crate::engine::conflict::Take;
+use crate::report::render::Format as ReportFormat;
+use crate::scaffold::ArtifactKind;
+use crate::schema::InputFormat;
+use crate::spec::DeleteMode;
+use crate::validate::sample::Strategy;
+
+/// Generate, preview and write data-migration artifacts.
+#[derive(Debug, Parser)]
+#[command(name = "migration_generator", version, about)]
+pub struct Cli {
+    /// Only print errors
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Print a machine-readable JSON report on stdout
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Settings file layered under `.env` and the environment
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Settings profile of the config file to apply; defaults to
+    /// $MIGRATION_PROFILE, then the file's `profile`
+    #[arg(long, global = true, value_enum)]
+    pub profile: Option<Profile>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Write stdin to a single file
+    Write(WriteArgs),
+    /// Split stdin into several files separated by `=== path ===` headers
+    Bundle(BundleArgs),
+    /// Generate migration, validation and rollback scripts from entity specs
+    Scaffold(ScaffoldArgs),
+    /// Build a typed schema snapshot from pg_dump DDL or a catalog JSON export
+    Introspect(IntrospectArgs),
+    /// Suggest source-to-target column mappings from two introspected schemas
+    MapSuggest(MapSuggestArgs),
+    /// Order entities by their FK dependencies into parallel stages
+    Plan(PlanArgs),
+    /// Migrate one entity from the source to the target database
+    Run(RunArgs),
+    /// Export `migration_mappings` to a binary file or import one
+    Mappings(MappingsArgs),
+    /// Print the deterministic UUID of legacy rows, or find the row a UUID
+    /// was mapped from
+    Uuid(UuidArgs),
+    /// List or reset the executor's resume checkpoints
+    Checkpoints(CheckpointsArgs),
+    /// Classify source rows as new, modified, deleted or unchanged against
+    /// the target
+    Differential(DifferentialArgs),
+    /// Find the rows that differ between source and target by comparing
+    /// checksums of legacy-ID ranges
+    Reconcile(ReconcileArgs),
+    /// List the conflicts queued for a manual decision, or settle one
+    Resolve(ResolveArgs),
+    /// Apply the delete policy to target rows whose legacy row was deleted
+    Tombstones(TombstonesArgs),
+    /// Run the scheduled sync jobs of `synchronization_jobs` until stopped
+    Daemon(DaemonArgs),
+    /// Show the effective settings or check them for mistakes
+    Config(ConfigArgs),
+    /// Check the migrated data and record a pass/warn/fail matrix per entity
+    Validate(ValidateArgs),
+    /// Compare sampled rows field by field with what the spec says the
+    /// target should hold
+    Verify(VerifyArgs),
+    /// Check the target's data for broken references
+    Audit(AuditArgs),
+    /// Summarize the recorded runs, logs and validations as a Markdown,
+    /// HTML or JSON report
+    Report(ReportArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct WriteOpts {
+    /// Keep the previous version as `<name>.bak.<timestamp>`
+    #[arg(long, conflicts_with_all = ["dry_run", "check"])]
+    pub backup: bool,
+
+    /// Print a unified diff against the existing file instead of writing
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Write nothing and exit non-zero if the content would change
+    #[arg(long)]
+    pub check: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct WriteArgs {
+    /// Destination file
+    pub filename: PathBuf,
+
+    #[command(flatten)]
+    pub write: WriteOpts,
+}
+
+#[derive(Debug, Args)]
+pub struct BundleArgs {
+    /// Directory the bundle paths are resolved against
+    #[arg(long, default_value = ".")]
+    pub root: PathBuf,
+
+    #[command(flatten)]
+    pub write: WriteOpts,
+}
+
+#[derive(Debug, Args)]
+pub struct ScaffoldArgs {
+    /// Entity spec files (.toml, .yaml, .yml) or directories containing them
+    #[arg(required = true)]
+    pub specs: Vec<PathBuf>,
+
+    /// Directory the generated files are written to
+    #[arg(long, default_value = ".")]
+    pub out_dir: PathBuf,
+
+    /// Only generate these artifacts (repeatable)
+    #[arg(long, value_enum)]
+    pub only: Vec<ArtifactKind>,
+
+    /// Directory whose `*.tmpl` files override the built-in templates
+    #[arg(long, default_value = "templates")]
+    pub templates: PathBuf,
+
+    /// Source schema whose foreign keys order the rollbacks and mark
+    /// backfilled columns (pg_dump DDL, catalog JSON export or snapshot)
+    #[arg(long)]
+    pub schema: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub write: WriteOpts,
+}
+
+#[derive(Debug, Args)]
+pub struct IntrospectArgs {
+    /// `pg_dump --schema-only` file, information_schema JSON export or snapshot
+    #[arg(required_unless_present = "print_query")]
+    pub input: Option<PathBuf>,
+
+    /// Input format
+    #[arg(long, value_enum, default_value = "auto")]
+    pub format: InputFormat,
+
+    /// Write the schema snapshot to this file
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Rebuild the snapshot even if it is current for this input
+    #[arg(long)]
+    pub force: bool,
+
+    /// Table inventory (e.g. `\\dt` or pg_tables output) that must all be present
+    #[arg(long)]
+    pub expect: Option<PathBuf>,
+
+    /// Print the SQL that produces a catalog JSON export and exit
+    #[arg(long, conflicts_with_all = ["input", "output", "expect"])]
+    pub print_query: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct MapSuggestArgs {
+    /// Legacy schema: snapshot, pg_dump file or catalog JSON export
+    #[arg(long)]
+    pub source: PathBuf,
+
+    /// Target schema: snapshot, pg_dump file or catalog JSON export
+    #[arg(long)]
+    pub target: PathBuf,
+
+    /// Pair a source table with a target table explicitly (repeatable)
+    #[arg(long, value_name = "SOURCE=TARGET")]
+    pub pair: Vec<String>,
+
+    /// Only suggest mappings for these source tables (repeatable)
+    #[arg(long)]
+    pub table: Vec<String>,
+
+    /// Markdown mapping file to write
+    #[arg(short, long, default_value = "SUGGESTED_FIELD_MAPPING.md")]
+    pub output: PathBuf,
+
+    #[command(flatten)]
+    pub write: WriteOpts,
+}
+
+#[derive(Debug, Args)]
+pub struct PlanArgs {
+    /// Entity spec files or directories; without specs every schema table is planned
+    pub specs: Vec<PathBuf>,
+
+    /// Source schema (snapshot, pg_dump file or catalog JSON) to read FKs from
+    #[arg(long)]
+    pub schema: Option<PathBuf>,
+
+    /// Write the execution plan as JSON
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Write the dependency graph as Graphviz DOT
+    #[arg(long)]
+    pub dot: Option<PathBuf>,
+
+    /// Write the dependency graph as a Mermaid flowchart
+    #[arg(long)]
+    pub mermaid: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub write: WriteOpts,
+}
+
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    /// Entity to migrate (the spec's `name`)
+    pub entity: String,
+
+    /// Entity spec files or directories to find the entity in
+    #[arg(long, default_value = "entities")]
+    pub specs: Vec<PathBuf>,
+
+    /// Source connection string; defaults to the SOURCE_DB_* variables
+    #[arg(long)]
+    pub source_url: Option<String>,
+
+    /// Target connection string; defaults to the TARGET_DB_* variables
+    #[arg(long)]
+    pub target_url: Option<String>,
+
+    /// Rows per batch and transaction; defaults to the configured
+    /// `BATCH_SIZE`, then the spec's `batch_size`
+    #[arg(long)]
+    pub batch_size: Option<usize>,
+
+    /// How batches are written: multi-row upsert, or binary COPY into a
+    /// staging table merged with one INSERT … SELECT (for large tables)
+    #[arg(long, value_enum, default_value = "upsert")]
+    pub load: LoadMode,
+
+    /// Stop after this many source rows
+    #[arg(long)]
+    pub limit: Option<u64>,
+
+    /// `migration_mappings.migration_batch`; defaults to
+    /// `<entity_type>_migration_<timestamp>`
+    #[arg(long)]
+    pub migration_batch: Option<String>,
+
+    /// Resolve lookups from a `mappings export` file instead of the
+    /// target's `migration_mappings`
+    #[arg(long)]
+    pub mappings: Option<PathBuf>,
+
+    /// Continue after the last committed batch of the entity's checkpoint
+    /// (or of a TypeScript `migration_checkpoints` row)
+    #[arg(long)]
+    pub resume: bool,
+
+    /// Only migrate the new and modified rows of a `differential` or
+    /// `reconcile` JSON output
+    #[arg(long, conflicts_with = "resume")]
+    pub changes: Option<PathBuf>,
+
+    /// Read and transform every batch but write nothing
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct MappingsArgs {
+    #[command(subcommand)]
+    pub action: MappingsAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MappingsAction {
+    /// Write the target's `migration_mappings` to a binary file
+    Export(MappingsExportArgs),
+    /// Insert the rows of an export file into `migration_mappings`, keeping
+    /// existing rows
+    Import(MappingsImportArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct MappingsExportArgs {
+    /// Destination file
+    pub file: PathBuf,
+
+    /// Entity types to export (repeatable); defaults to all of them
+    #[arg(long = "entity")]
+    pub entities: Vec<String>,
+
+    /// Target connection string; defaults to the TARGET_DB_* variables
+    #[arg(long)]
+    pub target_url: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct MappingsImportArgs {
+    /// File written by `mappings export`
+    pub file: PathBuf,
+
+    /// Target connection string; defaults to the TARGET_DB_* variables
+    #[arg(long)]
+    pub target_url: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct UuidArgs {
+    /// Entity type or `dispatch_*` table
+    #[arg(required_unless_present_any = ["reverse", "list"])]
+    pub entity: Option<String>,
+
+    /// Legacy primary keys
+    #[arg(required_unless_present_any = ["reverse", "list"])]
+    pub legacy_ids: Vec<i64>,
+
+    /// Find the entity type and legacy ID that a target UUID was mapped from
+    #[arg(long, value_name = "UUID", conflicts_with_all = ["entity", "list"])]
+    pub reverse: Option<Uuid>,
+
+    /// Print the namespace of every registered `dispatch_*` table
+    #[arg(long, conflicts_with = "entity")]
+    pub list: bool,
+
+    /// Look IDs up in a `mappings export` file
+    #[arg(long, conflicts_with = "target_url")]
+    pub mappings: Option<PathBuf>,
+
+    /// Look IDs up in the target's `migration_mappings`; `--reverse`
+    /// defaults to the TARGET_DB_* variables
+    #[arg(long)]
+    pub target_url: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct CheckpointsArgs {
+    #[command(subcommand)]
+    pub action: CheckpointsAction,
+
+    /// Target connection string; defaults to the TARGET_DB_* variables
+    #[arg(long, global = true)]
+    pub target_url: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CheckpointsAction {
+    /// Show every checkpoint, including those of the TypeScript tools
+    List,
+    /// Forget an entity's checkpoint so the next run starts from the beginning
+    Reset(CheckpointsResetArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct CheckpointsResetArgs {
+    /// Entity (the spec's `name`)
+    pub entity: String,
+
+    /// Also delete the `migration_checkpoints` rows with this entity type
+    #[arg(long)]
+    pub legacy: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DifferentialArgs {
+    /// Entity to compare (the spec's `name`)
+    pub entity: String,
+
+    /// Entity spec files or directories to find the entity in
+    #[arg(long, default_value = "entities")]
+    pub specs: Vec<PathBuf>,
+
+    /// Source connection string; defaults to the SOURCE_DB_* variables
+    #[arg(long)]
+    pub source_url: Option<String>,
+
+    /// Target connection string; defaults to the TARGET_DB_* variables
+    #[arg(long)]
+    pub target_url: Option<String>,
+
+    /// Compare a content hash of the mapped columns, or a timestamp column
+    #[arg(long, value_enum, default_value = "hash")]
+    pub mode: DetectionMode,
+
+    /// Source column compared in timestamp mode
+    #[arg(long, default_value = "updated_at")]
+    pub timestamp_column: String,
+
+    /// Rows fetched per round trip on each side
+    #[arg(long, default_value_t = 10_000)]
+    pub fetch_size: usize,
+
+    /// Also write the analysis as JSON to this file
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+
+    /// Insert the analysis into the target's `differential_analysis_results`
+    #[arg(long)]
+    pub record: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ReconcileArgs {
+    /// Entity to reconcile (the spec's `name`)
+    pub entity: String,
+
+    /// Entity spec files or directories to find the entity in
+    #[arg(long, default_value = "entities")]
+    pub specs: Vec<PathBuf>,
+
+    /// Source connection string; defaults to the SOURCE_DB_* variables
+    #[arg(long)]
+    pub source_url: Option<String>,
+
+    /// Target connection string; defaults to the TARGET_DB_* variables
+    #[arg(long)]
+    pub target_url: Option<String>,
+
+    /// Sub-ranges each mismatched range is split into
+    #[arg(long, default_value_t = 16)]
+    pub fanout: u32,
+
+    /// Compare ranges of at most this many rows row by row
+    #[arg(long, default_value_t = 1_000)]
+    pub leaf_rows: u64,
+
+    /// Also write the analysis as JSON to this file (`run --changes` input)
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+
+    /// Insert the analysis into the target's `differential_analysis_results`
+    /// and `data_differentials`
+    #[arg(long)]
+    pub record: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ResolveArgs {
+    #[command(subcommand)]
+    pub action: ResolveAction,
+
+    /// Target connection string; defaults to the TARGET_DB_* variables
+    #[arg(long, global = true)]
+    pub target_url: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ResolveAction {
+    /// Show the pending conflicts with both versions of the queued columns
+    List(ResolveListArgs),
+    /// Keep the source or the target version of a queued conflict
+    Apply(ResolveApplyArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ResolveListArgs {
+    /// Only conflicts of this entity (the spec's `name`)
+    #[arg(long)]
+    pub entity: Option<String>,
+
+    /// Include resolved and superseded conflicts
+    #[arg(long)]
+    pub all: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ResolveApplyArgs {
+    /// Queued conflict ID, as shown by `resolve list`
+    pub id: i64,
+
+    /// Version every queued column keeps unless `--column` says otherwise
+    #[arg(long, value_enum)]
+    pub take: Option<Take>,
+
+    /// Version one column keeps, as `COLUMN=source` or `COLUMN=target`
+    #[arg(long = "column", value_name = "COLUMN=SIDE")]
+    pub columns: Vec<String>,
+
+    /// Name recorded as `decided_by`; defaults to $USER
+    #[arg(long)]
+    pub by: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct TombstonesArgs {
+    /// Entity whose deleted rows to handle (the spec's `name`)
+    pub entity: String,
+
+    /// Entity spec files or directories; dependents are looked up among all
+    /// of them
+    #[arg(long, default_value = "entities")]
+    pub specs: Vec<PathBuf>,
+
+    /// Source connection string; defaults to the SOURCE_DB_* variables
+    #[arg(long)]
+    pub source_url: Option<String>,
+
+    /// Target connection string; defaults to the TARGET_DB_* variables
+    #[arg(long)]
+    pub target_url: Option<String>,
+
+    /// Take the deleted rows from an analysis written by `differential
+    /// --output` or `reconcile --output` instead of comparing again
+    #[arg(long)]
+    pub changes: Option<PathBuf>,
+
+    /// Use this policy instead of the spec's `deletes.policy`
+    #[arg(long, value_enum)]
+    pub policy: Option<DeleteMode>,
+
+    /// List the rows and their dependents without changing anything
+    #[arg(long)]
+    pub preview: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DaemonArgs {
+    /// Entity spec files or directories the jobs' entities are found in
+    #[arg(long, default_value = "entities")]
+    pub specs: Vec<PathBuf>,
+
+    /// Source connection string; defaults to the SOURCE_DB_* variables
+    #[arg(long)]
+    pub source_url: Option<String>,
+
+    /// Target connection string; defaults to the TARGET_DB_* variables
+    #[arg(long)]
+    pub target_url: Option<String>,
+
+    /// Advisory lock name; daemons sharing it elect one leader
+    #[arg(long, default_value = "migration_generator_daemon")]
+    pub lock_name: String,
+
+    /// Seconds between checks for due jobs (and for the lock, on standby)
+    #[arg(long, default_value_t = 30)]
+    pub poll: u64,
+
+    /// Delay each run by up to this many seconds, unless the job's
+    /// `schedule_config` sets `jitter_seconds`
+    #[arg(long, default_value_t = 0)]
+    pub jitter: u64,
+
+    /// Stop a run after this many seconds, unless the job's `schedule_config`
+    /// sets `max_runtime_seconds`
+    #[arg(long)]
+    pub max_runtime: Option<u64>,
+
+    /// Run the jobs that are due once and exit
+    #[arg(long)]
+    pub once: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub action: ConfigAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigAction {
+    /// Print every setting with the layer it came from
+    Show(ConfigShowArgs),
+    /// Report unknown keys, invalid values and conflicting settings
+    Validate,
+}
+
+#[derive(Debug, Args)]
+pub struct ConfigShowArgs {
+    /// Mask passwords, including those in connection strings
+    #[arg(long)]
+    pub redacted: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ValidateArgs {
+    /// Check file (.toml or .yaml) of `[[check]]` entries; defaults to row
+    /// counts, FK orphans, unique legacy IDs and a sample for every entity
+    #[arg(long)]
+    pub checks: Option<PathBuf>,
+
+    /// Only run the checks of this entity (the spec's `name`)
+    #[arg(long)]
+    pub entity: Option<String>,
+
+    /// Entity spec files or directories the checks refer to
+    #[arg(long, default_value = "entities")]
+    pub specs: Vec<PathBuf>,
+
+    /// Source connection string; defaults to the SOURCE_DB_* variables
+    #[arg(long)]
+    pub source_url: Option<String>,
+
+    /// Target connection string; defaults to the TARGET_DB_* variables
+    #[arg(long)]
+    pub target_url: Option<String>,
+
+    /// Days the results are kept in `migration_validation_reports`
+    #[arg(long, default_value_t = 30)]
+    pub keep_days: u32,
+
+    /// Do not write the results to `migration_validation_reports`
+    #[arg(long)]
+    pub no_record: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    /// Entities to verify (the specs' `name`); all of them when omitted
+    pub entities: Vec<String>,
+
+    /// Entity spec files or directories
+    #[arg(long, default_value = "entities")]
+    pub specs: Vec<PathBuf>,
+
+    /// Source connection string; defaults to the SOURCE_DB_* variables
+    #[arg(long)]
+    pub source_url: Option<String>,
+
+    /// Target connection string; defaults to the TARGET_DB_* variables
+    #[arg(long)]
+    pub target_url: Option<String>,
+
+    /// Source rows drawn per entity
+    #[arg(long, default_value_t = 100)]
+    pub size: u64,
+
+    /// How the rows are drawn
+    #[arg(long, value_enum, default_value_t = Strategy::Random)]
+    pub strategy: Strategy,
+
+    /// Ranges of source keys a stratified sample is spread over
+    #[arg(long, default_value_t = 10)]
+    pub strata: u64,
+
+    /// Only compare this target column; repeatable
+    #[arg(long = "column", value_name = "COLUMN")]
+    pub columns: Vec<String>,
+
+    /// Differing rows shown per column
+    #[arg(long, default_value_t = 3)]
+    pub examples: usize,
+
+    /// Highest mismatch rate of any column, in percent, that still passes
+    #[arg(long, default_value_t = 0.0)]
+    pub max_rate: f64,
+
+    /// Write the full report as JSON
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct AuditArgs {
+    #[command(subcommand)]
+    pub action: AuditAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AuditAction {
+    /// Find orphaned, unresolved and duplicated references in the target
+    /// and write SQL repairing them through `migration_mappings`
+    Refs(AuditRefsArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct AuditRefsArgs {
+    /// Entity spec files or directories whose FK lookups are audited
+    #[arg(long, default_value = "entities")]
+    pub specs: Vec<PathBuf>,
+
+    /// File (.toml or .yaml) of `[[ref]]` logical FKs the target declares no
+    /// constraint for
+    #[arg(long)]
+    pub refs: Option<PathBuf>,
+
+    /// Only audit references from this table; repeatable
+    #[arg(long = "table", value_name = "TABLE")]
+    pub tables: Vec<String>,
+
+    /// Source connection string, read for the legacy FKs of broken rows;
+    /// defaults to the SOURCE_DB_* variables
+    #[arg(long)]
+    pub source_url: Option<String>,
+
+    /// Target connection string; defaults to the TARGET_DB_* variables
+    #[arg(long)]
+    pub target_url: Option<String>,
+
+    /// Write the repair SQL to this file
+    #[arg(long)]
+    pub repair: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    /// JSON export of the run tables (see `--print-query`) to read instead
+    /// of the target; repeatable
+    #[arg(long = "from", value_name = "FILE", conflicts_with = "target_url")]
+    pub from: Vec<PathBuf>,
+
+    /// Target connection string; defaults to the TARGET_DB_* variables
+    #[arg(long)]
+    pub target_url: Option<String>,
+
+    /// Report format
+    #[arg(long, value_enum, default_value_t = ReportFormat::Markdown)]
+    pub format: ReportFormat,
+
+    /// Write the report to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Earlier report written with `--format json` to list the changes since
+    #[arg(long)]
+    pub previous: Option<PathBuf>,
+
+    /// Report heading
+    #[arg(long, default_value = "Migration report")]
+    pub title: String,
+
+    /// Print the SQL that exports the run tables as JSON and exit
+    #[arg(long, conflicts_with_all = ["from", "target_url", "output", "previous"])]
+    pub print_query: bool,
+}
+
+/// Rewrite the pre-subcommand invocations (`<filename>`, `--bundle`) into
+/// their subcommand equivalents so existing callers keep working.
+pub fn normalize_legacy_args(mut args: Vec<OsString>) -> Vec<OsString> {
+    let is_flag = |arg: &OsString| arg.to_str().is_some_and(|s| s.starts_with('-'));
+    // The values of `--config PATH` and `--profile NAME` are not positionals.
+    let mut rest = args.iter().skip(1);
+    let mut first_positional = None;
+    while let Some(arg) = rest.next() {
+        if arg == "--config" || arg == "--profile" {
+            rest.next();
+        } else if !is_flag(arg) {
+            first_positional = Some(arg);
+            break;
+        }
+    }
+    if let Some(name) = first_positional.and_then(|a| a.to_str()) {
+        let command = Cli::command();
+        if name == "help" || command.get_subcommands().any(|c| c.get_name() == name) {
+            return args;
+        }
+    }
+
+    if let Some(idx) = args.iter().position(|a| a == "--bundle") {
+        args.remove(idx);
+        args.insert(1, OsString::from("bundle"));
+    } else if first_positional.is_some() {
+        args.insert(1, OsString::from("write"));
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(args: &str) -> String {
+        let args: Vec<OsString> = args.split_whitespace().map(OsString::from).collect();
+        let args: Vec<String> = normalize_legacy_args(args)
+            .into_iter()
+            .map(|a| a.into_string().unwrap())
+            .collect();
+        args.join(" ")
+    }
+
+    #[test]
+    fn legacy_invocations_become_subcommands() {
+        let cases = [
+            ("mg foo.ts", "mg write foo.ts"),
+            ("mg --dry-run foo.ts", "mg write --dry-run foo.ts"),
+            ("mg foo.ts --check", "mg write foo.ts --check"),
+            (
+                "mg --config run.toml foo.ts",
+                "mg write --config run.toml foo.ts",
+            ),
+            ("mg --profile prod foo.ts", "mg write --profile prod foo.ts"),
+            ("mg --bundle", "mg bundle"),
+            (
+                "mg --quiet --bundle --root out",
+                "mg bundle --quiet --root out",
+            ),
+            // Real subcommands, and names that only look like files.
+            ("mg write foo.ts", "mg write foo.ts"),
+            ("mg bundle --root out", "mg bundle --root out"),
+            ("mg run offices --resume", "mg run offices --resume"),
+            (
+                "mg map-suggest a.json b.json",
+                "mg map-suggest a.json b.json",
+            ),
+            ("mg --json plan", "mg --json plan"),
+            ("mg --config write run", "mg --config write run"),
+            ("mg help run", "mg help run"),
+            ("mg", "mg"),
+            ("mg --version", "mg --version"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalize(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn normalized_invocations_parse() {
+        let parse = |args: &str| {
+            let args: Vec<OsString> = args.split_whitespace().map(OsString::from).collect();
+            Cli::try_parse_from(normalize_legacy_args(args))
+                .unwrap()
+                .command
+        };
+        match parse("mg --dry-run src/foo.ts") {
+            Command::Write(args) => {
+                assert_eq!(args.filename, PathBuf::from("src/foo.ts"));
+                assert!(args.write.dry_run);
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse("mg --bundle --root out --check") {
+            Command::Bundle(args) => {
+                assert_eq!(args.root, PathBuf::from("out"));
+                assert!(args.write.check);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn cli_is_consistent() {
+        Cli::command().debug_assert();
+    }
+}
@@ -0,0 +1,133 @@
+use crate::atomic_write::{WriteOptions, write_atomic};
+use crate::audit::{self, Audit, Auditor, Legacy, repair};
+use crate::cli::{AuditAction, AuditArgs, AuditRefsArgs};
+use crate::engine::{self, Side};
+use crate::error::{Error, Result};
+use crate::output::Output;
+use crate::spec;
+
+pub fn run(args: &AuditArgs, out: &Output) -> Result<()> {
+    match &args.action {
+        AuditAction::Refs(refs) => run_refs(refs, out),
+    }
+}
+
+fn run_refs(args: &AuditRefsArgs, out: &Output) -> Result<()> {
+    let specs = spec::load_all(&args.specs)?;
+    let entries = match &args.refs {
+        Some(path) => audit::load(path).map_err(Error::Failed)?,
+        None => Vec::new(),
+    };
+    let mut target =
+        engine::connect(Side::Target, args.target_url.as_deref()).map_err(Error::Failed)?;
+    let mut graph = audit::graph(&mut target, &specs, &entries).map_err(Error::Failed)?;
+    if !args.tables.is_empty() {
+        graph.retain(|r| args.tables.contains(&r.table));
+    }
+    if graph.is_empty() {
+        return Err(Error::Failed(
+            "no references to audit: the target declares no FKs and neither the specs nor --refs add any".into(),
+        ));
+    }
+    let logical = graph
+        .iter()
+        .filter(|r| {
+            !r.origins
+                .iter()
+                .any(|o| matches!(o, audit::Origin::Constraint(_)))
+        })
+        .count();
+    out.line(format_args!(
+        "🔎 Auditing {} reference(s), {} of them without a constraint",
+        graph.len(),
+        logical
+    ));
+
+    let mut auditor = Auditor::new(&specs, &mut target, args.source_url.as_deref());
+    let audits: Vec<Audit> = graph.iter().map(|r| auditor.audit(r)).collect();
+    for audit in &audits {
+        summarize(audit, out);
+    }
+
+    let broken = audits.iter().filter(|a| !a.is_clean()).count();
+    if let Some(path) = &args.repair {
+        match repair::script(&audits) {
+            Some(sql) => {
+                write_atomic(path, sql.as_bytes(), WriteOptions::default())?;
+                let repairable: u64 = audits.iter().map(|a| a.findings.repairable).sum();
+                out.line(format_args!(
+                    "✓ Wrote {} ({} row(s) re-resolved through migration_mappings)",
+                    path.display(),
+                    repairable
+                ));
+            }
+            None => out.line(format_args!(
+                "✓ Nothing to repair; {} not written",
+                path.display()
+            )),
+        }
+    }
+    out.report(&audits);
+    if broken > 0 {
+        return Err(Error::Failed(format!(
+            "{} of {} reference(s) are broken",
+            broken,
+            audits.len()
+        )));
+    }
+    out.line(format_args!("✅ All {} reference(s) intact", audits.len()));
+    Ok(())
+}
+
+fn summarize(audit: &Audit, out: &Output) {
+    let reference = &audit.reference;
+    let findings = &audit.findings;
+    let origins: Vec<String> = reference.origins.iter().map(|o| o.to_string()).collect();
+    let heading = format!("{} ({})", reference.label(), origins.join(", "));
+    if let Some(error) = &findings.error {
+        out.line(format_args!("  ✗ {}: could not audit: {}", heading, error));
+        return;
+    }
+    if audit.is_clean() {
+        out.line(format_args!(
+            "  ✓ {}: {} reference(s)",
+            heading, findings.referencing_rows
+        ));
+        return;
+    }
+    let mut found = vec![format!("{} orphan(s)", findings.orphans)];
+    if reference.legacy.is_some() {
+        found.push(format!("{} unset", findings.unset));
+        if matches!(reference.legacy, Some(Legacy::Column { .. })) {
+            found.push(format!("{} misdirected", findings.misdirected));
+        }
+        found.push(format!("{} dangling legacy ID(s)", findings.dangling));
+    }
+    if reference.one_to_one {
+        found.push(format!("{} duplicate(s)", findings.duplicates));
+    }
+    out.line(format_args!(
+        "  ✗ {}: {}; {} repairable",
+        heading,
+        found.join(", "),
+        findings.repairable
+    ));
+    let examples = [
+        ("orphaned values", findings.orphan_values.join(", ")),
+        (
+            "unmapped legacy IDs",
+            findings
+                .dangling_ids
+                .iter()
+                .map(i64::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        ("shared values", findings.duplicate_values.join(", ")),
+    ];
+    for (label, values) in examples {
+        if !values.is_empty() {
+            out.line(format_args!("      {}: {}", label, values));
+        }
+    }
+}
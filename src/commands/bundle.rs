@@ -0,0 +1,91 @@
+use serde::Serialize;
+
+use crate::bundle;
+use crate::cli::BundleArgs;
+use crate::commands::write::{FileReport, check_result, emit_file, read_stdin};
+use crate::error::{Error, Result};
+use crate::output::Output;
+
+#[derive(Debug, Serialize)]
+struct FailedFile {
+    path: String,
+    error: String,
+}
+
+#[derive(Debug, Serialize)]
+struct BundleReport {
+    files: Vec<FileReport>,
+    failed: Vec<FailedFile>,
+}
+
+pub fn run(args: &BundleArgs, out: &Output) -> Result<()> {
+    let content = read_stdin()?;
+    let entries = bundle::parse(&content)?;
+    let preview = args.write.dry_run || args.write.check;
+
+    let mut report = BundleReport {
+        files: Vec::new(),
+        failed: Vec::new(),
+    };
+    // Check every destination before writing any, so an entry escaping
+    // through a symlink fails the bundle instead of leaving it half-written.
+    let mut destinations = Vec::with_capacity(entries.len());
+    for entry in &entries {
+        match bundle::check_destination(&args.root, &entry.path) {
+            Ok(dest) => destinations.push(dest),
+            Err(e) => {
+                let label = entry.path.display().to_string();
+                out.warn(format_args!("✗ Rejected: {} ({})", label, e));
+                report.failed.push(FailedFile {
+                    path: label,
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+    if !report.failed.is_empty() {
+        out.report(&report);
+        return Err(Error::Failed(format!(
+            "{} of {} files rejected; nothing was written",
+            report.failed.len(),
+            entries.len()
+        )));
+    }
+
+    for (entry, dest) in entries.iter().zip(destinations) {
+        let label = entry.path.display().to_string();
+        let result = if preview {
+            Ok(dest)
+        } else {
+            bundle::prepare_destination(&args.root, &entry.path)
+        }
+        .and_then(|dest| emit_file(&dest, &label, &entry.contents, &args.write, out));
+        match result {
+            Ok(file) => report.files.push(file),
+            Err(e) => {
+                out.warn(format_args!("✗ Failed: {} ({})", label, e));
+                report.failed.push(FailedFile {
+                    path: label,
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+    if !preview {
+        out.line(format_args!(
+            "{} of {} files written",
+            report.files.len(),
+            entries.len()
+        ));
+    }
+    out.report(&report);
+
+    if !report.failed.is_empty() {
+        return Err(Error::Failed(format!(
+            "{} of {} files failed",
+            report.failed.len(),
+            entries.len()
+        )));
+    }
+    check_result(&args.write, &report.files)
+}
@@ -0,0 +1,84 @@
+use serde::Serialize;
+
+use crate::cli::{CheckpointsAction, CheckpointsArgs};
+use crate::engine::{self, Side, checkpoint};
+use crate::error::{Error, Result};
+use crate::output::Output;
+
+#[derive(Debug, Serialize)]
+struct ResetReport<'a> {
+    entity: &'a str,
+    deleted: u64,
+    legacy_deleted: u64,
+}
+
+pub fn run(args: &CheckpointsArgs, out: &Output) -> Result<()> {
+    let mut target =
+        engine::connect(Side::Target, args.target_url.as_deref()).map_err(Error::Failed)?;
+    match &args.action {
+        CheckpointsAction::List => {
+            let checkpoints = checkpoint::list(&mut target).map_err(Error::Failed)?;
+            if checkpoints.is_empty() {
+                out.line("No checkpoints");
+            }
+            for c in &checkpoints {
+                let key = c
+                    .last_source_key
+                    .map(|k| k.to_string())
+                    .unwrap_or_else(|| "-".into());
+                let origin = match &c.legacy_id {
+                    Some(id) => format!("migration_checkpoints {}", id),
+                    None => c.migration_batch.clone().unwrap_or_default(),
+                };
+                out.line(format_args!(
+                    "{:<24} {:<10} after {:<10} {:>10} rows  {}  {}",
+                    c.entity,
+                    c.status.as_deref().unwrap_or("-"),
+                    key,
+                    c.records_processed,
+                    c.updated_at.as_deref().unwrap_or("-"),
+                    origin
+                ));
+                if let Some(error) = &c.error_message {
+                    out.line(format_args!("  {}", error));
+                }
+            }
+            out.report(&checkpoints);
+        }
+        CheckpointsAction::Reset(reset) => {
+            let (deleted, legacy_deleted) =
+                checkpoint::reset(&mut target, &reset.entity, reset.legacy)
+                    .map_err(Error::Failed)?;
+            out.line(format_args!(
+                "✓ Reset {}: {} checkpoint(s) deleted{}",
+                reset.entity,
+                deleted,
+                if reset.legacy {
+                    format!(", {} migration_checkpoints row(s)", legacy_deleted)
+                } else {
+                    String::new()
+                }
+            ));
+            if !reset.legacy {
+                let remaining = checkpoint::list(&mut target)
+                    .map_err(Error::Failed)?
+                    .into_iter()
+                    .filter(|c| c.is_legacy() && c.entity == reset.entity)
+                    .count();
+                if remaining > 0 {
+                    out.warn(format_args!(
+                        "⚠ {} migration_checkpoints row(s) for {} remain and would be resumed; \
+                         pass --legacy to delete them",
+                        remaining, reset.entity
+                    ));
+                }
+            }
+            out.report(&ResetReport {
+                entity: &reset.entity,
+                deleted,
+                legacy_deleted,
+            });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use crate::cli::{ConfigAction, ConfigArgs};
+use crate::config::{self, Severity};
+use crate::error::{Error, Result};
+use crate::output::Output;
+
+pub fn run(args: &ConfigArgs, out: &Output) -> Result<()> {
+    let layers = config::load(config::selection()).map_err(Error::Failed)?;
+    let sources = if layers.files.is_empty() {
+        "defaults and environment only".to_string()
+    } else {
+        format!("{}, then the environment", layers.files.join(", "))
+    };
+    let profile = match (&layers.profile, &layers.profile_origin) {
+        (Some(profile), Some(origin)) => format!("profile {} ({})", profile.as_str(), origin),
+        _ => "no profile".to_string(),
+    };
+    match &args.action {
+        ConfigAction::Show(show) => {
+            out.line(format_args!("⚙ {}; layered from {}", profile, sources));
+            for setting in &layers.settings {
+                out.line(format_args!(
+                    "  {:<34} {:<36} {:<20} {}",
+                    setting.key,
+                    setting.display(show.redacted).as_deref().unwrap_or("-"),
+                    setting.origin,
+                    setting.var
+                ));
+            }
+            let errors = layers.errors();
+            if errors > 0 {
+                out.warn(format_args!(
+                    "⚠ {} error(s) in the configuration; see `config validate`",
+                    errors
+                ));
+            }
+            if show.redacted {
+                out.report(&layers.redacted());
+            } else {
+                out.report(&layers);
+            }
+        }
+        ConfigAction::Validate => {
+            out.line(format_args!("🔎 Checking {}; {}", sources, profile));
+            for issue in &layers.issues {
+                out.warn(format_args!(
+                    "{} {}: {}: {}",
+                    match issue.severity {
+                        Severity::Error => "✗",
+                        Severity::Warning => "⚠",
+                    },
+                    issue.origin,
+                    issue.key,
+                    issue.message
+                ));
+            }
+            out.report(&layers.issues);
+            let errors = layers.errors();
+            if errors > 0 {
+                return Err(Error::Failed(format!(
+                    "{} configuration error(s), {} warning(s)",
+                    errors,
+                    layers.issues.len() - errors
+                )));
+            }
+            out.line(format_args!(
+                "✅ Configuration is valid ({} warning(s))",
+                layers.issues.len()
+            ));
+        }
+    }
+    Ok(())
+}
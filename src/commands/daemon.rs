@@ -0,0 +1,187 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use postgres::Client;
+
+use crate::cli::DaemonArgs;
+use crate::daemon::jobs::{self, Defaults, timestamp};
+use crate::daemon::{self, JobRun, Runner};
+use crate::error::{Error, Result};
+use crate::output::Output;
+use crate::spec;
+
+pub fn run(args: &DaemonArgs, out: &Output) -> Result<()> {
+    let specs = spec::load_all(&args.specs)?;
+    if args.poll == 0 {
+        return Err(Error::Failed("--poll must be greater than zero".into()));
+    }
+    let defaults = Defaults {
+        jitter_seconds: args.jitter,
+        max_runtime_seconds: args.max_runtime,
+    };
+    let stop = daemon::install_signal_handlers();
+    let runner = Runner {
+        specs: &specs,
+        source_url: args.source_url.as_deref(),
+        target_url: args.target_url.as_deref(),
+        stop,
+    };
+
+    let mut leader: Option<Client> = None;
+    let mut standing_by = false;
+    let mut reported_invalid = HashSet::new();
+    let mut runs: Vec<JobRun> = Vec::new();
+    loop {
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+        if let Some(control) = leader.as_mut()
+            && !daemon::still_leading(control)
+        {
+            out.warn(format_args!(
+                "[{}] ⚠ Lost the target connection and with it the leader lock",
+                timestamp()
+            ));
+            leader = None;
+        }
+        if leader.is_none() {
+            match daemon::try_lead(args.target_url.as_deref(), &args.lock_name) {
+                Ok(Some(mut control)) => {
+                    out.line(format_args!(
+                        "[{}] 👑 Leading (advisory lock '{}')",
+                        timestamp(),
+                        args.lock_name
+                    ));
+                    standing_by = false;
+                    let recovered = jobs::ensure_tables(&mut control)
+                        .and_then(|()| jobs::recover(&mut control))
+                        .map_err(Error::Failed)?;
+                    if recovered > 0 {
+                        out.warn(format_args!(
+                            "[{}] ↻ {} run(s) left running by a previous leader marked cancelled; \
+                             their jobs are due again",
+                            timestamp(),
+                            recovered
+                        ));
+                    }
+                    leader = Some(control);
+                }
+                Ok(None) => {
+                    if !standing_by {
+                        out.line(format_args!(
+                            "[{}] ⏸ Another daemon holds '{}'; standing by",
+                            timestamp(),
+                            args.lock_name
+                        ));
+                        standing_by = true;
+                    }
+                }
+                Err(e) => out.warn(format_args!("[{}] ⚠ {}", timestamp(), e)),
+            }
+        }
+
+        let mut wait = Duration::from_secs(args.poll);
+        if let Some(control) = leader.as_mut() {
+            match tick(control, &runner, defaults, &mut reported_invalid, out) {
+                Ok((done, next)) => {
+                    runs.extend(done);
+                    if let Some(seconds) = next {
+                        wait = wait.min(Duration::from_secs_f64(seconds.max(1.0)));
+                    }
+                }
+                Err(e) => {
+                    out.warn(format_args!("[{}] ⚠ {}", timestamp(), e));
+                    leader = None;
+                }
+            }
+        }
+        if args.once {
+            break;
+        }
+        sleep(wait, stop);
+    }
+    if stop.load(Ordering::SeqCst) {
+        out.line(format_args!("[{}] ⏹ Stopped", timestamp()));
+    }
+    // Dropping the connection releases the lock.
+    drop(leader);
+    out.report(&runs);
+    Ok(())
+}
+
+/// Schedule new jobs and run the due ones; returns the runs and the seconds
+/// until the next job is due.
+fn tick(
+    control: &mut Client,
+    runner: &Runner<'_>,
+    defaults: Defaults,
+    reported_invalid: &mut HashSet<String>,
+    out: &Output,
+) -> std::result::Result<(Vec<JobRun>, Option<f64>), String> {
+    for (job, reason) in jobs::schedule_new(control, defaults)? {
+        if reported_invalid.insert(job.clone()) {
+            out.warn(format_args!(
+                "[{}] ⚠ Job {} is not scheduled: {}",
+                timestamp(),
+                job,
+                reason
+            ));
+        }
+    }
+    let mut runs = Vec::new();
+    for job in jobs::due(control, defaults)? {
+        if runner.stop.load(Ordering::SeqCst) {
+            break;
+        }
+        out.line(format_args!(
+            "[{}] ▶ Job {}: {}",
+            timestamp(),
+            job.name,
+            job.entities.join(", ")
+        ));
+        let run = runner.run_job(control, &job, |entity| {
+            out.line(format_args!(
+                "  {}: {} new, {} modified, {} deleted → {} upserted, {} failed, {} tombstoned ({:.1}s)",
+                entity.entity,
+                entity.new,
+                entity.modified,
+                entity.deleted,
+                entity.upserted,
+                entity.failed,
+                entity.tombstoned,
+                entity.duration_ms as f64 / 1000.0
+            ));
+        })?;
+        match &run.error {
+            Some(error) => out.warn(format_args!(
+                "[{}] ✗ Job {} {}: {}",
+                timestamp(),
+                run.job,
+                run.status,
+                error
+            )),
+            None => out.line(format_args!(
+                "[{}] ✅ Job {} completed in {:.1}s",
+                timestamp(),
+                run.job,
+                run.duration_ms as f64 / 1000.0
+            )),
+        }
+        runs.push(run);
+    }
+    Ok((runs, jobs::seconds_until_next(control)?))
+}
+
+/// Sleep for `duration`, waking early when `stop` is set.
+fn sleep(duration: Duration, stop: &AtomicBool) {
+    let until = Instant::now() + duration;
+    while !stop.load(Ordering::SeqCst) {
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        thread::sleep(left.min(Duration::from_millis(250)));
+    }
+}
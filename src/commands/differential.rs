@@ -0,0 +1,110 @@
+use std::path::Path;
+
+use crate::atomic_write::{WriteOptions, write_atomic};
+use crate::cli::DifferentialArgs;
+use crate::differential::{self, Analysis, DetectOptions, DetectionMode};
+use crate::engine::{self, Side};
+use crate::error::{Error, Result};
+use crate::mapping::MappingStore;
+use crate::output::Output;
+use crate::spec::{self, EntitySpec};
+
+/// IDs listed per class before the rest is elided.
+const SHOWN_IDS: usize = 10;
+
+pub fn run(args: &DifferentialArgs, out: &Output) -> Result<()> {
+    let specs = spec::load_all(&args.specs)?;
+    let spec = spec::find(&specs, &args.entity).map_err(Error::Failed)?;
+    if args.fetch_size == 0 {
+        return Err(Error::Failed(
+            "--fetch-size must be greater than zero".into(),
+        ));
+    }
+    let options = DetectOptions {
+        mode: args.mode,
+        timestamp_column: args.timestamp_column.clone(),
+        fetch_size: args.fetch_size,
+    };
+
+    let mut source =
+        engine::connect(Side::Source, args.source_url.as_deref()).map_err(Error::Failed)?;
+    let mut target =
+        engine::connect(Side::Target, args.target_url.as_deref()).map_err(Error::Failed)?;
+    out.line(format_args!(
+        "🔎 Comparing {} → {} ({} mode)",
+        spec.source_table,
+        spec.target_table,
+        match args.mode {
+            DetectionMode::Hash => "hash",
+            DetectionMode::Timestamp => "timestamp",
+            DetectionMode::Merkle => "merkle",
+        }
+    ));
+    let mut mappings = MappingStore::new();
+    let analysis = differential::detect(spec, &mut source, &mut target, &mut mappings, &options)?;
+
+    summarize(spec, &analysis, args.output.as_deref(), out)?;
+    if args.record {
+        let id = differential::record(&mut target, &analysis).map_err(Error::Failed)?;
+        out.line(format_args!(
+            "✓ Recorded as differential_analysis_results {}",
+            id
+        ));
+    }
+    out.report(&analysis);
+    Ok(())
+}
+
+/// Print the counts and the first IDs of each class, and write the JSON
+/// output; shared with `reconcile`.
+pub fn summarize(
+    spec: &EntitySpec,
+    analysis: &Analysis,
+    output: Option<&Path>,
+    out: &Output,
+) -> Result<()> {
+    out.line(format_args!(
+        "📊 {}: {} source, {} target rows — {} new, {} modified, {} deleted, {} unchanged ({:.1}s)",
+        spec.name,
+        analysis.source_record_count,
+        analysis.destination_record_count,
+        analysis.new_records.len(),
+        analysis.modified_records.len(),
+        analysis.deleted_records.len(),
+        analysis.unchanged(),
+        analysis.analysis_metadata.duration_ms as f64 / 1000.0
+    ));
+    for (label, ids) in [
+        ("new", &analysis.new_records),
+        ("modified", &analysis.modified_records),
+        ("deleted", &analysis.deleted_records),
+    ] {
+        if ids.is_empty() {
+            continue;
+        }
+        let shown: Vec<String> = ids.iter().take(SHOWN_IDS).map(i64::to_string).collect();
+        out.line(format_args!(
+            "  {:<9} {}{}",
+            label,
+            shown.join(", "),
+            if ids.len() > SHOWN_IDS {
+                format!(" … and {} more", ids.len() - SHOWN_IDS)
+            } else {
+                String::new()
+            }
+        ));
+    }
+    if analysis.analysis_metadata.duplicate_destination_keys > 0 {
+        out.warn(format_args!(
+            "⚠ {} target row(s) share a {} with another row and were ignored",
+            analysis.analysis_metadata.duplicate_destination_keys, spec.legacy_id_column
+        ));
+    }
+
+    if let Some(path) = output {
+        let json = serde_json::to_string_pretty(&analysis).expect("analysis serializes");
+        write_atomic(path, json.as_bytes(), WriteOptions::default())?;
+        out.line(format_args!("✓ Wrote {}", path.display()));
+    }
+    Ok(())
+}
@@ -0,0 +1,166 @@
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::atomic_write::{WriteOptions, write_atomic};
+use crate::cli::IntrospectArgs;
+use crate::error::Result;
+use crate::output::Output;
+use crate::schema::snapshot::{self, Snapshot};
+use crate::schema::{self, InputFormat, Schema, SchemaError, catalog, table_key};
+
+#[derive(Debug, Serialize)]
+struct IntrospectReport {
+    source: String,
+    format: InputFormat,
+    fingerprint: String,
+    tables: usize,
+    columns: usize,
+    foreign_keys: usize,
+    enums: usize,
+    /// The existing `--output` snapshot was already current.
+    cached: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    missing_tables: Vec<String>,
+    /// Full model, included only when no `--output` file was requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schema: Option<Schema>,
+}
+
+pub fn run(args: &IntrospectArgs, out: &Output) -> Result<()> {
+    let Some(input) = &args.input else {
+        // `--print-query` is the only way clap lets `input` be absent.
+        out.raw(format_args!("{}\n", catalog::EXPORT_QUERY));
+        return Ok(());
+    };
+    let text = fs::read_to_string(input).map_err(|e| SchemaError {
+        path: input.clone(),
+        message: e.to_string(),
+    })?;
+    let format = schema::resolve_format(input, &text, args.format);
+    let fingerprint = snapshot::fingerprint(text.as_bytes());
+
+    let cached = match &args.output {
+        Some(output) if !args.force => load_current(output, &fingerprint),
+        _ => None,
+    };
+    let is_cached = cached.is_some();
+    let schema = match cached {
+        Some(schema) => schema,
+        None => schema::parse(input, &text, format)?,
+    };
+
+    if let Some(output) = &args.output {
+        if is_cached {
+            out.line(format_args!(
+                "✓ {} is up to date with {}",
+                output.display(),
+                input.display()
+            ));
+        } else {
+            let snapshot = Snapshot::new(
+                input.display().to_string(),
+                fingerprint.clone(),
+                schema.clone(),
+            );
+            write_atomic(
+                output,
+                snapshot.to_json().as_bytes(),
+                WriteOptions::default(),
+            )?;
+            out.line(format_args!("✓ Wrote {}", output.display()));
+        }
+    } else {
+        for table in schema.tables.values() {
+            out.line(format_args!(
+                "  {:<40} {:>3} columns  {:>2} FKs  pk({})",
+                table.name,
+                table.columns.len(),
+                table.foreign_keys.len(),
+                table.primary_key.join(", ")
+            ));
+        }
+    }
+
+    out.line(format_args!(
+        "{} tables, {} columns, {} foreign keys, {} enums",
+        schema.tables.len(),
+        schema.column_count(),
+        schema.foreign_key_count(),
+        schema.enums.len()
+    ));
+
+    let missing_tables = match &args.expect {
+        Some(inventory) => missing_tables(&schema, inventory)?,
+        None => Vec::new(),
+    };
+    if !missing_tables.is_empty() {
+        out.warn(format_args!(
+            "⚠ {} table(s) from {} are missing: {}",
+            missing_tables.len(),
+            args.expect.as_deref().unwrap_or(Path::new("")).display(),
+            missing_tables.join(", ")
+        ));
+    }
+
+    out.report(&IntrospectReport {
+        source: input.display().to_string(),
+        format,
+        fingerprint,
+        tables: schema.tables.len(),
+        columns: schema.column_count(),
+        foreign_keys: schema.foreign_key_count(),
+        enums: schema.enums.len(),
+        cached: is_cached,
+        output: args.output.as_ref().map(|p| p.display().to_string()),
+        missing_tables,
+        schema: args.output.is_none().then_some(schema),
+    });
+    Ok(())
+}
+
+/// The schema stored in `path` if it is a current snapshot of `fingerprint`.
+fn load_current(path: &Path, fingerprint: &str) -> Option<Schema> {
+    let text = fs::read_to_string(path).ok()?;
+    let snapshot = Snapshot::from_json(&text).ok()?;
+    snapshot.is_current(fingerprint).then_some(snapshot.schema)
+}
+
+/// Tables listed in `inventory` that `schema` does not contain.
+///
+/// Accepts psql's aligned `schemaname | tablename | ...` output (as in
+/// `source_tables_inventory.txt`) or one table name per line.
+fn missing_tables(schema: &Schema, inventory: &Path) -> Result<Vec<String>> {
+    let text = fs::read_to_string(inventory).map_err(|e| SchemaError {
+        path: inventory.to_path_buf(),
+        message: e.to_string(),
+    })?;
+    let mut missing = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty()
+            || line.starts_with('-')
+            || line.starts_with('(')
+            || line.starts_with("List of")
+        {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('|').map(str::trim).collect();
+        let key = match fields.as_slice() {
+            [name] => table_key(None, name),
+            [schema_name, name, ..] => {
+                if *name == "tablename" || *name == "Name" {
+                    continue;
+                }
+                table_key(Some(schema_name), name)
+            }
+            [] => continue,
+        };
+        if schema.table(&key).is_none() {
+            missing.push(key);
+        }
+    }
+    Ok(missing)
+}
@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use serde::Serialize;
+
+use crate::cli::MapSuggestArgs;
+use crate::commands::write::{FileReport, check_result, emit_file};
+use crate::error::{Error, Result};
+use crate::output::Output;
+use crate::schema::{self, InputFormat};
+use crate::suggest::{self, MatchKind, Suggestions, TablePair};
+
+#[derive(Debug, Serialize)]
+struct MapSuggestReport<'a> {
+    file: FileReport,
+    #[serde(flatten)]
+    suggestions: &'a Suggestions,
+}
+
+pub fn run(args: &MapSuggestArgs, out: &Output) -> Result<()> {
+    let source = schema::load(&args.source, InputFormat::Auto)?;
+    let target = schema::load(&args.target, InputFormat::Auto)?;
+
+    let mut explicit = BTreeMap::new();
+    for pair in &args.pair {
+        let Some((from, to)) = pair.split_once('=') else {
+            return Err(Error::Failed(format!(
+                "--pair expects <source_table>=<target_table>, got '{}'",
+                pair
+            )));
+        };
+        if target.table(to).is_none() {
+            return Err(Error::Failed(format!(
+                "--pair {}: target table '{}' not found in {}",
+                pair,
+                to,
+                args.target.display()
+            )));
+        }
+        explicit.insert(from.to_string(), to.to_string());
+    }
+    for table in &args.table {
+        if source.table(table).is_none() {
+            return Err(Error::Failed(format!(
+                "source table '{}' not found in {}",
+                table,
+                args.source.display()
+            )));
+        }
+    }
+
+    let suggestions = suggest::suggest(&source, &target, &explicit, &args.table);
+    let markdown = render(&suggestions, args);
+
+    for pair in &suggestions.pairs {
+        let required = pair.required_unmapped().count();
+        let narrowing = pair.narrowing().count();
+        let mark = if required > 0 { "⚠" } else { "✓" };
+        out.line(format_args!(
+            "{} {} → {}: {} of {} target columns mapped, {} unmapped source, {} required without source, {} narrowing",
+            mark,
+            pair.source,
+            pair.target,
+            pair.columns
+                .iter()
+                .filter(|c| c.kind != MatchKind::Unmapped)
+                .count(),
+            pair.columns.len(),
+            pair.unmapped_source.len(),
+            required,
+            narrowing
+        ));
+    }
+    if !suggestions.unpaired.is_empty() {
+        out.warn(format_args!(
+            "⚠ No target table found for: {} (use --pair <source>=<target>)",
+            suggestions.unpaired.join(", ")
+        ));
+    }
+
+    let label = args.output.display().to_string();
+    let file = emit_file(&args.output, &label, &markdown, &args.write, out)?;
+    let result = check_result(&args.write, std::slice::from_ref(&file));
+    out.report(&MapSuggestReport {
+        file,
+        suggestions: &suggestions,
+    });
+    result
+}
+
+/// Render the suggestions in the layout of `COMPREHENSIVE_FIELD_MAPPING.md`.
+fn render(suggestions: &Suggestions, args: &MapSuggestArgs) -> String {
+    let mut md = String::new();
+    let _ = writeln!(md, "# Suggested Source to Target Field Mapping");
+    let _ = writeln!(md, "**Generated by:** `migration_generator map-suggest`");
+    let _ = writeln!(
+        md,
+        "**Based on:** `{}` (source) and `{}` (target)",
+        args.source.display(),
+        args.target.display()
+    );
+    let _ = writeln!(
+        md,
+        "**Purpose:** Starting point for entity specs; review and edit every row before relying on it"
+    );
+    let _ = writeln!(md, "\n---\n\n## 📋 DETAILED FIELD MAPPINGS");
+
+    for (i, pair) in suggestions.pairs.iter().enumerate() {
+        render_pair(&mut md, i + 1, pair);
+    }
+
+    let primary: Vec<String> = suggestions
+        .pairs
+        .iter()
+        .filter_map(|pair| {
+            let legacy = pair
+                .columns
+                .iter()
+                .find(|c| c.kind == MatchKind::LegacyId)?;
+            Some(format!(
+                "Source {}.{} -> Target {}.{} -> Target {}.id",
+                pair.source,
+                legacy.source.as_deref().unwrap_or("id"),
+                pair.target,
+                legacy.target,
+                pair.target
+            ))
+        })
+        .collect();
+    let foreign: Vec<String> = suggestions
+        .pairs
+        .iter()
+        .flat_map(|pair| {
+            pair.columns
+                .iter()
+                .filter(|c| c.kind == MatchKind::Lookup)
+                .map(move |column| (pair, column))
+        })
+        .map(|(pair, column)| {
+            let entity = column.lookup_entity.as_deref().unwrap_or_default();
+            let referenced = suggestions
+                .pairs
+                .iter()
+                .find(|p| p.entity == entity)
+                .map_or_else(|| format!("<unpaired {}>", entity), |p| p.target.clone());
+            format!(
+                "{}.{} -> {}.id (via migration_mappings entity_type '{}')",
+                pair.target, column.target, referenced, entity
+            )
+        })
+        .collect();
+    if !primary.is_empty() || !foreign.is_empty() {
+        let _ = writeln!(md, "\n---\n\n## 🔄 RELATIONSHIP MAPPINGS");
+        for (heading, lines) in [
+            ("Primary Key Relationships", &primary),
+            ("Foreign Key Relationships", &foreign),
+        ] {
+            if !lines.is_empty() {
+                let _ = writeln!(md, "\n### {}\n```\n{}\n```", heading, lines.join("\n"));
+            }
+        }
+    }
+
+    if !suggestions.unpaired.is_empty() {
+        let _ = writeln!(md, "\n---\n\n## ⚠️ UNPAIRED SOURCE TABLES\n");
+        let _ = writeln!(
+            md,
+            "No target table matched by name or `legacy_<entity>_id` column. Re-run with `--pair <source>=<target>` to map them.\n"
+        );
+        for table in &suggestions.unpaired {
+            let _ = writeln!(md, "- `{}`", table);
+        }
+    }
+    md
+}
+
+fn render_pair(md: &mut String, number: usize, pair: &TablePair) {
+    let _ = writeln!(
+        md,
+        "\n### {}. {} TABLE MAPPING\n",
+        number,
+        pair.target.to_uppercase()
+    );
+    let _ = writeln!(
+        md,
+        "**Source:** `{}` (paired by {}; entity_type `{}`)\n",
+        pair.source, pair.paired_by, pair.entity
+    );
+    let _ = writeln!(md, "| Target Field | Source Field(s) | Type | Notes |");
+    let _ = writeln!(md, "|--------------|----------------|------|-------|");
+    for column in &pair.columns {
+        let source = match (&column.source, column.kind) {
+            (_, MatchKind::Generated) => "Generated UUID".to_string(),
+            (Some(s), MatchKind::Lookup) => {
+                format!("`{}.{}` via migration_mappings", pair.source, s)
+            }
+            (Some(s), _) => format!("`{}.{}`", pair.source, s),
+            (None, _) => "Not in source".to_string(),
+        };
+        let mut notes = match column.kind {
+            MatchKind::Generated => "Primary key in target".to_string(),
+            MatchKind::LegacyId => "**CRITICAL MAPPING**".to_string(),
+            MatchKind::Lookup => format!(
+                "Lookup entity_type '{}'",
+                column.lookup_entity.as_deref().unwrap_or_default()
+            ),
+            MatchKind::Exact => "Direct mapping".to_string(),
+            MatchKind::Renamed => "Renamed".to_string(),
+            MatchKind::Unmapped if column.required => {
+                "⚠️ NOT NULL without default; needs a value".to_string()
+            }
+            MatchKind::Unmapped => match &column.target_default {
+                Some(default) => format!("Default {}", default),
+                None => "Default null".to_string(),
+            },
+        };
+        if let Some(narrowing) = &column.narrowing {
+            let _ = write!(notes, "; ⚠️ narrowing {}", narrowing);
+        }
+        let _ = writeln!(
+            md,
+            "| `{}` | {} | {} | {} |",
+            column.target, source, column.target_type, notes
+        );
+    }
+
+    if !pair.unmapped_source.is_empty() {
+        let columns: Vec<String> = pair
+            .unmapped_source
+            .iter()
+            .map(|c| format!("`{}`", c))
+            .collect();
+        let _ = writeln!(
+            md,
+            "\n**⚠️ Unmapped source columns:** {}",
+            columns.join(", ")
+        );
+    }
+    let required: Vec<String> = pair
+        .required_unmapped()
+        .map(|c| format!("`{}`", c.target))
+        .collect();
+    if !required.is_empty() {
+        let _ = writeln!(
+            md,
+            "\n**⚠️ NOT NULL target columns without a source:** {}",
+            required.join(", ")
+        );
+    }
+    let narrowing: Vec<String> = pair
+        .narrowing()
+        .map(|c| {
+            format!(
+                "`{}` {}",
+                c.target,
+                c.narrowing.as_deref().unwrap_or_default()
+            )
+        })
+        .collect();
+    if !narrowing.is_empty() {
+        let _ = writeln!(
+            md,
+            "\n**⚠️ Type-narrowing conversions:** {}",
+            narrowing.join(", ")
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::cli::{MappingsAction, MappingsArgs};
+use crate::engine::{self, Side};
+use crate::error::{Error, Result};
+use crate::mapping::MappingStore;
+use crate::output::Output;
+
+#[derive(Debug, Serialize)]
+struct MappingsReport<'a> {
+    action: &'static str,
+    file: String,
+    /// Mappings per entity type in the file.
+    entities: BTreeMap<&'a str, usize>,
+    /// Rows added to `migration_mappings` (import only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inserted: Option<u64>,
+}
+
+pub fn run(args: &MappingsArgs, out: &Output) -> Result<()> {
+    match &args.action {
+        MappingsAction::Export(args) => {
+            let mut target =
+                engine::connect(Side::Target, args.target_url.as_deref()).map_err(Error::Failed)?;
+            let entities = if args.entities.is_empty() {
+                MappingStore::entity_types(&mut target).map_err(Error::Failed)?
+            } else {
+                args.entities.clone()
+            };
+            let wanted: Vec<&str> = entities.iter().map(String::as_str).collect();
+            let mut store = MappingStore::new();
+            store.preload(&mut target, &wanted).map_err(Error::Failed)?;
+            let written = store.export(&args.file).map_err(Error::Failed)?;
+            out.line(format_args!(
+                "✓ Exported {} mapping(s) of {} entity type(s) to {}",
+                written,
+                wanted.len(),
+                args.file.display()
+            ));
+            out.report(&MappingsReport {
+                action: "export",
+                file: args.file.display().to_string(),
+                entities: store.counts(),
+                inserted: None,
+            });
+        }
+        MappingsAction::Import(args) => {
+            let store = MappingStore::import(&args.file).map_err(Error::Failed)?;
+            let mut target =
+                engine::connect(Side::Target, args.target_url.as_deref()).map_err(Error::Failed)?;
+            let inserted = store.save(&mut target).map_err(Error::Failed)?;
+            out.line(format_args!(
+                "✓ Imported {} of {} mapping(s) from {} ({} already present)",
+                inserted,
+                store.len(),
+                args.file.display(),
+                store.len() as u64 - inserted
+            ));
+            out.report(&MappingsReport {
+                action: "import",
+                file: args.file.display().to_string(),
+                entities: store.counts(),
+                inserted: Some(inserted),
+            });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,20 @@
+pub mod audit;
+pub mod bundle;
+pub mod checkpoints;
+pub mod config;
+pub mod daemon;
+pub mod differential;
+pub mod introspect;
+pub mod map_suggest;
+pub mod mappings;
+pub mod plan;
+pub mod reconcile;
+pub mod report;
+pub mod resolve;
+pub mod run;
+pub mod scaffold;
+pub mod tombstones;
+pub mod uuid;
+pub mod validate;
+pub mod verify;
+pub mod write;
@@ -0,0 +1,98 @@
+use serde::Serialize;
+
+use crate::cli::PlanArgs;
+use crate::commands::write::{FileReport, check_result, emit_file};
+use crate::error::{Error, Result};
+use crate::output::Output;
+use crate::plan::{self, Graph, Plan};
+use crate::schema::{self, InputFormat};
+use crate::spec;
+
+#[derive(Debug, Serialize)]
+struct PlanReport {
+    plan: Plan,
+    files: Vec<FileReport>,
+}
+
+pub fn run(args: &PlanArgs, out: &Output) -> Result<()> {
+    let specs = spec::load_all(&args.specs)?;
+    let schema = match &args.schema {
+        Some(path) => Some(schema::load(path, InputFormat::Auto)?),
+        None => None,
+    };
+    if specs.is_empty() && schema.is_none() {
+        return Err(Error::Failed(
+            "nothing to plan: pass entity specs, --schema, or both".into(),
+        ));
+    }
+    let plan = Graph::build(&specs, schema.as_ref())
+        .map_err(Error::Failed)?
+        .plan();
+
+    for stage in &plan.stages {
+        let names: Vec<&str> = stage
+            .entities
+            .iter()
+            .map(|e| e.node.name.as_str())
+            .collect();
+        out.line(format_args!("Stage {}: {}", stage.stage, names.join(", ")));
+    }
+    for fill in &plan.backfill {
+        out.line(format_args!(
+            "Backfill: {}.{} -> {}",
+            fill.entity,
+            fill.columns.join(", "),
+            fill.references
+        ));
+    }
+    for cycle in &plan.cycles {
+        let b = &cycle.resolution;
+        let mark = if b.safe { "↻" } else { "⚠" };
+        let mut path = cycle.entities.clone();
+        path.push(cycle.entities[0].clone());
+        out.warn(format_args!(
+            "{} Cycle {}: {}",
+            mark,
+            path.join(" → "),
+            b.proposal
+        ));
+    }
+    for conflict in &plan.order_conflicts {
+        out.warn(format_args!("⚠ dependency_order conflict: {}", conflict));
+    }
+    if !specs.is_empty() {
+        for ext in &plan.external {
+            out.warn(format_args!(
+                "⚠ {}.{} references {}, which has no entity spec",
+                ext.entity,
+                ext.columns.join(", "),
+                ext.table
+            ));
+        }
+    }
+
+    let mut files = Vec::new();
+    let outputs = [
+        (&args.output, "json"),
+        (&args.dot, "dot"),
+        (&args.mermaid, "mermaid"),
+    ];
+    for (path, kind) in outputs {
+        let Some(path) = path else { continue };
+        let contents = match kind {
+            "json" => {
+                let mut text = serde_json::to_string_pretty(&plan)
+                    .map_err(|e| Error::Failed(e.to_string()))?;
+                text.push('\n');
+                text
+            }
+            "dot" => plan::to_dot(&plan),
+            _ => plan::to_mermaid(&plan),
+        };
+        let label = path.display().to_string();
+        files.push(emit_file(path, &label, &contents, &args.write, out)?);
+    }
+    let result = check_result(&args.write, &files);
+    out.report(&PlanReport { plan, files });
+    result
+}
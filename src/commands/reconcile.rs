@@ -0,0 +1,55 @@
+use crate::cli::ReconcileArgs;
+use crate::commands::differential::summarize;
+use crate::differential::{self, merkle};
+use crate::engine::{self, Side};
+use crate::error::{Error, Result};
+use crate::mapping::MappingStore;
+use crate::output::Output;
+use crate::spec;
+
+pub fn run(args: &ReconcileArgs, out: &Output) -> Result<()> {
+    let specs = spec::load_all(&args.specs)?;
+    let spec = spec::find(&specs, &args.entity).map_err(Error::Failed)?;
+    if args.fanout < 2 {
+        return Err(Error::Failed("--fanout must be at least 2".into()));
+    }
+    if args.leaf_rows == 0 {
+        return Err(Error::Failed(
+            "--leaf-rows must be greater than zero".into(),
+        ));
+    }
+    let options = merkle::MerkleOptions {
+        fanout: args.fanout,
+        leaf_rows: args.leaf_rows,
+    };
+
+    let mut source =
+        engine::connect(Side::Source, args.source_url.as_deref()).map_err(Error::Failed)?;
+    let mut target =
+        engine::connect(Side::Target, args.target_url.as_deref()).map_err(Error::Failed)?;
+    out.line(format_args!(
+        "🔎 Reconciling {} → {} (fanout {}, leaf ranges of {} rows)",
+        spec.source_table, spec.target_table, args.fanout, args.leaf_rows
+    ));
+    let mut mappings = MappingStore::new();
+    let analysis = merkle::reconcile(spec, &mut source, &mut target, &mut mappings, &options)?;
+    summarize(spec, &analysis, args.output.as_deref(), out)?;
+    let metadata = &analysis.analysis_metadata;
+    out.line(format_args!(
+        "  {} range checksum(s) compared, {} row(s) read back",
+        metadata.ranges_compared.unwrap_or_default(),
+        metadata.rows_compared.unwrap_or_default()
+    ));
+
+    if args.record {
+        let id = differential::record(&mut target, &analysis).map_err(Error::Failed)?;
+        let rows = merkle::record_differentials(&mut target, &analysis).map_err(Error::Failed)?;
+        out.line(format_args!(
+            "✓ Recorded as differential_analysis_results {} and {} data_differentials row(s)",
+            id,
+            rows.len()
+        ));
+    }
+    out.report(&analysis);
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use crate::atomic_write::{WriteOptions, write_atomic};
+use crate::cli::ReportArgs;
+use crate::engine::{self, Side};
+use crate::error::{Error, Result};
+use crate::output::Output;
+use crate::report::render::{self, Format};
+use crate::report::{self, Inputs};
+
+pub fn run(args: &ReportArgs, out: &Output) -> Result<()> {
+    if args.print_query {
+        out.raw(format_args!("{}\n", report::EXPORT_QUERY));
+        return Ok(());
+    }
+
+    let inputs = if args.from.is_empty() {
+        let mut target =
+            engine::connect(Side::Target, args.target_url.as_deref()).map_err(Error::Failed)?;
+        Inputs::read(&mut target, "target").map_err(Error::Failed)?
+    } else {
+        let mut inputs = Inputs::default();
+        for path in &args.from {
+            inputs.load(path).map_err(Error::Failed)?;
+        }
+        inputs
+    };
+    let mut report = report::build(&args.title, &inputs);
+    if let Some(path) = &args.previous {
+        let previous = report::load_previous(path).map_err(Error::Failed)?;
+        report.changes = Some(report::compare(&previous, &report));
+    }
+
+    let text = match args.format {
+        Format::Markdown => render::markdown(&report),
+        Format::Html => render::html(&report),
+        Format::Json => serde_json::to_string_pretty(&report)
+            .map(|json| json + "\n")
+            .map_err(|e| Error::Failed(format!("cannot serialize the report: {}", e)))?,
+    };
+    match &args.output {
+        Some(path) => {
+            write_atomic(path, text.as_bytes(), WriteOptions::default())?;
+            out.line(format_args!("✓ Wrote {}", path.display()));
+            let totals = &report.totals;
+            out.line(format_args!(
+                "📈 {} entit{}, {} run(s), {} failed; {} of {} record(s) rejected; {} error group(s)",
+                totals.entities,
+                if totals.entities == 1 { "y" } else { "ies" },
+                totals.runs,
+                totals.failed_runs,
+                totals.records_failed,
+                totals.records_processed,
+                report.errors.len()
+            ));
+        }
+        None => out.raw(&text),
+    }
+    out.report(&report);
+    Ok(())
+}
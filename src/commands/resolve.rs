@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::cli::{ResolveAction, ResolveApplyArgs, ResolveArgs};
+use crate::engine::conflict::{self, Take};
+use crate::engine::{self, Side};
+use crate::error::{Error, Result};
+use crate::output::Output;
+
+#[derive(Debug, Serialize)]
+struct ApplyReport<'a> {
+    id: i64,
+    decided_by: &'a str,
+    decision: HashMap<String, Take>,
+}
+
+pub fn run(args: &ResolveArgs, out: &Output) -> Result<()> {
+    let mut target =
+        engine::connect(Side::Target, args.target_url.as_deref()).map_err(Error::Failed)?;
+    match &args.action {
+        ResolveAction::List(list) => {
+            let queued = conflict::queued(&mut target, list.entity.as_deref(), list.all)
+                .map_err(Error::Failed)?;
+            if queued.is_empty() {
+                out.line("No queued conflicts");
+            }
+            for q in &queued {
+                out.line(format_args!(
+                    "#{:<6} {:<20} {} {:<10} {:<10} {}",
+                    q.id, q.entity, q.target_table, q.legacy_id, q.status, q.created_at
+                ));
+                for column in &q.columns {
+                    out.line(format_args!(
+                        "  {:<24} source {}  target {}",
+                        column,
+                        shown(&q.source_values[column]),
+                        shown(&q.target_values[column])
+                    ));
+                }
+            }
+            out.report(&queued);
+        }
+        ResolveAction::Apply(apply) => {
+            let columns = decisions(apply)?;
+            let by = apply
+                .by
+                .clone()
+                .or_else(|| std::env::var("USER").ok())
+                .unwrap_or_else(|| "unknown".into());
+            let decided = conflict::apply(&mut target, apply.id, apply.take, &columns, &by)
+                .map_err(Error::Failed)?;
+            for (column, take) in &decided {
+                out.line(format_args!(
+                    "  {:<24} {}",
+                    column,
+                    match take {
+                        Take::Source => "source",
+                        Take::Target => "target",
+                    }
+                ));
+            }
+            out.line(format_args!(
+                "✓ Resolved conflict {} ({} column(s), decided by {})",
+                apply.id,
+                decided.len(),
+                by
+            ));
+            out.report(&ApplyReport {
+                id: apply.id,
+                decided_by: &by,
+                decision: decided.into_iter().collect(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Parse the `--column COLUMN=SIDE` flags.
+fn decisions(args: &ResolveApplyArgs) -> Result<HashMap<String, Take>> {
+    let mut columns = HashMap::new();
+    for flag in &args.columns {
+        let take = match flag.split_once('=') {
+            Some((column, "source")) => (column, Take::Source),
+            Some((column, "target")) => (column, Take::Target),
+            _ => {
+                return Err(Error::Failed(format!(
+                    "--column {} is not COLUMN=source or COLUMN=target",
+                    flag
+                )));
+            }
+        };
+        columns.insert(take.0.to_string(), take.1);
+    }
+    Ok(columns)
+}
+
+fn shown(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".into(),
+        Value::String(s) => format!("{:?}", s),
+        other => other.to_string(),
+    }
+}
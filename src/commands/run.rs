@@ -0,0 +1,174 @@
+use crate::cli::RunArgs;
+use crate::config;
+use crate::differential;
+use crate::engine::{self, LoadMode, RunOptions, Side, checkpoint};
+use crate::error::{Error, Result};
+use crate::mapping::MappingStore;
+use crate::output::Output;
+use crate::spec;
+
+pub fn run(args: &RunArgs, out: &Output) -> Result<()> {
+    let specs = spec::load_all(&args.specs)?;
+    let spec = spec::find(&specs, &args.entity).map_err(Error::Failed)?;
+    let batch_size = match args.batch_size {
+        Some(n) => n,
+        None => config::current()
+            .map_err(Error::Failed)?
+            .migration
+            .batch_size
+            .unwrap_or(spec.batch_size),
+    };
+    if batch_size == 0 {
+        return Err(Error::Failed(
+            "--batch-size must be greater than zero".into(),
+        ));
+    }
+    let mut options = RunOptions {
+        batch_size,
+        load: args.load,
+        limit: args.limit,
+        dry_run: args.dry_run,
+        migration_batch: args
+            .migration_batch
+            .clone()
+            .unwrap_or_else(|| engine::default_migration_batch(spec)),
+        resume_after: None,
+        only: None,
+        stop: None,
+        deadline: None,
+    };
+    if let Some(path) = &args.changes {
+        let keys = differential::load_changes(path, &spec.name).map_err(Error::Failed)?;
+        out.line(format_args!(
+            "🎯 {} new or modified row(s) from {}",
+            keys.len(),
+            path.display()
+        ));
+        options.only = Some(keys);
+    }
+
+    let mut source =
+        engine::connect(Side::Source, args.source_url.as_deref()).map_err(Error::Failed)?;
+    let mut target =
+        engine::connect(Side::Target, args.target_url.as_deref()).map_err(Error::Failed)?;
+    if args.resume {
+        match checkpoint::find(&mut target, spec).map_err(Error::Failed)? {
+            Some(found) if found.last_source_key.is_some() => {
+                options.resume_after = found.last_source_key;
+                if args.migration_batch.is_none()
+                    && let Some(batch) = &found.migration_batch
+                {
+                    options.migration_batch = batch.clone();
+                }
+                out.line(format_args!(
+                    "↻ Resuming after {} {} ({} checkpoint{}, {} rows processed, {})",
+                    spec.source_key,
+                    found.last_source_key.unwrap_or_default(),
+                    found.status.as_deref().unwrap_or("legacy"),
+                    found
+                        .legacy_id
+                        .as_ref()
+                        .map(|id| format!(" {} from migration_checkpoints", id))
+                        .unwrap_or_default(),
+                    found.records_processed,
+                    found.updated_at.as_deref().unwrap_or("no timestamp")
+                ));
+            }
+            _ => out.warn(format_args!(
+                "⚠ No committed batch is checkpointed for {}; starting from the beginning",
+                spec.name
+            )),
+        }
+    }
+    let mut mappings = match &args.mappings {
+        Some(path) => MappingStore::import(path).map_err(Error::Failed)?,
+        None => MappingStore::new(),
+    };
+
+    out.line(format_args!(
+        "🚀 Migrating {} → {} ({} load, batch size {}, migration_batch {}{})",
+        spec.source_table,
+        spec.target_table,
+        if args.load == LoadMode::Copy {
+            "copy"
+        } else {
+            "upsert"
+        },
+        batch_size,
+        options.migration_batch,
+        if args.dry_run { ", dry run" } else { "" }
+    ));
+    let stats = engine::run(
+        spec,
+        &mut source,
+        &mut target,
+        &mut mappings,
+        &options,
+        |stats| {
+            out.line(format_args!(
+                "📈 Batch {}: {} rows read, {} upserted, {} skipped, {} failed (last {}: {})",
+                stats.batches,
+                stats.read,
+                stats.upserted,
+                stats.skipped,
+                stats.failed,
+                spec.source_key,
+                stats.last_legacy_id.unwrap_or_default()
+            ));
+        },
+    )?;
+
+    let seconds = stats.elapsed_ms as f64 / 1000.0;
+    out.line(format_args!(
+        "✅ {}: {} read, {} {}, {} skipped in {:.1}s ({:.0} rows/s)",
+        spec.name,
+        stats.read,
+        stats.upserted,
+        if args.dry_run {
+            "would be upserted"
+        } else {
+            "upserted"
+        },
+        stats.skipped,
+        seconds,
+        if seconds > 0.0 {
+            stats.read as f64 / seconds
+        } else {
+            0.0
+        }
+    ));
+    if stats.skipped > 0 {
+        out.warn(format_args!(
+            "⚠ {} row(s) skipped because a required lookup had no mapping",
+            stats.skipped
+        ));
+    }
+    if stats.conflicts > 0 {
+        out.line(format_args!(
+            "⚖ {} conflicting value(s) decided by policy, {} row(s) queued for `resolve`",
+            stats.conflicts, stats.queued_conflicts
+        ));
+    }
+    if stats.failed > 0 {
+        for failure in &stats.failures {
+            out.warn(format_args!(
+                "  ✗ {} {}: {}",
+                spec.source_key, failure.legacy_id, failure.error
+            ));
+        }
+        if stats.failed > stats.failures.len() as u64 {
+            out.warn(format_args!(
+                "  … and {} more",
+                stats.failed - stats.failures.len() as u64
+            ));
+        }
+    }
+    out.report(&stats);
+    if stats.failed > 0 {
+        return Err(Error::Failed(format!(
+            "{} row(s) were rejected by the target ({} batch(es) retried row by row)",
+            stats.failed, stats.fallback_batches
+        )));
+    }
+    Ok(())
+}
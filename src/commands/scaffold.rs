@@ -0,0 +1,92 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::cli::ScaffoldArgs;
+use crate::commands::write::{FileReport, check_result, emit_file};
+use crate::error::{Error, Result};
+use crate::output::Output;
+use crate::plan::Graph;
+use crate::scaffold::{self, ArtifactKind, RollbackPlan};
+use crate::schema::{self, InputFormat};
+use crate::spec;
+
+#[derive(Debug, Serialize)]
+struct ScaffoldReport {
+    entities: Vec<String>,
+    files: Vec<FileReport>,
+}
+
+pub fn run(args: &ScaffoldArgs, out: &Output) -> Result<()> {
+    let specs = spec::load_all(&args.specs)?;
+    let loader = scaffold::loader(&args.templates);
+    for kind in ArtifactKind::ALL {
+        if loader.is_overridden(kind.template_name()) {
+            out.line(format_args!(
+                "ℹ Using {} from {}",
+                kind.template_name(),
+                args.templates.display()
+            ));
+        }
+    }
+    let kinds: Vec<ArtifactKind> = if args.only.is_empty() {
+        ArtifactKind::ALL.to_vec()
+    } else {
+        args.only.clone()
+    };
+    if !(args.write.dry_run || args.write.check) {
+        fs::create_dir_all(&args.out_dir)?;
+    }
+    let rollback = if kinds.contains(&ArtifactKind::Rollback) {
+        let schema = match &args.schema {
+            Some(path) => Some(schema::load(path, InputFormat::Auto)?),
+            None => None,
+        };
+        let plan = Graph::build(&specs, schema.as_ref())
+            .map_err(Error::Failed)?
+            .plan();
+        for cycle in plan.cycles.iter().filter(|c| !c.resolution.safe) {
+            out.warn(format_args!(
+                "⚠ Cycle {} cannot be rolled back in order: {}",
+                cycle.entities.join(" → "),
+                cycle.resolution.proposal
+            ));
+        }
+        RollbackPlan::new(&specs, &plan)
+    } else {
+        RollbackPlan::default()
+    };
+
+    let mut report = ScaffoldReport {
+        entities: Vec::new(),
+        files: Vec::new(),
+    };
+    let mut artifacts = Vec::new();
+    for spec in &specs {
+        for &kind in &kinds {
+            artifacts.push(scaffold::render(&loader, spec, kind, &rollback)?);
+        }
+        report.entities.push(spec.name.clone());
+    }
+    if specs.len() > 1 && kinds.contains(&ArtifactKind::Rollback) {
+        artifacts.push(scaffold::render_rollback_all(&loader, &specs, &rollback)?);
+    }
+    for artifact in &artifacts {
+        let dest = if args.out_dir == Path::new(".") {
+            PathBuf::from(&artifact.file_name)
+        } else {
+            args.out_dir.join(&artifact.file_name)
+        };
+        let label = dest.display().to_string();
+        report.files.push(emit_file(
+            &dest,
+            &label,
+            &artifact.contents,
+            &args.write,
+            out,
+        )?);
+    }
+    out.report(&report);
+    check_result(&args.write, &report.files)
+}
@@ -0,0 +1,167 @@
+use crate::cli::TombstonesArgs;
+use crate::differential::tombstone::{self, Tombstones};
+use crate::differential::{self, DetectOptions, DetectionMode};
+use crate::engine::{self, Side};
+use crate::error::{Error, Result};
+use crate::mapping::MappingStore;
+use crate::output::Output;
+use crate::spec::{self, DeleteMode};
+
+/// Rows listed per class before the rest is elided.
+const SHOWN: usize = 10;
+
+pub fn run(args: &TombstonesArgs, out: &Output) -> Result<()> {
+    let specs = spec::load_all(&args.specs)?;
+    let spec = spec::find(&specs, &args.entity).map_err(Error::Failed)?;
+    let mut policy = spec.deletes.clone().unwrap_or_default();
+    if let Some(mode) = args.policy {
+        policy.policy = mode;
+    }
+
+    let mut target =
+        engine::connect(Side::Target, args.target_url.as_deref()).map_err(Error::Failed)?;
+    let deleted = match &args.changes {
+        Some(path) => differential::load_deletions(path, &spec.name).map_err(Error::Failed)?,
+        None => {
+            let mut source =
+                engine::connect(Side::Source, args.source_url.as_deref()).map_err(Error::Failed)?;
+            out.line(format_args!(
+                "🔎 Comparing {} → {} for deleted rows",
+                spec.source_table, spec.target_table
+            ));
+            let options = DetectOptions {
+                mode: DetectionMode::Hash,
+                timestamp_column: "updated_at".into(),
+                fetch_size: 10_000,
+            };
+            let mut mappings = MappingStore::new();
+            differential::detect(spec, &mut source, &mut target, &mut mappings, &options)?
+                .deleted_records
+        }
+    };
+
+    out.line(format_args!(
+        "🪦 {} deleted legacy row(s) of {} ({} policy{})",
+        deleted.len(),
+        spec.name,
+        policy.policy.as_str(),
+        if args.preview { ", preview" } else { "" }
+    ));
+    let result = tombstone::apply(
+        &mut target,
+        &specs,
+        spec,
+        &policy,
+        &deleted,
+        args.preview,
+        &engine::default_migration_batch(spec),
+    )
+    .map_err(Error::Failed)?;
+    summarize(&result, args.preview, out);
+    out.report(&result);
+    Ok(())
+}
+
+fn summarize(result: &Tombstones, preview: bool, out: &Output) {
+    let applied = match (result.policy, preview) {
+        (DeleteMode::Hard, false) => "deleted",
+        (DeleteMode::Hard, true) => "would be deleted",
+        (DeleteMode::Soft, false) => "soft-deleted",
+        (DeleteMode::Soft, true) => "would be soft-deleted",
+        (DeleteMode::Archive, false) => "archived",
+        (DeleteMode::Archive, true) => "would be archived",
+        (DeleteMode::Report, _) => "reported",
+    };
+    out.line(format_args!(
+        "📊 {}: {} {}, {} blocked by dependents, {} already marked, {} not in {}",
+        result.entity,
+        result.applied.len(),
+        applied,
+        result.blocked.len(),
+        result.already_marked.len(),
+        result.absent,
+        result.target_table
+    ));
+    for (label, ids) in [(applied, &result.applied), ("blocked", &result.blocked)] {
+        if ids.is_empty() {
+            continue;
+        }
+        let shown: Vec<String> = ids.iter().take(SHOWN).map(i64::to_string).collect();
+        out.line(format_args!(
+            "  {}: {}{}",
+            label,
+            shown.join(", "),
+            if ids.len() > SHOWN {
+                format!(" … and {} more", ids.len() - SHOWN)
+            } else {
+                String::new()
+            }
+        ));
+    }
+    if result.dependent_rows > 0 {
+        out.line(format_args!(
+            "  {} dependent target row(s):",
+            result.dependent_rows
+        ));
+        for group in &result.dependent_groups {
+            out.line(format_args!(
+                "  {}↳ {}.{}: {} row(s)",
+                "  ".repeat(group.depth as usize),
+                group.target_table,
+                group.column,
+                group.rows
+            ));
+        }
+    }
+    if preview {
+        for dependent in result.dependents.iter().take(SHOWN) {
+            out.line(format_args!(
+                "    {} {} ({}) → {}",
+                dependent.target_table,
+                dependent
+                    .legacy_id
+                    .map(|id| id.to_string())
+                    .unwrap_or_else(|| "-".into()),
+                dependent.key,
+                dependent.references
+            ));
+        }
+        if result.dependent_rows > SHOWN as u64 {
+            out.line(format_args!(
+                "    … and {} more",
+                result.dependent_rows - SHOWN as u64
+            ));
+        }
+    }
+    if !result.duplicated.is_empty() {
+        let shown: Vec<String> = result
+            .duplicated
+            .iter()
+            .take(SHOWN)
+            .map(i64::to_string)
+            .collect();
+        out.warn(format_args!(
+            "⚠ {} legacy ID(s) are claimed by more than one row of {} and were left alone: {}{}",
+            result.duplicated.len(),
+            result.target_table,
+            shown.join(", "),
+            if result.duplicated.len() > SHOWN {
+                format!(" … and {} more", result.duplicated.len() - SHOWN)
+            } else {
+                String::new()
+            }
+        ));
+    }
+    if !result.blocked.is_empty() {
+        out.warn(format_args!(
+            "⚠ {} row(s) kept because other target rows reference them; \
+             soft-delete them or remove the dependents first",
+            result.blocked.len()
+        ));
+    }
+    if let (Some(archive), false) = (&result.archive_table, preview)
+        && !result.applied.is_empty()
+    {
+        out.line(format_args!("✓ Moved to {}", archive));
+    }
+}
@@ -0,0 +1,126 @@
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::cli::UuidArgs;
+use crate::engine::{self, Side};
+use crate::error::{Error, Result};
+use crate::mapping::{self, MappingStore, registry};
+use crate::output::Output;
+
+#[derive(Debug, Serialize)]
+struct UuidEntry {
+    entity_type: String,
+    legacy_id: i64,
+    /// The target UUID: the mapped one if there is a mapping.
+    uuid: Uuid,
+    deterministic: Uuid,
+    mapped: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    migration_batch: Option<String>,
+}
+
+pub fn run(args: &UuidArgs, out: &Output) -> Result<()> {
+    if args.list {
+        let registered: Vec<_> = registry::all().collect();
+        for r in &registered {
+            out.line(format_args!(
+                "{:<32} {:<24} {}",
+                r.source_table, r.entity_type, r.namespace
+            ));
+        }
+        out.report(&registered);
+        return Ok(());
+    }
+
+    if let Some(new_id) = args.reverse {
+        return reverse(args, new_id, out);
+    }
+
+    let name = args.entity.as_deref().unwrap_or_default();
+    let entity_type = match registry::find(name) {
+        Some(r) => r.entity_type,
+        None => {
+            out.warn(format_args!(
+                "⚠ '{}' is not a registered entity type; using its own namespace",
+                name
+            ));
+            name
+        }
+    };
+    let store = match (&args.mappings, &args.target_url) {
+        (Some(path), _) => Some(MappingStore::import(path).map_err(Error::Failed)?),
+        (None, Some(url)) => {
+            let mut client = engine::connect(Side::Target, Some(url)).map_err(Error::Failed)?;
+            let mut store = MappingStore::new();
+            store
+                .resolve(&mut client, entity_type, args.legacy_ids.iter().copied())
+                .map_err(Error::Failed)?;
+            Some(store)
+        }
+        (None, None) => None,
+    };
+    let mut entries = Vec::with_capacity(args.legacy_ids.len());
+    for &legacy_id in &args.legacy_ids {
+        let deterministic = mapping::deterministic_id(entity_type, legacy_id);
+        let mapped = store
+            .as_ref()
+            .and_then(|store| store.get(entity_type, legacy_id));
+        let uuid = mapped.unwrap_or(deterministic);
+        out.line(uuid);
+        if mapped.is_some_and(|id| id != deterministic) {
+            out.warn(format_args!(
+                "⚠ {} {} is mapped to {}, not its deterministic UUID {}",
+                entity_type, legacy_id, uuid, deterministic
+            ));
+        }
+        entries.push(UuidEntry {
+            entity_type: entity_type.to_string(),
+            legacy_id,
+            uuid,
+            deterministic,
+            mapped: mapped.is_some(),
+            migration_batch: store
+                .as_ref()
+                .and_then(|store| store.batch(entity_type, legacy_id))
+                .filter(|b| !b.is_empty())
+                .map(str::to_string),
+        });
+    }
+    out.report(&entries);
+    Ok(())
+}
+
+fn reverse(args: &UuidArgs, new_id: Uuid, out: &Output) -> Result<()> {
+    let store = match &args.mappings {
+        Some(path) => MappingStore::import(path).map_err(Error::Failed)?,
+        None => {
+            let mut client =
+                engine::connect(Side::Target, args.target_url.as_deref()).map_err(Error::Failed)?;
+            let mut store = MappingStore::new();
+            store
+                .resolve_new_id(&mut client, new_id)
+                .map_err(Error::Failed)?;
+            store
+        }
+    };
+    let Some((entity_type, legacy_id, batch)) = store.reverse(new_id) else {
+        return Err(Error::Failed(format!("no mapping has new_id {}", new_id)));
+    };
+    let deterministic = mapping::deterministic_id(entity_type, legacy_id);
+    out.line(format_args!("{} {}", entity_type, legacy_id));
+    if deterministic != new_id {
+        out.warn(format_args!(
+            "⚠ {} {} is mapped to {}, not its deterministic UUID {}",
+            entity_type, legacy_id, new_id, deterministic
+        ));
+    }
+    out.report(&UuidEntry {
+        entity_type: entity_type.to_string(),
+        legacy_id,
+        uuid: new_id,
+        deterministic,
+        mapped: true,
+        migration_batch: Some(batch.to_string()).filter(|b| !b.is_empty()),
+    });
+    Ok(())
+}
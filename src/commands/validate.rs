@@ -0,0 +1,151 @@
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::cli::ValidateArgs;
+use crate::engine::{self, Side};
+use crate::error::{Error, Result};
+use crate::output::Output;
+use crate::spec;
+use crate::validate::{self, CheckResult, CheckType, Status, Validator};
+
+#[derive(Serialize)]
+struct Report<'a> {
+    run: &'a str,
+    recorded: bool,
+    passed: usize,
+    warned: usize,
+    failed: usize,
+    results: &'a [CheckResult],
+}
+
+pub fn run(args: &ValidateArgs, out: &Output) -> Result<()> {
+    let specs = spec::load_all(&args.specs)?;
+    let mut checks = match &args.checks {
+        Some(path) => validate::load(path, &specs).map_err(Error::Failed)?,
+        None => specs.iter().flat_map(validate::default_checks).collect(),
+    };
+    if let Some(entity) = &args.entity {
+        checks.retain(|c| &c.entity == entity);
+        if checks.is_empty() {
+            return Err(Error::Failed(format!("no checks for entity '{}'", entity)));
+        }
+    }
+    if checks.is_empty() {
+        return Err(Error::Failed("no checks to run".into()));
+    }
+
+    let mut source = if validate::needs_source(&checks) {
+        Some(engine::connect(Side::Source, args.source_url.as_deref()).map_err(Error::Failed)?)
+    } else {
+        None
+    };
+    let mut target =
+        engine::connect(Side::Target, args.target_url.as_deref()).map_err(Error::Failed)?;
+    let run = format!("validation_{}", Utc::now().format("%Y%m%d%H%M%S"));
+    out.line(format_args!(
+        "🔎 Running {} check(s){}",
+        checks.len(),
+        args.checks
+            .as_ref()
+            .map(|p| format!(" from {}", p.display()))
+            .unwrap_or_default()
+    ));
+
+    let mut results = Vec::with_capacity(checks.len());
+    let mut validator = Validator::new(&specs, source.as_mut(), &mut target);
+    for check in &checks {
+        let result = validator.run(check);
+        out.line(format_args!(
+            "  {} {} {}: {}",
+            match result.status {
+                Status::Pass => "✓",
+                Status::Warn => "⚠",
+                Status::Fail => "✗",
+            },
+            result.entity,
+            result.check,
+            result.message
+        ));
+        results.push(result);
+    }
+    print_matrix(&results, out);
+
+    if !args.no_record {
+        validate::ensure_table(&mut target).map_err(Error::Failed)?;
+        validate::record(&mut target, &run, &results, args.keep_days).map_err(Error::Failed)?;
+        out.line(format_args!(
+            "📝 Recorded {} result(s) in migration_validation_reports as {}",
+            results.len(),
+            run
+        ));
+    }
+
+    let count = |status| results.iter().filter(|r| r.status == status).count();
+    let (passed, warned, failed) = (
+        count(Status::Pass),
+        count(Status::Warn),
+        count(Status::Fail),
+    );
+    out.report(&Report {
+        run: &run,
+        recorded: !args.no_record,
+        passed,
+        warned,
+        failed,
+        results: &results,
+    });
+    if failed > 0 {
+        return Err(Error::Failed(format!(
+            "{} of {} check(s) failed",
+            failed,
+            results.len()
+        )));
+    }
+    if warned > 0 {
+        out.line(format_args!(
+            "✅ {} check(s) passed, {} with warnings",
+            passed, warned
+        ));
+    } else {
+        out.line(format_args!("✅ All {} check(s) passed", passed));
+    }
+    Ok(())
+}
+
+/// One row per entity, one column per check type that ran, plus the worst
+/// status of the row.
+fn print_matrix(results: &[CheckResult], out: &Output) {
+    let kinds: Vec<CheckType> = CheckType::ALL
+        .into_iter()
+        .filter(|k| results.iter().any(|r| r.kind == *k))
+        .collect();
+    let matrix = validate::matrix(results);
+    let width = matrix
+        .iter()
+        .map(|(e, _)| e.chars().count())
+        .chain(["entity".len()])
+        .max()
+        .unwrap_or_default();
+    let cell = |kind: CheckType| kind.as_str().len().max("⚠ warn".chars().count());
+
+    let mut header = format!("  {:<width$}", "entity");
+    for kind in &kinds {
+        header.push_str(&format!("  {:<w$}", kind.as_str(), w = cell(*kind)));
+    }
+    header.push_str("  overall");
+    out.line(format_args!("📊 Validation matrix"));
+    out.line(header);
+    for (entity, cells) in &matrix {
+        let mut line = format!("  {:<width$}", entity);
+        for kind in &kinds {
+            let text = cells
+                .get(kind)
+                .map(Status::to_string)
+                .unwrap_or_else(|| "-".into());
+            line.push_str(&format!("  {:<w$}", text, w = cell(*kind)));
+        }
+        let overall = cells.values().max().copied().unwrap_or(Status::Pass);
+        line.push_str(&format!("  {}", overall));
+        out.line(line);
+    }
+}
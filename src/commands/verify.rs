@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::atomic_write::{WriteOptions, write_atomic};
+use crate::cli::VerifyArgs;
+use crate::engine::{self, Side};
+use crate::error::{Error, Result};
+use crate::mapping::MappingStore;
+use crate::output::Output;
+use crate::spec::{self, EntitySpec};
+use crate::validate::sample::{self, Mismatch, Sample, SampleOptions, Strategy};
+
+/// Unmapped or missing legacy IDs listed before the rest is elided.
+const SHOWN: usize = 10;
+
+#[derive(Serialize)]
+struct Verification {
+    entity: String,
+    source_table: String,
+    target_table: String,
+    /// Whether every column and the unmapped and missing rows stay within
+    /// `--max-rate`.
+    passed: bool,
+    #[serde(flatten)]
+    sample: Sample,
+}
+
+pub fn run(args: &VerifyArgs, out: &Output) -> Result<()> {
+    if args.size == 0 {
+        return Err(Error::Failed("--size must be greater than zero".into()));
+    }
+    if args.strata == 0 {
+        return Err(Error::Failed("--strata must be greater than zero".into()));
+    }
+    if !(0.0..=100.0).contains(&args.max_rate) {
+        return Err(Error::Failed(
+            "--max-rate is a percentage between 0 and 100".into(),
+        ));
+    }
+    let specs = spec::load_all(&args.specs)?;
+    let mut selected: Vec<&EntitySpec> = if args.entities.is_empty() {
+        specs.iter().collect()
+    } else {
+        args.entities
+            .iter()
+            .map(|name| spec::find(&specs, name))
+            .collect::<std::result::Result<_, _>>()
+            .map_err(Error::Failed)?
+    };
+    if selected.is_empty() {
+        return Err(Error::Failed("no entity specs to verify".into()));
+    }
+    selected.sort_by_key(|s| (s.dependency_order, s.name.as_str()));
+
+    let mut source =
+        engine::connect(Side::Source, args.source_url.as_deref()).map_err(Error::Failed)?;
+    let mut target =
+        engine::connect(Side::Target, args.target_url.as_deref()).map_err(Error::Failed)?;
+    let mut referenced: Vec<&str> = selected
+        .iter()
+        .flat_map(|s| s.lookups.iter().map(|l| l.entity.as_str()))
+        .collect();
+    referenced.sort_unstable();
+    referenced.dedup();
+    let mut mappings = MappingStore::new();
+    mappings
+        .preload(&mut target, &referenced)
+        .map_err(Error::Failed)?;
+
+    out.line(format_args!(
+        "🎯 Verifying {} entit{}, {} row(s) each{}",
+        selected.len(),
+        if selected.len() == 1 { "y" } else { "ies" },
+        args.size,
+        match args.strategy {
+            Strategy::Random => " drawn at random".to_string(),
+            Strategy::Stratified => format!(" over {} key range(s)", args.strata),
+        }
+    ));
+    let options = SampleOptions {
+        size: args.size,
+        strategy: args.strategy,
+        strata: args.strata,
+        columns: &args.columns,
+        examples: args.examples,
+    };
+    let mut verifications = Vec::with_capacity(selected.len());
+    for spec in selected {
+        let types: HashMap<String, String> =
+            engine::target_types(&mut target, spec).map_err(Error::Failed)?;
+        let sample = sample::compare(spec, &mut source, &mut target, &types, &mappings, &options)
+            .map_err(|e| Error::Failed(format!("{}: {}", spec.name, e)))?;
+        let passed = within(&sample, args.max_rate);
+        summarize(&spec.name, &sample, out);
+        verifications.push(Verification {
+            entity: spec.name.clone(),
+            source_table: spec.source_table.clone(),
+            target_table: spec.target_table.clone(),
+            passed,
+            sample,
+        });
+    }
+
+    if let Some(path) = &args.output {
+        let json = serde_json::to_string_pretty(&verifications).expect("report serializes");
+        write_atomic(path, json.as_bytes(), WriteOptions::default())?;
+        out.line(format_args!("✓ Wrote {}", path.display()));
+    }
+    out.report(&verifications);
+    let failed: Vec<&str> = verifications
+        .iter()
+        .filter(|v| !v.passed)
+        .map(|v| v.entity.as_str())
+        .collect();
+    if !failed.is_empty() {
+        return Err(Error::Failed(format!(
+            "mismatch rate above {}% in {}",
+            args.max_rate,
+            failed.join(", ")
+        )));
+    }
+    out.line(format_args!(
+        "✅ {} entit{} the spec",
+        verifications.len(),
+        if verifications.len() == 1 {
+            "y matches"
+        } else {
+            "ies match"
+        }
+    ));
+    Ok(())
+}
+
+/// Whether no column, and not the unmapped and missing rows together, are
+/// above `max_rate` percent.
+fn within(sample: &Sample, max_rate: f64) -> bool {
+    let absent = (sample.unmapped.len() + sample.missing.len()) as f64;
+    sample
+        .columns
+        .iter()
+        .map(|c| c.mismatch_rate)
+        .chain((sample.sampled > 0).then(|| absent / sample.sampled as f64))
+        .all(|rate| rate * 100.0 <= max_rate)
+}
+
+fn summarize(entity: &str, sample: &Sample, out: &Output) {
+    out.line(format_args!(
+        "📊 {}: {} sampled, {} differ, {} unmapped, {} missing in the target",
+        entity,
+        sample.sampled,
+        sample.mismatched_rows,
+        sample.unmapped.len(),
+        sample.missing.len()
+    ));
+    for (label, ids) in [("unmapped", &sample.unmapped), ("missing", &sample.missing)] {
+        if !ids.is_empty() {
+            out.line(format_args!("  {}: {}", label, listed(ids)));
+        }
+    }
+    if sample.columns.is_empty() {
+        return;
+    }
+    let width = sample
+        .columns
+        .iter()
+        .map(|c| c.column.len())
+        .chain(["column".len()])
+        .max()
+        .unwrap_or_default();
+    out.line(format_args!(
+        "  {:<width$}  {:>8}  {:>10}  {:>7}",
+        "column", "compared", "mismatched", "rate"
+    ));
+    for column in &sample.columns {
+        out.line(format_args!(
+            "  {:<width$}  {:>8}  {:>10}  {:>6.1}%",
+            column.column,
+            column.compared,
+            column.mismatched,
+            column.mismatch_rate * 100.0
+        ));
+        for example in &column.examples {
+            out.line(format_args!("    {}", described(example)));
+        }
+    }
+}
+
+fn described(mismatch: &Mismatch) -> String {
+    let value = |v: &Option<String>| match v {
+        Some(v) => format!("{:?}", v),
+        None => "NULL".into(),
+    };
+    format!(
+        "legacy {}: expected {}, found {}",
+        mismatch.legacy_id,
+        value(&mismatch.expected),
+        value(&mismatch.actual)
+    )
+}
+
+fn listed(ids: &[i64]) -> String {
+    let mut shown: Vec<String> = ids.iter().take(SHOWN).map(i64::to_string).collect();
+    if ids.len() > SHOWN {
+        shown.push(format!("… {} more", ids.len() - SHOWN));
+    }
+    shown.join(", ")
+}
@@ -0,0 +1,272 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::atomic_write::{WriteOptions, write_atomic};
+use crate::cli::{WriteArgs, WriteOpts};
+use crate::diff;
+use crate::error::{Error, Result};
+use crate::output::Output;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    Created,
+    Updated,
+    Unchanged,
+    WouldCreate,
+    WouldUpdate,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileReport {
+    pub path: String,
+    pub status: FileStatus,
+    pub bytes: u64,
+    pub backup: Option<String>,
+    pub insertions: usize,
+    pub deletions: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+impl FileReport {
+    pub fn is_change(&self) -> bool {
+        matches!(
+            self.status,
+            FileStatus::WouldCreate | FileStatus::WouldUpdate
+        )
+    }
+}
+
+impl From<&WriteOpts> for WriteOptions {
+    fn from(opts: &WriteOpts) -> Self {
+        WriteOptions {
+            backup: opts.backup,
+        }
+    }
+}
+
+pub fn read_stdin() -> Result<String> {
+    let mut content = String::new();
+    io::stdin().read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// Write `contents` to `dest`, or with `--dry-run`/`--check` only compare it
+/// against what is on disk. `label` is the path shown to the user.
+pub fn emit_file(
+    dest: &Path,
+    label: &str,
+    contents: &str,
+    opts: &WriteOpts,
+    out: &Output,
+) -> io::Result<FileReport> {
+    // Bytes, not text: a non-UTF-8 file on disk is simply out of date.
+    let existing = match fs::read(dest) {
+        Ok(bytes) => Some(bytes),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    let unchanged = existing.as_deref() == Some(contents.as_bytes());
+    let file_diff = diff::unified(
+        &String::from_utf8_lossy(existing.as_deref().unwrap_or_default()),
+        contents,
+        &format!("a/{}", label),
+        &format!("b/{}", label),
+    );
+    let bytes = contents.len() as u64;
+
+    if opts.dry_run || opts.check {
+        let status = match (&existing, unchanged) {
+            (None, _) => FileStatus::WouldCreate,
+            (Some(_), true) => FileStatus::Unchanged,
+            (Some(_), false) => FileStatus::WouldUpdate,
+        };
+        if !file_diff.is_empty() {
+            if diff::use_color() {
+                out.raw(diff::colorize(&file_diff.unified));
+            } else {
+                out.raw(&file_diff.unified);
+            }
+        }
+        let summary = match status {
+            FileStatus::Unchanged => format!("= Unchanged: {}", label),
+            _ if opts.check => format!("✗ Out of date: {}", label),
+            FileStatus::WouldCreate => format!("+ Would create: {} ({} bytes)", label, bytes),
+            _ => format!(
+                "~ Would update: {} (+{} -{})",
+                label, file_diff.insertions, file_diff.deletions
+            ),
+        };
+        out.line(summary);
+        return Ok(FileReport {
+            path: label.to_string(),
+            status,
+            bytes,
+            backup: None,
+            insertions: file_diff.insertions,
+            deletions: file_diff.deletions,
+            diff: (!file_diff.is_empty()).then_some(file_diff.unified),
+        });
+    }
+
+    let outcome = write_atomic(dest, contents.as_bytes(), opts.into())?;
+    let backup = outcome.backup.as_ref().map(|p| p.display().to_string());
+    if let Some(backup) = &backup {
+        out.line(format_args!("↺ Backup: {}", backup));
+    }
+    out.line(format_args!(
+        "✓ Created: {} ({} bytes)",
+        label, outcome.bytes
+    ));
+    Ok(FileReport {
+        path: label.to_string(),
+        status: if existing.is_some() {
+            FileStatus::Updated
+        } else {
+            FileStatus::Created
+        },
+        bytes: outcome.bytes,
+        backup,
+        insertions: file_diff.insertions,
+        deletions: file_diff.deletions,
+        diff: None,
+    })
+}
+
+/// With `--check`, fail when any of the reports describes a pending change.
+pub fn check_result<'a>(
+    opts: &WriteOpts,
+    reports: impl IntoIterator<Item = &'a FileReport>,
+) -> Result<()> {
+    if !opts.check {
+        return Ok(());
+    }
+    let stale = reports.into_iter().filter(|r| r.is_change()).count();
+    if stale > 0 {
+        return Err(Error::OutOfDate(stale));
+    }
+    Ok(())
+}
+
+pub fn run(args: &WriteArgs, out: &Output) -> Result<()> {
+    let content = read_stdin()?;
+    let label = args.filename.display().to_string();
+    let report = emit_file(&args.filename, &label, &content, &args.write, out)?;
+    out.report(&report);
+    check_result(&args.write, [&report])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(dry_run: bool, check: bool) -> WriteOpts {
+        WriteOpts {
+            backup: false,
+            dry_run,
+            check,
+        }
+    }
+
+    #[test]
+    fn outcomes() {
+        let dir = std::env::temp_dir().join(format!("write-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let dest = dir.join("a.ts");
+        let quiet = Output::new(true, false);
+        let emit = |contents: &str, opts: &WriteOpts| {
+            emit_file(&dest, "a.ts", contents, opts, &quiet).unwrap()
+        };
+
+        // (contents, dry_run, check, expected status, out of date)
+        let cases = [
+            ("one\n", true, false, FileStatus::WouldCreate, false),
+            ("one\n", false, true, FileStatus::WouldCreate, true),
+            ("one\n", false, false, FileStatus::Created, false),
+            ("one\n", false, true, FileStatus::Unchanged, false),
+            ("one\n", true, false, FileStatus::Unchanged, false),
+            ("two\n", false, true, FileStatus::WouldUpdate, true),
+            ("two\n", true, false, FileStatus::WouldUpdate, false),
+            ("two\n", false, false, FileStatus::Updated, false),
+            ("two\n", false, true, FileStatus::Unchanged, false),
+        ];
+        for (i, (contents, dry_run, check, status, stale)) in cases.into_iter().enumerate() {
+            let opts = opts(dry_run, check);
+            let before = fs::read(&dest).ok();
+            let report = emit(contents, &opts);
+            assert_eq!(report.status, status, "case {}", i);
+            assert_eq!(report.bytes, contents.len() as u64, "case {}", i);
+            if dry_run || check {
+                assert_eq!(fs::read(&dest).ok(), before, "case {} wrote", i);
+            } else {
+                assert_eq!(fs::read_to_string(&dest).unwrap(), contents, "case {}", i);
+            }
+            match check_result(&opts, [&report]) {
+                Err(Error::OutOfDate(1)) => assert!(stale, "case {}", i),
+                Ok(()) => assert!(!stale, "case {}", i),
+                Err(e) => panic!("case {}: {}", i, e),
+            }
+        }
+
+        let report = emit("two\n", &opts(true, false));
+        assert_eq!((report.insertions, report.deletions), (0, 0));
+        assert_eq!(report.diff, None);
+        let report = emit("two\nthree\n", &opts(true, false));
+        assert_eq!((report.insertions, report.deletions), (1, 0));
+        assert!(report.diff.unwrap().contains("+three"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn non_utf8_files_are_out_of_date() {
+        let dir = std::env::temp_dir().join(format!("write-utf8-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let dest = dir.join("a.ts");
+        fs::write(&dest, b"caf\xe9\n").unwrap();
+        let quiet = Output::new(true, false);
+
+        let check = opts(false, true);
+        let report = emit_file(&dest, "a.ts", "café\n", &check, &quiet).unwrap();
+        assert_eq!(report.status, FileStatus::WouldUpdate);
+        assert!(matches!(
+            check_result(&check, [&report]),
+            Err(Error::OutOfDate(1))
+        ));
+
+        let report = emit_file(&dest, "a.ts", "café\n", &opts(false, false), &quiet).unwrap();
+        assert_eq!(report.status, FileStatus::Updated);
+        assert_eq!(fs::read(&dest).unwrap(), "café\n".as_bytes());
+        let report = emit_file(&dest, "a.ts", "café\n", &check, &quiet).unwrap();
+        assert_eq!(report.status, FileStatus::Unchanged);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn check_counts_every_stale_file() {
+        let report = |status| FileReport {
+            path: String::new(),
+            status,
+            bytes: 0,
+            backup: None,
+            insertions: 0,
+            deletions: 0,
+            diff: None,
+        };
+        let reports = [
+            report(FileStatus::WouldCreate),
+            report(FileStatus::Unchanged),
+            report(FileStatus::WouldUpdate),
+        ];
+        assert!(matches!(
+            check_result(&opts(false, true), &reports),
+            Err(Error::OutOfDate(2))
+        ));
+        assert!(check_result(&opts(true, false), &reports).is_ok());
+    }
+}
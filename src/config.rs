@@ -0,0 +1,1133 @@
+//! Layered settings for the database connections and the executor.
+//!
+//! Every setting is looked up in these layers, a later one winning:
+//!
+//! 1. the built-in defaults, those of `src/lib/environment-config.ts`;
+//! 2. `[source]`, `[target]` and `[migration]` in `migration.toml` (or the
+//!    file given with `--config`);
+//! 3. `.env` in the working directory;
+//! 4. `[profiles.<profile>.source]` etc. in `migration.toml` for the selected
+//!    profile;
+//! 5. environment variables.
+//!
+//! A connection string on the command line (`--source-url`, `--target-url`)
+//! overrides all of them. The profile is `--profile`, else `MIGRATION_PROFILE`
+//! (from the environment, then `.env`), else `profile` in `migration.toml`.
+//! Each setting keeps the variable name the TypeScript tools read, so an
+//! existing `.env` works unchanged.
+//!
+//! Invalid values and unknown keys in `migration.toml` are errors that stop
+//! every command needing the settings; `config validate` lists them along
+//! with the warnings (conflicting keys, unknown `SOURCE_DB_*` variables).
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::engine::{Side, TlsMode};
+
+pub const DEFAULT_PATH: &str = "migration.toml";
+const DOTENV_PATH: &str = ".env";
+const PROFILE_VAR: &str = "MIGRATION_PROFILE";
+
+/// `SOURCE_DB_*` / `TARGET_DB_*` suffixes only the TypeScript pool reads.
+const POOL_SUFFIXES: [&str; 3] = ["TIMEOUT", "IDLE_TIMEOUT", "POOL_SIZE"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    Dev,
+    Staging,
+    Cutover,
+}
+
+impl Profile {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Profile::Dev => "dev",
+            Profile::Staging => "staging",
+            Profile::Cutover => "cutover",
+        }
+    }
+
+    fn parse(name: &str) -> Result<Profile, String> {
+        Profile::from_str(name, true).map_err(|_| {
+            format!(
+                "unknown profile '{}' (expected dev, staging or cutover)",
+                name
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Text,
+    Secret,
+    /// A connection string, which may embed a password.
+    Url,
+    Port,
+    /// `true`, `false` or an `sslmode`.
+    Tls,
+    Count {
+        min: u64,
+        max: u64,
+    },
+}
+
+struct Key {
+    name: &'static str,
+    var: &'static str,
+    default: Option<&'static str>,
+    kind: Kind,
+}
+
+const fn key(
+    name: &'static str,
+    var: &'static str,
+    default: Option<&'static str>,
+    kind: Kind,
+) -> Key {
+    Key {
+        name,
+        var,
+        default,
+        kind,
+    }
+}
+
+const KEYS: &[Key] = &[
+    key("source.url", "SOURCE_DB_URL", None, Kind::Url),
+    key(
+        "source.host",
+        "SOURCE_DB_HOST",
+        Some("localhost"),
+        Kind::Text,
+    ),
+    key("source.port", "SOURCE_DB_PORT", Some("5432"), Kind::Port),
+    key(
+        "source.name",
+        "SOURCE_DB_NAME",
+        Some("brius_legacy"),
+        Kind::Text,
+    ),
+    key(
+        "source.user",
+        "SOURCE_DB_USER",
+        Some("postgres"),
+        Kind::Text,
+    ),
+    key(
+        "source.password",
+        "SOURCE_DB_PASSWORD",
+        Some("password"),
+        Kind::Secret,
+    ),
+    // Unset: `prefer`, or the URL's own `sslmode`.
+    key("source.ssl", "SOURCE_DB_SSL", None, Kind::Tls),
+    key("target.url", "TARGET_DB_URL", None, Kind::Url),
+    key(
+        "target.host",
+        "TARGET_DB_HOST",
+        Some("localhost"),
+        Kind::Text,
+    ),
+    key("target.port", "TARGET_DB_PORT", Some("5432"), Kind::Port),
+    key(
+        "target.name",
+        "TARGET_DB_NAME",
+        Some("postgres"),
+        Kind::Text,
+    ),
+    key(
+        "target.user",
+        "TARGET_DB_USER",
+        Some("postgres"),
+        Kind::Text,
+    ),
+    key(
+        "target.password",
+        "TARGET_DB_PASSWORD",
+        Some("password"),
+        Kind::Secret,
+    ),
+    // Unset: `prefer`, or the URL's own `sslmode`.
+    key("target.ssl", "TARGET_DB_SSL", None, Kind::Tls),
+    // Unset: each spec's `batch_size`.
+    key(
+        "migration.batch_size",
+        "BATCH_SIZE",
+        None,
+        Kind::Count {
+            min: 1,
+            max: 10_000,
+        },
+    ),
+    key(
+        "migration.max_parallel_entities",
+        "MAX_PARALLEL_ENTITIES",
+        Some("4"),
+        Kind::Count { min: 1, max: 20 },
+    ),
+    key(
+        "migration.memory_limit_mb",
+        "MEMORY_LIMIT_MB",
+        Some("512"),
+        Kind::Count {
+            min: 128,
+            max: 4096,
+        },
+    ),
+    key(
+        "migration.checkpoint_frequency",
+        "CHECKPOINT_FREQUENCY",
+        Some("1000"),
+        Kind::Count {
+            min: 1,
+            max: u32::MAX as u64,
+        },
+    ),
+    key(
+        "migration.max_retry_attempts",
+        "MAX_RETRY_ATTEMPTS",
+        Some("3"),
+        Kind::Count { min: 0, max: 100 },
+    ),
+    key(
+        "migration.timeout_ms",
+        "MIGRATION_TIMEOUT",
+        Some("300000"),
+        Kind::Count {
+            min: 1,
+            max: u32::MAX as u64,
+        },
+    ),
+];
+
+const SECTIONS: [&str; 3] = ["source", "target", "migration"];
+
+/// Where a value came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    File(PathBuf),
+    DotEnv,
+    Profile(Profile),
+    Environment,
+    /// `--profile`; only ever the origin of the profile itself.
+    Flag,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::File(path) => write!(f, "{}", path.display()),
+            Origin::DotEnv => write!(f, "{}", DOTENV_PATH),
+            Origin::Profile(profile) => write!(f, "profile {}", profile.as_str()),
+            Origin::Environment => write!(f, "environment"),
+            Origin::Flag => write!(f, "--profile"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Issue {
+    pub severity: Severity,
+    /// File, profile or `environment`.
+    pub origin: String,
+    /// Setting, variable or TOML key the issue is about.
+    pub key: String,
+    pub message: String,
+}
+
+/// The effective value of one setting.
+#[derive(Debug, Clone, Serialize)]
+pub struct Setting {
+    pub key: &'static str,
+    pub var: &'static str,
+    pub value: Option<String>,
+    pub origin: String,
+    #[serde(skip)]
+    kind: Kind,
+}
+
+impl Setting {
+    /// The value for display; passwords and the passwords in connection
+    /// strings are masked when `redacted`.
+    pub fn display(&self, redacted: bool) -> Option<String> {
+        let value = self.value.as_deref()?;
+        Some(match self.kind {
+            Kind::Secret if redacted => "********".to_string(),
+            Kind::Url if redacted => redact_url(value),
+            _ => value.to_string(),
+        })
+    }
+
+    fn redacted(&self) -> Setting {
+        Setting {
+            value: self.display(true),
+            ..self.clone()
+        }
+    }
+}
+
+/// Which file and profile to read; set once from the global flags.
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    pub path: Option<PathBuf>,
+    pub profile: Option<Profile>,
+}
+
+/// Every layer merged, before the values are typed.
+#[derive(Debug, Clone, Serialize)]
+pub struct Layers {
+    pub profile: Option<Profile>,
+    /// Where the profile was selected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile_origin: Option<String>,
+    /// The files that were read, in layer order.
+    pub files: Vec<String>,
+    pub settings: Vec<Setting>,
+    pub issues: Vec<Issue>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Database {
+    /// Takes the place of every other field but `ssl` when set.
+    pub url: Option<String>,
+    pub host: String,
+    pub port: u16,
+    pub name: String,
+    pub user: String,
+    pub password: String,
+    pub ssl: Option<TlsMode>,
+}
+
+/// The `[migration]` settings this binary uses. The others
+/// (`MAX_PARALLEL_ENTITIES`, `MEMORY_LIMIT_MB`, `CHECKPOINT_FREQUENCY`, …)
+/// are read by the TypeScript orchestrator and only validated here.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    /// Overrides every spec's `batch_size`, like `BATCH_SIZE` does for the
+    /// scaffolded scripts.
+    pub batch_size: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub source: Database,
+    pub target: Database,
+    pub migration: Migration,
+}
+
+impl Config {
+    pub fn database(&self, side: Side) -> &Database {
+        match side {
+            Side::Source => &self.source,
+            Side::Target => &self.target,
+        }
+    }
+}
+
+static SELECTION: OnceLock<Selection> = OnceLock::new();
+static CURRENT: OnceLock<Result<Config, String>> = OnceLock::new();
+
+/// Record the global `--config` and `--profile` flags; the first call wins.
+pub fn select(selection: Selection) {
+    let _ = SELECTION.set(selection);
+}
+
+pub fn selection() -> &'static Selection {
+    SELECTION.get_or_init(Selection::default)
+}
+
+/// The effective settings, loaded on first use.
+pub fn current() -> Result<&'static Config, String> {
+    CURRENT
+        .get_or_init(|| load(selection())?.config())
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+type Values = BTreeMap<&'static str, String>;
+
+/// Read every layer. Fails only when a file cannot be read or parsed; bad
+/// values are reported in `issues`.
+pub fn load(selection: &Selection) -> Result<Layers, String> {
+    let mut issues = Vec::new();
+    let mut files = Vec::new();
+
+    let path = selection
+        .path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_PATH));
+    let file = if path.exists() || selection.path.is_some() {
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        files.push(path.display().to_string());
+        parse_file(&path, &text, &mut issues)?
+    } else {
+        TomlFile::default()
+    };
+
+    let dotenv = if Path::new(DOTENV_PATH).exists() {
+        let text = fs::read_to_string(DOTENV_PATH)
+            .map_err(|e| format!("cannot read {}: {}", DOTENV_PATH, e))?;
+        files.push(DOTENV_PATH.to_string());
+        parse_dotenv(&text, &mut issues)
+    } else {
+        Vec::new()
+    };
+    let environment: Vec<(String, String)> = env::vars().collect();
+    Ok(Layers {
+        files,
+        ..merge(selection, &path, file, &dotenv, &environment, issues)
+    })
+}
+
+/// Merge layers that have been read: defaults < file < `.env` < profile <
+/// environment.
+fn merge(
+    selection: &Selection,
+    path: &Path,
+    file: TomlFile,
+    dotenv: &[(String, String)],
+    environment: &[(String, String)],
+    mut issues: Vec<Issue>,
+) -> Layers {
+    let mut chosen = selection
+        .profile
+        .map(|p| (p.as_str().to_string(), Origin::Flag));
+    for (vars, origin) in [(environment, Origin::Environment), (dotenv, Origin::DotEnv)] {
+        if chosen.is_none()
+            && let Some((_, name)) = vars
+                .iter()
+                .rfind(|(var, value)| var == PROFILE_VAR && !value.is_empty())
+        {
+            chosen = Some((name.clone(), origin));
+        }
+    }
+    if chosen.is_none()
+        && let Some(name) = &file.profile
+    {
+        chosen = Some((name.clone(), Origin::File(path.to_path_buf())));
+    }
+    let profile = match &chosen {
+        Some((name, origin)) => match Profile::parse(name) {
+            Ok(profile) => Some(profile),
+            Err(e) => {
+                issues.push(issue(Severity::Error, origin, PROFILE_VAR, e));
+                None
+            }
+        },
+        None => None,
+    };
+    if let Some(profile) = profile
+        && !file.profiles.contains_key(&profile.as_str())
+    {
+        issues.push(issue(
+            Severity::Warning,
+            &Origin::File(path.to_path_buf()),
+            &format!("profiles.{}", profile.as_str()),
+            format!(
+                "profile {} is selected but has no settings; only the other layers apply",
+                profile.as_str()
+            ),
+        ));
+    }
+
+    let defaults: Values = KEYS
+        .iter()
+        .filter_map(|k| Some((k.name, k.default?.to_string())))
+        .collect();
+    let mut layers = vec![
+        (Origin::Default, defaults),
+        (Origin::File(path.to_path_buf()), file.base),
+        (
+            Origin::DotEnv,
+            from_vars(dotenv, &Origin::DotEnv, &mut issues),
+        ),
+    ];
+    if let Some(profile) = profile
+        && let Some(values) = file.profiles.get(profile.as_str())
+    {
+        layers.push((Origin::Profile(profile), values.clone()));
+    }
+    layers.push((
+        Origin::Environment,
+        from_vars(environment, &Origin::Environment, &mut issues),
+    ));
+
+    let settings: Vec<Setting> = KEYS
+        .iter()
+        .map(|k| {
+            let found = layers
+                .iter()
+                .rev()
+                .find_map(|(origin, values)| Some((values.get(k.name)?, origin)));
+            Setting {
+                key: k.name,
+                var: k.var,
+                value: found.map(|(v, _)| v.clone()),
+                origin: found
+                    .map(|(_, o)| o.to_string())
+                    .unwrap_or_else(|| "unset".into()),
+                kind: k.kind,
+            }
+        })
+        .collect();
+    for (setting, k) in settings.iter().zip(KEYS) {
+        if let Some(value) = &setting.value
+            && let Err(e) = check(k.kind, value)
+        {
+            issues.push(Issue {
+                severity: Severity::Error,
+                origin: setting.origin.clone(),
+                key: k.name.to_string(),
+                message: format!("{} ({})", e, k.var),
+            });
+        }
+    }
+    conflicts(&settings, &mut issues);
+
+    Layers {
+        profile,
+        profile_origin: chosen.map(|(_, origin)| origin.to_string()),
+        files: Vec::new(),
+        settings,
+        issues,
+    }
+}
+
+impl Layers {
+    pub fn errors(&self) -> usize {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .count()
+    }
+
+    pub fn redacted(&self) -> Layers {
+        Layers {
+            settings: self.settings.iter().map(Setting::redacted).collect(),
+            ..self.clone()
+        }
+    }
+
+    /// The typed settings; fails when there is any error.
+    pub fn config(&self) -> Result<Config, String> {
+        if let Some(first) = self.issues.iter().find(|i| i.severity == Severity::Error) {
+            return Err(format!(
+                "invalid configuration: {} in {}: {}{}; see `config validate`",
+                first.key,
+                first.origin,
+                first.message,
+                match self.errors() {
+                    1 => String::new(),
+                    n => format!(" (and {} more error(s))", n - 1),
+                }
+            ));
+        }
+        let text = |name: &str| self.value(name).unwrap_or_default().to_string();
+        let number = |name: &str| self.value(name).and_then(|v| v.parse::<u64>().ok());
+        let database = |side: &str| Database {
+            url: self.value(&format!("{}.url", side)).map(str::to_string),
+            host: text(&format!("{}.host", side)),
+            port: number(&format!("{}.port", side)).unwrap_or(5432) as u16,
+            name: text(&format!("{}.name", side)),
+            user: text(&format!("{}.user", side)),
+            password: text(&format!("{}.password", side)),
+            ssl: self
+                .value(&format!("{}.ssl", side))
+                .and_then(|v| TlsMode::parse(v).ok()),
+        };
+        Ok(Config {
+            source: database("source"),
+            target: database("target"),
+            migration: Migration {
+                batch_size: number("migration.batch_size").map(|n| n as usize),
+            },
+        })
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.settings
+            .iter()
+            .find(|s| s.key == name)?
+            .value
+            .as_deref()
+    }
+}
+
+fn issue(severity: Severity, origin: &Origin, key: &str, message: String) -> Issue {
+    Issue {
+        severity,
+        origin: origin.to_string(),
+        key: key.to_string(),
+        message,
+    }
+}
+
+#[derive(Debug, Default)]
+struct TomlFile {
+    profile: Option<String>,
+    base: Values,
+    profiles: BTreeMap<&'static str, Values>,
+}
+
+fn parse_file(path: &Path, text: &str, issues: &mut Vec<Issue>) -> Result<TomlFile, String> {
+    let table: toml::Table =
+        toml::from_str(text).map_err(|e| format!("invalid {}: {}", path.display(), e))?;
+    let origin = Origin::File(path.to_path_buf());
+    let mut file = TomlFile::default();
+    for (name, value) in &table {
+        match (name.as_str(), value) {
+            ("profile", toml::Value::String(profile)) => file.profile = Some(profile.clone()),
+            ("profiles", toml::Value::Table(profiles)) => {
+                for (profile, sections) in profiles {
+                    let at = format!("profiles.{}", profile);
+                    let profile = match Profile::parse(profile) {
+                        Ok(profile) => profile,
+                        Err(e) => {
+                            issues.push(issue(Severity::Error, &origin, &at, e));
+                            continue;
+                        }
+                    };
+                    let values = file.profiles.entry(profile.as_str()).or_default();
+                    match sections {
+                        toml::Value::Table(sections) => {
+                            parse_sections(sections, &at, &origin, values, issues)
+                        }
+                        _ => issues.push(issue(
+                            Severity::Error,
+                            &origin,
+                            &at,
+                            "must be a table".into(),
+                        )),
+                    }
+                }
+            }
+            _ if SECTIONS.contains(&name.as_str()) => {
+                let mut section = toml::Table::new();
+                section.insert(name.clone(), value.clone());
+                parse_sections(&section, "", &origin, &mut file.base, issues);
+            }
+            _ => issues.push(issue(
+                Severity::Error,
+                &origin,
+                name,
+                "unknown key (expected profile, source, target, migration or profiles)".into(),
+            )),
+        }
+    }
+    Ok(file)
+}
+
+/// Read `[source]`-style tables into `values`; `at` prefixes the keys in
+/// messages.
+fn parse_sections(
+    sections: &toml::Table,
+    at: &str,
+    origin: &Origin,
+    values: &mut Values,
+    issues: &mut Vec<Issue>,
+) {
+    let qualified = |name: &str| {
+        if at.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", at, name)
+        }
+    };
+    for (section, table) in sections {
+        if !SECTIONS.contains(&section.as_str()) {
+            issues.push(issue(
+                Severity::Error,
+                origin,
+                &qualified(section),
+                "unknown section (expected source, target or migration)".into(),
+            ));
+            continue;
+        }
+        let Some(table) = table.as_table() else {
+            issues.push(issue(
+                Severity::Error,
+                origin,
+                &qualified(section),
+                "must be a table".into(),
+            ));
+            continue;
+        };
+        for (name, value) in table {
+            let full = format!("{}.{}", section, name);
+            let Some(key) = KEYS.iter().find(|k| k.name == full) else {
+                issues.push(issue(
+                    Severity::Error,
+                    origin,
+                    &qualified(&full),
+                    "unknown key".into(),
+                ));
+                continue;
+            };
+            match value {
+                toml::Value::String(s) => {
+                    values.insert(key.name, s.clone());
+                }
+                toml::Value::Integer(n) => {
+                    values.insert(key.name, n.to_string());
+                }
+                toml::Value::Boolean(b) => {
+                    values.insert(key.name, b.to_string());
+                }
+                _ => issues.push(issue(
+                    Severity::Error,
+                    origin,
+                    &qualified(&full),
+                    "must be a string, an integer or a boolean".into(),
+                )),
+            }
+        }
+    }
+}
+
+/// `KEY=value` lines, as dotenv reads them: `export` prefixes, comments and
+/// single or double quotes are allowed.
+fn parse_dotenv(text: &str, issues: &mut Vec<Issue>) -> Vec<(String, String)> {
+    let mut vars: Vec<(String, String)> = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((name, value)) = line.split_once('=') else {
+            issues.push(issue(
+                Severity::Warning,
+                &Origin::DotEnv,
+                &format!("line {}", n + 1),
+                "not a KEY=value line; ignored".into(),
+            ));
+            continue;
+        };
+        let name = name.trim().to_string();
+        let value = value.trim();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..]
+                .split_once(quote)
+                .map_or(&value[1..], |(inner, _)| inner),
+            _ => value.split_once(" #").map_or(value, |(v, _)| v).trim_end(),
+        }
+        .to_string();
+        if let Some((_, earlier)) = vars.iter().rfind(|(var, _)| *var == name)
+            && *earlier != value
+        {
+            issues.push(issue(
+                Severity::Warning,
+                &Origin::DotEnv,
+                &name,
+                format!("set more than once; line {} wins", n + 1),
+            ));
+        }
+        vars.push((name, value));
+    }
+    vars
+}
+
+/// The settings among `vars`; empty values count as unset, as they do for
+/// the TypeScript tools.
+fn from_vars(vars: &[(String, String)], origin: &Origin, issues: &mut Vec<Issue>) -> Values {
+    let mut values = Values::new();
+    for (var, value) in vars {
+        if let Some(key) = KEYS.iter().find(|k| k.var == var) {
+            if !value.is_empty() {
+                values.insert(key.name, value.clone());
+            }
+        } else if let Some(suffix) = var
+            .strip_prefix("SOURCE_DB_")
+            .or_else(|| var.strip_prefix("TARGET_DB_"))
+            && !POOL_SUFFIXES.contains(&suffix)
+        {
+            issues.push(issue(
+                Severity::Warning,
+                origin,
+                var,
+                "unknown variable; ignored".into(),
+            ));
+        }
+    }
+    values
+}
+
+fn check(kind: Kind, value: &str) -> Result<(), String> {
+    match kind {
+        Kind::Text | Kind::Secret | Kind::Url => Ok(()),
+        Kind::Tls => TlsMode::parse(value).map(|_| ()),
+        Kind::Port => match value.parse::<u16>() {
+            Ok(port) if port > 0 => Ok(()),
+            _ => Err(format!("'{}' is not a port number", value)),
+        },
+        Kind::Count { min, max } => match value.parse::<u64>() {
+            Ok(n) if (min..=max).contains(&n) => Ok(()),
+            Ok(n) => Err(format!("{} is outside {}-{}", n, min, max)),
+            Err(_) => Err(format!("'{}' is not a whole number", value)),
+        },
+    }
+}
+
+/// Keys that contradict each other: a connection string next to the
+/// settings it replaces, and a target that is the source.
+fn conflicts(settings: &[Setting], issues: &mut Vec<Issue>) {
+    let get = |name: &str| settings.iter().find(|s| s.key == name);
+    for side in ["source", "target"] {
+        let Some(url) = get(&format!("{}.url", side)).filter(|s| s.value.is_some()) else {
+            continue;
+        };
+        let replaced: Vec<String> = ["host", "port", "name", "user", "password"]
+            .iter()
+            .filter_map(|field| get(&format!("{}.{}", side, field)))
+            .filter(|s| s.origin != "default")
+            .map(|s| format!("{} ({})", s.key, s.origin))
+            .collect();
+        if !replaced.is_empty() {
+            issues.push(Issue {
+                severity: Severity::Warning,
+                origin: url.origin.clone(),
+                key: url.key.to_string(),
+                message: format!("overrides {}", replaced.join(", ")),
+            });
+        }
+    }
+    let identity = |side: &str| -> Option<String> {
+        if let Some(url) = get(&format!("{}.url", side))?.value.clone() {
+            return Some(url);
+        }
+        let parts: Option<Vec<String>> = ["host", "port", "name"]
+            .iter()
+            .map(|f| get(&format!("{}.{}", side, f))?.value.clone())
+            .collect();
+        parts.map(|p| p.join(":"))
+    };
+    if let (Some(source), Some(target)) = (identity("source"), identity("target"))
+        && source == target
+    {
+        issues.push(Issue {
+            severity: Severity::Warning,
+            origin: get("target.host")
+                .map(|s| s.origin.clone())
+                .unwrap_or_default(),
+            key: "target".into(),
+            message: "source and target are the same database".into(),
+        });
+    }
+}
+
+/// Mask the password of a `postgresql://` URL, in its userinfo or its
+/// query string, or of a `key=value` string.
+fn redact_url(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return redact_conninfo(url);
+    };
+    let (rest, query) = match rest.split_once('?') {
+        Some((rest, query)) => (rest, Some(query)),
+        None => (rest, None),
+    };
+    // The host follows the last `@`; an unencoded `@` may be in the password.
+    let mut redacted = if let Some((userinfo, host)) = rest.rsplit_once('@')
+        && let Some((user, _)) = userinfo.split_once(':')
+    {
+        format!("{}://{}:********@{}", scheme, user, host)
+    } else {
+        format!("{}://{}", scheme, rest)
+    };
+    if let Some(query) = query {
+        let params: Vec<String> = query
+            .split('&')
+            .map(|param| match param.split_once('=') {
+                Some((key, _)) if key.eq_ignore_ascii_case("password") => {
+                    format!("{}=********", key)
+                }
+                _ => param.to_string(),
+            })
+            .collect();
+        redacted.push('?');
+        redacted.push_str(&params.join("&"));
+    }
+    redacted
+}
+
+/// Mask the `password` of a libpq `key = value` string, whose values may be
+/// single-quoted with backslash escapes. Everything else is kept as written.
+fn redact_conninfo(conninfo: &str) -> String {
+    let bytes = conninfo.as_bytes();
+    let skip_space = |mut i: usize| {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        i
+    };
+    let mut out = String::with_capacity(conninfo.len());
+    let mut copied = 0;
+    let mut i = skip_space(0);
+    while i < bytes.len() {
+        let key_start = i;
+        while i < bytes.len() && bytes[i] != b'=' && !bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let key = &conninfo[key_start..i];
+        i = skip_space(i);
+        if bytes.get(i) != Some(&b'=') {
+            continue;
+        }
+        i = skip_space(i + 1);
+        let value_start = i;
+        let quoted = bytes.get(i) == Some(&b'\'');
+        if quoted {
+            i += 1;
+        }
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' => i += 1,
+                b'\'' if quoted => {
+                    i += 1;
+                    break;
+                }
+                b if !quoted && b.is_ascii_whitespace() => break,
+                _ => {}
+            }
+            i += 1;
+        }
+        i = i.min(bytes.len());
+        if key.eq_ignore_ascii_case("password") {
+            out.push_str(&conninfo[copied..value_start]);
+            out.push_str("********");
+            copied = i;
+        }
+        i = skip_space(i);
+    }
+    out.push_str(&conninfo[copied..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_url() {
+        let cases = [
+            (
+                "postgresql://app:secret@db:5432/brius",
+                "postgresql://app:********@db:5432/brius",
+            ),
+            (
+                "postgres://app:p@ss@db/brius",
+                "postgres://app:********@db/brius",
+            ),
+            (
+                "postgres://app:a:b@db/brius",
+                "postgres://app:********@db/brius",
+            ),
+            ("postgresql://app@db/brius", "postgresql://app@db/brius"),
+            ("postgresql://db/brius", "postgresql://db/brius"),
+            (
+                "postgresql://app@db/brius?sslmode=require&password=secret",
+                "postgresql://app@db/brius?sslmode=require&password=********",
+            ),
+            (
+                "postgresql://db/brius?PASSWORD=secret&user=app",
+                "postgresql://db/brius?PASSWORD=********&user=app",
+            ),
+            (
+                "host=db password=secret dbname=brius",
+                "host=db password=******** dbname=brius",
+            ),
+            (
+                "host=db password='a b' dbname=brius",
+                "host=db password=******** dbname=brius",
+            ),
+            (
+                "password = 'it\\'s a b'  user=app",
+                "password = ********  user=app",
+            ),
+            ("password=a\\ b host=db", "password=******** host=db"),
+            ("PASSWORD=secret", "PASSWORD=********"),
+            ("host=db user=app", "host=db user=app"),
+            (
+                "host = 'my db' passwordless=1",
+                "host = 'my db' passwordless=1",
+            ),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(super::redact_url(url), expected, "{}", url);
+        }
+    }
+
+    #[test]
+    fn parse_dotenv() {
+        let text = "\
+# comment
+export SOURCE_DB_HOST=legacy-db
+TARGET_DB_HOST = spaced  
+SOURCE_DB_PASSWORD=\"pa ss # word\" # trailing
+TARGET_DB_PASSWORD='single'
+BATCH_SIZE=500 # inline comment
+TARGET_DB_NAME=a#b
+SOURCE_DB_NAME=\"unterminated
+not a setting
+
+BATCH_SIZE=1000
+";
+        let mut issues = Vec::new();
+        let vars = super::parse_dotenv(text, &mut issues);
+        let vars: Vec<(&str, &str)> = vars.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        assert_eq!(
+            vars,
+            [
+                ("SOURCE_DB_HOST", "legacy-db"),
+                ("TARGET_DB_HOST", "spaced"),
+                ("SOURCE_DB_PASSWORD", "pa ss # word"),
+                ("TARGET_DB_PASSWORD", "single"),
+                ("BATCH_SIZE", "500"),
+                ("TARGET_DB_NAME", "a#b"),
+                ("SOURCE_DB_NAME", "unterminated"),
+                ("BATCH_SIZE", "1000"),
+            ]
+        );
+        let issues: Vec<(&str, &str)> = issues
+            .iter()
+            .map(|i| (i.key.as_str(), i.message.as_str()))
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                ("line 9", "not a KEY=value line; ignored"),
+                ("BATCH_SIZE", "set more than once; line 11 wins"),
+            ]
+        );
+    }
+
+    #[test]
+    fn layer_precedence() {
+        let toml = r#"
+profile = "staging"
+
+[source]
+host = "file-host"
+port = 6000
+name = "file-db"
+user = "file-user"
+
+[target]
+host = "file-target"
+
+[profiles.staging.source]
+host = "staging-host"
+name = "staging-db"
+
+[profiles.cutover.source]
+name = "cutover-db"
+"#;
+        let path = Path::new("migration.toml");
+        let vars = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let dotenv = vars(&[
+            ("SOURCE_DB_HOST", "dotenv-host"),
+            ("SOURCE_DB_PORT", "7000"),
+            ("SOURCE_DB_NAME", "dotenv-db"),
+            ("SOURCE_DB_USER", ""),
+        ]);
+        let layers = |selection: Selection, environment: &[(&str, &str)]| {
+            let mut issues = Vec::new();
+            let file = parse_file(path, toml, &mut issues).unwrap();
+            merge(&selection, path, file, &dotenv, &vars(environment), issues)
+        };
+        let effective = |layers: &Layers, key: &str| {
+            let setting = layers.settings.iter().find(|s| s.key == key).unwrap();
+            (
+                setting.value.clone().unwrap_or_default(),
+                setting.origin.clone(),
+            )
+        };
+
+        let plain = layers(Selection::default(), &[("SOURCE_DB_HOST", "env-host")]);
+        assert_eq!(plain.profile, Some(Profile::Staging));
+        assert_eq!(plain.profile_origin.as_deref(), Some("migration.toml"));
+        let cases = [
+            // The environment beats every file.
+            ("source.host", "env-host", "environment"),
+            // The profile beats .env and the base file.
+            ("source.name", "staging-db", "profile staging"),
+            // .env beats the base file; an empty value counts as unset.
+            ("source.port", "7000", ".env"),
+            ("source.user", "file-user", "migration.toml"),
+            ("target.host", "file-target", "migration.toml"),
+            ("target.port", "5432", "default"),
+            ("source.url", "", "unset"),
+        ];
+        for (key, value, origin) in cases {
+            assert_eq!(
+                effective(&plain, key),
+                (value.to_string(), origin.to_string()),
+                "{}",
+                key
+            );
+        }
+        assert!(plain.issues.is_empty(), "{:?}", plain.issues);
+
+        // --profile beats MIGRATION_PROFILE, which beats the file's profile.
+        let from_env = layers(Selection::default(), &[("MIGRATION_PROFILE", "cutover")]);
+        assert_eq!(from_env.profile_origin.as_deref(), Some("environment"));
+        assert_eq!(
+            effective(&from_env, "source.name"),
+            ("cutover-db".into(), "profile cutover".into())
+        );
+        assert_eq!(
+            effective(&from_env, "source.host"),
+            ("dotenv-host".into(), ".env".into())
+        );
+        let flagged = layers(
+            Selection {
+                path: None,
+                profile: Some(Profile::Dev),
+            },
+            &[("MIGRATION_PROFILE", "cutover")],
+        );
+        assert_eq!(flagged.profile, Some(Profile::Dev));
+        assert_eq!(flagged.profile_origin.as_deref(), Some("--profile"));
+        assert_eq!(
+            effective(&flagged, "source.name"),
+            ("dotenv-db".into(), ".env".into())
+        );
+        assert_eq!(
+            flagged.issues[0].message,
+            "profile dev is selected but has no settings; only the other layers apply"
+        );
+
+        // Bad values are issues, not failures.
+        let bad = layers(Selection::default(), &[("TARGET_DB_PORT", "http")]);
+        assert_eq!(bad.errors(), 1);
+        assert!(
+            bad.config()
+                .unwrap_err()
+                .starts_with("invalid configuration: target.port in environment:")
+        );
+    }
+}
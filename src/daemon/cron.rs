@@ -0,0 +1,290 @@
+//! Five-field cron expressions: `minute hour day-of-month month day-of-week`,
+//! evaluated in UTC.
+//!
+//! Fields take `*`, lists, ranges and `/step`; months and weekdays also take
+//! three-letter names, and Sunday is 0 or 7. When both day fields are
+//! restricted a day matches either of them, as in Vixie cron. `@hourly`,
+//! `@daily` (`@midnight`), `@weekly`, `@monthly` and `@yearly` (`@annually`)
+//! are shorthands, and so are `hourly`, `daily` and `weekly`, the schedules of
+//! the TypeScript scheduler.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Timelike, Utc};
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Years searched for a matching minute before a schedule is declared
+/// unsatisfiable (e.g. `0 0 31 2 *`).
+const HORIZON_YEARS: i32 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Day-of-month was `*`.
+    any_day: bool,
+    /// Day-of-week was `*`.
+    any_weekday: bool,
+}
+
+impl Schedule {
+    pub fn parse(expression: &str) -> Result<Schedule, String> {
+        let expanded = match expression.trim().to_ascii_lowercase().as_str() {
+            "@hourly" | "hourly" => "0 * * * *".to_string(),
+            "@daily" | "@midnight" | "daily" => "0 0 * * *".to_string(),
+            "@weekly" | "weekly" => "0 0 * * 0".to_string(),
+            "@monthly" => "0 0 1 * *".to_string(),
+            "@yearly" | "@annually" => "0 0 1 1 *".to_string(),
+            other => other.to_string(),
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "'{}' is not a cron expression (expected 5 fields: minute hour day month weekday)",
+                expression
+            ));
+        };
+        let invalid = |e: String| format!("'{}': {}", expression, e);
+        let mut weekdays = field(weekday, 0, 7, &WEEKDAYS, 0).map_err(invalid)?;
+        // Sunday is both 0 and 7.
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Schedule {
+            minutes: field(minute, 0, 59, &[], 0).map_err(invalid)?,
+            hours: field(hour, 0, 23, &[], 0).map_err(invalid)?,
+            days: field(day, 1, 31, &[], 0).map_err(invalid)?,
+            months: field(month, 1, 12, &MONTHS, 1).map_err(invalid)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// The first matching minute strictly after `after`, or None when none
+    /// falls within the next [`HORIZON_YEARS`] years.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.naive_utc();
+        let mut t =
+            start.date().and_hms_opt(start.hour(), start.minute(), 0)? + Duration::minutes(1);
+        let limit = start.year() + HORIZON_YEARS;
+        while t.year() <= limit {
+            if !has(self.months, t.month()) {
+                let (year, month) = if t.month() == 12 {
+                    (t.year() + 1, 1)
+                } else {
+                    (t.year(), t.month() + 1)
+                };
+                t = midnight(NaiveDate::from_ymd_opt(year, month, 1)?);
+            } else if !self.day_matches(t.date()) {
+                t = midnight(t.date().succ_opt()?);
+            } else if !has(self.hours, t.hour()) {
+                t = t.date().and_hms_opt(t.hour(), 0, 0)? + Duration::hours(1);
+            } else if !has(self.minutes, t.minute()) {
+                t += Duration::minutes(1);
+            } else {
+                return Some(t.and_utc());
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = has(self.days, date.day());
+        let weekday = has(self.weekdays, date.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+fn has(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+fn midnight(date: NaiveDate) -> NaiveDateTime {
+    date.and_hms_opt(0, 0, 0).expect("midnight exists")
+}
+
+/// Parse one field into a bit mask; `names[i]` stands for `i + offset`.
+fn field(text: &str, min: u32, max: u32, names: &[&str], offset: u32) -> Result<u64, String> {
+    let value = |s: &str| -> Result<u32, String> {
+        let n = match names.iter().position(|name| *name == s) {
+            Some(i) => i as u32 + offset,
+            None => s
+                .parse()
+                .map_err(|_| format!("'{}' is not a number or a name", s))?,
+        };
+        if n < min || n > max {
+            return Err(format!("{} is outside {}-{}", n, min, max));
+        }
+        Ok(n)
+    };
+    let mut mask = 0u64;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("invalid step in '{}'", part))?;
+                (range, Some(step))
+            }
+            None => (part, None),
+        };
+        let (low, high) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((low, high)) => (value(low)?, value(high)?),
+                // `5/15` runs from 5 to the end of the range.
+                None if step.is_some() => (value(range)?, max),
+                None => {
+                    let v = value(range)?;
+                    (v, v)
+                }
+            },
+        };
+        if low > high {
+            return Err(format!("empty range '{}'", range));
+        }
+        let mut v = low;
+        while v <= high {
+            mask |= 1 << v;
+            v += step.unwrap_or(1);
+        }
+    }
+    Ok(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S")
+            .expect(text)
+            .and_utc()
+    }
+
+    #[test]
+    fn next_after() {
+        // 2026-10-15 is a Thursday.
+        let cases = [
+            // Strictly after, to the minute.
+            (
+                "0 * * * *",
+                "2026-10-15 11:00:00",
+                Some("2026-10-15 12:00:00"),
+            ),
+            (
+                "*/15 * * * *",
+                "2026-10-15 10:07:30",
+                Some("2026-10-15 10:15:00"),
+            ),
+            (
+                "@hourly",
+                "2026-10-15 10:59:59",
+                Some("2026-10-15 11:00:00"),
+            ),
+            (
+                "0 0 1 1 *",
+                "2026-12-31 23:59:00",
+                Some("2027-01-01 00:00:00"),
+            ),
+            // One day field restricted: only that one counts.
+            (
+                "0 0 20 * *",
+                "2026-10-15 00:00:00",
+                Some("2026-10-20 00:00:00"),
+            ),
+            (
+                "0 0 * * 5",
+                "2026-10-15 00:00:00",
+                Some("2026-10-16 00:00:00"),
+            ),
+            // Both restricted: either matches.
+            (
+                "0 0 13 * 5",
+                "2026-10-15 00:00:00",
+                Some("2026-10-16 00:00:00"),
+            ),
+            (
+                "0 0 1 * 1",
+                "2026-10-15 00:00:00",
+                Some("2026-10-19 00:00:00"),
+            ),
+            (
+                "0 0 1 * 1",
+                "2026-10-26 00:00:00",
+                Some("2026-11-01 00:00:00"),
+            ),
+            // Sunday is 0, 7 and `sun`, also inside ranges.
+            (
+                "30 6 * * 0",
+                "2026-10-15 00:00:00",
+                Some("2026-10-18 06:30:00"),
+            ),
+            (
+                "30 6 * * 7",
+                "2026-10-15 00:00:00",
+                Some("2026-10-18 06:30:00"),
+            ),
+            (
+                "30 6 * * sun",
+                "2026-10-15 00:00:00",
+                Some("2026-10-18 06:30:00"),
+            ),
+            (
+                "0 12 * * 6-7",
+                "2026-10-17 13:00:00",
+                Some("2026-10-18 12:00:00"),
+            ),
+            (
+                "0 12 * * 6-7",
+                "2026-10-18 13:00:00",
+                Some("2026-10-24 12:00:00"),
+            ),
+            // Rare and impossible dates.
+            (
+                "0 0 29 2 *",
+                "2026-10-15 00:00:00",
+                Some("2028-02-29 00:00:00"),
+            ),
+            ("0 0 31 2 *", "2026-10-15 00:00:00", None),
+            ("0 0 31 4,6 *", "2026-10-15 00:00:00", None),
+        ];
+        for (expression, after, expected) in cases {
+            let schedule = Schedule::parse(expression).expect(expression);
+            assert_eq!(
+                schedule.next_after(at(after)),
+                expected.map(at),
+                "{} after {}",
+                expression,
+                after
+            );
+        }
+    }
+
+    #[test]
+    fn parse_rejects() {
+        for expression in [
+            "0 0 * *",
+            "60 * * * *",
+            "0 0 0 * *",
+            "0 0 * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert!(Schedule::parse(expression).is_err(), "{}", expression);
+        }
+    }
+}
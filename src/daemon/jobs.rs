@@ -0,0 +1,337 @@
+//! `synchronization_jobs` and `sync_run_history`, in the layout of
+//! `SynchronizationJobModel` and `SyncRunHistoryModel`
+//! (`specs/old-001-i-need-to/contracts/database-schema.sql`).
+//!
+//! Only `scheduled_sync` jobs in status `scheduled`, `completed` or `failed`
+//! are run. The schedule is read from `schedule_config`, either a JSON string
+//! or an object with `cron` (or `schedule`) and the optional
+//! `jitter_seconds` and `max_runtime_seconds`. Due-ness lives in
+//! `next_run_at`, so a restarted daemon picks up where the last one stopped.
+
+use std::hash::{BuildHasher, RandomState};
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use postgres::Client;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::cron::Schedule;
+use crate::engine::describe;
+
+const CREATE_SQL: &str = "CREATE TABLE IF NOT EXISTS synchronization_jobs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    job_name VARCHAR(100) NOT NULL UNIQUE,
+    job_type VARCHAR(50) NOT NULL,
+    schedule_config JSONB DEFAULT '{}',
+    entities_to_sync JSONB NOT NULL DEFAULT '[]',
+    sync_direction VARCHAR(20) NOT NULL DEFAULT 'source_to_target',
+    conflict_resolution VARCHAR(20) NOT NULL DEFAULT 'source_wins',
+    max_records_per_batch INTEGER NOT NULL DEFAULT 50000,
+    status VARCHAR(20) NOT NULL,
+    last_run_at TIMESTAMPTZ,
+    next_run_at TIMESTAMPTZ,
+    total_records_synced INTEGER NOT NULL DEFAULT 0,
+    success_rate DECIMAL(5,2) DEFAULT 0.00,
+    average_duration_ms INTEGER DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    metadata JSONB DEFAULT '{}'
+);
+CREATE TABLE IF NOT EXISTS sync_run_history (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    job_id UUID NOT NULL REFERENCES synchronization_jobs(id) ON DELETE CASCADE,
+    run_type VARCHAR(20) NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+    records_synced INTEGER NOT NULL DEFAULT 0,
+    records_failed INTEGER NOT NULL DEFAULT 0,
+    status VARCHAR(20) NOT NULL,
+    error_summary TEXT,
+    performance_metrics JSONB DEFAULT '{}',
+    entities_processed JSONB DEFAULT '[]',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+)";
+
+/// Jobs the daemon runs.
+const ELIGIBLE: &str =
+    "job_type = 'scheduled_sync' AND status IN ('scheduled', 'completed', 'failed')";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: String,
+    pub name: String,
+    pub entities: Vec<String>,
+    pub batch_size: usize,
+    #[serde(skip)]
+    pub schedule: Schedule,
+    pub jitter_seconds: u64,
+    pub max_runtime_seconds: Option<u64>,
+}
+
+/// Daemon-wide settings a job's `schedule_config` can override.
+#[derive(Debug, Clone, Copy)]
+pub struct Defaults {
+    pub jitter_seconds: u64,
+    pub max_runtime_seconds: Option<u64>,
+}
+
+pub fn ensure_tables(control: &mut Client) -> Result<(), String> {
+    control
+        .batch_execute(CREATE_SQL)
+        .map_err(|e| format!("cannot create synchronization_jobs: {}", describe(&e)))
+}
+
+/// Close the runs a previous leader left open and make their jobs due
+/// again; returns the number of runs closed.
+pub fn recover(control: &mut Client) -> Result<u64, String> {
+    let failed = |e: postgres::Error| format!("cannot recover interrupted runs: {}", describe(&e));
+    let mut tx = control.transaction().map_err(failed)?;
+    let closed = tx
+        .execute(
+            "UPDATE sync_run_history
+             SET status = 'cancelled', completed_at = NOW(),
+                 error_summary = 'the daemon stopped before the run finished'
+             WHERE status = 'running'",
+            &[],
+        )
+        .map_err(failed)?;
+    tx.execute(
+        "UPDATE synchronization_jobs SET status = 'scheduled', updated_at = NOW()
+         WHERE job_type = 'scheduled_sync' AND status = 'running'",
+        &[],
+    )
+    .map_err(failed)?;
+    tx.commit().map_err(failed)?;
+    Ok(closed)
+}
+
+/// Give every eligible job without a `next_run_at` its first run time.
+/// Returns the jobs whose schedule cannot be used, with the reason.
+pub fn schedule_new(
+    control: &mut Client,
+    defaults: Defaults,
+) -> Result<Vec<(String, String)>, String> {
+    let rows = control
+        .query(
+            &format!(
+                "SELECT id::text, job_name, schedule_config::text, entities_to_sync::text,
+                        max_records_per_batch
+                 FROM synchronization_jobs
+                 WHERE {} AND next_run_at IS NULL",
+                ELIGIBLE
+            ),
+            &[],
+        )
+        .map_err(|e| format!("cannot read synchronization_jobs: {}", describe(&e)))?;
+    let mut invalid = Vec::new();
+    for row in &rows {
+        match job(row, defaults) {
+            Ok(job) => set_next_run(control, &job, Utc::now())?,
+            Err(e) => invalid.push((row.get(1), e)),
+        }
+    }
+    Ok(invalid)
+}
+
+/// Eligible jobs whose `next_run_at` has passed, oldest first.
+pub fn due(control: &mut Client, defaults: Defaults) -> Result<Vec<Job>, String> {
+    let rows = control
+        .query(
+            &format!(
+                "SELECT id::text, job_name, schedule_config::text, entities_to_sync::text,
+                        max_records_per_batch
+                 FROM synchronization_jobs
+                 WHERE {} AND next_run_at <= NOW()
+                 ORDER BY next_run_at",
+                ELIGIBLE
+            ),
+            &[],
+        )
+        .map_err(|e| format!("cannot read synchronization_jobs: {}", describe(&e)))?;
+    Ok(rows
+        .iter()
+        .filter_map(|row| job(row, defaults).ok())
+        .collect())
+}
+
+/// Seconds until the next eligible job is due, if any is scheduled.
+pub fn seconds_until_next(control: &mut Client) -> Result<Option<f64>, String> {
+    let row = control
+        .query_one(
+            &format!(
+                "SELECT EXTRACT(EPOCH FROM min(next_run_at) - NOW())::float8
+                 FROM synchronization_jobs WHERE {}",
+                ELIGIBLE
+            ),
+            &[],
+        )
+        .map_err(|e| format!("cannot read synchronization_jobs: {}", describe(&e)))?;
+    Ok(row.get(0))
+}
+
+fn job(row: &postgres::Row, defaults: Defaults) -> Result<Job, String> {
+    let config: Option<String> = row.get(2);
+    let config: Value = config
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or(Value::Null);
+    let expression = match &config {
+        Value::String(s) => Some(s.as_str()),
+        Value::Object(o) => o
+            .get("cron")
+            .or_else(|| o.get("schedule"))
+            .and_then(Value::as_str),
+        _ => None,
+    }
+    .ok_or("`schedule_config` has no `cron` expression")?;
+    let seconds = |key: &str| config.get(key).and_then(Value::as_u64);
+    let entities: String = row.get(3);
+    let entities: Vec<String> = serde_json::from_str(&entities)
+        .map_err(|_| "`entities_to_sync` is not a list of entity names".to_string())?;
+    if entities.is_empty() {
+        return Err("`entities_to_sync` is empty".into());
+    }
+    let batch_size: i32 = row.get(4);
+    Ok(Job {
+        id: row.get(0),
+        name: row.get(1),
+        entities,
+        batch_size: batch_size.max(1) as usize,
+        schedule: Schedule::parse(expression)?,
+        jitter_seconds: seconds("jitter_seconds").unwrap_or(defaults.jitter_seconds),
+        max_runtime_seconds: seconds("max_runtime_seconds").or(defaults.max_runtime_seconds),
+    })
+}
+
+/// The next run after `after`, delayed by up to the job's jitter so that
+/// jobs on the same schedule do not all hit the source at once.
+pub fn next_run(job: &Job, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let next = job.schedule.next_after(after)?;
+    let jitter = match job.jitter_seconds {
+        0 => 0,
+        max => RandomState::new().hash_one(&job.id) % (max + 1),
+    };
+    Some(next + Duration::seconds(jitter as i64))
+}
+
+fn set_next_run(control: &mut Client, job: &Job, after: DateTime<Utc>) -> Result<(), String> {
+    let next = next_run(job, after).map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true));
+    control
+        .execute(
+            "UPDATE synchronization_jobs
+             SET next_run_at = $2::text::timestamptz, updated_at = NOW()
+             WHERE id = $1::text::uuid",
+            &[&job.id, &next],
+        )
+        .map_err(|e| format!("cannot schedule job {}: {}", job.name, describe(&e)))?;
+    Ok(())
+}
+
+/// Mark `job` running and open its `sync_run_history` row; returns the
+/// row's ID.
+pub fn start_run(control: &mut Client, job: &Job) -> Result<String, String> {
+    let failed = |e: postgres::Error| format!("cannot start job {}: {}", job.name, describe(&e));
+    let entities = serde_json::to_string(&job.entities).expect("names serialize");
+    let mut tx = control.transaction().map_err(failed)?;
+    tx.execute(
+        "UPDATE synchronization_jobs SET status = 'running', updated_at = NOW()
+         WHERE id = $1::text::uuid",
+        &[&job.id],
+    )
+    .map_err(failed)?;
+    let row = tx
+        .query_one(
+            "INSERT INTO sync_run_history (job_id, run_type, status, entities_processed)
+             VALUES ($1::text::uuid, 'scheduled', 'running', $2::text::jsonb)
+             RETURNING id::text",
+            &[&job.id, &entities],
+        )
+        .map_err(failed)?;
+    tx.commit().map_err(failed)?;
+    Ok(row.get(0))
+}
+
+/// How a run ended, for its history row and its job.
+pub struct Finished<'a> {
+    /// `completed`, `failed` or `cancelled`.
+    pub status: &'a str,
+    pub records_synced: u64,
+    pub records_failed: u64,
+    pub error_summary: Option<String>,
+    pub performance_metrics: Value,
+    pub started_at: DateTime<Utc>,
+    /// Interrupted by shutdown: the job stays due for the next leader.
+    pub interrupted: bool,
+}
+
+pub fn finish_run(
+    control: &mut Client,
+    job: &Job,
+    run_id: &str,
+    finished: &Finished<'_>,
+) -> Result<(), String> {
+    let failed = |e: postgres::Error| format!("cannot record job {}: {}", job.name, describe(&e));
+    let synced = finished.records_synced.min(i32::MAX as u64) as i32;
+    let rejected = finished.records_failed.min(i32::MAX as u64) as i32;
+    let metrics = finished.performance_metrics.to_string();
+    let job_status = match finished.status {
+        "completed" => "completed",
+        _ if finished.interrupted => "scheduled",
+        _ => "failed",
+    };
+    let started = finished
+        .started_at
+        .to_rfc3339_opts(SecondsFormat::Micros, true);
+    let next = if finished.interrupted {
+        None
+    } else {
+        next_run(job, Utc::now()).map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true))
+    };
+    let mut tx = control.transaction().map_err(failed)?;
+    tx.execute(
+        "UPDATE sync_run_history
+         SET status = $2, completed_at = NOW(), records_synced = $3, records_failed = $4,
+             error_summary = $5, performance_metrics = $6::text::jsonb
+         WHERE id = $1::text::uuid",
+        &[
+            &run_id,
+            &finished.status,
+            &synced,
+            &rejected,
+            &finished.error_summary,
+            &metrics,
+        ],
+    )
+    .map_err(failed)?;
+    tx.execute(
+        "UPDATE synchronization_jobs SET
+           status = $2,
+           last_run_at = CASE WHEN $4 THEN last_run_at ELSE $3::text::timestamptz END,
+           next_run_at = CASE WHEN $4 THEN next_run_at ELSE $5::text::timestamptz END,
+           total_records_synced = total_records_synced + $6,
+           success_rate = COALESCE((
+             SELECT round(100.0 * count(*) FILTER (WHERE status = 'completed') / count(*), 2)
+             FROM sync_run_history WHERE job_id = $1::text::uuid AND status <> 'running'
+             HAVING count(*) > 0), 0),
+           average_duration_ms = COALESCE((
+             SELECT round(avg(EXTRACT(EPOCH FROM completed_at - started_at) * 1000))::integer
+             FROM sync_run_history WHERE job_id = $1::text::uuid AND completed_at IS NOT NULL), 0),
+           updated_at = NOW()
+         WHERE id = $1::text::uuid",
+        &[
+            &job.id,
+            &job_status,
+            &started,
+            &finished.interrupted,
+            &next,
+            &synced,
+        ],
+    )
+    .map_err(failed)?;
+    tx.commit().map_err(failed)?;
+    Ok(())
+}
+
+/// Now, for log lines.
+pub fn timestamp() -> String {
+    Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
@@ -0,0 +1,285 @@
+//! Scheduled differential syncs, replacing `SyncSchedulerService`.
+//!
+//! Any number of daemons may run against one target; the one holding the
+//! session-level advisory lock on the target leads and the others stand by
+//! until the lock is released with its connection. The leader runs the due
+//! `synchronization_jobs` (see [`jobs`]) one at a time. For each entity of a
+//! job it detects the changed rows in hash mode, runs them through the
+//! executor, and applies the spec's `deletes` policy when it has one.
+//!
+//! SIGTERM and SIGINT set a flag the executor checks after every batch, so the
+//! batch in progress is committed, the run is recorded as cancelled and the
+//! job stays due for the next leader. A job's maximum runtime stops it the
+//! same way. Detection itself is not interrupted.
+
+pub mod cron;
+pub mod jobs;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use postgres::Client;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::differential::{self, DetectOptions, DetectionMode, tombstone};
+use crate::engine::{self, LoadMode, RunOptions, Side, describe};
+use crate::mapping::MappingStore;
+use crate::spec::{DeleteMode, EntitySpec};
+
+use jobs::{Finished, Job};
+
+static STOP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    STOP.store(true, Ordering::SeqCst);
+    // A second signal terminates at once.
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+    }
+}
+
+/// Route SIGTERM and SIGINT to the returned flag.
+pub fn install_signal_handlers() -> &'static AtomicBool {
+    let handler = on_signal as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+    &STOP
+}
+
+/// Connect to the target and try to take the leader lock; None when another
+/// daemon holds it. The lock lives as long as the returned connection.
+pub fn try_lead(target_url: Option<&str>, lock_name: &str) -> Result<Option<Client>, String> {
+    let mut control = engine::connect(Side::Target, target_url)?;
+    let locked: bool = control
+        .query_one("SELECT pg_try_advisory_lock(hashtext($1))", &[&lock_name])
+        .map_err(|e| format!("cannot take the leader lock: {}", describe(&e)))?
+        .get(0);
+    Ok(locked.then_some(control))
+}
+
+/// Whether the leader's connection, and so its lock, is still alive.
+pub fn still_leading(control: &mut Client) -> bool {
+    control.is_valid(Duration::from_secs(10)).is_ok()
+}
+
+/// One entity of a job run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EntitySync {
+    pub entity: String,
+    pub new: usize,
+    pub modified: usize,
+    pub deleted: usize,
+    pub upserted: u64,
+    pub failed: u64,
+    /// Deleted rows the `deletes` policy was applied to.
+    pub tombstoned: usize,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobRun {
+    pub job: String,
+    pub run_id: String,
+    pub status: String,
+    pub entities: Vec<EntitySync>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// `shutdown` or `max_runtime` when the run was stopped early.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stopped_by: Option<&'static str>,
+    pub duration_ms: u64,
+}
+
+/// What every job run needs besides the job.
+pub struct Runner<'a> {
+    /// Every spec, in dependency order, as `spec::load_all` returns them.
+    pub specs: &'a [EntitySpec],
+    pub source_url: Option<&'a str>,
+    pub target_url: Option<&'a str>,
+    pub stop: &'static AtomicBool,
+}
+
+impl Runner<'_> {
+    /// Run `job` and record it; `progress` is called after every entity.
+    pub fn run_job(
+        &self,
+        control: &mut Client,
+        job: &Job,
+        mut progress: impl FnMut(&EntitySync),
+    ) -> Result<JobRun, String> {
+        let stop = self.stop;
+        let started_at = Utc::now();
+        let started = Instant::now();
+        let deadline = job
+            .max_runtime_seconds
+            .map(|s| started + Duration::from_secs(s));
+        let run_id = jobs::start_run(control, job)?;
+
+        let mut entities = Vec::new();
+        let result = self.sync(job, deadline, &mut entities, &mut progress);
+        let stopped_by = if stop.load(Ordering::SeqCst) {
+            Some("shutdown")
+        } else if deadline.is_some_and(|d| Instant::now() >= d) {
+            Some("max_runtime")
+        } else {
+            None
+        };
+        let duration_ms = started.elapsed().as_millis() as u64;
+        let synced: u64 = entities
+            .iter()
+            .map(|e| e.upserted + e.tombstoned as u64)
+            .sum();
+        let rejected: u64 = entities.iter().map(|e| e.failed).sum();
+        let status = match (&result, stopped_by) {
+            (Err(_), _) => "failed",
+            (Ok(()), Some(_)) => "cancelled",
+            (Ok(()), None) if rejected > 0 => "failed",
+            (Ok(()), None) => "completed",
+        };
+        let error = match (&result, stopped_by) {
+            (Err(e), _) => Some(e.clone()),
+            (Ok(()), Some("shutdown")) => Some("stopped by shutdown".to_string()),
+            (Ok(()), Some(_)) => Some(format!(
+                "stopped after exceeding the maximum runtime of {}s",
+                job.max_runtime_seconds.unwrap_or_default()
+            )),
+            (Ok(()), None) if rejected > 0 => {
+                Some(format!("{} row(s) rejected by the target", rejected))
+            }
+            (Ok(()), None) => None,
+        };
+        let seconds = duration_ms as f64 / 1000.0;
+        jobs::finish_run(
+            control,
+            job,
+            &run_id,
+            &Finished {
+                status,
+                records_synced: synced,
+                records_failed: rejected,
+                error_summary: error.clone(),
+                performance_metrics: json!({
+                    "duration_ms": duration_ms,
+                    "records_per_second": if seconds > 0.0 { (synced as f64 / seconds).round() } else { 0.0 },
+                    "entities": entities,
+                    "stopped_by": stopped_by,
+                }),
+                started_at,
+                interrupted: stopped_by == Some("shutdown"),
+            },
+        )?;
+        Ok(JobRun {
+            job: job.name.clone(),
+            run_id,
+            status: status.to_string(),
+            entities,
+            error,
+            stopped_by,
+            duration_ms,
+        })
+    }
+
+    fn sync(
+        &self,
+        job: &Job,
+        deadline: Option<Instant>,
+        entities: &mut Vec<EntitySync>,
+        progress: &mut impl FnMut(&EntitySync),
+    ) -> Result<(), String> {
+        let (specs, stop) = (self.specs, self.stop);
+        if let Some(unknown) = job
+            .entities
+            .iter()
+            .find(|name| !specs.iter().any(|s| &s.name == *name))
+        {
+            return Err(format!("no entity spec named '{}'", unknown));
+        }
+        let stopping =
+            || stop.load(Ordering::SeqCst) || deadline.is_some_and(|d| Instant::now() >= d);
+        let mut source = engine::connect(Side::Source, self.source_url)?;
+        let mut target = engine::connect(Side::Target, self.target_url)?;
+        let mut mappings = MappingStore::new();
+        // `load_all` returns the specs in dependency order.
+        for spec in specs.iter().filter(|s| job.entities.contains(&s.name)) {
+            if stopping() {
+                break;
+            }
+            let started = Instant::now();
+            let analysis = differential::detect(
+                spec,
+                &mut source,
+                &mut target,
+                &mut mappings,
+                &DetectOptions {
+                    mode: DetectionMode::Hash,
+                    timestamp_column: "updated_at".into(),
+                    fetch_size: 10_000,
+                },
+            )
+            .map_err(|e| e.to_string())?;
+            let mut entity = EntitySync {
+                entity: spec.name.clone(),
+                new: analysis.new_records.len(),
+                modified: analysis.modified_records.len(),
+                deleted: analysis.deleted_records.len(),
+                ..EntitySync::default()
+            };
+            let mut keys: Vec<i64> = analysis
+                .new_records
+                .iter()
+                .chain(&analysis.modified_records)
+                .copied()
+                .collect();
+            keys.sort_unstable();
+            let migration_batch = engine::default_migration_batch(spec);
+            if !keys.is_empty() && !stopping() {
+                let options = RunOptions {
+                    batch_size: job.batch_size,
+                    load: LoadMode::Upsert,
+                    limit: None,
+                    dry_run: false,
+                    migration_batch: migration_batch.clone(),
+                    resume_after: None,
+                    only: Some(keys),
+                    stop: Some(stop),
+                    deadline,
+                };
+                let stats = engine::run(
+                    spec,
+                    &mut source,
+                    &mut target,
+                    &mut mappings,
+                    &options,
+                    |_| {},
+                )
+                .map_err(|e| e.to_string())?;
+                entity.upserted = stats.upserted;
+                entity.failed = stats.failed;
+            }
+            if let Some(policy) = &spec.deletes
+                && policy.policy != DeleteMode::Report
+                && !analysis.deleted_records.is_empty()
+                && !stopping()
+            {
+                let result = tombstone::apply(
+                    &mut target,
+                    specs,
+                    spec,
+                    policy,
+                    &analysis.deleted_records,
+                    false,
+                    &migration_batch,
+                )?;
+                entity.tombstoned = result.applied.len();
+            }
+            entity.duration_ms = started.elapsed().as_millis() as u64;
+            progress(&entity);
+            entities.push(entity);
+        }
+        Ok(())
+    }
+}
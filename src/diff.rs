@@ -0,0 +1,78 @@
+//! Unified diffs for previewing overwrites.
+
+use std::env;
+use std::io::{self, IsTerminal};
+
+use similar::{ChangeTag, TextDiff};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone)]
+pub struct FileDiff {
+    pub unified: String,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl FileDiff {
+    pub fn is_empty(&self) -> bool {
+        self.insertions == 0 && self.deletions == 0
+    }
+}
+
+pub fn unified(old: &str, new: &str, old_label: &str, new_label: &str) -> FileDiff {
+    let diff = TextDiff::from_lines(old, new);
+    let (mut insertions, mut deletions) = (0, 0);
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => insertions += 1,
+            ChangeTag::Delete => deletions += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+    let unified = diff
+        .unified_diff()
+        .context_radius(3)
+        .header(old_label, new_label)
+        .to_string();
+    FileDiff {
+        unified,
+        insertions,
+        deletions,
+    }
+}
+
+/// Whether stdout should receive ANSI colors (a terminal, and `NO_COLOR` unset).
+pub fn use_color() -> bool {
+    env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal()
+}
+
+pub fn colorize(unified: &str) -> String {
+    let mut out = String::with_capacity(unified.len() + unified.len() / 4);
+    for line in unified.split_inclusive('\n') {
+        let color = if line.starts_with("+++") || line.starts_with("---") {
+            BOLD
+        } else if line.starts_with("@@") {
+            CYAN
+        } else if line.starts_with('+') {
+            GREEN
+        } else if line.starts_with('-') {
+            RED
+        } else {
+            out.push_str(line);
+            continue;
+        };
+        let body = line.trim_end_matches('\n');
+        out.push_str(color);
+        out.push_str(body);
+        out.push_str(RESET);
+        if body.len() < line.len() {
+            out.push('\n');
+        }
+    }
+    out
+}
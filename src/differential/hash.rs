@@ -0,0 +1,189 @@
+//! Canonical row content and its hash.
+//!
+//! Source values are compared after the spec's transforms, target values as
+//! stored, so both sides are brought to one text form per target column type
+//! before hashing: `0.0800` and `0.08` are the same numeric, `t` and `true`
+//! the same boolean, and JSON is compared with sorted keys. Timestamps are
+//! rendered by the databases themselves (see [`timestamp_sql`]).
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Fixed text form of a timestamp, UTC for `timestamptz`.
+const TIMESTAMP_FORMAT: &str = "YYYY-MM-DD HH24:MI:SS.US";
+
+/// Wrap `expr` so timestamp-like target columns read the same on both sides;
+/// other types are read as text.
+pub fn timestamp_sql(expr: &str, target_type: &str) -> String {
+    if target_type.starts_with("timestamp with time zone") {
+        format!(
+            "to_char(({})::timestamptz AT TIME ZONE 'UTC', '{}')",
+            expr, TIMESTAMP_FORMAT
+        )
+    } else if target_type.starts_with("timestamp") {
+        format!("to_char(({})::timestamp, '{}')", expr, TIMESTAMP_FORMAT)
+    } else if target_type == "date" {
+        format!("({})::date::text", expr)
+    } else {
+        format!("({})::text", expr)
+    }
+}
+
+/// The comparable form of `value` for a column of `target_type`.
+pub fn canonical(target_type: &str, value: Option<String>) -> Option<String> {
+    let value = value?;
+    let canonical = match target_type {
+        "smallint" | "integer" | "bigint" | "real" | "double precision" => number(&value),
+        t if t.starts_with("numeric") => number(&value),
+        "boolean" => match value.trim().to_ascii_lowercase().as_str() {
+            "t" | "true" | "y" | "yes" | "on" | "1" => Some("t".to_string()),
+            "f" | "false" | "n" | "no" | "off" | "0" => Some("f".to_string()),
+            _ => None,
+        },
+        "json" | "jsonb" => serde_json::from_str::<Value>(&value)
+            .ok()
+            .map(|v| v.to_string()),
+        "uuid" => Some(value.trim().to_ascii_lowercase()),
+        t if t.starts_with("character(") => Some(value.trim_end().to_string()),
+        _ => None,
+    };
+    Some(canonical.unwrap_or(value))
+}
+
+/// A decimal without sign noise or trailing fractional zeros.
+fn number(value: &str) -> Option<String> {
+    let v = value.trim();
+    let (negative, digits) = match v.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, v.strip_prefix('+').unwrap_or(v)),
+    };
+    if !digits.chars().any(|c| c.is_ascii_digit())
+        || !digits.chars().all(|c| c.is_ascii_digit() || c == '.')
+    {
+        return None;
+    }
+    let (int, frac) = digits.split_once('.').unwrap_or((digits, ""));
+    if frac.contains('.') {
+        return None;
+    }
+    let int = int.trim_start_matches('0');
+    let frac = frac.trim_end_matches('0');
+    let int = if int.is_empty() { "0" } else { int };
+    let mut out = String::with_capacity(digits.len() + 1);
+    if negative && (int != "0" || !frac.is_empty()) {
+        out.push('-');
+    }
+    out.push_str(int);
+    if !frac.is_empty() {
+        out.push('.');
+        out.push_str(frac);
+    }
+    Some(out)
+}
+
+/// SHA-256 over length-prefixed values; NULL and the empty string differ.
+pub fn row_hash(values: &[Option<String>]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for value in values {
+        match value {
+            None => hasher.update([0u8]),
+            Some(v) => {
+                hasher.update([1u8]);
+                hasher.update((v.len() as u64).to_le_bytes());
+                hasher.update(v.as_bytes());
+            }
+        }
+    }
+    hasher.finalize().into()
+}
+
+pub fn hex(hash: &[u8]) -> String {
+    hash.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// SQL counterpart of [`canonical`] for a text expression, for checksums
+/// computed inside the databases. It only has to agree with itself on both
+/// sides: a value it renders differently from [`canonical`] costs a row
+/// comparison, never a missed change.
+pub fn canonical_sql(expr: &str, target_type: &str) -> String {
+    match target_type {
+        "smallint" | "integer" | "bigint" | "real" | "double precision" => {
+            format!("trim_scale(({})::numeric)::text", expr)
+        }
+        t if t.starts_with("numeric") => format!("trim_scale(({})::numeric)::text", expr),
+        "boolean" => format!("({})::boolean::text", expr),
+        "json" | "jsonb" => format!("({})::jsonb::text", expr),
+        "uuid" => format!("lower({})", expr),
+        t if t.starts_with("character(") => format!("rtrim({})", expr),
+        _ => expr.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn number() {
+        let cases = [
+            ("0.0800", Some("0.08")),
+            ("080", Some("80")),
+            ("+5", Some("5")),
+            (" 42 ", Some("42")),
+            ("-0.50", Some("-0.5")),
+            ("-0.000", Some("0")),
+            ("-0", Some("0")),
+            (".5", Some("0.5")),
+            ("5.", Some("5")),
+            ("0", Some("0")),
+            ("", None),
+            ("-", None),
+            (".", None),
+            ("1.2.3", None),
+            ("1e5", None),
+            ("--1", None),
+            ("NaN", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(super::number(value).as_deref(), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn canonical() {
+        let cases = [
+            ("numeric(6,4)", Some("0.0800"), Some("0.08")),
+            ("numeric", Some("-0.0"), Some("0")),
+            ("integer", Some("007"), Some("7")),
+            ("double precision", Some("2.50"), Some("2.5")),
+            // Values a type cannot read are compared as they are.
+            ("numeric", Some("NaN"), Some("NaN")),
+            ("boolean", Some(" Yes"), Some("t")),
+            ("boolean", Some("OFF"), Some("f")),
+            ("boolean", Some("maybe"), Some("maybe")),
+            (
+                "jsonb",
+                Some(r#"{"b": 1, "a": [1, 2]}"#),
+                Some(r#"{"a":[1,2],"b":1}"#),
+            ),
+            ("json", Some("{not json"), Some("{not json")),
+            (
+                "uuid",
+                Some(" 0F8FAD5B-D9CB-469F-A165-70867728950E"),
+                Some("0f8fad5b-d9cb-469f-a165-70867728950e"),
+            ),
+            ("character(5)", Some("ab   "), Some("ab")),
+            ("character varying(5)", Some("ab   "), Some("ab   ")),
+            ("text", Some(" 0.10 "), Some(" 0.10 ")),
+            ("text", Some(""), Some("")),
+            ("integer", None, None),
+        ];
+        for (target_type, value, expected) in cases {
+            assert_eq!(
+                super::canonical(target_type, value.map(String::from)).as_deref(),
+                expected,
+                "{} {:?}",
+                target_type,
+                value
+            );
+        }
+    }
+}
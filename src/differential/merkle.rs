@@ -0,0 +1,482 @@
+//! Reconciliation through hierarchical range checksums.
+//!
+//! The legacy-ID span of an entity is cut into `fanout` ranges and both
+//! databases, queried side by side, sum a 64-bit row hash
+//! (`hashtextextended`, stable across Postgres versions since 11) per range,
+//! so only one count and one checksum per range travel back. Ranges whose
+//! checksums agree are done; the others are cut again, until a range holds
+//! at most `leaf_rows` rows. Those are read back and compared row by row
+//! exactly like `differential` does, which makes the result exact up to
+//! checksum collisions: a range whose differing rows happen to sum to the
+//! same checksum is taken as equal.
+//!
+//! Row hashes are taken over the mapped columns in their canonical text form
+//! (see [`hash::canonical_sql`]), with the spec's transforms and defaults
+//! applied to the source in SQL. Lookups are compared as legacy IDs: the
+//! target's UUIDs are mapped back through `migration_mappings`. A range
+//! whose checksum query fails, for instance on a value that does not cast to
+//! the target type, is simply cut further.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::thread;
+use std::time::Instant;
+
+use postgres::{Client, IsolationLevel, Transaction};
+use sha2::{Digest, Sha256};
+
+use super::{Analysis, DetectionMode, Metadata, compare_range, hash, last_migration};
+use crate::engine::{self, EngineError, default_text, describe};
+use crate::mapping::MappingStore;
+use crate::spec::{ColumnMap, EntitySpec, Transform, literal};
+
+#[derive(Debug, Clone, Copy)]
+pub struct MerkleOptions {
+    /// Sub-ranges per range.
+    pub fanout: u32,
+    /// Ranges with at most this many rows on either side are compared row
+    /// by row.
+    pub leaf_rows: u64,
+}
+
+/// `(row count, checksum)` per bucket of a range.
+type Buckets = BTreeMap<i64, (i64, String)>;
+
+/// How one side of the comparison is read.
+struct Side {
+    table: String,
+    /// `FROM … WHERE …` without the key range.
+    from: String,
+    key: String,
+    row: String,
+}
+
+impl Side {
+    fn source(spec: &EntitySpec, types: &HashMap<String, String>) -> Self {
+        let key = format!("s.{}", spec.source_key);
+        let mut values = vec![format!("{}::bigint", key)];
+        values.extend(spec.columns.iter().map(|c| {
+            let ty = &types[&c.target];
+            let value = hash::timestamp_sql(&format!("s.{}", c.source), ty);
+            hash::canonical_sql(&transform_sql(&value, c), ty)
+        }));
+        values.extend(
+            spec.lookups
+                .iter()
+                .map(|l| format!("s.{}::bigint", l.source)),
+        );
+        let mut from = format!("FROM {} s WHERE {} IS NOT NULL", spec.source_table, key);
+        if let Some(filter) = &spec.filter {
+            from.push_str(&format!(" AND ({})", filter));
+        }
+        Side {
+            table: spec.source_table.clone(),
+            from,
+            key,
+            row: values.join(", "),
+        }
+    }
+
+    fn target(spec: &EntitySpec, types: &HashMap<String, String>, has_mappings: bool) -> Self {
+        let key = format!("t.{}", spec.legacy_id_column);
+        let mut values = vec![format!("{}::bigint", key)];
+        values.extend(spec.columns.iter().map(|c| {
+            let ty = &types[&c.target];
+            hash::canonical_sql(&hash::timestamp_sql(&format!("t.{}", c.target), ty), ty)
+        }));
+        let mut from = format!("FROM {} t", spec.target_table);
+        for (i, lookup) in spec.lookups.iter().enumerate() {
+            if has_mappings {
+                values.push(format!("m{}.legacy_id::bigint", i));
+                from.push_str(&format!(
+                    " LEFT JOIN migration_mappings m{i} ON m{i}.entity_type = {} \
+                     AND m{i}.new_id::text = t.{}::text",
+                    literal(&lookup.entity),
+                    lookup.target
+                ));
+            } else {
+                values.push("NULL::bigint".to_string());
+            }
+        }
+        from.push_str(&format!(" WHERE {} IS NOT NULL", key));
+        Side {
+            table: spec.target_table.clone(),
+            from,
+            key,
+            row: values.join(", "),
+        }
+    }
+
+    /// `(min key, max key, distinct keys)`.
+    fn bounds(&self, tx: &mut Transaction<'_>) -> Result<(Option<i64>, Option<i64>, i64), String> {
+        let row = tx
+            .query_one(
+                &format!(
+                    "SELECT min({k})::bigint, max({k})::bigint, count(DISTINCT {k}) {}",
+                    self.from,
+                    k = self.key
+                ),
+                &[],
+            )
+            .map_err(|e| format!("cannot read {}: {}", self.table, describe(&e)))?;
+        Ok((row.get(0), row.get(1), row.get(2)))
+    }
+
+    /// Bucket checksums of `low..=high`, or `None` if the query failed.
+    fn buckets(&self, tx: &mut Transaction<'_>, low: i64, high: i64, step: i64) -> Option<Buckets> {
+        let sql = format!(
+            "SELECT div({k}::numeric - $1::bigint, $3::bigint)::bigint, count(*),
+                    sum(hashtextextended(ROW({row})::text, 0)::numeric)::text
+             {from} AND {k} BETWEEN $1::bigint AND $2::bigint
+             GROUP BY 1",
+            k = self.key,
+            row = self.row,
+            from = self.from
+        );
+        let mut savepoint = tx.transaction().ok()?;
+        let rows = savepoint.query(&sql, &[&low, &high, &step]).ok()?;
+        savepoint.commit().ok()?;
+        Some(
+            rows.iter()
+                .map(|r| (r.get(0), (r.get(1), r.get(2))))
+                .collect(),
+        )
+    }
+}
+
+/// Reconcile `spec`'s source and target rows; the result reads like a
+/// `differential` analysis in hash mode.
+pub fn reconcile(
+    spec: &EntitySpec,
+    source: &mut Client,
+    target: &mut Client,
+    mappings: &mut MappingStore,
+    options: &MerkleOptions,
+) -> Result<Analysis, EngineError> {
+    let started = Instant::now();
+    let fail = |message: String| EngineError {
+        entity: spec.name.clone(),
+        message,
+    };
+    let types = engine::target_types(target, spec).map_err(fail)?;
+    let mut referenced: Vec<&str> = spec.lookups.iter().map(|l| l.entity.as_str()).collect();
+    referenced.sort_unstable();
+    referenced.dedup();
+    mappings.preload(target, &referenced).map_err(fail)?;
+    let last_migration = last_migration(target, spec).map_err(fail)?;
+    let has_mappings: bool = target
+        .query_one("SELECT to_regclass('migration_mappings') IS NOT NULL", &[])
+        .map_err(|e| {
+            fail(format!(
+                "cannot inspect migration_mappings: {}",
+                describe(&e)
+            ))
+        })?
+        .get(0);
+
+    let left = Side::source(spec, &types);
+    let right = Side::target(spec, &types, has_mappings);
+    let mut source_tx = snapshot(source, &spec.source_table).map_err(fail)?;
+    let mut target_tx = snapshot(target, &spec.target_table).map_err(fail)?;
+    let (source_min, source_max, source_count) = left.bounds(&mut source_tx).map_err(fail)?;
+    let (target_min, target_max, target_count) = right.bounds(&mut target_tx).map_err(fail)?;
+
+    let mut analysis = Analysis {
+        entity_type: spec.name.clone(),
+        analysis_timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        source_record_count: source_count as u64,
+        destination_record_count: target_count as u64,
+        new_records: Vec::new(),
+        modified_records: Vec::new(),
+        deleted_records: Vec::new(),
+        last_migration_timestamp: last_migration,
+        analysis_metadata: Metadata {
+            detection_method: DetectionMode::Merkle,
+            source_table: spec.source_table.clone(),
+            destination_table: spec.target_table.clone(),
+            unchanged_records: 0,
+            duplicate_destination_keys: 0,
+            timestamp_column: None,
+            source_digest: None,
+            destination_digest: None,
+            ranges_compared: Some(0),
+            rows_compared: Some(0),
+            duration_ms: 0,
+        },
+    };
+    let low = source_min.into_iter().chain(target_min).min();
+    let high = source_max.into_iter().chain(target_max).max();
+    let fanout = i128::from(options.fanout.max(2));
+    let leaf_rows = options.leaf_rows.max(1);
+
+    let mut pending = VecDeque::new();
+    if let (Some(low), Some(high)) = (low, high) {
+        pending.push_back((low, high));
+    }
+    let mut root = true;
+    while let Some((low, high)) = pending.pop_front() {
+        let width = i128::from(high) - i128::from(low) + 1;
+        if width <= i128::from(leaf_rows) {
+            compare(
+                spec,
+                &types,
+                mappings,
+                &mut source_tx,
+                &mut target_tx,
+                (low, high),
+                &mut analysis,
+            )
+            .map_err(fail)?;
+            continue;
+        }
+        let step = ((width + fanout - 1) / fanout) as i64;
+        let sides = thread::scope(|scope| {
+            let source = scope.spawn(|| left.buckets(&mut source_tx, low, high, step));
+            let target = right.buckets(&mut target_tx, low, high, step);
+            (source.join().expect("checksum thread panicked"), target)
+        });
+        if root {
+            if let (Some(s), Some(t)) = &sides {
+                analysis.analysis_metadata.source_digest = Some(digest(s));
+                analysis.analysis_metadata.destination_digest = Some(digest(t));
+            }
+            root = false;
+        }
+        let buckets = ((width + i128::from(step) - 1) / i128::from(step)) as i64;
+        for bucket in 0..buckets {
+            let child_low = low + bucket * step;
+            let child_high =
+                (i128::from(child_low) + i128::from(step) - 1).min(i128::from(high)) as i64;
+            let rows = match &sides {
+                (Some(s), Some(t)) => {
+                    if let Some(compared) = analysis.analysis_metadata.ranges_compared.as_mut() {
+                        *compared += 1;
+                    }
+                    match (s.get(&bucket), t.get(&bucket)) {
+                        (None, None) => continue,
+                        (Some(a), Some(b)) if a == b => continue,
+                        (a, b) => Some(a.map_or(0, |a| a.0).max(b.map_or(0, |b| b.0)) as u64),
+                    }
+                }
+                _ => None,
+            };
+            if rows.is_some_and(|rows| rows <= leaf_rows) {
+                compare(
+                    spec,
+                    &types,
+                    mappings,
+                    &mut source_tx,
+                    &mut target_tx,
+                    (child_low, child_high),
+                    &mut analysis,
+                )
+                .map_err(fail)?;
+            } else {
+                pending.push_back((child_low, child_high));
+            }
+        }
+    }
+    source_tx.commit().map_err(|e| {
+        fail(format!(
+            "cannot read {}: {}",
+            spec.source_table,
+            describe(&e)
+        ))
+    })?;
+    target_tx.commit().map_err(|e| {
+        fail(format!(
+            "cannot read {}: {}",
+            spec.target_table,
+            describe(&e)
+        ))
+    })?;
+
+    analysis.new_records.sort_unstable();
+    analysis.modified_records.sort_unstable();
+    analysis.deleted_records.sort_unstable();
+    analysis.analysis_metadata.unchanged_records = analysis
+        .source_record_count
+        .saturating_sub((analysis.new_records.len() + analysis.modified_records.len()) as u64);
+    analysis.analysis_metadata.duration_ms = started.elapsed().as_millis() as u64;
+    Ok(analysis)
+}
+
+fn snapshot<'a>(client: &'a mut Client, table: &str) -> Result<Transaction<'a>, String> {
+    client
+        .build_transaction()
+        .isolation_level(IsolationLevel::RepeatableRead)
+        .read_only(true)
+        .start()
+        .map_err(|e| format!("cannot read {}: {}", table, describe(&e)))
+}
+
+fn compare(
+    spec: &EntitySpec,
+    types: &HashMap<String, String>,
+    mappings: &MappingStore,
+    source: &mut Transaction<'_>,
+    target: &mut Transaction<'_>,
+    range: (i64, i64),
+    analysis: &mut Analysis,
+) -> Result<(), String> {
+    let rows = compare_range(spec, types, mappings, source, target, range, analysis)?;
+    if let Some(compared) = analysis.analysis_metadata.rows_compared.as_mut() {
+        *compared += rows;
+    }
+    Ok(())
+}
+
+/// The spec's transform and default in SQL; see `engine::column_value`.
+fn transform_sql(expr: &str, column: &ColumnMap) -> String {
+    let value = match column.transform {
+        None => expr.to_string(),
+        Some(Transform::Trim) => format!("btrim({}, E' \\t\\n\\r')", expr),
+        Some(Transform::Lowercase) => format!("lower({})", expr),
+        Some(Transform::Uppercase) => format!("upper({})", expr),
+        Some(Transform::NullIfEmpty) => format!("NULLIF({}, '')", expr),
+        Some(Transform::Json) => format!(
+            "CASE WHEN {e} ~ '^\\s*([[{{\"]|-?[0-9]|(true|false|null)\\s*$)' THEN {e} \
+             ELSE to_jsonb({e})::text END",
+            e = expr
+        ),
+    };
+    match column.default.as_ref().and_then(default_text) {
+        Some(default) => format!("COALESCE({}, {})", value, literal(&default)),
+        None => value,
+    }
+}
+
+/// SHA-256 over a range's bucket counts and checksums.
+fn digest(buckets: &Buckets) -> String {
+    let mut hasher = Sha256::new();
+    for (bucket, (count, sum)) in buckets {
+        hasher.update(format!("{}:{}:{};", bucket, count, sum));
+    }
+    hash::hex(&hasher.finalize())
+}
+
+/// Insert `analysis` into `data_differentials`. The table is created in the
+/// `src/migration-coverage` layout when missing; a table in the layout of
+/// `src/services/data-comparator.ts` gets one row per non-empty class, as
+/// that service writes them. Returns the new rows' IDs.
+pub fn record_differentials(
+    target: &mut Client,
+    analysis: &Analysis,
+) -> Result<Vec<String>, String> {
+    let failed = |e: postgres::Error| format!("cannot write data_differentials: {}", describe(&e));
+    let columns: Vec<String> = target
+        .query(
+            "SELECT column_name::text FROM information_schema.columns
+             WHERE table_schema = current_schema() AND table_name = 'data_differentials'",
+            &[],
+        )
+        .map_err(failed)?
+        .iter()
+        .map(|r| r.get(0))
+        .collect();
+    let ids = |ids: &[i64]| serde_json::json!(ids.iter().map(i64::to_string).collect::<Vec<_>>());
+    let meta = &analysis.analysis_metadata;
+
+    if columns.iter().any(|c| c == "comparison_type") {
+        let mut inserted = Vec::new();
+        for (kind, records, strategy) in [
+            ("missing_records", &analysis.new_records, "source_wins"),
+            (
+                "conflicted_records",
+                &analysis.modified_records,
+                "source_wins",
+            ),
+            (
+                "deleted_records",
+                &analysis.deleted_records,
+                "manual_review",
+            ),
+        ] {
+            if records.is_empty() {
+                continue;
+            }
+            let criteria = serde_json::json!({
+                "entity_type": analysis.entity_type,
+                "detection_method": meta.detection_method,
+            });
+            let metadata = serde_json::json!({
+                "entity_type": analysis.entity_type,
+                "total_source": analysis.source_record_count,
+                "total_target": analysis.destination_record_count,
+            });
+            let row = target
+                .query_one(
+                    "INSERT INTO data_differentials
+                       (source_table, target_table, comparison_type, legacy_ids, record_count,
+                        comparison_criteria, resolution_strategy, resolved, metadata)
+                     VALUES ($1, $2, $3, $4::text::jsonb, $5, $6::text::jsonb, $7, false,
+                             $8::text::jsonb)
+                     RETURNING id::text",
+                    &[
+                        &meta.source_table,
+                        &meta.destination_table,
+                        &kind,
+                        &ids(records).to_string(),
+                        &(records.len().min(i32::MAX as usize) as i32),
+                        &criteria.to_string(),
+                        &strategy,
+                        &metadata.to_string(),
+                    ],
+                )
+                .map_err(failed)?;
+            inserted.push(row.get(0));
+        }
+        return Ok(inserted);
+    }
+
+    if columns.is_empty() {
+        target
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS data_differentials (
+                    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                    source_table VARCHAR(100) NOT NULL,
+                    target_table VARCHAR(100) NOT NULL,
+                    comparison_date TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                    source_count INTEGER NOT NULL,
+                    target_count INTEGER NOT NULL,
+                    missing_in_target INTEGER DEFAULT 0,
+                    extra_in_target INTEGER DEFAULT 0,
+                    data_hash_source VARCHAR(64),
+                    data_hash_target VARCHAR(64),
+                    differences JSONB,
+                    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+                )",
+            )
+            .map_err(failed)?;
+    }
+    let count = |n: u64| n.min(i32::MAX as u64) as i32;
+    let differences = serde_json::json!({
+        "entity_type": analysis.entity_type,
+        "detection_method": meta.detection_method,
+        "missing": ids(&analysis.new_records),
+        "modified": ids(&analysis.modified_records),
+        "extra": ids(&analysis.deleted_records),
+    });
+    let row = target
+        .query_one(
+            "INSERT INTO data_differentials
+               (source_table, target_table, comparison_date, source_count, target_count,
+                missing_in_target, extra_in_target, data_hash_source, data_hash_target,
+                differences)
+             VALUES ($1, $2, $3::text::timestamptz, $4, $5, $6, $7, $8, $9, $10::text::jsonb)
+             RETURNING id::text",
+            &[
+                &meta.source_table,
+                &meta.destination_table,
+                &analysis.analysis_timestamp,
+                &count(analysis.source_record_count),
+                &count(analysis.destination_record_count),
+                &count(analysis.new_records.len() as u64),
+                &count(analysis.deleted_records.len() as u64),
+                &meta.source_digest,
+                &meta.destination_digest,
+                &differences.to_string(),
+            ],
+        )
+        .map_err(failed)?;
+    Ok(vec![row.get(0)])
+}
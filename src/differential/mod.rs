@@ -0,0 +1,657 @@
+//! Differential change detection for one entity spec.
+//!
+//! Source and target are streamed side by side, both ordered by the legacy
+//! ID, through server-side cursors in read-only repeatable-read transactions,
+//! and merge-joined: a key only in the source is new, a key only in the
+//! target is deleted, and a key on both sides is modified or unchanged.
+//! Memory stays bounded by the fetch size plus the lists of changed keys,
+//! however large the tables are.
+//!
+//! Two detection modes decide "modified":
+//!
+//! - `hash` compares a content hash of the mapped columns, taken after the
+//!   spec's transforms on the source side (see [`hash`] for how values are
+//!   made comparable);
+//! - `timestamp` compares a timestamp column the way `DifferentialDetector`
+//!   did: a row is modified when the source copy is newer.
+//!
+//! Results use the layout of `differential_analysis_results`
+//! (`src/differential-migration/sql/001_create_differential_migration_tables.sql`).
+
+pub mod hash;
+pub mod merkle;
+pub mod tombstone;
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use postgres::{Client, IsolationLevel, Row, Transaction};
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+use crate::engine::{self, EngineError, column_value, describe};
+use crate::mapping::MappingStore;
+use crate::spec::EntitySpec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum DetectionMode {
+    Hash,
+    Timestamp,
+    /// Range checksums narrowed down to row hashes; see [`merkle`].
+    #[value(skip)]
+    Merkle,
+}
+
+#[derive(Debug, Clone)]
+pub struct DetectOptions {
+    pub mode: DetectionMode,
+    /// Source column compared in timestamp mode; the target column is the
+    /// one the spec maps it to, else the same name.
+    pub timestamp_column: String,
+    /// Rows per cursor fetch, on each side.
+    pub fetch_size: usize,
+}
+
+/// One `differential_analysis_results` row.
+#[derive(Debug, Clone, Serialize)]
+pub struct Analysis {
+    /// The spec name, which is what the TypeScript tooling keyed results on.
+    pub entity_type: String,
+    pub analysis_timestamp: String,
+    pub source_record_count: u64,
+    pub destination_record_count: u64,
+    #[serde(serialize_with = "ids_as_strings")]
+    pub new_records: Vec<i64>,
+    #[serde(serialize_with = "ids_as_strings")]
+    pub modified_records: Vec<i64>,
+    #[serde(serialize_with = "ids_as_strings")]
+    pub deleted_records: Vec<i64>,
+    /// Newest `migration_mappings.migrated_at` of the entity type.
+    pub last_migration_timestamp: Option<String>,
+    pub analysis_metadata: Metadata,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Metadata {
+    pub detection_method: DetectionMode,
+    pub source_table: String,
+    pub destination_table: String,
+    pub unchanged_records: u64,
+    /// Target rows sharing a legacy ID with the row before them; ignored.
+    pub duplicate_destination_keys: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp_column: Option<String>,
+    /// SHA-256 over every `(legacy ID, row hash)` in key order (hash mode):
+    /// equal digests mean identical content.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_digest: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination_digest: Option<String>,
+    /// Key ranges whose checksums were compared (merkle).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ranges_compared: Option<u64>,
+    /// Rows read back to find the divergent IDs in mismatched ranges
+    /// (merkle).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows_compared: Option<u64>,
+    pub duration_ms: u64,
+}
+
+fn ids_as_strings<S: Serializer>(ids: &[i64], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(ids.iter().map(|id| id.to_string()))
+}
+
+impl Analysis {
+    pub fn unchanged(&self) -> u64 {
+        self.analysis_metadata.unchanged_records
+    }
+}
+
+/// One side of the merge-join, read through a cursor.
+struct Stream<'a> {
+    tx: Transaction<'a>,
+    portal: postgres::Portal,
+    rows: VecDeque<Row>,
+    fetch: i32,
+    done: bool,
+}
+
+impl<'a> Stream<'a> {
+    fn open(client: &'a mut Client, sql: &str, fetch: i32) -> Result<Self, postgres::Error> {
+        let mut tx = client
+            .build_transaction()
+            .isolation_level(IsolationLevel::RepeatableRead)
+            .read_only(true)
+            .start()?;
+        let portal = tx.bind(sql, &[])?;
+        Ok(Stream {
+            tx,
+            portal,
+            rows: VecDeque::new(),
+            fetch,
+            done: false,
+        })
+    }
+
+    fn next(&mut self) -> Result<Option<Row>, postgres::Error> {
+        if self.rows.is_empty() && !self.done {
+            self.rows = self.tx.query_portal(&self.portal, self.fetch)?.into();
+            self.done = self.rows.is_empty();
+        }
+        Ok(self.rows.pop_front())
+    }
+
+    fn close(self) -> Result<(), postgres::Error> {
+        self.tx.commit()
+    }
+}
+
+/// What one side contributes to the comparison.
+struct Keyed {
+    key: i64,
+    hash: Option<[u8; 32]>,
+    timestamp: Option<i64>,
+}
+
+/// Compare `spec`'s source rows with its target rows.
+pub fn detect(
+    spec: &EntitySpec,
+    source: &mut Client,
+    target: &mut Client,
+    mappings: &mut MappingStore,
+    options: &DetectOptions,
+) -> Result<Analysis, EngineError> {
+    let started = Instant::now();
+    let fail = |message: String| EngineError {
+        entity: spec.name.clone(),
+        message,
+    };
+    let types = engine::target_types(target, spec).map_err(fail)?;
+    let timestamp_target = match options.mode {
+        DetectionMode::Hash | DetectionMode::Merkle => None,
+        DetectionMode::Timestamp => {
+            let column = spec
+                .columns
+                .iter()
+                .find(|c| c.source == options.timestamp_column)
+                .map_or(options.timestamp_column.as_str(), |c| c.target.as_str());
+            if !types.contains_key(column) {
+                return Err(fail(format!(
+                    "timestamp mode needs {}.{}; pass --timestamp-column",
+                    spec.target_table, column
+                )));
+            }
+            Some(column.to_string())
+        }
+    };
+    let mut referenced: Vec<&str> = spec.lookups.iter().map(|l| l.entity.as_str()).collect();
+    referenced.sort_unstable();
+    referenced.dedup();
+    if options.mode != DetectionMode::Timestamp {
+        mappings.preload(target, &referenced).map_err(fail)?;
+    }
+    let last_migration = last_migration(target, spec).map_err(fail)?;
+
+    let source_sql = source_sql(spec, &types, options, None);
+    let target_sql = target_sql(spec, &types, timestamp_target.as_deref(), None);
+    let fetch = i32::try_from(options.fetch_size).unwrap_or(i32::MAX);
+    let read_source = |e: postgres::Error| {
+        fail(format!(
+            "cannot read {}: {}",
+            spec.source_table,
+            describe(&e)
+        ))
+    };
+    let read_target = |e: postgres::Error| {
+        fail(format!(
+            "cannot read {}: {}",
+            spec.target_table,
+            describe(&e)
+        ))
+    };
+    let mut left = Stream::open(source, &source_sql, fetch).map_err(read_source)?;
+    let mut right = Stream::open(target, &target_sql, fetch).map_err(read_target)?;
+
+    let mut analysis = Analysis {
+        entity_type: spec.name.clone(),
+        analysis_timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        source_record_count: 0,
+        destination_record_count: 0,
+        new_records: Vec::new(),
+        modified_records: Vec::new(),
+        deleted_records: Vec::new(),
+        last_migration_timestamp: last_migration,
+        analysis_metadata: Metadata {
+            detection_method: options.mode,
+            source_table: spec.source_table.clone(),
+            destination_table: spec.target_table.clone(),
+            unchanged_records: 0,
+            duplicate_destination_keys: 0,
+            timestamp_column: timestamp_target,
+            source_digest: None,
+            destination_digest: None,
+            ranges_compared: None,
+            rows_compared: None,
+            duration_ms: 0,
+        },
+    };
+    let mut source_digest = Sha256::new();
+    let mut target_digest = Sha256::new();
+
+    let mut next_source = |analysis: &mut Analysis| -> Result<Option<Keyed>, EngineError> {
+        let Some(row) = left.next().map_err(read_source)? else {
+            return Ok(None);
+        };
+        analysis.source_record_count += 1;
+        let keyed = source_keyed(spec, &types, mappings, options.mode, &row).map_err(fail)?;
+        if let Some(hash) = &keyed.hash {
+            source_digest.update(keyed.key.to_le_bytes());
+            source_digest.update(hash);
+        }
+        Ok(Some(keyed))
+    };
+    let mut last_target = None;
+    let mut next_target = |analysis: &mut Analysis| -> Result<Option<Keyed>, EngineError> {
+        loop {
+            let Some(row) = right.next().map_err(read_target)? else {
+                return Ok(None);
+            };
+            let keyed = target_keyed(spec, &types, options.mode, &row).map_err(fail)?;
+            if last_target == Some(keyed.key) {
+                analysis.analysis_metadata.duplicate_destination_keys += 1;
+                continue;
+            }
+            last_target = Some(keyed.key);
+            analysis.destination_record_count += 1;
+            if let Some(hash) = &keyed.hash {
+                target_digest.update(keyed.key.to_le_bytes());
+                target_digest.update(hash);
+            }
+            return Ok(Some(keyed));
+        }
+    };
+
+    let mut s = next_source(&mut analysis)?;
+    let mut t = next_target(&mut analysis)?;
+    loop {
+        match (&s, &t) {
+            (None, None) => break,
+            (Some(a), Some(b)) if a.key == b.key => {
+                if modified(options.mode, a, b) {
+                    analysis.modified_records.push(a.key);
+                } else {
+                    analysis.analysis_metadata.unchanged_records += 1;
+                }
+                s = next_source(&mut analysis)?;
+                t = next_target(&mut analysis)?;
+            }
+            (Some(a), Some(b)) if a.key < b.key => {
+                analysis.new_records.push(a.key);
+                s = next_source(&mut analysis)?;
+            }
+            (Some(a), None) => {
+                analysis.new_records.push(a.key);
+                s = next_source(&mut analysis)?;
+            }
+            (_, Some(b)) => {
+                analysis.deleted_records.push(b.key);
+                t = next_target(&mut analysis)?;
+            }
+        }
+    }
+    left.close().map_err(read_source)?;
+    right.close().map_err(read_target)?;
+
+    if options.mode != DetectionMode::Timestamp {
+        analysis.analysis_metadata.source_digest = Some(hash::hex(&source_digest.finalize()));
+        analysis.analysis_metadata.destination_digest = Some(hash::hex(&target_digest.finalize()));
+    }
+    analysis.analysis_metadata.duration_ms = started.elapsed().as_millis() as u64;
+    Ok(analysis)
+}
+
+fn modified(mode: DetectionMode, source: &Keyed, target: &Keyed) -> bool {
+    match mode {
+        DetectionMode::Hash | DetectionMode::Merkle => source.hash != target.hash,
+        DetectionMode::Timestamp => match (source.timestamp, target.timestamp) {
+            (Some(source), Some(target)) => source > target,
+            (Some(_), None) => true,
+            (None, _) => false,
+        },
+    }
+}
+
+/// Compare the rows of one key range in hash mode and add them to
+/// `analysis`'s lists; returns the number of rows read. Used by [`merkle`]
+/// below the level where checksums stop paying off.
+fn compare_range(
+    spec: &EntitySpec,
+    types: &HashMap<String, String>,
+    mappings: &MappingStore,
+    source: &mut Transaction<'_>,
+    target: &mut Transaction<'_>,
+    range: (i64, i64),
+    analysis: &mut Analysis,
+) -> Result<u64, String> {
+    let options = DetectOptions {
+        mode: DetectionMode::Hash,
+        timestamp_column: String::new(),
+        fetch_size: 0,
+    };
+    let left = source
+        .query(&source_sql(spec, types, &options, Some(range)), &[])
+        .map_err(|e| format!("cannot read {}: {}", spec.source_table, describe(&e)))?
+        .iter()
+        .map(|row| source_keyed(spec, types, mappings, DetectionMode::Hash, row))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut right = target
+        .query(&target_sql(spec, types, None, Some(range)), &[])
+        .map_err(|e| format!("cannot read {}: {}", spec.target_table, describe(&e)))?
+        .iter()
+        .map(|row| target_keyed(spec, types, DetectionMode::Hash, row))
+        .collect::<Result<Vec<_>, _>>()?;
+    let read = (left.len() + right.len()) as u64;
+    let before = right.len();
+    right.dedup_by_key(|k| k.key);
+    analysis.analysis_metadata.duplicate_destination_keys += (before - right.len()) as u64;
+
+    let (mut i, mut j) = (0, 0);
+    loop {
+        match (left.get(i), right.get(j)) {
+            (None, None) => break,
+            (Some(a), Some(b)) if a.key == b.key => {
+                if modified(DetectionMode::Hash, a, b) {
+                    analysis.modified_records.push(a.key);
+                }
+                i += 1;
+                j += 1;
+            }
+            (Some(a), Some(b)) if a.key < b.key => {
+                analysis.new_records.push(a.key);
+                i += 1;
+            }
+            (Some(a), None) => {
+                analysis.new_records.push(a.key);
+                i += 1;
+            }
+            (_, Some(b)) => {
+                analysis.deleted_records.push(b.key);
+                j += 1;
+            }
+        }
+    }
+    Ok(read)
+}
+
+/// The new and modified keys of a JSON analysis written by `differential` or
+/// `reconcile` for `entity`, for `run --changes`.
+pub fn load_changes(path: &Path, entity: &str) -> Result<Vec<i64>, String> {
+    load_keys(path, entity, &["new_records", "modified_records"])
+}
+
+/// The deleted keys of a JSON analysis, for `tombstones --changes`.
+pub fn load_deletions(path: &Path, entity: &str) -> Result<Vec<i64>, String> {
+    load_keys(path, entity, &["deleted_records"])
+}
+
+fn load_keys(path: &Path, entity: &str, fields: &[&str]) -> Result<Vec<i64>, String> {
+    let invalid = |message: String| format!("{}: {}", path.display(), message);
+    let text = fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+    let value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+    match value.get("entity_type").and_then(|v| v.as_str()) {
+        Some(name) if name == entity => {}
+        Some(name) => {
+            return Err(invalid(format!(
+                "the analysis is for '{}', not '{}'",
+                name, entity
+            )));
+        }
+        None => return Err(invalid("not a differential analysis".into())),
+    }
+    let mut keys = Vec::new();
+    for field in fields {
+        let ids = value
+            .get(field)
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| invalid(format!("`{}` is missing", field)))?;
+        for id in ids {
+            let key = match id {
+                serde_json::Value::String(s) => s.parse().ok(),
+                other => other.as_i64(),
+            };
+            keys.push(key.ok_or_else(|| invalid(format!("invalid ID {} in `{}`", id, field)))?);
+        }
+    }
+    keys.sort_unstable();
+    keys.dedup();
+    Ok(keys)
+}
+
+/// Insert `analysis` into `differential_analysis_results`, creating the
+/// table if the target has none; returns the new row's ID.
+pub fn record(target: &mut Client, analysis: &Analysis) -> Result<String, String> {
+    let failed = |e: postgres::Error| {
+        format!(
+            "cannot write differential_analysis_results: {}",
+            describe(&e)
+        )
+    };
+    target
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS differential_analysis_results (
+                id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                entity_type VARCHAR(100) NOT NULL,
+                analysis_timestamp TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+                source_record_count INTEGER NOT NULL DEFAULT 0,
+                destination_record_count INTEGER NOT NULL DEFAULT 0,
+                new_records JSONB NOT NULL DEFAULT '[]',
+                modified_records JSONB NOT NULL DEFAULT '[]',
+                deleted_records JSONB NOT NULL DEFAULT '[]',
+                last_migration_timestamp TIMESTAMP WITH TIME ZONE,
+                analysis_metadata JSONB NOT NULL DEFAULT '{}',
+                created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+                updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+            )",
+        )
+        .map_err(failed)?;
+    let ids = |ids: &[i64]| {
+        serde_json::to_string(&ids.iter().map(i64::to_string).collect::<Vec<_>>())
+            .expect("strings serialize")
+    };
+    let metadata = serde_json::to_string(&analysis.analysis_metadata).expect("metadata serializes");
+    let row = target
+        .query_one(
+            "INSERT INTO differential_analysis_results
+               (entity_type, analysis_timestamp, source_record_count, destination_record_count,
+                new_records, modified_records, deleted_records, last_migration_timestamp,
+                analysis_metadata)
+             VALUES ($1, $2::text::timestamptz, $3, $4, $5::text::jsonb, $6::text::jsonb,
+                     $7::text::jsonb, $8::text::timestamptz, $9::text::jsonb)
+             RETURNING id::text",
+            &[
+                &analysis.entity_type,
+                &analysis.analysis_timestamp,
+                &(analysis.source_record_count.min(i32::MAX as u64) as i32),
+                &(analysis.destination_record_count.min(i32::MAX as u64) as i32),
+                &ids(&analysis.new_records),
+                &ids(&analysis.modified_records),
+                &ids(&analysis.deleted_records),
+                &analysis.last_migration_timestamp,
+                &metadata,
+            ],
+        )
+        .map_err(failed)?;
+    Ok(row.get(0))
+}
+
+/// Microseconds since the epoch, comparable across both databases.
+fn epoch_sql(expr: &str) -> String {
+    format!(
+        "(extract(epoch from ({})::timestamptz) * 1000000)::bigint",
+        expr
+    )
+}
+
+fn source_sql(
+    spec: &EntitySpec,
+    types: &HashMap<String, String>,
+    options: &DetectOptions,
+    range: Option<(i64, i64)>,
+) -> String {
+    let mut select = vec![format!("s.{}::bigint", spec.source_key)];
+    if options.mode != DetectionMode::Timestamp {
+        select.extend(
+            spec.columns
+                .iter()
+                .map(|c| hash::timestamp_sql(&format!("s.{}", c.source), &types[&c.target])),
+        );
+        select.extend(
+            spec.lookups
+                .iter()
+                .map(|l| format!("s.{}::bigint", l.source)),
+        );
+    } else {
+        select.push(epoch_sql(&format!("s.{}", options.timestamp_column)));
+    }
+    let mut sql = format!("SELECT {}\nFROM {} s", select.join(", "), spec.source_table);
+    let mut conditions = Vec::new();
+    if let Some(filter) = &spec.filter {
+        conditions.push(format!("({})", filter));
+    }
+    if let Some((low, high)) = range {
+        conditions.push(format!(
+            "s.{} BETWEEN {} AND {}",
+            spec.source_key, low, high
+        ));
+    }
+    if !conditions.is_empty() {
+        sql.push_str(&format!("\nWHERE {}", conditions.join(" AND ")));
+    }
+    sql.push_str("\nORDER BY 1");
+    sql
+}
+
+fn target_sql(
+    spec: &EntitySpec,
+    types: &HashMap<String, String>,
+    timestamp: Option<&str>,
+    range: Option<(i64, i64)>,
+) -> String {
+    let legacy = format!("t.{}", spec.legacy_id_column);
+    let mut select = vec![format!("{}::bigint", legacy)];
+    match timestamp {
+        None => select.extend(
+            crate::scaffold::target_columns(spec)
+                .into_iter()
+                .skip(1)
+                .map(|c| hash::timestamp_sql(&format!("t.{}", c), &types[c])),
+        ),
+        Some(column) => select.push(epoch_sql(&format!("t.{}", column))),
+    }
+    let condition = match range {
+        Some((low, high)) => format!("{} BETWEEN {} AND {}", legacy, low, high),
+        None => format!("{} IS NOT NULL", legacy),
+    };
+    format!(
+        "SELECT {}\nFROM {} t\nWHERE {}\nORDER BY 1",
+        select.join(", "),
+        spec.target_table,
+        condition
+    )
+}
+
+fn source_keyed(
+    spec: &EntitySpec,
+    types: &HashMap<String, String>,
+    mappings: &MappingStore,
+    mode: DetectionMode,
+    row: &Row,
+) -> Result<Keyed, String> {
+    let bad = |e: postgres::Error| format!("unexpected value in {}: {}", spec.source_table, e);
+    let key: Option<i64> = row.try_get(0).map_err(bad)?;
+    let key = key.ok_or_else(|| {
+        format!(
+            "{}.{} is NULL; the source key must be set on every row",
+            spec.source_table, spec.source_key
+        )
+    })?;
+    if mode == DetectionMode::Timestamp {
+        return Ok(Keyed {
+            key,
+            hash: None,
+            timestamp: row.try_get(1).map_err(bad)?,
+        });
+    }
+    let mut values = Vec::with_capacity(spec.columns.len() + spec.lookups.len());
+    for (i, column) in spec.columns.iter().enumerate() {
+        let raw: Option<String> = row.try_get(1 + i).map_err(bad)?;
+        values.push(hash::canonical(
+            &types[&column.target],
+            column_value(column, raw),
+        ));
+    }
+    let offset = 1 + spec.columns.len();
+    for (i, lookup) in spec.lookups.iter().enumerate() {
+        let legacy: Option<i64> = row.try_get(offset + i).map_err(bad)?;
+        let resolved = legacy
+            .and_then(|id| mappings.get(&lookup.entity, id))
+            .map(|id| id.to_string());
+        values.push(hash::canonical(&types[&lookup.target], resolved));
+    }
+    Ok(Keyed {
+        key,
+        hash: Some(hash::row_hash(&values)),
+        timestamp: None,
+    })
+}
+
+fn target_keyed(
+    spec: &EntitySpec,
+    types: &HashMap<String, String>,
+    mode: DetectionMode,
+    row: &Row,
+) -> Result<Keyed, String> {
+    let bad = |e: postgres::Error| format!("unexpected value in {}: {}", spec.target_table, e);
+    let key: i64 = row.try_get(0).map_err(bad)?;
+    if mode == DetectionMode::Timestamp {
+        return Ok(Keyed {
+            key,
+            hash: None,
+            timestamp: row.try_get(1).map_err(bad)?,
+        });
+    }
+    let columns = crate::scaffold::target_columns(spec);
+    let mut values = Vec::with_capacity(columns.len() - 1);
+    for (i, column) in columns.iter().skip(1).enumerate() {
+        let raw: Option<String> = row.try_get(1 + i).map_err(bad)?;
+        values.push(hash::canonical(&types[*column], raw));
+    }
+    Ok(Keyed {
+        key,
+        hash: Some(hash::row_hash(&values)),
+        timestamp: None,
+    })
+}
+
+fn last_migration(target: &mut Client, spec: &EntitySpec) -> Result<Option<String>, String> {
+    let failed = |e: postgres::Error| format!("cannot read migration_mappings: {}", describe(&e));
+    let exists: bool = target
+        .query_one("SELECT to_regclass('migration_mappings') IS NOT NULL", &[])
+        .map_err(failed)?
+        .get(0);
+    if !exists {
+        return Ok(None);
+    }
+    let row = target
+        .query_one(
+            "SELECT max(migrated_at)::timestamptz::text FROM migration_mappings
+             WHERE entity_type = $1",
+            &[&spec.entity_type()],
+        )
+        .map_err(failed)?;
+    Ok(row.get(0))
+}
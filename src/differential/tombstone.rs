@@ -0,0 +1,459 @@
+//! Target rows whose legacy row was deleted.
+//!
+//! `ConflictResolverService.resolveDeletedRecords` deleted such rows outright
+//! or left them alone. Here the spec's `deletes` policy decides: delete the
+//! row, mark it through `deleted_at`/`is_active`, move it to an archive
+//! table, or only report it. Before anything is removed, the dependency graph
+//! is walked from the tombstoned rows through the specs' FK lookups, level by
+//! level, to find every target row that references them directly or
+//! transitively. Rows that something still references are never removed;
+//! they are reported as blocked and left for a soft delete or a manual
+//! cleanup.
+//!
+//! Every decision is appended to `migration_tombstones` in the transaction
+//! that applies it; a preview rolls back instead.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use postgres::types::ToSql;
+use postgres::{Client, Transaction};
+use serde::Serialize;
+
+use crate::engine::describe;
+use crate::plan::{Graph, Reason};
+use crate::spec::{DeleteMode, DeletePolicy, EntitySpec};
+
+/// Dependent rows listed in the result; the counts are always exact.
+pub const MAX_DEPENDENTS: usize = 1000;
+
+const LOG_SQL: &str = "CREATE TABLE IF NOT EXISTS migration_tombstones (
+    id BIGSERIAL PRIMARY KEY,
+    entity TEXT NOT NULL,
+    target_table TEXT NOT NULL,
+    legacy_id BIGINT NOT NULL,
+    target_key TEXT,
+    policy TEXT NOT NULL,
+    action TEXT NOT NULL,
+    migration_batch TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+)";
+
+/// A target row that references a tombstoned row, directly or through other
+/// dependents.
+#[derive(Debug, Clone, Serialize)]
+pub struct Dependent {
+    pub entity: String,
+    pub target_table: String,
+    /// Column holding the referenced row's key.
+    pub column: String,
+    /// 1 for a reference to a tombstoned row, 2 for a reference to such a
+    /// dependent, and so on.
+    pub depth: u32,
+    pub legacy_id: Option<i64>,
+    pub key: String,
+    pub references: String,
+}
+
+/// Dependent rows per referencing column and depth.
+#[derive(Debug, Clone, Serialize)]
+pub struct DependentGroup {
+    pub depth: u32,
+    pub entity: String,
+    pub target_table: String,
+    pub column: String,
+    pub rows: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Tombstones {
+    pub entity: String,
+    pub target_table: String,
+    pub policy: DeleteMode,
+    pub preview: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_table: Option<String>,
+    /// Legacy rows reported deleted by the source.
+    pub deleted_records: usize,
+    /// Of those, IDs the target has no row for.
+    pub absent: usize,
+    /// Rows a soft delete had already marked.
+    pub already_marked: Vec<i64>,
+    /// Rows the policy was (or, in a preview, would be) applied to.
+    pub applied: Vec<i64>,
+    /// Rows kept because other target rows reference them.
+    pub blocked: Vec<i64>,
+    /// Legacy IDs more than one target row claims; none of those rows is
+    /// touched, since which of them the legacy row became is unknown.
+    pub duplicated: Vec<i64>,
+    pub dependent_rows: u64,
+    pub dependent_groups: Vec<DependentGroup>,
+    /// The first [`MAX_DEPENDENTS`] dependent rows.
+    pub dependents: Vec<Dependent>,
+}
+
+/// Apply `policy` to the target rows of `deleted`, or with `preview` only
+/// work out what it would do.
+pub fn apply(
+    target: &mut Client,
+    specs: &[EntitySpec],
+    spec: &EntitySpec,
+    policy: &DeletePolicy,
+    deleted: &[i64],
+    preview: bool,
+    migration_batch: &str,
+) -> Result<Tombstones, String> {
+    let mode = policy.policy;
+    if mode == DeleteMode::Soft && policy.deleted_at.is_none() && policy.is_active.is_none() {
+        return Err(format!(
+            "{}: a `soft` delete policy needs `deletes.deleted_at`, `deletes.is_active` or both",
+            spec.name
+        ));
+    }
+    let graph = Graph::build(specs, None)?;
+    let failed = |e: postgres::Error| {
+        format!(
+            "cannot tombstone {} rows: {}",
+            spec.target_table,
+            describe(&e)
+        )
+    };
+    let mut tx = target.transaction().map_err(failed)?;
+
+    let mut marked = Vec::new();
+    if mode == DeleteMode::Soft {
+        if let Some(column) = &policy.deleted_at {
+            marked.push(format!("t.{} IS NOT NULL", column));
+        }
+        if let Some(column) = &policy.is_active {
+            marked.push(format!("t.{} IS FALSE", column));
+        }
+    } else {
+        marked.push("false".to_string());
+    }
+    let rows = tx
+        .query(
+            &format!(
+                "SELECT t.{legacy}::bigint, t.{key}::text, {marked}
+                 FROM {table} t
+                 WHERE t.{legacy} = ANY($1::bigint[])
+                 ORDER BY 1
+                 FOR UPDATE",
+                legacy = spec.legacy_id_column,
+                key = spec.target_key,
+                marked = marked.join(" AND "),
+                table = spec.target_table,
+            ),
+            &[&deleted],
+        )
+        .map_err(failed)?;
+
+    let mut result = Tombstones {
+        entity: spec.name.clone(),
+        target_table: spec.target_table.clone(),
+        policy: mode,
+        preview,
+        archive_table: (mode == DeleteMode::Archive).then(|| policy.archive_table(spec)),
+        deleted_records: deleted.len(),
+        absent: 0,
+        already_marked: Vec::new(),
+        applied: Vec::new(),
+        blocked: Vec::new(),
+        duplicated: Vec::new(),
+        dependent_rows: 0,
+        dependent_groups: Vec::new(),
+        dependents: Vec::new(),
+    };
+    let mut claims: BTreeMap<i64, usize> = BTreeMap::new();
+    for row in &rows {
+        *claims.entry(row.get(0)).or_default() += 1;
+    }
+    let wanted: BTreeSet<i64> = deleted.iter().copied().collect();
+    result.absent = wanted.len() - claims.len();
+    result.duplicated = claims
+        .iter()
+        .filter(|(_, rows)| **rows > 1)
+        .map(|(legacy_id, _)| *legacy_id)
+        .collect();
+    let mut candidates: Vec<(i64, String)> = Vec::new();
+    let mut duplicates: Vec<(i64, String)> = Vec::new();
+    for row in &rows {
+        let legacy_id: i64 = row.get(0);
+        if claims[&legacy_id] > 1 {
+            duplicates.push((legacy_id, row.get(1)));
+        } else if row.get::<_, bool>(2) {
+            result.already_marked.push(legacy_id);
+        } else {
+            candidates.push((legacy_id, row.get(1)));
+        }
+    }
+
+    let keys: Vec<String> = candidates.iter().map(|(_, key)| key.clone()).collect();
+    let referenced = walk(&mut tx, specs, &graph, spec, &keys, &mut result).map_err(failed)?;
+    for (legacy_id, key) in &candidates {
+        if mode.removes() && referenced.contains(key) {
+            result.blocked.push(*legacy_id);
+        } else {
+            result.applied.push(*legacy_id);
+        }
+    }
+
+    if preview {
+        tx.rollback().map_err(failed)?;
+        return Ok(result);
+    }
+    if mode != DeleteMode::Report {
+        execute(&mut tx, spec, policy, &result.applied, migration_batch).map_err(failed)?;
+        tx.batch_execute(LOG_SQL).map_err(failed)?;
+        let action = match mode {
+            DeleteMode::Hard => "deleted",
+            DeleteMode::Soft => "soft_deleted",
+            DeleteMode::Archive => "archived",
+            DeleteMode::Report => unreachable!(),
+        };
+        let mut legacy_ids = Vec::new();
+        let mut target_keys = Vec::new();
+        let mut actions = Vec::new();
+        for (legacy_id, key) in &candidates {
+            legacy_ids.push(*legacy_id);
+            target_keys.push(key.as_str());
+            actions.push(if result.blocked.contains(legacy_id) {
+                "blocked"
+            } else {
+                action
+            });
+        }
+        for (legacy_id, key) in &duplicates {
+            legacy_ids.push(*legacy_id);
+            target_keys.push(key.as_str());
+            actions.push("duplicate");
+        }
+        tx.execute(
+            "INSERT INTO migration_tombstones
+               (entity, target_table, legacy_id, target_key, policy, action, migration_batch)
+             SELECT $1, $2, l.legacy_id, l.target_key, $3, l.action, $4
+             FROM UNNEST($5::bigint[], $6::text[], $7::text[]) AS l(legacy_id, target_key, action)",
+            &[
+                &spec.name,
+                &spec.target_table,
+                &mode.as_str(),
+                &migration_batch,
+                &legacy_ids,
+                &target_keys,
+                &actions,
+            ],
+        )
+        .map_err(failed)?;
+    }
+    tx.commit().map_err(failed)?;
+    Ok(result)
+}
+
+/// Walk the rows that reference `keys` of `spec`, depth by depth, into
+/// `result`; returns the keys of `spec` referenced at depth 1.
+fn walk(
+    tx: &mut Transaction<'_>,
+    specs: &[EntitySpec],
+    graph: &Graph,
+    spec: &EntitySpec,
+    keys: &[String],
+    result: &mut Tombstones,
+) -> Result<BTreeSet<String>, postgres::Error> {
+    let mut referenced = BTreeSet::new();
+    let mut groups: BTreeMap<(u32, String, String), (String, u64)> = BTreeMap::new();
+    let mut seen: HashSet<(String, String)> = keys
+        .iter()
+        .map(|key| (spec.name.clone(), key.clone()))
+        .collect();
+    let mut frontier: Vec<(&EntitySpec, Vec<String>)> = vec![(spec, keys.to_vec())];
+    let mut depth = 0;
+    while !frontier.is_empty() {
+        depth += 1;
+        let mut next: BTreeMap<&str, (&EntitySpec, Vec<String>)> = BTreeMap::new();
+        for (parent, keys) in &frontier {
+            if keys.is_empty() {
+                continue;
+            }
+            for (child, column) in referencing(specs, graph, &parent.name) {
+                let Some(ty) = column_type(tx, &child.target_table, column)? else {
+                    continue;
+                };
+                let rows = tx.query(
+                    &format!(
+                        "SELECT d.{key}::text, d.{legacy}::bigint, d.{column}::text
+                         FROM {table} d
+                         WHERE d.{column} = ANY($1::text[]::{ty}[])",
+                        key = child.target_key,
+                        legacy = child.legacy_id_column,
+                        column = column,
+                        table = child.target_table,
+                        ty = ty,
+                    ),
+                    &[keys],
+                )?;
+                for row in rows {
+                    let key: String = row.get(0);
+                    let references: String = row.get(2);
+                    if depth == 1 {
+                        referenced.insert(references.clone());
+                    }
+                    if !seen.insert((child.name.clone(), key.clone())) {
+                        continue;
+                    }
+                    result.dependent_rows += 1;
+                    groups
+                        .entry((depth, child.name.clone(), column.to_string()))
+                        .or_insert_with(|| (child.target_table.clone(), 0))
+                        .1 += 1;
+                    if result.dependents.len() < MAX_DEPENDENTS {
+                        result.dependents.push(Dependent {
+                            entity: child.name.clone(),
+                            target_table: child.target_table.clone(),
+                            column: column.to_string(),
+                            depth,
+                            legacy_id: row.get(1),
+                            key: key.clone(),
+                            references,
+                        });
+                    }
+                    next.entry(child.name.as_str())
+                        .or_insert_with(|| (child, Vec::new()))
+                        .1
+                        .push(key);
+                }
+            }
+        }
+        frontier = next.into_values().collect();
+    }
+    result.dependent_groups = groups
+        .into_iter()
+        .map(
+            |((depth, entity, column), (target_table, rows))| DependentGroup {
+                depth,
+                entity,
+                target_table,
+                column,
+                rows,
+            },
+        )
+        .collect();
+    Ok(referenced)
+}
+
+/// The specs that reference entity `name` through an FK lookup, with the
+/// target column holding the reference.
+fn referencing<'a>(
+    specs: &'a [EntitySpec],
+    graph: &Graph,
+    name: &str,
+) -> Vec<(&'a EntitySpec, &'a str)> {
+    let mut found = Vec::new();
+    for (node, reasons) in graph.dependents(name) {
+        let Some(child) = specs.iter().find(|s| s.name == node.name) else {
+            continue;
+        };
+        for reason in reasons {
+            if let Reason::Lookup { column, .. } = reason
+                && let Some(lookup) = child.lookups.iter().find(|l| &l.source == column)
+            {
+                found.push((child, lookup.target.as_str()));
+            }
+        }
+    }
+    found
+}
+
+/// The type of `table.column`, or None when the target lacks either.
+fn column_type(
+    tx: &mut Transaction<'_>,
+    table: &str,
+    column: &str,
+) -> Result<Option<String>, postgres::Error> {
+    Ok(tx
+        .query_opt(
+            "SELECT format_type(a.atttypid, a.atttypmod)
+             FROM pg_attribute a
+             WHERE a.attrelid = to_regclass($1) AND a.attname = $2 AND NOT a.attisdropped",
+            &[&table, &column],
+        )?
+        .map(|row| row.get(0)))
+}
+
+fn execute(
+    tx: &mut Transaction<'_>,
+    spec: &EntitySpec,
+    policy: &DeletePolicy,
+    legacy_ids: &[i64],
+    migration_batch: &str,
+) -> Result<(), postgres::Error> {
+    if legacy_ids.is_empty() {
+        return Ok(());
+    }
+    let table = &spec.target_table;
+    let legacy = &spec.legacy_id_column;
+    match policy.policy {
+        DeleteMode::Hard => {
+            tx.execute(
+                &format!("DELETE FROM {} WHERE {} = ANY($1::bigint[])", table, legacy),
+                &[&legacy_ids],
+            )?;
+        }
+        DeleteMode::Soft => {
+            let mut assignments = Vec::new();
+            if let Some(column) = &policy.deleted_at {
+                assignments.push(format!("{0} = COALESCE({0}, NOW())", column));
+            }
+            if let Some(column) = &policy.is_active {
+                assignments.push(format!("{} = false", column));
+            }
+            tx.execute(
+                &format!(
+                    "UPDATE {} SET {} WHERE {} = ANY($1::bigint[])",
+                    table,
+                    assignments.join(", "),
+                    legacy
+                ),
+                &[&legacy_ids],
+            )?;
+        }
+        DeleteMode::Archive => {
+            let archive = policy.archive_table(spec);
+            tx.batch_execute(&format!(
+                "CREATE TABLE IF NOT EXISTS {archive} (LIKE {table});
+                 ALTER TABLE {archive}
+                   ADD COLUMN IF NOT EXISTS archived_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                   ADD COLUMN IF NOT EXISTS archive_batch TEXT",
+                archive = archive,
+                table = table
+            ))?;
+            let columns: Vec<String> = tx
+                .query(
+                    "SELECT quote_ident(a.attname::text)
+                     FROM pg_attribute a
+                     WHERE a.attrelid = to_regclass($1) AND a.attnum > 0 AND NOT a.attisdropped
+                     ORDER BY a.attnum",
+                    &[table],
+                )?
+                .iter()
+                .map(|row| row.get(0))
+                .collect();
+            let columns = columns.join(", ");
+            let params: [&(dyn ToSql + Sync); 2] = [&legacy_ids, &migration_batch];
+            tx.execute(
+                &format!(
+                    "WITH moved AS (
+                       DELETE FROM {table} WHERE {legacy} = ANY($1::bigint[]) RETURNING {columns}
+                     )
+                     INSERT INTO {archive} ({columns}, archive_batch)
+                     SELECT {columns}, $2 FROM moved",
+                    table = table,
+                    legacy = legacy,
+                    columns = columns,
+                    archive = archive,
+                ),
+                &params,
+            )?;
+        }
+        DeleteMode::Report => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,309 @@
+//! Resumable executor progress.
+//!
+//! One row per entity spec records the last source key whose batch has been
+//! committed. The row is advanced inside the batch's own transaction, next to
+//! the data and its `migration_mappings` rows, so a resumed run starts exactly
+//! after the last batch that made it to the target: nothing is written twice
+//! and nothing is lost. Rows the target rejected in a committed batch are not
+//! retried on resume; they are reported by the run that met them.
+//!
+//! The TypeScript checkpoint managers kept their state in
+//! `migration_checkpoints`, in one of two layouts (`src/lib/checkpoint-manager.ts`
+//! and `src/differential-migration/lib/checkpoint-manager.ts`). Both share
+//! `entity_type`, `last_processed_id` and `records_processed`, which is all a
+//! hand-over needs; those rows are read, never written.
+
+use postgres::{Client, Row, Transaction};
+use serde::Serialize;
+
+use super::RunOptions;
+use super::connect::describe;
+use crate::spec::EntitySpec;
+
+pub const TABLE: &str = "migration_executor_checkpoints";
+
+const LEGACY_TABLE: &str = "migration_checkpoints";
+
+const CREATE_SQL: &str = "CREATE TABLE IF NOT EXISTS migration_executor_checkpoints (
+    entity TEXT PRIMARY KEY,
+    entity_type TEXT NOT NULL,
+    source_table TEXT NOT NULL,
+    migration_batch TEXT NOT NULL,
+    last_source_key BIGINT,
+    batches BIGINT NOT NULL DEFAULT 0,
+    records_processed BIGINT NOT NULL DEFAULT 0,
+    status TEXT NOT NULL,
+    error_message TEXT,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+)";
+
+const COLUMNS: &str = "entity, entity_type, source_table, migration_batch, last_source_key,
+       batches, records_processed, status, error_message,
+       to_char(updated_at, 'YYYY-MM-DD HH24:MI:SS')";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Checkpoint {
+    /// Spec name, or the `entity_type` of a legacy row.
+    pub entity: String,
+    pub entity_type: String,
+    pub source_table: Option<String>,
+    pub migration_batch: Option<String>,
+    /// The last source key of the last committed batch.
+    pub last_source_key: Option<i64>,
+    pub batches: Option<i64>,
+    pub records_processed: i64,
+    pub status: Option<String>,
+    pub error_message: Option<String>,
+    pub updated_at: Option<String>,
+    /// `migration_checkpoints.id` for rows written by the TypeScript tools.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub legacy_id: Option<String>,
+}
+
+impl Checkpoint {
+    pub fn is_legacy(&self) -> bool {
+        self.legacy_id.is_some()
+    }
+
+    fn from_row(row: &Row) -> Self {
+        Checkpoint {
+            entity: row.get(0),
+            entity_type: row.get(1),
+            source_table: row.get(2),
+            migration_batch: row.get(3),
+            last_source_key: row.get(4),
+            batches: row.get(5),
+            records_processed: row.get(6),
+            status: row.get(7),
+            error_message: row.get(8),
+            updated_at: row.get(9),
+            legacy_id: None,
+        }
+    }
+}
+
+/// Where a batch leaves the run; written in the batch's transaction.
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub control: Option<i32>,
+    /// Source rows read by this run so far, this batch included.
+    pub processed: u64,
+    /// Source rows in this batch.
+    pub rows: u64,
+    pub last_key: i64,
+}
+
+/// Open or restart the entity's checkpoint before the first batch. A resumed
+/// run keeps its counters; a fresh one starts them over.
+pub fn start(target: &mut Client, spec: &EntitySpec, options: &RunOptions) -> Result<(), String> {
+    let failed = |e: postgres::Error| format!("cannot write {}: {}", TABLE, describe(&e));
+    target.batch_execute(CREATE_SQL).map_err(failed)?;
+    target
+        .execute(
+            "INSERT INTO migration_executor_checkpoints
+               (entity, entity_type, source_table, migration_batch, last_source_key, status)
+             VALUES ($1, $2, $3, $4, $5, 'running')
+             ON CONFLICT (entity) DO UPDATE SET
+               entity_type = EXCLUDED.entity_type,
+               source_table = EXCLUDED.source_table,
+               migration_batch = EXCLUDED.migration_batch,
+               last_source_key = EXCLUDED.last_source_key,
+               batches = CASE WHEN $6 THEN migration_executor_checkpoints.batches ELSE 0 END,
+               records_processed =
+                 CASE WHEN $6 THEN migration_executor_checkpoints.records_processed ELSE 0 END,
+               started_at = CASE WHEN $6 THEN migration_executor_checkpoints.started_at ELSE NOW() END,
+               status = 'running',
+               error_message = NULL,
+               updated_at = NOW()",
+            &[
+                &spec.name,
+                &spec.entity_type(),
+                &spec.source_table,
+                &options.migration_batch,
+                &options.resume_after,
+                &options.resume_after.is_some(),
+            ],
+        )
+        .map_err(failed)?;
+    Ok(())
+}
+
+pub fn advance(
+    tx: &mut Transaction<'_>,
+    spec: &EntitySpec,
+    progress: &Progress,
+) -> Result<(), postgres::Error> {
+    tx.execute(
+        "UPDATE migration_executor_checkpoints
+         SET last_source_key = $2, batches = batches + 1,
+             records_processed = records_processed + $3, updated_at = NOW()
+         WHERE entity = $1",
+        &[&spec.name, &progress.last_key, &(progress.rows as i64)],
+    )?;
+    Ok(())
+}
+
+pub fn finish(
+    target: &mut Client,
+    spec: &EntitySpec,
+    status: &str,
+    error: Option<&String>,
+) -> Result<(), String> {
+    target
+        .execute(
+            "UPDATE migration_executor_checkpoints
+             SET status = $2, error_message = $3, updated_at = NOW()
+             WHERE entity = $1",
+            &[&spec.name, &status, &error],
+        )
+        .map_err(|e| format!("cannot write {}: {}", TABLE, describe(&e)))?;
+    Ok(())
+}
+
+/// The checkpoint `run --resume` continues from: the executor's own, else
+/// the newest legacy row for the spec's name, entity type or tables.
+pub fn find(target: &mut Client, spec: &EntitySpec) -> Result<Option<Checkpoint>, String> {
+    if table_exists(target, TABLE)? {
+        let row = target
+            .query_opt(
+                &format!("SELECT {} FROM {} WHERE entity = $1", COLUMNS, TABLE),
+                &[&spec.name],
+            )
+            .map_err(|e| format!("cannot read {}: {}", TABLE, describe(&e)))?;
+        if let Some(row) = row {
+            return Ok(Some(Checkpoint::from_row(&row)));
+        }
+    }
+    let names = [
+        spec.name.as_str(),
+        spec.entity_type(),
+        spec.target_table.as_str(),
+        spec.source_table.as_str(),
+    ];
+    Ok(legacy(target, Some(&names))?
+        .into_iter()
+        .find(|c| c.last_source_key.is_some()))
+}
+
+/// Every executor checkpoint, then every legacy row.
+pub fn list(target: &mut Client) -> Result<Vec<Checkpoint>, String> {
+    let mut checkpoints = Vec::new();
+    if table_exists(target, TABLE)? {
+        let rows = target
+            .query(
+                &format!("SELECT {} FROM {} ORDER BY entity", COLUMNS, TABLE),
+                &[],
+            )
+            .map_err(|e| format!("cannot read {}: {}", TABLE, describe(&e)))?;
+        checkpoints.extend(rows.iter().map(Checkpoint::from_row));
+    }
+    checkpoints.extend(legacy(target, None)?);
+    Ok(checkpoints)
+}
+
+/// Delete the executor checkpoint of `entity`, and with `legacy` the legacy
+/// rows whose `entity_type` is `entity`. Returns both counts.
+pub fn reset(target: &mut Client, entity: &str, legacy: bool) -> Result<(u64, u64), String> {
+    let failed = |e: postgres::Error| format!("cannot reset checkpoints: {}", describe(&e));
+    let own = if table_exists(target, TABLE)? {
+        target
+            .execute(
+                "DELETE FROM migration_executor_checkpoints WHERE entity = $1",
+                &[&entity],
+            )
+            .map_err(failed)?
+    } else {
+        0
+    };
+    let legacy = if legacy && table_exists(target, LEGACY_TABLE)? {
+        target
+            .execute(
+                "DELETE FROM migration_checkpoints WHERE entity_type = $1",
+                &[&entity],
+            )
+            .map_err(failed)?
+    } else {
+        0
+    };
+    Ok((own, legacy))
+}
+
+/// Rows of `migration_checkpoints`, newest first, in whichever layout the
+/// target has.
+fn legacy(target: &mut Client, names: Option<&[&str]>) -> Result<Vec<Checkpoint>, String> {
+    let failed = |e: postgres::Error| format!("cannot read {}: {}", LEGACY_TABLE, describe(&e));
+    let columns: Vec<String> = target
+        .query(
+            "SELECT column_name::text FROM information_schema.columns
+             WHERE table_schema = current_schema() AND table_name = $1",
+            &[&LEGACY_TABLE],
+        )
+        .map_err(failed)?
+        .iter()
+        .map(|r| r.get(0))
+        .collect();
+    let has = |c: &str| columns.iter().any(|x| x == c);
+    if ![
+        "id",
+        "entity_type",
+        "last_processed_id",
+        "records_processed",
+    ]
+    .iter()
+    .all(|c| has(c))
+    {
+        return Ok(Vec::new());
+    }
+    let optional = |c: &str, expr: &str| {
+        if has(c) {
+            expr.to_string()
+        } else {
+            "NULL::text".to_string()
+        }
+    };
+    let sql = format!(
+        "SELECT id::text, entity_type::text, last_processed_id::text, records_processed::bigint,
+                {}, {}, {}
+         FROM migration_checkpoints
+         WHERE $1::text[] IS NULL OR entity_type = ANY($1)
+         ORDER BY {} DESC NULLS LAST",
+        optional("status", "status::text"),
+        optional("error_message", "error_message::text"),
+        optional("updated_at", "to_char(updated_at, 'YYYY-MM-DD HH24:MI:SS')"),
+        if has("updated_at") {
+            "updated_at"
+        } else {
+            "id"
+        },
+    );
+    let names: Option<Vec<&str>> = names.map(<[&str]>::to_vec);
+    let rows = target.query(&sql, &[&names]).map_err(failed)?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let entity_type: String = row.get(1);
+            let last: Option<String> = row.get(2);
+            Checkpoint {
+                entity: entity_type.clone(),
+                entity_type,
+                source_table: None,
+                migration_batch: None,
+                last_source_key: last.and_then(|id| id.trim().parse().ok()),
+                batches: None,
+                records_processed: row.get(3),
+                status: row.get(4),
+                error_message: row.get(5),
+                updated_at: row.get(6),
+                legacy_id: Some(row.get(0)),
+            }
+        })
+        .collect())
+}
+
+fn table_exists(target: &mut Client, table: &str) -> Result<bool, String> {
+    let row = target
+        .query_one("SELECT to_regclass($1) IS NOT NULL", &[&table])
+        .map_err(|e| format!("cannot inspect {}: {}", table, describe(&e)))?;
+    Ok(row.get(0))
+}
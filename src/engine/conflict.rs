@@ -0,0 +1,815 @@
+//! Conflict policies for rows that already exist in the target.
+//!
+//! Without `conflicts` in the spec the upsert overwrites every mapped column,
+//! which is what `ConflictResolverService.resolveConflictedRecords` did. With
+//! it, the target rows of a batch are read and locked in the batch's
+//! transaction, every column whose value differs from the source is decided
+//! by its policy, and the upsert writes the decided values. Each decision is
+//! appended to `migration_conflict_log`; rows with a `manual` column keep
+//! their target value and are queued in `migration_conflict_queue` with both
+//! versions until `resolve apply` settles them.
+
+use std::collections::HashMap;
+
+use postgres::types::ToSql;
+use postgres::{Client, Transaction};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::connect::describe;
+use super::load::Prepared;
+use crate::scaffold;
+use crate::spec::{ConflictPolicies, ConflictPolicy, EntitySpec};
+
+pub const QUEUE_TABLE: &str = "migration_conflict_queue";
+
+const CREATE_SQL: &str = "CREATE TABLE IF NOT EXISTS migration_conflict_queue (
+    id BIGSERIAL PRIMARY KEY,
+    entity TEXT NOT NULL,
+    entity_type TEXT NOT NULL,
+    target_table TEXT NOT NULL,
+    legacy_id_column TEXT NOT NULL,
+    legacy_id BIGINT NOT NULL,
+    columns TEXT[] NOT NULL,
+    source_values JSONB NOT NULL,
+    target_values JSONB NOT NULL,
+    migration_batch TEXT,
+    status TEXT NOT NULL DEFAULT 'pending',
+    decision JSONB,
+    decided_by TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ
+);
+CREATE INDEX IF NOT EXISTS idx_migration_conflict_queue_pending
+    ON migration_conflict_queue (entity, legacy_id) WHERE status = 'pending';
+CREATE TABLE IF NOT EXISTS migration_conflict_log (
+    id BIGSERIAL PRIMARY KEY,
+    entity TEXT NOT NULL,
+    target_table TEXT NOT NULL,
+    legacy_id BIGINT NOT NULL,
+    column_name TEXT NOT NULL,
+    policy TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    source_value TEXT,
+    target_value TEXT,
+    resolved_value TEXT,
+    migration_batch TEXT,
+    queue_id BIGINT,
+    decided_by TEXT NOT NULL,
+    decided_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+)";
+
+/// Who decided a conflict that no person looked at.
+const POLICY: &str = "policy";
+
+/// Create the queue and the log if the target has none.
+pub fn ensure_tables(target: &mut Client) -> Result<(), String> {
+    target
+        .batch_execute(CREATE_SQL)
+        .map_err(|e| format!("cannot create {}: {}", QUEUE_TABLE, describe(&e)))
+}
+
+/// Conflicts met by one batch.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Resolution {
+    /// Column values that differed between source and target.
+    pub conflicts: u64,
+    /// Rows queued for a manual decision.
+    pub queued: u64,
+}
+
+impl Resolution {
+    pub fn add(&mut self, other: Resolution) {
+        self.conflicts += other.conflicts;
+        self.queued += other.queued;
+    }
+}
+
+struct Column {
+    name: String,
+    policy: ConflictPolicy,
+}
+
+/// One decided column value, as logged.
+struct Decision {
+    legacy_id: i64,
+    column: usize,
+    outcome: &'static str,
+    source: Option<String>,
+    target: Option<String>,
+    resolved: Option<String>,
+}
+
+pub struct Resolver<'a> {
+    spec: &'a EntitySpec,
+    /// Mapped target columns in `scaffold::target_columns` order, without
+    /// the legacy ID.
+    columns: Vec<Column>,
+    /// Index in `columns` of the `updated_at` column, when a column is
+    /// decided by `newest-updated-at`.
+    updated_at: Option<usize>,
+    fetch: String,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(
+        spec: &'a EntitySpec,
+        policies: &ConflictPolicies,
+        types: &HashMap<String, String>,
+    ) -> Result<Self, String> {
+        let names = scaffold::target_columns(spec);
+        let mut columns = Vec::with_capacity(names.len() - 1);
+        let mut select = vec!["s.legacy_id".to_string()];
+        for (i, name) in names[1..].iter().enumerate() {
+            let policy = policies.policy(name);
+            let ty = types[*name].as_str();
+            let array = ty.ends_with("[]");
+            if policy == ConflictPolicy::Merge && !array && !matches!(ty, "json" | "jsonb") {
+                return Err(format!(
+                    "`merge` needs a json, jsonb or array column; {}.{} is {}",
+                    spec.target_table, name, ty
+                ));
+            }
+            let source = format!("CAST(s.v{} AS {})", i, ty);
+            let same = if ty == "json" {
+                format!("t.{}::jsonb IS NOT DISTINCT FROM {}::jsonb", name, source)
+            } else {
+                format!("t.{} IS NOT DISTINCT FROM {}", name, source)
+            };
+            // Arrays are merged in SQL, keeping the target's order and
+            // appending the source elements it lacks.
+            let merged = if policy == ConflictPolicy::Merge && array {
+                format!(
+                    "(t.{c} || ARRAY(SELECT x FROM unnest({s}) WITH ORDINALITY AS a(x, o) \
+                     WHERE t.{c} IS NULL OR NOT x = ANY(t.{c}) ORDER BY o))::text",
+                    c = name,
+                    s = source
+                )
+            } else {
+                "NULL::text".to_string()
+            };
+            select.push(format!("t.{}::text", name));
+            select.push(same);
+            select.push(merged);
+            columns.push(Column {
+                name: name.to_string(),
+                policy,
+            });
+        }
+        let updated_at = columns
+            .iter()
+            .position(|c| c.name == policies.updated_at)
+            .filter(|_| {
+                columns
+                    .iter()
+                    .any(|c| c.policy == ConflictPolicy::NewestUpdatedAt)
+            });
+        // NULL when either side is; `source_is_newer` settles those.
+        let newer = match updated_at {
+            Some(i) => format!(
+                "CAST(s.v{i} AS {ty}) > t.{c}",
+                i = i,
+                c = columns[i].name,
+                ty = types[&columns[i].name]
+            ),
+            None => "NULL::boolean".to_string(),
+        };
+        select.push(newer);
+
+        let mut arrays = vec!["$1::bigint[]".to_string()];
+        arrays.extend((0..columns.len()).map(|i| format!("${}::text[]", i + 2)));
+        let mut aliases = vec!["legacy_id".to_string()];
+        aliases.extend((0..columns.len()).map(|i| format!("v{}", i)));
+        let fetch = format!(
+            "SELECT {select}
+FROM UNNEST({arrays}) AS s({aliases})
+JOIN {table} t ON t.{legacy} = s.legacy_id
+FOR UPDATE OF t",
+            select = select.join(", "),
+            arrays = arrays.join(", "),
+            aliases = aliases.join(", "),
+            table = spec.target_table,
+            legacy = spec.legacy_id_column,
+        );
+        Ok(Resolver {
+            spec,
+            columns,
+            updated_at,
+            fetch,
+        })
+    }
+
+    /// Decide the values of `rows` that already exist in the target, in
+    /// place, and log and queue the decisions in `tx`.
+    pub fn resolve(
+        &self,
+        tx: &mut Transaction<'_>,
+        rows: &mut [Prepared],
+        migration_batch: &str,
+    ) -> Result<Resolution, postgres::Error> {
+        let mut resolution = Resolution::default();
+        if rows.is_empty() {
+            return Ok(resolution);
+        }
+        let legacy_ids: Vec<i64> = rows.iter().map(|(id, _, _)| *id).collect();
+        let values: Vec<Vec<Option<String>>> = (0..self.columns.len())
+            .map(|i| rows.iter().map(|(_, _, v)| v[i].clone()).collect())
+            .collect();
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![&legacy_ids];
+        params.extend(values.iter().map(|v| v as &(dyn ToSql + Sync)));
+        let existing: HashMap<i64, postgres::Row> = tx
+            .query(self.fetch.as_str(), &params)?
+            .into_iter()
+            .map(|row| (row.get(0), row))
+            .collect();
+
+        let newer_at = 1 + 3 * self.columns.len();
+        let mut decisions = Vec::new();
+        for (legacy_id, _, values) in rows.iter_mut() {
+            let Some(current) = existing.get(legacy_id) else {
+                continue;
+            };
+            let source_values = values.clone();
+            let newer = self.updated_at.is_some_and(|at| {
+                source_is_newer(
+                    source_values[at].as_deref(),
+                    current.get(1 + 3 * at),
+                    current.get(newer_at),
+                )
+            });
+            let mut queued = Vec::new();
+            for (i, column) in self.columns.iter().enumerate() {
+                if current.get::<_, Option<bool>>(2 + 3 * i) == Some(true) {
+                    continue;
+                }
+                resolution.conflicts += 1;
+                let target: Option<String> = current.get(1 + 3 * i);
+                let source = values[i].clone();
+                let (outcome, resolved) = decide(
+                    column.policy,
+                    source.as_deref(),
+                    target.as_deref(),
+                    current.get(3 + 3 * i),
+                    newer,
+                );
+                if column.policy == ConflictPolicy::Manual {
+                    queued.push(i);
+                }
+                values[i] = resolved.clone();
+                decisions.push(Decision {
+                    legacy_id: *legacy_id,
+                    column: i,
+                    outcome,
+                    source,
+                    target,
+                    resolved,
+                });
+            }
+            if !queued.is_empty() {
+                resolution.queued += 1;
+                let target_values: Vec<Option<String>> = (0..self.columns.len())
+                    .map(|i| current.get(1 + 3 * i))
+                    .collect();
+                self.enqueue(
+                    tx,
+                    *legacy_id,
+                    &queued,
+                    &source_values,
+                    &target_values,
+                    migration_batch,
+                )?;
+            }
+        }
+        self.log(tx, &decisions, migration_batch)?;
+        Ok(resolution)
+    }
+
+    fn enqueue(
+        &self,
+        tx: &mut Transaction<'_>,
+        legacy_id: i64,
+        queued: &[usize],
+        source: &[Option<String>],
+        target: &[Option<String>],
+        migration_batch: &str,
+    ) -> Result<(), postgres::Error> {
+        let spec = self.spec;
+        let object = |values: &[Option<String>]| {
+            let map: Map<String, Value> = self
+                .columns
+                .iter()
+                .zip(values)
+                .map(|(c, v)| (c.name.clone(), v.clone().map_or(Value::Null, Value::String)))
+                .collect();
+            Value::Object(map).to_string()
+        };
+        let columns: Vec<&str> = queued
+            .iter()
+            .map(|&i| self.columns[i].name.as_str())
+            .collect();
+        tx.execute(
+            "UPDATE migration_conflict_queue SET status = 'superseded', resolved_at = NOW()
+             WHERE entity = $1 AND legacy_id = $2 AND status = 'pending'",
+            &[&spec.name, &legacy_id],
+        )?;
+        tx.execute(
+            "INSERT INTO migration_conflict_queue
+               (entity, entity_type, target_table, legacy_id_column, legacy_id, columns,
+                source_values, target_values, migration_batch)
+             VALUES ($1, $2, $3, $4, $5, $6, $7::text::jsonb, $8::text::jsonb, $9)",
+            &[
+                &spec.name,
+                &spec.entity_type(),
+                &spec.target_table,
+                &spec.legacy_id_column,
+                &legacy_id,
+                &columns,
+                &object(source),
+                &object(target),
+                &migration_batch,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn log(
+        &self,
+        tx: &mut Transaction<'_>,
+        decisions: &[Decision],
+        migration_batch: &str,
+    ) -> Result<(), postgres::Error> {
+        if decisions.is_empty() {
+            return Ok(());
+        }
+        let legacy_ids: Vec<i64> = decisions.iter().map(|d| d.legacy_id).collect();
+        let columns: Vec<&str> = decisions
+            .iter()
+            .map(|d| self.columns[d.column].name.as_str())
+            .collect();
+        let policies: Vec<&str> = decisions
+            .iter()
+            .map(|d| self.columns[d.column].policy.as_str())
+            .collect();
+        let outcomes: Vec<&str> = decisions.iter().map(|d| d.outcome).collect();
+        let sources: Vec<Option<&str>> = decisions.iter().map(|d| d.source.as_deref()).collect();
+        let targets: Vec<Option<&str>> = decisions.iter().map(|d| d.target.as_deref()).collect();
+        let resolved: Vec<Option<&str>> = decisions.iter().map(|d| d.resolved.as_deref()).collect();
+        tx.execute(
+            "INSERT INTO migration_conflict_log
+               (entity, target_table, legacy_id, column_name, policy, outcome, source_value,
+                target_value, resolved_value, migration_batch, decided_by)
+             SELECT $1, $2, d.*, $10, $11
+             FROM UNNEST($3::bigint[], $4::text[], $5::text[], $6::text[], $7::text[],
+                         $8::text[], $9::text[]) AS d",
+            &[
+                &self.spec.name,
+                &self.spec.target_table,
+                &legacy_ids,
+                &columns,
+                &policies,
+                &outcomes,
+                &sources,
+                &targets,
+                &resolved,
+                &migration_batch,
+                &POLICY,
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+/// Whether the source row was updated after the target row. `later` is
+/// SQL's `source > target`; a row without `updated_at` is the older one, and
+/// the target wins when neither has one.
+fn source_is_newer(source: Option<&str>, target: Option<&str>, later: Option<bool>) -> bool {
+    match (source, target) {
+        (None, _) => false,
+        (Some(_), None) => true,
+        (Some(_), Some(_)) => later == Some(true),
+    }
+}
+
+/// The outcome and the value kept for one differing column. `merged` is the
+/// array merge done in SQL, if any; `manual` keeps the target until queued
+/// conflicts are applied.
+fn decide(
+    policy: ConflictPolicy,
+    source: Option<&str>,
+    target: Option<&str>,
+    merged: Option<String>,
+    newer: bool,
+) -> (&'static str, Option<String>) {
+    match policy {
+        ConflictPolicy::SourceWins => ("source", source.map(str::to_string)),
+        ConflictPolicy::TargetWins => ("target", target.map(str::to_string)),
+        ConflictPolicy::NewestUpdatedAt if newer => ("source", source.map(str::to_string)),
+        ConflictPolicy::NewestUpdatedAt => ("target", target.map(str::to_string)),
+        ConflictPolicy::Merge => match merged {
+            Some(merged) => ("merged", Some(merged)),
+            None => ("merged", merge_json(target, source)),
+        },
+        ConflictPolicy::Manual => ("queued", target.map(str::to_string)),
+    }
+}
+
+/// Merge two JSON texts: objects key by key, arrays by appending the source
+/// elements the target lacks, the source winning anywhere else. A side that
+/// is NULL or not JSON leaves the other.
+fn merge_json(target: Option<&str>, source: Option<&str>) -> Option<String> {
+    let parse = |text: Option<&str>| text.and_then(|t| serde_json::from_str::<Value>(t).ok());
+    match (parse(target), parse(source)) {
+        (Some(target), Some(source)) => Some(merge_values(target, source).to_string()),
+        (None, _) => source.map(str::to_string),
+        (_, None) => target.map(str::to_string),
+    }
+}
+
+fn merge_values(target: Value, source: Value) -> Value {
+    match (target, source) {
+        (Value::Object(mut target), Value::Object(source)) => {
+            for (key, value) in source {
+                let merged = match target.remove(&key) {
+                    Some(existing) => merge_values(existing, value),
+                    None => value,
+                };
+                target.insert(key, merged);
+            }
+            Value::Object(target)
+        }
+        (Value::Array(mut target), Value::Array(source)) => {
+            for value in source {
+                if !target.contains(&value) {
+                    target.push(value);
+                }
+            }
+            Value::Array(target)
+        }
+        (_, source) => source,
+    }
+}
+
+/// Which version a manual decision keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Take {
+    Source,
+    Target,
+}
+
+impl Take {
+    fn as_str(self) -> &'static str {
+        match self {
+            Take::Source => "source",
+            Take::Target => "target",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Queued {
+    pub id: i64,
+    pub entity: String,
+    pub target_table: String,
+    pub legacy_id: i64,
+    pub columns: Vec<String>,
+    pub source_values: Value,
+    pub target_values: Value,
+    pub migration_batch: Option<String>,
+    pub status: String,
+    pub created_at: String,
+}
+
+/// Queued conflicts, oldest first: pending ones, or all with `all`.
+pub fn queued(target: &mut Client, entity: Option<&str>, all: bool) -> Result<Vec<Queued>, String> {
+    let failed = |e: postgres::Error| format!("cannot read {}: {}", QUEUE_TABLE, describe(&e));
+    let exists: bool = target
+        .query_one("SELECT to_regclass($1) IS NOT NULL", &[&QUEUE_TABLE])
+        .map_err(failed)?
+        .get(0);
+    if !exists {
+        return Ok(Vec::new());
+    }
+    let rows = target
+        .query(
+            "SELECT id, entity, target_table, legacy_id, columns, source_values::text,
+                    target_values::text, migration_batch, status,
+                    to_char(created_at, 'YYYY-MM-DD HH24:MI:SS')
+             FROM migration_conflict_queue
+             WHERE ($1::text IS NULL OR entity = $1) AND ($2 OR status = 'pending')
+             ORDER BY id",
+            &[&entity, &all],
+        )
+        .map_err(failed)?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let json = |i: usize| {
+                let text: String = row.get(i);
+                serde_json::from_str(&text).unwrap_or(Value::Null)
+            };
+            Queued {
+                id: row.get(0),
+                entity: row.get(1),
+                target_table: row.get(2),
+                legacy_id: row.get(3),
+                columns: row.get(4),
+                source_values: json(5),
+                target_values: json(6),
+                migration_batch: row.get(7),
+                status: row.get(8),
+                created_at: row.get(9),
+            }
+        })
+        .collect())
+}
+
+/// Settle queued conflict `id`: every queued column takes the version named
+/// in `columns`, else `default`. Returns the decision per column.
+pub fn apply(
+    target: &mut Client,
+    id: i64,
+    default: Option<Take>,
+    columns: &HashMap<String, Take>,
+    decided_by: &str,
+) -> Result<Vec<(String, Take)>, String> {
+    let failed = |e: postgres::Error| format!("cannot apply conflict {}: {}", id, describe(&e));
+    let mut tx = target.transaction().map_err(failed)?;
+    let row = tx
+        .query_opt(
+            "SELECT entity, target_table, legacy_id_column, legacy_id, columns,
+                    source_values::text, target_values::text, migration_batch, status
+             FROM migration_conflict_queue WHERE id = $1 FOR UPDATE",
+            &[&id],
+        )
+        .map_err(failed)?
+        .ok_or_else(|| format!("no queued conflict has ID {}", id))?;
+    let status: String = row.get(8);
+    if status != "pending" {
+        return Err(format!("conflict {} is already {}", id, status));
+    }
+    let entity: String = row.get(0);
+    let table: String = row.get(1);
+    let legacy_column: String = row.get(2);
+    let legacy_id: i64 = row.get(3);
+    let queued: Vec<String> = row.get(4);
+    let values = |i: usize| -> Map<String, Value> {
+        let text: String = row.get(i);
+        match serde_json::from_str(&text) {
+            Ok(Value::Object(map)) => map,
+            _ => Map::new(),
+        }
+    };
+    let (source, current) = (values(5), values(6));
+    let migration_batch: Option<String> = row.get(7);
+    if let Some(unknown) = columns.keys().find(|c| !queued.contains(c)) {
+        return Err(format!(
+            "conflict {} has no queued column '{}' (queued: {})",
+            id,
+            unknown,
+            queued.join(", ")
+        ));
+    }
+    let mut decisions = Vec::with_capacity(queued.len());
+    for column in &queued {
+        let take = columns.get(column).copied().or(default).ok_or_else(|| {
+            format!(
+                "no decision for column '{}' of conflict {}; pass --take or --column",
+                column, id
+            )
+        })?;
+        decisions.push((column.clone(), take));
+    }
+
+    let text = |map: &Map<String, Value>, column: &str| -> Option<String> {
+        match map.get(column) {
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(Value::Null) | None => None,
+            Some(other) => Some(other.to_string()),
+        }
+    };
+    let types: HashMap<String, String> = tx
+        .query(
+            "SELECT a.attname::text, format_type(a.atttypid, a.atttypmod)
+             FROM pg_attribute a
+             WHERE a.attrelid = to_regclass($1) AND a.attnum > 0 AND NOT a.attisdropped",
+            &[&table],
+        )
+        .map_err(failed)?
+        .iter()
+        .map(|r| (r.get(0), r.get(1)))
+        .collect();
+    let taken: Vec<(&String, Option<String>)> = decisions
+        .iter()
+        .filter(|(_, take)| *take == Take::Source)
+        .map(|(column, _)| (column, text(&source, column)))
+        .collect();
+    if !taken.is_empty() {
+        let mut assignments = Vec::with_capacity(taken.len());
+        for (i, (column, _)) in taken.iter().enumerate() {
+            let ty = types
+                .get(column.as_str())
+                .ok_or_else(|| format!("{} has no column {}", table, column))?;
+            assignments.push(format!("{} = CAST(${} AS {})", column, i + 2, ty));
+        }
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![&legacy_id];
+        params.extend(taken.iter().map(|(_, v)| v as &(dyn ToSql + Sync)));
+        let updated = tx
+            .execute(
+                &format!(
+                    "UPDATE {} SET {} WHERE {} = $1::bigint",
+                    table,
+                    assignments.join(", "),
+                    legacy_column
+                ),
+                &params,
+            )
+            .map_err(failed)?;
+        if updated == 0 {
+            return Err(format!(
+                "{} has no row with {} {} any more",
+                table, legacy_column, legacy_id
+            ));
+        }
+    }
+    for (column, take) in &decisions {
+        let (source_value, target_value) = (text(&source, column), text(&current, column));
+        let resolved = match take {
+            Take::Source => &source_value,
+            Take::Target => &target_value,
+        };
+        tx.execute(
+            "INSERT INTO migration_conflict_log
+               (entity, target_table, legacy_id, column_name, policy, outcome, source_value,
+                target_value, resolved_value, migration_batch, queue_id, decided_by)
+             VALUES ($1, $2, $3, $4, 'manual', $5, $6, $7, $8, $9, $10, $11)",
+            &[
+                &entity,
+                &table,
+                &legacy_id,
+                column,
+                &take.as_str(),
+                &source_value,
+                &target_value,
+                resolved,
+                &migration_batch,
+                &id,
+                &decided_by,
+            ],
+        )
+        .map_err(failed)?;
+    }
+    let decision: Map<String, Value> = decisions
+        .iter()
+        .map(|(c, t)| (c.clone(), Value::String(t.as_str().to_string())))
+        .collect();
+    tx.execute(
+        "UPDATE migration_conflict_queue
+         SET status = 'resolved', decision = $2::text::jsonb, decided_by = $3, resolved_at = NOW()
+         WHERE id = $1",
+        &[&id, &Value::Object(decision).to_string(), &decided_by],
+    )
+    .map_err(failed)?;
+    tx.commit().map_err(failed)?;
+    Ok(decisions)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn merges_values() {
+        let cases = [
+            // Objects merge key by key, recursively.
+            (
+                json!({"a": 1, "n": {"x": 1, "y": [1]}}),
+                json!({"b": 2, "n": {"y": [1, 2], "z": 3}}),
+                json!({"a": 1, "b": 2, "n": {"x": 1, "y": [1, 2], "z": 3}}),
+            ),
+            // Arrays keep the target's order and append what it lacks once.
+            (json!([3, 1]), json!([1, 2, 2, 3, 4]), json!([3, 1, 2, 4])),
+            (
+                json!([{"a": 1}]),
+                json!([{"a": 1}, {"a": 2}]),
+                json!([{"a": 1}, {"a": 2}]),
+            ),
+            // Anything else: the source wins.
+            (json!({"a": 1}), json!("text"), json!("text")),
+            (json!(1), json!({"a": 1}), json!({"a": 1})),
+            (json!([1]), json!({"a": 1}), json!({"a": 1})),
+            (json!({"a": 1}), json!({"a": null}), json!({"a": null})),
+            (json!({"a": {"b": 1}}), json!({"a": 2}), json!({"a": 2})),
+        ];
+        for (target, source, expected) in cases {
+            let label = format!("{} + {}", target, source);
+            assert_eq!(merge_values(target, source), expected, "{}", label);
+        }
+    }
+
+    #[test]
+    fn merges_json_text() {
+        let cases = [
+            (
+                Some(r#"{"a":1}"#),
+                Some(r#"{"b":2}"#),
+                Some(r#"{"a":1,"b":2}"#),
+            ),
+            (None, Some(r#"{"b":2}"#), Some(r#"{"b":2}"#)),
+            (Some(r#"{"a":1}"#), None, Some(r#"{"a":1}"#)),
+            (None, None, None),
+            (Some("not json"), Some("[1]"), Some("[1]")),
+            (Some("[1]"), Some("{broken"), Some("[1]")),
+            (Some("{broken"), Some("also broken"), Some("also broken")),
+        ];
+        for (target, source, expected) in cases {
+            assert_eq!(
+                merge_json(target, source).as_deref(),
+                expected,
+                "{:?} + {:?}",
+                target,
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn decides_by_policy() {
+        use ConflictPolicy::*;
+
+        // (policy, source updated_at, target updated_at, SQL `source >
+        // target`, outcome, kept)
+        let cases = [
+            (SourceWins, None, None, None, "source", Some("s")),
+            (TargetWins, None, None, None, "target", Some("t")),
+            (Manual, None, None, None, "queued", Some("t")),
+            (
+                NewestUpdatedAt,
+                Some("2"),
+                Some("1"),
+                Some(true),
+                "source",
+                Some("s"),
+            ),
+            (
+                NewestUpdatedAt,
+                Some("1"),
+                Some("2"),
+                Some(false),
+                "target",
+                Some("t"),
+            ),
+            (
+                NewestUpdatedAt,
+                Some("1"),
+                Some("1"),
+                Some(false),
+                "target",
+                Some("t"),
+            ),
+            // A side without updated_at is the older one.
+            (NewestUpdatedAt, Some("1"), None, None, "source", Some("s")),
+            (NewestUpdatedAt, None, Some("1"), None, "target", Some("t")),
+            (NewestUpdatedAt, None, None, None, "target", Some("t")),
+        ];
+        for (i, (policy, source_at, target_at, later, outcome, kept)) in
+            cases.into_iter().enumerate()
+        {
+            let newer = source_is_newer(source_at, target_at, later);
+            let decided = decide(policy, Some("s"), Some("t"), None, newer);
+            assert_eq!(decided, (outcome, kept.map(str::to_string)), "case {}", i);
+        }
+
+        // NULL values are decided like any other.
+        assert_eq!(
+            decide(SourceWins, None, Some("t"), None, false),
+            ("source", None)
+        );
+        assert_eq!(
+            decide(TargetWins, Some("s"), None, None, false),
+            ("target", None)
+        );
+        assert_eq!(
+            decide(Manual, Some("s"), None, None, true),
+            ("queued", None)
+        );
+
+        // Arrays arrive merged from SQL; JSON is merged here.
+        let merged = decide(
+            Merge,
+            Some("{1,2}"),
+            Some("{1}"),
+            Some("{1,2}".into()),
+            false,
+        );
+        assert_eq!(merged, ("merged", Some("{1,2}".to_string())));
+        let merged = decide(Merge, Some(r#"{"b":2}"#), Some(r#"{"a":1}"#), None, false);
+        assert_eq!(merged, ("merged", Some(r#"{"a":1,"b":2}"#.to_string())));
+        assert_eq!(
+            decide(Merge, None, Some("[1]"), None, false),
+            ("merged", Some("[1]".into()))
+        );
+    }
+}
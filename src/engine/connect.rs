@@ -0,0 +1,204 @@
+//! Source and target connection settings.
+//!
+//! Without an explicit connection string the layered settings of
+//! [`crate::config`] are used: `migration.toml`, its profiles, `.env` and the
+//! `SOURCE_DB_*` / `TARGET_DB_*` variables, with the same defaults as the
+//! TypeScript migrations. A host starting with `/` is a Unix socket
+//! directory, which is how a throwaway local Postgres is usually reached.
+//!
+//! TLS follows libpq's `sslmode`, given in the connection string or as
+//! `SOURCE_DB_SSL` / `TARGET_DB_SSL`: `prefer` (the default) and `require`
+//! encrypt without checking the server's certificate, as the TypeScript
+//! pools' `rejectUnauthorized: false` did; `verify-ca` and `verify-full`
+//! check it against `sslrootcert`, or the system's roots without one.
+
+use openssl::error::ErrorStack;
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use postgres::config::SslMode;
+use postgres::{Client, Config, NoTls};
+use postgres_openssl::MakeTlsConnector;
+
+use crate::config;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Side {
+    Source,
+    Target,
+}
+
+impl Side {
+    pub fn label(self) -> &'static str {
+        match self {
+            Side::Source => "source",
+            Side::Target => "target",
+        }
+    }
+}
+
+/// libpq's `sslmode`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TlsMode {
+    Disable,
+    #[default]
+    Prefer,
+    Require,
+    VerifyCa,
+    VerifyFull,
+}
+
+impl TlsMode {
+    /// An `sslmode` value, or `true` / `false` as the TypeScript tools read
+    /// `*_DB_SSL`: `require` or `disable`.
+    pub fn parse(value: &str) -> Result<TlsMode, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "disable" | "false" => Ok(TlsMode::Disable),
+            "prefer" => Ok(TlsMode::Prefer),
+            "require" | "true" => Ok(TlsMode::Require),
+            "verify-ca" => Ok(TlsMode::VerifyCa),
+            "verify-full" => Ok(TlsMode::VerifyFull),
+            _ => Err(format!(
+                "'{}' is not an sslmode (expected true, false, disable, prefer, require, verify-ca or verify-full)",
+                value
+            )),
+        }
+    }
+
+    fn driver(self) -> SslMode {
+        match self {
+            TlsMode::Disable => SslMode::Disable,
+            TlsMode::Prefer => SslMode::Prefer,
+            _ => SslMode::Require,
+        }
+    }
+
+    fn connector(self, root_cert: Option<&str>) -> Result<MakeTlsConnector, ErrorStack> {
+        let mut builder = SslConnector::builder(SslMethod::tls())?;
+        match (self, root_cert) {
+            (TlsMode::VerifyCa | TlsMode::VerifyFull, Some(path)) => builder.set_ca_file(path)?,
+            (TlsMode::VerifyCa | TlsMode::VerifyFull, None) => {}
+            _ => builder.set_verify(SslVerifyMode::NONE),
+        }
+        let mut connector = MakeTlsConnector::new(builder.build());
+        if self != TlsMode::VerifyFull {
+            connector.set_callback(|config, _| {
+                config.set_verify_hostname(false);
+                Ok(())
+            });
+        }
+        Ok(connector)
+    }
+}
+
+/// What a connection needs besides the driver's settings.
+#[derive(Debug, Default)]
+struct Tls {
+    mode: Option<TlsMode>,
+    root_cert: Option<String>,
+}
+
+/// `url` without its `sslmode` and `sslrootcert`, which are returned apart:
+/// the driver knows neither the verifying modes nor root certificates.
+fn split_tls(url: &str) -> Result<(String, Tls), String> {
+    let mut tls = Tls::default();
+    let mut take = |key: &str, value: &str| -> Result<bool, String> {
+        match key {
+            "sslmode" => tls.mode = Some(TlsMode::parse(value)?),
+            "sslrootcert" => tls.root_cert = Some(value.trim_matches('\'').to_string()),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    };
+    let rest = if let Some((base, query)) = url.split_once('?')
+        && base.contains("://")
+    {
+        let mut kept = Vec::new();
+        for param in query.split('&') {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            if !take(key, value)? {
+                kept.push(param);
+            }
+        }
+        if kept.is_empty() {
+            base.to_string()
+        } else {
+            format!("{}?{}", base, kept.join("&"))
+        }
+    } else if url.contains("://") {
+        url.to_string()
+    } else {
+        let mut kept = Vec::new();
+        for part in url.split_whitespace() {
+            match part.split_once('=') {
+                Some((key, value)) if take(key, value)? => {}
+                _ => kept.push(part),
+            }
+        }
+        kept.join(" ")
+    };
+    Ok((rest, tls))
+}
+
+/// Connection settings from `url` (a `postgresql://` URL or `key=value`
+/// string) or, when absent, from the layered settings, whose `ssl` also
+/// applies to their URL unless it names an `sslmode` itself.
+fn settings(side: Side, url: Option<&str>) -> Result<(Config, Tls), String> {
+    let parse = |url: &str| {
+        let (url, tls) = split_tls(url)
+            .map_err(|e| format!("invalid {} connection string: {}", side.label(), e))?;
+        let config = url
+            .parse::<Config>()
+            .map_err(|e| format!("invalid {} connection string: {}", side.label(), e))?;
+        Ok::<_, String>((config, tls))
+    };
+    if let Some(url) = url {
+        return parse(url);
+    }
+    let database = config::current()?.database(side);
+    if let Some(url) = &database.url {
+        let (config, mut tls) = parse(url)?;
+        tls.mode = tls.mode.or(database.ssl);
+        return Ok((config, tls));
+    }
+    let mut config = Config::new();
+    config
+        .host(&database.host)
+        .port(database.port)
+        .dbname(&database.name)
+        .user(&database.user)
+        .password(&database.password)
+        .application_name("migration_generator");
+    let tls = Tls {
+        mode: database.ssl,
+        root_cert: None,
+    };
+    Ok((config, tls))
+}
+
+pub fn connect(side: Side, url: Option<&str>) -> Result<Client, String> {
+    let (mut config, tls) = settings(side, url)?;
+    let failed = |e: String| format!("cannot connect to {} database: {}", side.label(), e);
+    let mode = tls.mode.unwrap_or_default();
+    config.ssl_mode(mode.driver());
+    if mode == TlsMode::Disable {
+        return config.connect(NoTls).map_err(|e| failed(describe(&e)));
+    }
+    let connector = mode
+        .connector(tls.root_cert.as_deref())
+        .map_err(|e| failed(format!("cannot set up TLS: {}", e)))?;
+    config.connect(connector).map_err(|e| failed(describe(&e)))
+}
+
+/// The server's message and detail for database errors, which the plain
+/// `Display` of `postgres::Error` leaves out.
+pub fn describe(e: &postgres::Error) -> String {
+    match e.as_db_error() {
+        Some(db) => match db.detail() {
+            Some(detail) => format!("{} ({})", db.message(), detail),
+            None => db.message().to_string(),
+        },
+        None => match std::error::Error::source(e) {
+            Some(cause) => format!("{}: {}", e, cause),
+            None => e.to_string(),
+        },
+    }
+}
@@ -0,0 +1,562 @@
+//! Writing a transformed batch into the target.
+//!
+//! Two load paths share the same lineage and progress bookkeeping:
+//!
+//! - `upsert` resolves legacy FKs in memory and sends one `INSERT … SELECT
+//!   FROM UNNEST(…)` per batch, with one array parameter per column.
+//! - `copy` streams the batch with `COPY … FROM STDIN (FORMAT binary)` into a
+//!   session temp table and merges it with a single `INSERT … SELECT … ON
+//!   CONFLICT`, resolving legacy FKs with a join on `migration_mappings`.
+//!
+//! When a batch fails with a database error it is retried row by row, each
+//! row behind its own savepoint, so one bad row costs only itself.
+//!
+//! With conflict policies in the spec, the upsert path first lets the
+//! [`Resolver`] decide the values of rows that already exist in the target.
+//!
+//! A UUID target key is filled from the [`MappingStore`]: the mapped UUID when
+//! the row was migrated before, the deterministic one otherwise.
+
+use std::collections::{BTreeSet, HashMap};
+
+use postgres::binary_copy::BinaryCopyInWriter;
+use postgres::types::{ToSql, Type};
+use postgres::{Client, Row, Transaction};
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::RunOptions;
+use super::checkpoint::{self, Progress};
+use super::conflict::{Resolution, Resolver};
+use super::connect::describe;
+use super::transform::{SourceRow, column_value, transform_row};
+use crate::mapping::MappingStore;
+use crate::scaffold;
+use crate::spec::{EntitySpec, literal};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoadMode {
+    Upsert,
+    Copy,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RowFailure {
+    pub legacy_id: i64,
+    pub error: String,
+}
+
+#[derive(Debug, Default)]
+pub struct BatchOutcome {
+    /// Rows upserted (or that would be, in a dry run).
+    pub written: usize,
+    pub failures: Vec<RowFailure>,
+    /// The batch failed as a whole and was retried row by row.
+    pub fell_back: bool,
+    pub resolution: Resolution,
+}
+
+impl BatchOutcome {
+    /// Cache the committed rows so later batches (self-references included)
+    /// resolve them without a query.
+    fn remember(
+        self,
+        mappings: &mut MappingStore,
+        spec: &EntitySpec,
+        returned: &[Row],
+        options: &RunOptions,
+    ) -> Self {
+        for row in returned {
+            let key: &str = row.get(0);
+            if let Ok(new_id) = Uuid::parse_str(key) {
+                mappings.insert(
+                    spec.entity_type(),
+                    row.get(1),
+                    new_id,
+                    &options.migration_batch,
+                );
+            }
+        }
+        self
+    }
+}
+
+const STAGE_TABLE: &str = "migration_stage";
+
+const MAPPINGS_SQL: &str =
+    "INSERT INTO migration_mappings (entity_type, legacy_id, new_id, migrated_at, migration_batch)
+SELECT $1::text, m.legacy_id, m.new_id::uuid, NOW(), $2::text
+FROM UNNEST($3::bigint[], $4::text[]) AS m(legacy_id, new_id)
+ON CONFLICT (entity_type, legacy_id) DO NOTHING";
+
+/// A transformed row ready for the upsert: legacy ID, target key, then the
+/// values in `scaffold::target_columns` order.
+pub(super) type Prepared = (i64, Uuid, Vec<Option<String>>);
+
+pub struct Loader<'a> {
+    spec: &'a EntitySpec,
+    mode: LoadMode,
+    /// The target key is a UUID the loader supplies instead of its default.
+    assign_key: bool,
+    upsert: String,
+    copy: String,
+    merge: String,
+    stage_types: Vec<Type>,
+    resolver: Option<Resolver<'a>>,
+}
+
+impl<'a> Loader<'a> {
+    /// Prepare the statements for `spec`; the copy path also (re)creates the
+    /// session's staging table.
+    pub fn new(
+        target: &mut Client,
+        spec: &'a EntitySpec,
+        types: &HashMap<String, String>,
+        mode: LoadMode,
+    ) -> Result<Self, String> {
+        let assign_key = types.get(&spec.target_key).map(String::as_str) == Some("uuid");
+        let mut stage_columns = vec!["legacy_id bigint".to_string()];
+        stage_columns.extend((0..spec.columns.len()).map(|i| format!("c{} text", i)));
+        stage_columns.extend((0..spec.lookups.len()).map(|i| format!("l{} bigint", i)));
+        let mut stage_types = vec![Type::INT8];
+        stage_types.extend(spec.columns.iter().map(|_| Type::TEXT));
+        stage_types.extend(spec.lookups.iter().map(|_| Type::INT8));
+        if assign_key {
+            stage_columns.push("new_id uuid".to_string());
+            stage_types.push(Type::UUID);
+        }
+
+        let resolver = match &spec.conflicts {
+            Some(_) if mode == LoadMode::Copy => {
+                return Err(
+                    "conflict policies are applied by the upsert load; run with --load upsert"
+                        .into(),
+                );
+            }
+            Some(policies) => Some(Resolver::new(spec, policies, types)?),
+            None => None,
+        };
+        if mode == LoadMode::Copy {
+            target
+                .batch_execute(&format!(
+                    "DROP TABLE IF EXISTS pg_temp.{stage};
+                     CREATE TEMP TABLE {stage} ({columns}) ON COMMIT DELETE ROWS",
+                    stage = STAGE_TABLE,
+                    columns = stage_columns.join(", ")
+                ))
+                .map_err(|e| format!("cannot create the staging table: {}", describe(&e)))?;
+        }
+        Ok(Loader {
+            spec,
+            mode,
+            assign_key,
+            upsert: upsert_sql(spec, types, assign_key),
+            copy: format!("COPY {} FROM STDIN (FORMAT binary)", STAGE_TABLE),
+            merge: merge_sql(spec, types, assign_key),
+            stage_types,
+            resolver,
+        })
+    }
+
+    /// Write one batch in a single target transaction, together with its
+    /// `migration_mappings` rows, the `migration_control` progress and the
+    /// checkpoint. The rows written are added to `mappings`.
+    pub fn load(
+        &self,
+        target: &mut Client,
+        mappings: &mut MappingStore,
+        batch: &[SourceRow],
+        options: &RunOptions,
+        progress: &Progress,
+    ) -> Result<BatchOutcome, String> {
+        let spec = self.spec;
+        if self.assign_key {
+            mappings.resolve(
+                target,
+                spec.entity_type(),
+                batch.iter().map(|r| r.legacy_id),
+            )?;
+        }
+        // The upsert path, dry runs and the row-by-row fallback resolve
+        // lookups in memory; the copy path joins them in SQL.
+        let in_memory = options.dry_run || self.mode == LoadMode::Upsert;
+        if in_memory {
+            resolve_lookups(target, mappings, spec, batch)?;
+        }
+        if options.dry_run {
+            let written = batch
+                .iter()
+                .filter(|row| transform_row(spec, row, mappings).is_some())
+                .count();
+            return Ok(BatchOutcome {
+                written,
+                ..BatchOutcome::default()
+            });
+        }
+
+        let attempt = match self.mode {
+            LoadMode::Upsert => self.upsert_batch(target, mappings, batch, options, progress),
+            LoadMode::Copy => self
+                .copy_batch(target, mappings, batch, options, progress)
+                .map(|returned| (returned, Resolution::default())),
+        };
+        let outcome = match attempt {
+            Ok((returned, resolution)) => BatchOutcome {
+                written: returned.len(),
+                resolution,
+                ..BatchOutcome::default()
+            }
+            .remember(mappings, spec, &returned, options),
+            // Constraint and data errors are worth isolating; a lost
+            // connection is not.
+            Err(e) if e.as_db_error().is_some() => {
+                if !in_memory {
+                    resolve_lookups(target, mappings, spec, batch)?;
+                }
+                self.row_by_row(target, mappings, batch, options, progress)?
+            }
+            Err(e) => return Err(self.batch_error(batch, &e)),
+        };
+        Ok(outcome)
+    }
+
+    fn prepare(&self, mappings: &MappingStore, row: &SourceRow) -> Option<Prepared> {
+        let values = transform_row(self.spec, row, mappings)?;
+        let key = mappings.assign(self.spec.entity_type(), row.legacy_id);
+        Some((row.legacy_id, key, values))
+    }
+
+    fn upsert_batch(
+        &self,
+        target: &mut Client,
+        mappings: &MappingStore,
+        batch: &[SourceRow],
+        options: &RunOptions,
+        progress: &Progress,
+    ) -> Result<(Vec<Row>, Resolution), postgres::Error> {
+        let mut rows: Vec<Prepared> = batch
+            .iter()
+            .filter_map(|row| self.prepare(mappings, row))
+            .collect();
+        let mut tx = target.transaction()?;
+        let resolution = match &self.resolver {
+            Some(resolver) => resolver.resolve(&mut tx, &mut rows, &options.migration_batch)?,
+            None => Resolution::default(),
+        };
+        // A batch of skipped rows still moves the checkpoint.
+        let returned = if rows.is_empty() {
+            Vec::new()
+        } else {
+            self.upsert_rows(&mut tx, &rows)?
+        };
+        finish(&mut tx, self.spec, &returned, options, progress)?;
+        tx.commit()?;
+        Ok((returned, resolution))
+    }
+
+    fn copy_batch(
+        &self,
+        target: &mut Client,
+        mappings: &MappingStore,
+        batch: &[SourceRow],
+        options: &RunOptions,
+        progress: &Progress,
+    ) -> Result<Vec<Row>, postgres::Error> {
+        let mut tx = target.transaction()?;
+        let writer = tx.copy_in(self.copy.as_str())?;
+        let mut writer = BinaryCopyInWriter::new(writer, &self.stage_types);
+        for row in batch {
+            let columns: Vec<Option<String>> = self
+                .spec
+                .columns
+                .iter()
+                .zip(&row.columns)
+                .map(|(column, value)| column_value(column, value.clone()))
+                .collect();
+            let key = mappings.assign(self.spec.entity_type(), row.legacy_id);
+            let mut values: Vec<&(dyn ToSql + Sync)> = vec![&row.legacy_id];
+            values.extend(columns.iter().map(|c| c as &(dyn ToSql + Sync)));
+            values.extend(row.lookups.iter().map(|l| l as &(dyn ToSql + Sync)));
+            if self.assign_key {
+                values.push(&key);
+            }
+            writer.write(&values)?;
+        }
+        writer.finish()?;
+        let returned = tx.query(self.merge.as_str(), &[])?;
+        finish(&mut tx, self.spec, &returned, options, progress)?;
+        tx.commit()?;
+        Ok(returned)
+    }
+
+    /// Retry a failed batch one row at a time; rows that fail are rolled
+    /// back to their savepoint and reported, the rest are committed.
+    fn row_by_row(
+        &self,
+        target: &mut Client,
+        mappings: &mut MappingStore,
+        batch: &[SourceRow],
+        options: &RunOptions,
+        progress: &Progress,
+    ) -> Result<BatchOutcome, String> {
+        let spec = self.spec;
+        let mut outcome = BatchOutcome {
+            fell_back: true,
+            ..BatchOutcome::default()
+        };
+        let mut tx = target
+            .transaction()
+            .map_err(|e| self.batch_error(batch, &e))?;
+        let mut returned = Vec::new();
+        for row in batch {
+            let Some(prepared) = self.prepare(mappings, row) else {
+                continue;
+            };
+            let mut savepoint = tx
+                .savepoint("migration_row")
+                .map_err(|e| self.batch_error(batch, &e))?;
+            match self.upsert_row(&mut savepoint, prepared, options) {
+                Ok((rows, resolution)) => {
+                    savepoint
+                        .commit()
+                        .map_err(|e| self.batch_error(batch, &e))?;
+                    returned.extend(rows);
+                    outcome.resolution.add(resolution);
+                }
+                Err(e) if e.as_db_error().is_some() => {
+                    savepoint
+                        .rollback()
+                        .map_err(|e| self.batch_error(batch, &e))?;
+                    outcome.failures.push(RowFailure {
+                        legacy_id: row.legacy_id,
+                        error: describe(&e),
+                    });
+                }
+                Err(e) => return Err(self.batch_error(batch, &e)),
+            }
+        }
+        finish(&mut tx, spec, &returned, options, progress)
+            .map_err(|e| self.batch_error(batch, &e))?;
+        tx.commit().map_err(|e| self.batch_error(batch, &e))?;
+        outcome.written = returned.len();
+        Ok(outcome.remember(mappings, spec, &returned, options))
+    }
+
+    fn upsert_row(
+        &self,
+        tx: &mut Transaction<'_>,
+        prepared: Prepared,
+        options: &RunOptions,
+    ) -> Result<(Vec<Row>, Resolution), postgres::Error> {
+        let mut rows = [prepared];
+        let resolution = match &self.resolver {
+            Some(resolver) => resolver.resolve(tx, &mut rows, &options.migration_batch)?,
+            None => Resolution::default(),
+        };
+        Ok((self.upsert_rows(tx, &rows)?, resolution))
+    }
+
+    fn upsert_rows(
+        &self,
+        tx: &mut Transaction<'_>,
+        rows: &[Prepared],
+    ) -> Result<Vec<Row>, postgres::Error> {
+        let width = self.spec.columns.len() + self.spec.lookups.len();
+        let legacy_ids: Vec<i64> = rows.iter().map(|(id, _, _)| *id).collect();
+        let keys: Vec<Uuid> = rows.iter().map(|(_, key, _)| *key).collect();
+        let mut columns: Vec<Vec<Option<String>>> = vec![Vec::with_capacity(rows.len()); width];
+        for (_, _, values) in rows {
+            for (column, value) in columns.iter_mut().zip(values) {
+                column.push(value.clone());
+            }
+        }
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![&legacy_ids];
+        params.extend(columns.iter().map(|c| c as &(dyn ToSql + Sync)));
+        if self.assign_key {
+            params.push(&keys);
+        }
+        tx.query(self.upsert.as_str(), &params)
+    }
+
+    fn batch_error(&self, batch: &[SourceRow], e: &postgres::Error) -> String {
+        format!(
+            "batch {} {}..={} failed and was rolled back: {}",
+            self.spec.source_key,
+            batch[0].legacy_id,
+            batch[batch.len() - 1].legacy_id,
+            describe(e)
+        )
+    }
+}
+
+/// Record lineage for the rows an upsert or merge returned and advance the
+/// `migration_control` counter and the checkpoint, inside the batch's
+/// transaction.
+fn finish(
+    tx: &mut Transaction<'_>,
+    spec: &EntitySpec,
+    returned: &[Row],
+    options: &RunOptions,
+    progress: &Progress,
+) -> Result<(), postgres::Error> {
+    let new_ids: Vec<String> = returned.iter().map(|r| r.get(0)).collect();
+    let legacy_ids: Vec<i64> = returned.iter().map(|r| r.get(1)).collect();
+    tx.execute(
+        MAPPINGS_SQL,
+        &[
+            &spec.entity_type(),
+            &options.migration_batch,
+            &legacy_ids,
+            &new_ids,
+        ],
+    )?;
+    if let Some(id) = progress.control {
+        tx.execute(
+            "UPDATE migration_control SET records_processed = $2 WHERE id = $1",
+            &[&id, &(progress.processed.min(i32::MAX as u64) as i32)],
+        )?;
+    }
+    checkpoint::advance(tx, spec, progress)
+}
+
+/// Fetch the batch's uncached lookup IDs, one query per referenced entity
+/// type.
+fn resolve_lookups(
+    target: &mut Client,
+    mappings: &mut MappingStore,
+    spec: &EntitySpec,
+    batch: &[SourceRow],
+) -> Result<(), String> {
+    let mut wanted: HashMap<&str, BTreeSet<i64>> = HashMap::new();
+    for row in batch {
+        for (lookup, id) in spec.lookups.iter().zip(&row.lookups) {
+            if let Some(id) = id {
+                wanted.entry(&lookup.entity).or_default().insert(*id);
+            }
+        }
+    }
+    for (entity, ids) in wanted {
+        mappings.resolve(target, entity, ids)?;
+    }
+    Ok(())
+}
+
+/// Target columns in insert order, with the key last when it is assigned.
+fn insert_columns(spec: &EntitySpec, assign_key: bool) -> Vec<&str> {
+    let mut columns = scaffold::target_columns(spec);
+    if assign_key {
+        columns.push(&spec.target_key);
+    }
+    columns
+}
+
+/// The `ON CONFLICT` clause shared by both load paths: refresh every target
+/// column except the legacy ID, like the generated TypeScript migrations.
+fn on_conflict(spec: &EntitySpec) -> String {
+    let columns = scaffold::target_columns(spec);
+    let mut updates: Vec<String> = columns[1..]
+        .iter()
+        .map(|c| format!("{0} = EXCLUDED.{0}", c))
+        .collect();
+    if updates.is_empty() {
+        updates.push(format!("{0} = EXCLUDED.{0}", columns[0]));
+    }
+    format!(
+        "ON CONFLICT ({legacy}) DO UPDATE SET {updates}
+RETURNING {key}::text, {legacy}::bigint",
+        legacy = spec.legacy_id_column,
+        updates = updates.join(", "),
+        key = spec.target_key,
+    )
+}
+
+/// A set-based upsert over one array parameter per target column.
+fn upsert_sql(spec: &EntitySpec, types: &HashMap<String, String>, assign_key: bool) -> String {
+    let columns = insert_columns(spec, assign_key);
+    let arrays: Vec<String> = (0..columns.len())
+        .map(|i| match i {
+            0 => "$1::bigint[]".to_string(),
+            _ if assign_key && i == columns.len() - 1 => format!("${}::uuid[]", i + 1),
+            _ => format!("${}::text[]", i + 1),
+        })
+        .collect();
+    let aliases: Vec<String> = (0..columns.len()).map(|i| format!("c{}", i)).collect();
+    let casts: Vec<String> = columns
+        .iter()
+        .zip(&aliases)
+        .map(|(column, alias)| format!("CAST(u.{} AS {})", alias, types[*column]))
+        .collect();
+    format!(
+        "INSERT INTO {table} ({columns})
+SELECT {casts}
+FROM UNNEST({arrays}) AS u({aliases})
+{on_conflict}",
+        table = spec.target_table,
+        columns = columns.join(", "),
+        casts = casts.join(", "),
+        arrays = arrays.join(", "),
+        aliases = aliases.join(", "),
+        on_conflict = on_conflict(spec),
+    )
+}
+
+/// Merge the staging table into the target, joining `migration_mappings`
+/// once per lookup; rows missing a required mapping are left out.
+fn merge_sql(spec: &EntitySpec, types: &HashMap<String, String>, assign_key: bool) -> String {
+    let columns = insert_columns(spec, assign_key);
+    let mut select = vec![format!(
+        "CAST(s.legacy_id AS {})",
+        types[&spec.legacy_id_column]
+    )];
+    select.extend(
+        spec.columns
+            .iter()
+            .enumerate()
+            .map(|(i, c)| format!("CAST(s.c{} AS {})", i, types[&c.target])),
+    );
+    select.extend(
+        spec.lookups
+            .iter()
+            .enumerate()
+            .map(|(i, l)| format!("CAST(m{}.new_id AS {})", i, types[&l.target])),
+    );
+    if assign_key {
+        select.push("s.new_id".to_string());
+    }
+    let joins: Vec<String> = spec
+        .lookups
+        .iter()
+        .enumerate()
+        .map(|(i, l)| {
+            format!(
+                "LEFT JOIN migration_mappings m{i} ON m{i}.entity_type = {} AND m{i}.legacy_id = s.l{i}",
+                literal(&l.entity),
+            )
+        })
+        .collect();
+    let required: Vec<String> = spec
+        .lookups
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| l.required)
+        .map(|(i, _)| format!("m{}.new_id IS NOT NULL", i))
+        .collect();
+    let mut sql = format!(
+        "INSERT INTO {} ({})\nSELECT {}\nFROM {} s",
+        spec.target_table,
+        columns.join(", "),
+        select.join(", "),
+        STAGE_TABLE
+    );
+    for join in &joins {
+        sql.push('\n');
+        sql.push_str(join);
+    }
+    if !required.is_empty() {
+        sql.push_str(&format!("\nWHERE {}", required.join(" AND ")));
+    }
+    sql.push('\n');
+    sql.push_str(&on_conflict(spec));
+    sql
+}
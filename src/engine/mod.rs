@@ -0,0 +1,419 @@
+//! Native executor for one entity spec.
+//!
+//! The source table is streamed through a server-side cursor inside a
+//! read-only, repeatable-read transaction, so memory stays bounded by the
+//! batch size no matter how large the table is. Each batch is transformed per
+//! the spec, its legacy FKs are resolved through a [`MappingStore`] (the
+//! referenced entity types are preloaded once per run), and it is upserted
+//! into the target together with its lineage rows in one transaction: a
+//! batch is either fully applied or not at all.
+//!
+//! Progress is recorded in `migration_control` (phase `execution`, operation
+//! `migrate`) when the target has the production table; the rollback scripts
+//! leave those rows alone and add one of phase `rollback`. Every batch also
+//! moves the entity's checkpoint (see [`checkpoint`]), which is what
+//! `run --resume` continues from.
+
+pub mod checkpoint;
+pub mod conflict;
+mod connect;
+mod load;
+mod transform;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use postgres::types::ToSql;
+use postgres::{Client, IsolationLevel, Row};
+use serde::Serialize;
+
+use crate::mapping::MappingStore;
+use crate::scaffold;
+use crate::spec::EntitySpec;
+
+use checkpoint::Progress;
+pub use connect::{Side, TlsMode, connect, describe};
+use load::Loader;
+pub use load::{LoadMode, RowFailure};
+use transform::SourceRow;
+pub use transform::{column_value, default_text};
+
+#[derive(Debug)]
+pub struct EngineError {
+    pub entity: String,
+    pub message: String,
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.entity, self.message)
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    pub batch_size: usize,
+    pub load: LoadMode,
+    /// Stop after this many source rows.
+    pub limit: Option<u64>,
+    /// Read, transform and resolve lookups, but write nothing.
+    pub dry_run: bool,
+    /// `migration_mappings.migration_batch` for every row of this run.
+    pub migration_batch: String,
+    /// Only read source rows with a greater key (a resumed run).
+    pub resume_after: Option<i64>,
+    /// Only read source rows with these keys (a run over detected changes).
+    pub only: Option<Vec<i64>>,
+    /// Stop after the batch in progress once this is set (e.g. by SIGTERM).
+    pub stop: Option<&'static AtomicBool>,
+    /// Stop after the first batch that ends past this instant.
+    pub deadline: Option<Instant>,
+}
+
+impl RunOptions {
+    fn stopping(&self) -> bool {
+        self.stop.is_some_and(|stop| stop.load(Ordering::SeqCst))
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RunStats {
+    pub entity: String,
+    pub migration_batch: String,
+    pub batches: u64,
+    pub read: u64,
+    pub upserted: u64,
+    /// Rows dropped because a required lookup had no mapping.
+    pub skipped: u64,
+    /// Rows the target rejected; the rest of their batch was still written.
+    pub failed: u64,
+    /// Batches that failed as a whole and were retried row by row.
+    pub fallback_batches: u64,
+    /// The first [`MAX_FAILURES`] rejected rows.
+    pub failures: Vec<RowFailure>,
+    pub last_legacy_id: Option<i64>,
+    pub elapsed_ms: u64,
+    pub dry_run: bool,
+    pub resumed_after: Option<i64>,
+    /// Mappings loaded up front for the spec's lookups.
+    pub preloaded_mappings: u64,
+    /// The run stopped early on request; the checkpoint is where it stopped.
+    pub stopped: bool,
+    /// Column values that differed from the existing target row.
+    pub conflicts: u64,
+    /// Rows queued in `migration_conflict_queue` for a manual decision.
+    pub queued_conflicts: u64,
+}
+
+/// Rejected rows kept for the report; the count is always exact.
+pub const MAX_FAILURES: usize = 100;
+
+/// The batch name the TypeScript migrations use:
+/// `<entity_type>_migration_<YYYYMMDDHHMMSS>`.
+pub fn default_migration_batch(spec: &EntitySpec) -> String {
+    format!(
+        "{}_migration_{}",
+        spec.entity_type(),
+        chrono::Utc::now().format("%Y%m%d%H%M%S")
+    )
+}
+
+/// Run `spec` from `source` into `target`, calling `progress` after every
+/// batch. Mappings of written rows are added to `mappings`.
+pub fn run(
+    spec: &EntitySpec,
+    source: &mut Client,
+    target: &mut Client,
+    mappings: &mut MappingStore,
+    options: &RunOptions,
+    mut progress: impl FnMut(&RunStats),
+) -> Result<RunStats, EngineError> {
+    let started = Instant::now();
+    let fail = |message: String| EngineError {
+        entity: spec.name.clone(),
+        message,
+    };
+    let types = target_types(target, spec).map_err(fail)?;
+    let select = select_sql(spec, options);
+    let loader = Loader::new(target, spec, &types, options.load).map_err(fail)?;
+    let mut referenced: Vec<&str> = spec.lookups.iter().map(|l| l.entity.as_str()).collect();
+    referenced.sort_unstable();
+    referenced.dedup();
+    let preloaded = mappings.preload(target, &referenced).map_err(fail)?;
+    let mut stats = RunStats {
+        entity: spec.name.clone(),
+        migration_batch: options.migration_batch.clone(),
+        batches: 0,
+        read: 0,
+        upserted: 0,
+        skipped: 0,
+        failed: 0,
+        fallback_batches: 0,
+        failures: Vec::new(),
+        last_legacy_id: None,
+        elapsed_ms: 0,
+        dry_run: options.dry_run,
+        resumed_after: options.resume_after,
+        preloaded_mappings: preloaded as u64,
+        stopped: false,
+        conflicts: 0,
+        queued_conflicts: 0,
+    };
+
+    let control = if options.dry_run {
+        None
+    } else {
+        checkpoint::start(target, spec, options).map_err(fail)?;
+        if spec.conflicts.is_some() {
+            conflict::ensure_tables(target).map_err(fail)?;
+        }
+        control_start(target, spec, options, &select).map_err(fail)?
+    };
+
+    let result = (|| -> Result<(), String> {
+        let mut reader = source
+            .build_transaction()
+            .isolation_level(IsolationLevel::RepeatableRead)
+            .read_only(true)
+            .start()
+            .map_err(|e| format!("cannot open source transaction: {}", describe(&e)))?;
+        let params: Vec<&(dyn ToSql + Sync)> = options
+            .only
+            .iter()
+            .map(|keys| keys as &(dyn ToSql + Sync))
+            .collect();
+        let cursor = reader
+            .bind(select.as_str(), &params)
+            .map_err(|e| format!("cannot read {}: {}", spec.source_table, describe(&e)))?;
+        let fetch = i32::try_from(options.batch_size).unwrap_or(i32::MAX);
+        loop {
+            let rows = reader
+                .query_portal(&cursor, fetch)
+                .map_err(|e| format!("cannot read {}: {}", spec.source_table, describe(&e)))?;
+            if rows.is_empty() {
+                break;
+            }
+            let batch = rows
+                .iter()
+                .map(|row| source_row(spec, row))
+                .collect::<Result<Vec<_>, _>>()?;
+            let position = Progress {
+                control,
+                processed: stats.read + batch.len() as u64,
+                rows: batch.len() as u64,
+                last_key: batch[batch.len() - 1].legacy_id,
+            };
+            let outcome = loader.load(target, mappings, &batch, options, &position)?;
+
+            stats.batches += 1;
+            stats.read += batch.len() as u64;
+            stats.upserted += outcome.written as u64;
+            stats.failed += outcome.failures.len() as u64;
+            stats.skipped += (batch.len() - outcome.written - outcome.failures.len()) as u64;
+            stats.fallback_batches += u64::from(outcome.fell_back);
+            stats.conflicts += outcome.resolution.conflicts;
+            stats.queued_conflicts += outcome.resolution.queued;
+            let room = MAX_FAILURES.saturating_sub(stats.failures.len());
+            stats
+                .failures
+                .extend(outcome.failures.into_iter().take(room));
+            stats.last_legacy_id = batch.last().map(|r| r.legacy_id);
+            stats.elapsed_ms = started.elapsed().as_millis() as u64;
+            progress(&stats);
+            if options.stopping() {
+                stats.stopped = true;
+                break;
+            }
+        }
+        reader
+            .commit()
+            .map_err(|e| format!("cannot close source transaction: {}", describe(&e)))
+    })();
+
+    stats.elapsed_ms = started.elapsed().as_millis() as u64;
+    let recorded = if options.dry_run {
+        Ok(())
+    } else {
+        let rejected =
+            (stats.failed > 0).then(|| format!("{} row(s) rejected by the target", stats.failed));
+        let (status, message) = match &result {
+            Err(e) => ("failed", Some(e)),
+            Ok(()) if stats.stopped => ("stopped", rejected.as_ref()),
+            Ok(()) => ("completed", rejected.as_ref()),
+        };
+        let finished = checkpoint::finish(target, spec, status, message);
+        let controlled = control.map_or(Ok(()), |id| control_finish(target, id, status, message));
+        finished.and(controlled)
+    };
+    // A run that failed reports why, even when recording the failure failed
+    // too (e.g. the connection dropped).
+    match (result, recorded) {
+        (Ok(()), Ok(())) => Ok(stats),
+        (Err(cause), Err(e)) => Err(fail(format!(
+            "{} (recording the failure also failed: {})",
+            cause, e
+        ))),
+        (Err(e), Ok(())) | (Ok(()), Err(e)) => Err(fail(e)),
+    }
+}
+
+/// Target column types, so text values can be cast back on insert.
+pub fn target_types(
+    target: &mut Client,
+    spec: &EntitySpec,
+) -> Result<HashMap<String, String>, String> {
+    let rows = target
+        .query(
+            "SELECT a.attname::text, format_type(a.atttypid, a.atttypmod)
+             FROM pg_attribute a
+             WHERE a.attrelid = to_regclass($1)
+               AND a.attnum > 0
+               AND NOT a.attisdropped",
+            &[&spec.target_table],
+        )
+        .map_err(|e| {
+            format!(
+                "cannot read {} columns: {}",
+                spec.target_table,
+                describe(&e)
+            )
+        })?;
+    if rows.is_empty() {
+        return Err(format!("target table {} not found", spec.target_table));
+    }
+    let types: HashMap<String, String> = rows.iter().map(|r| (r.get(0), r.get(1))).collect();
+    let missing: Vec<&str> = scaffold::target_columns(spec)
+        .into_iter()
+        .chain([spec.target_key.as_str()])
+        .filter(|c| !types.contains_key(*c))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "target table {} has no column(s) {}",
+            spec.target_table,
+            missing.join(", ")
+        ));
+    }
+    Ok(types)
+}
+
+/// The source query; with `options.only` it takes the keys as `$1`.
+fn select_sql(spec: &EntitySpec, options: &RunOptions) -> String {
+    let mut select = vec![format!("s.{}::bigint", spec.source_key)];
+    select.extend(spec.columns.iter().map(|c| format!("s.{}::text", c.source)));
+    select.extend(
+        spec.lookups
+            .iter()
+            .map(|l| format!("s.{}::bigint", l.source)),
+    );
+    let mut sql = format!("SELECT {}\nFROM {} s", select.join(", "), spec.source_table);
+    let mut conditions = Vec::new();
+    if let Some(filter) = &spec.filter {
+        conditions.push(format!("({})", filter));
+    }
+    if let Some(after) = options.resume_after {
+        conditions.push(format!("s.{} > {}", spec.source_key, after));
+    }
+    if options.only.is_some() {
+        conditions.push(format!("s.{} = ANY($1::bigint[])", spec.source_key));
+    }
+    if !conditions.is_empty() {
+        sql.push_str(&format!("\nWHERE {}", conditions.join(" AND ")));
+    }
+    sql.push_str(&format!("\nORDER BY s.{}", spec.source_key));
+    if let Some(limit) = options.limit {
+        sql.push_str(&format!("\nLIMIT {}", limit));
+    }
+    sql
+}
+
+fn source_row(spec: &EntitySpec, row: &Row) -> Result<SourceRow, String> {
+    let bad = |e: postgres::Error| format!("unexpected value in {}: {}", spec.source_table, e);
+    let legacy_id: Option<i64> = row.try_get(0).map_err(bad)?;
+    let legacy_id = legacy_id.ok_or_else(|| {
+        format!(
+            "{}.{} is NULL; the source key must be set on every row",
+            spec.source_table, spec.source_key
+        )
+    })?;
+    let columns = (0..spec.columns.len())
+        .map(|i| row.try_get(1 + i))
+        .collect::<Result<_, _>>()
+        .map_err(bad)?;
+    let offset = 1 + spec.columns.len();
+    let lookups = (0..spec.lookups.len())
+        .map(|i| row.try_get(offset + i))
+        .collect::<Result<_, _>>()
+        .map_err(bad)?;
+    Ok(SourceRow {
+        legacy_id,
+        columns,
+        lookups,
+    })
+}
+
+/// Open a `migration_control` row if the target has the production table.
+fn control_start(
+    target: &mut Client,
+    spec: &EntitySpec,
+    options: &RunOptions,
+    select: &str,
+) -> Result<Option<i32>, String> {
+    let present: bool = target
+        .query_one(
+            "SELECT count(*) = 3 FROM information_schema.columns
+             WHERE table_schema = current_schema()
+               AND table_name = 'migration_control'
+               AND column_name IN ('phase', 'table_name', 'records_processed')",
+            &[],
+        )
+        .map_err(|e| format!("cannot inspect migration_control: {}", describe(&e)))?
+        .get(0);
+    if !present {
+        return Ok(None);
+    }
+    let total = spec
+        .estimated_records
+        .map(|n| n.min(i32::MAX as u64) as i32);
+    let batch_size = options.batch_size.min(i32::MAX as usize) as i32;
+    let row = target
+        .query_one(
+            "INSERT INTO migration_control
+               (phase, table_name, operation, status, records_processed, total_records,
+                started_at, batch_size, source_query)
+             VALUES ('execution', $1, 'migrate', 'running', 0, $2, NOW(), $3, $4)
+             RETURNING id",
+            &[&spec.target_table, &total, &batch_size, &select],
+        )
+        .map_err(|e| {
+            format!(
+                "cannot record the run in migration_control: {}",
+                describe(&e)
+            )
+        })?;
+    Ok(Some(row.get(0)))
+}
+
+fn control_finish(
+    target: &mut Client,
+    id: i32,
+    status: &str,
+    error: Option<&String>,
+) -> Result<(), String> {
+    target
+        .execute(
+            "UPDATE migration_control
+             SET status = $2, completed_at = NOW(), error_message = $3
+             WHERE id = $1",
+            &[&id, &status, &error],
+        )
+        .map_err(|e| format!("cannot update migration_control: {}", describe(&e)))?;
+    Ok(())
+}
@@ -0,0 +1,74 @@
+//! Per-row transformation of source values according to the entity spec.
+//!
+//! Values travel as text in both directions: the source query casts every
+//! mapped column to `text` and the target insert casts back to the column's
+//! own type. That keeps the executor independent of the legacy column types
+//! and gives the same results as Postgres' own I/O conversions.
+
+use serde_json::Value;
+
+use crate::mapping::MappingStore;
+use crate::spec::{ColumnMap, EntitySpec, Transform};
+
+/// One source row as read by the executor's cursor.
+#[derive(Debug)]
+pub struct SourceRow {
+    pub legacy_id: i64,
+    /// One value per `spec.columns` entry.
+    pub columns: Vec<Option<String>>,
+    /// One legacy ID per `spec.lookups` entry.
+    pub lookups: Vec<Option<i64>>,
+}
+
+/// Target values in `scaffold::target_columns` order, without the legacy ID,
+/// or `None` when a required lookup is missing and the row is skipped.
+pub fn transform_row(
+    spec: &EntitySpec,
+    row: &SourceRow,
+    mappings: &MappingStore,
+) -> Option<Vec<Option<String>>> {
+    let mut values = Vec::with_capacity(spec.columns.len() + spec.lookups.len());
+    for (column, value) in spec.columns.iter().zip(&row.columns) {
+        values.push(column_value(column, value.clone()));
+    }
+    for (lookup, legacy) in spec.lookups.iter().zip(&row.lookups) {
+        let resolved = legacy
+            .and_then(|id| mappings.get(&lookup.entity, id))
+            .map(|id| id.to_string());
+        if resolved.is_none() && lookup.required {
+            return None;
+        }
+        values.push(resolved);
+    }
+    Some(values)
+}
+
+/// Apply the column's transform, then its default when the result is NULL.
+pub fn column_value(column: &ColumnMap, value: Option<String>) -> Option<String> {
+    let value = match (column.transform, value) {
+        (_, None) => None,
+        (None, value) => value,
+        (Some(Transform::Trim), Some(s)) => Some(s.trim().to_string()),
+        (Some(Transform::Lowercase), Some(s)) => Some(s.to_lowercase()),
+        (Some(Transform::Uppercase), Some(s)) => Some(s.to_uppercase()),
+        (Some(Transform::NullIfEmpty), Some(s)) if s.is_empty() => None,
+        (Some(Transform::NullIfEmpty), value) => value,
+        // json/jsonb sources already arrive as JSON text; anything else is
+        // encoded as a JSON string.
+        (Some(Transform::Json), Some(s)) => match serde_json::from_str::<Value>(&s) {
+            Ok(_) => Some(s),
+            Err(_) => Some(Value::String(s).to_string()),
+        },
+    };
+    value.or_else(|| column.default.as_ref().and_then(default_text))
+}
+
+pub fn default_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        other => Some(other.to_string()),
+    }
+}
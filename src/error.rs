@@ -0,0 +1,77 @@
+use std::fmt;
+use std::io;
+
+use crate::bundle::BundleError;
+use crate::engine::EngineError;
+use crate::schema::SchemaError;
+use crate::spec::SpecError;
+use crate::template::TemplateError;
+
+/// Top-level error for every subcommand; any of them exits with status 1.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Bundle(BundleError),
+    Spec(SpecError),
+    Template(TemplateError),
+    Schema(SchemaError),
+    Engine(EngineError),
+    Failed(String),
+    /// `--check` found this many files whose content would change.
+    OutOfDate(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Bundle(e) => write!(f, "invalid bundle: {}", e),
+            Error::Spec(e) => write!(f, "invalid entity spec: {}", e),
+            Error::Template(e) => write!(f, "template error: {}", e),
+            Error::Schema(e) => write!(f, "invalid schema: {}", e),
+            Error::Engine(e) => write!(f, "migration failed: {}", e),
+            Error::Failed(msg) => write!(f, "{}", msg),
+            Error::OutOfDate(n) => write!(f, "{} file(s) are out of date", n),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<BundleError> for Error {
+    fn from(e: BundleError) -> Self {
+        Error::Bundle(e)
+    }
+}
+
+impl From<SpecError> for Error {
+    fn from(e: SpecError) -> Self {
+        Error::Spec(e)
+    }
+}
+
+impl From<TemplateError> for Error {
+    fn from(e: TemplateError) -> Self {
+        Error::Template(e)
+    }
+}
+
+impl From<SchemaError> for Error {
+    fn from(e: SchemaError) -> Self {
+        Error::Schema(e)
+    }
+}
+
+impl From<EngineError> for Error {
+    fn from(e: EngineError) -> Self {
+        Error::Engine(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -1,24 +1,70 @@
+mod atomic_write;
+mod audit;
+mod bundle;
+mod cli;
+mod commands;
+mod config;
+mod daemon;
+mod diff;
+mod differential;
+mod engine;
+mod error;
+mod mapping;
+mod output;
+mod plan;
+mod report;
+mod scaffold;
+mod schema;
+mod spec;
+mod suggest;
+mod template;
+mod validate;
+
 use std::env;
-use std::fs;
-use std::io::{self, Read};
 use std::process;
 
+use clap::Parser;
+
+use cli::{Cli, Command};
+use output::Output;
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <filename>", args[0]);
-        eprintln!("Content will be read from stdin");
-        process::exit(1);
-    }
-    let filename = &args[1];
-    let mut content = String::new();
-    io::stdin().read_to_string(&mut content).unwrap_or_else(|e| {
-        eprintln!("Error reading stdin: {}", e);
-        process::exit(1);
+    let cli = Cli::parse_from(cli::normalize_legacy_args(env::args_os().collect()));
+    let out = Output::new(cli.quiet, cli.json);
+    config::select(config::Selection {
+        path: cli.config.clone(),
+        profile: cli.profile,
     });
-    fs::write(filename, content).unwrap_or_else(|e| {
-        eprintln!("Error writing file: {}", e);
+
+    let result = match &cli.command {
+        Command::Write(args) => commands::write::run(args, &out),
+        Command::Bundle(args) => commands::bundle::run(args, &out),
+        Command::Scaffold(args) => commands::scaffold::run(args, &out),
+        Command::Introspect(args) => commands::introspect::run(args, &out),
+        Command::MapSuggest(args) => commands::map_suggest::run(args, &out),
+        Command::Plan(args) => commands::plan::run(args, &out),
+        Command::Run(args) => commands::run::run(args, &out),
+        Command::Mappings(args) => commands::mappings::run(args, &out),
+        Command::Uuid(args) => commands::uuid::run(args, &out),
+        Command::Checkpoints(args) => commands::checkpoints::run(args, &out),
+        Command::Differential(args) => commands::differential::run(args, &out),
+        Command::Reconcile(args) => commands::reconcile::run(args, &out),
+        Command::Resolve(args) => commands::resolve::run(args, &out),
+        Command::Tombstones(args) => commands::tombstones::run(args, &out),
+        Command::Daemon(args) => commands::daemon::run(args, &out),
+        Command::Config(args) => commands::config::run(args, &out),
+        Command::Validate(args) => commands::validate::run(args, &out),
+        Command::Verify(args) => commands::verify::run(args, &out),
+        Command::Audit(args) => commands::audit::run(args, &out),
+        Command::Report(args) => commands::report::run(args, &out),
+    };
+
+    if let Err(e) = result {
+        if out.is_json() {
+            eprintln!("{}", serde_json::json!({ "error": e.to_string() }));
+        } else {
+            eprintln!("Error: {}", e);
+        }
         process::exit(1);
-    });
-    println!("✓ Created: {} ({} bytes)", filename, fs::metadata(filename).unwrap().len());
+    }
 }
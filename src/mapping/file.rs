@@ -0,0 +1,250 @@
+//! Binary export format for a [`MappingStore`].
+//!
+//! All integers are little-endian:
+//!
+//! ```text
+//! magic      b"MGMAP\n" and a u16 format version
+//! batches    u32 count, then per batch: u16 length + UTF-8 name
+//! entities   u32 count, then per entity type:
+//!              u16 length + UTF-8 name, u64 row count,
+//!              per row: i64 legacy_id, 16-byte new_id, u32 batch index
+//! checksum   SHA-256 of everything above
+//! ```
+//!
+//! Rows are written sorted by entity type and legacy ID, so the same store
+//! always produces the same bytes.
+
+use std::fs;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::{Mapping, MappingStore};
+use crate::atomic_write::{WriteOptions, write_atomic};
+
+const MAGIC: &[u8; 6] = b"MGMAP\n";
+const FORMAT_VERSION: u16 = 1;
+
+pub fn write(store: &MappingStore, path: &Path) -> Result<usize, String> {
+    let mut out = Vec::with_capacity(64 + store.len() * 28);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    out.extend_from_slice(&(store.batches.len() as u32).to_le_bytes());
+    for batch in &store.batches {
+        put_str(&mut out, batch)?;
+    }
+
+    let mut entities: Vec<(&String, _)> = store.entities.iter().collect();
+    entities.sort_by_key(|(name, _)| *name);
+    out.extend_from_slice(&(entities.len() as u32).to_le_bytes());
+    for (name, ids) in entities {
+        put_str(&mut out, name)?;
+        out.extend_from_slice(&(ids.len() as u64).to_le_bytes());
+        let mut rows: Vec<(&i64, &Mapping)> = ids.iter().collect();
+        rows.sort_by_key(|(legacy, _)| **legacy);
+        for (legacy, mapping) in rows {
+            out.extend_from_slice(&legacy.to_le_bytes());
+            out.extend_from_slice(mapping.new_id.as_bytes());
+            out.extend_from_slice(&mapping.batch.to_le_bytes());
+        }
+    }
+    let checksum = Sha256::digest(&out);
+    out.extend_from_slice(&checksum);
+
+    write_atomic(path, &out, WriteOptions::default())
+        .map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    Ok(store.len())
+}
+
+pub fn read(path: &Path) -> Result<MappingStore, String> {
+    let bytes = fs::read(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let invalid = |what: &str| format!("{} is not a mapping export: {}", path.display(), what);
+    if bytes.len() < MAGIC.len() + 2 + 32 || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(invalid("bad header"));
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - 32);
+    if Sha256::digest(body).as_slice() != checksum {
+        return Err(invalid("checksum mismatch (truncated or corrupted)"));
+    }
+
+    let mut reader = Reader {
+        bytes: body,
+        pos: MAGIC.len(),
+    };
+    let version = u16::from_le_bytes(reader.take()?);
+    if version != FORMAT_VERSION {
+        return Err(format!(
+            "{} uses mapping format version {}; this build reads version {}",
+            path.display(),
+            version,
+            FORMAT_VERSION
+        ));
+    }
+
+    let mut store = MappingStore::new();
+    let batches = u32::from_le_bytes(reader.take()?);
+    for _ in 0..batches {
+        let batch = reader.string()?;
+        store.intern(&batch);
+    }
+    let entities = u32::from_le_bytes(reader.take()?);
+    for _ in 0..entities {
+        let name = reader.string()?;
+        let count = u64::from_le_bytes(reader.take()?);
+        let ids = store.entities.entry(name.clone()).or_default();
+        ids.reserve(count.min(1 << 24) as usize);
+        for _ in 0..count {
+            let legacy = i64::from_le_bytes(reader.take()?);
+            let new_id = Uuid::from_bytes(reader.take()?);
+            let batch = u32::from_le_bytes(reader.take()?);
+            if batch as usize >= store.batches.len() {
+                return Err(invalid("batch index out of range"));
+            }
+            ids.insert(legacy, Mapping { new_id, batch });
+        }
+        store.complete.insert(name);
+    }
+    if reader.pos != body.len() {
+        return Err(invalid("trailing data"));
+    }
+    Ok(store)
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) -> Result<(), String> {
+    let len = u16::try_from(s.len()).map_err(|_| format!("name too long to export: '{}'", s))?;
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let end = self.pos + N;
+        let slice = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or("mapping export ends unexpectedly")?;
+        self.pos = end;
+        Ok(slice.try_into().expect("slice has length N"))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = u16::from_le_bytes(self.take()?) as usize;
+        let end = self.pos + len;
+        let slice = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or("mapping export ends unexpectedly")?;
+        self.pos = end;
+        String::from_utf8(slice.to_vec()).map_err(|_| "mapping export has a non-UTF-8 name".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mapping-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn store() -> MappingStore {
+        let mut store = MappingStore::new();
+        store.insert("patient", 20, Uuid::from_u128(20), "batch-2");
+        store.insert("office", 2, Uuid::from_u128(2), "batch-1");
+        store.insert("office", -1, Uuid::from_u128(u128::MAX), "batch-2");
+        store.insert("office", i64::MAX, Uuid::from_u128(3), "");
+        store.insert("patient", 10, Uuid::from_u128(10), "batch-1");
+        store
+    }
+
+    #[test]
+    fn round_trips() {
+        let dir = temp_dir("round-trip");
+        let path = dir.join("mappings.bin");
+        let original = store();
+        assert_eq!(write(&original, &path).unwrap(), 5);
+        let bytes = fs::read(&path).unwrap();
+        // Header, 3 batch names, then "office" and "patient" with a row
+        // count each and 28 bytes per row, then the checksum.
+        let batches = 4 + (2 + 7) * 2 + 2;
+        let entities = 4 + (2 + 6 + 8) + (2 + 7 + 8) + 5 * 28;
+        assert_eq!(bytes.len(), 8 + batches + entities + 32);
+
+        let imported = read(&path).unwrap();
+        assert_eq!(imported.counts(), original.counts());
+        for (entity, legacy) in [
+            ("office", 2),
+            ("office", -1),
+            ("office", i64::MAX),
+            ("patient", 10),
+            ("patient", 20),
+        ] {
+            assert_eq!(imported.get(entity, legacy), original.get(entity, legacy));
+            assert_eq!(
+                imported.batch(entity, legacy),
+                original.batch(entity, legacy)
+            );
+        }
+        // Imported entity types are complete: nothing is fetched for them.
+        assert_eq!(
+            imported.complete.iter().collect::<Vec<_>>(),
+            ["office", "patient"]
+        );
+
+        // The same store always produces the same bytes.
+        write(&imported, &path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_damaged_files() {
+        let dir = temp_dir("damaged");
+        let path = dir.join("mappings.bin");
+        write(&store(), &path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        let body = bytes.len() - 32;
+        let resealed = |mut body: Vec<u8>| {
+            let checksum = Sha256::digest(&body);
+            body.extend_from_slice(&checksum);
+            body
+        };
+
+        let mut flipped = bytes.clone();
+        flipped[40] ^= 1;
+        let mut version = bytes[..body].to_vec();
+        version[6] = 2;
+        let mut batch = bytes[..body].to_vec();
+        batch[body - 1] = 9;
+        let mut trailing = bytes[..body].to_vec();
+        trailing.push(0);
+        let cases = [
+            (flipped, "checksum mismatch"),
+            (bytes[..bytes.len() - 1].to_vec(), "checksum mismatch"),
+            (bytes[..20].to_vec(), "bad header"),
+            (b"NOTMAP".repeat(8), "bad header"),
+            (resealed(version), "uses mapping format version 2"),
+            (resealed(batch), "batch index out of range"),
+            (resealed(trailing), "trailing data"),
+            (resealed(bytes[..body - 4].to_vec()), "ends unexpectedly"),
+        ];
+        for (i, (contents, expected)) in cases.into_iter().enumerate() {
+            fs::write(&path, contents).unwrap();
+            let error = read(&path).err().unwrap_or_default();
+            assert!(error.contains(expected), "case {}: {}", i, error);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,339 @@
+//! Legacy ID → UUID mappings backed by `migration_mappings`.
+//!
+//! [`MappingStore`] replaces the per-lookup round trips of `UUIDMapperService`
+//! (`src/lib/uuid-mapper.ts`): whole entity types are preloaded into one hash
+//! map each, and IDs that are not cached are fetched in a single query per
+//! batch. New rows get a deterministic UUIDv5 derived from
+//! `(entity_type, legacy_id)`, so a re-run after a wipe reproduces the same
+//! UUIDs instead of minting fresh ones. A store can be exported to a compact
+//! binary file and imported again for runs without database access.
+
+mod file;
+pub mod registry;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+use postgres::Client;
+use uuid::Uuid;
+
+use crate::engine::describe;
+
+/// Root of every generated namespace: UUIDv5 of
+/// `migration_generator:migration_mappings` in the URL namespace.
+pub const ROOT_NAMESPACE: Uuid = Uuid::from_u128(0xa292baec_eab0_5417_9c3d_1a4ff73f5ef7);
+
+/// Legacy IDs per query when fetching misses.
+const MISS_CHUNK: usize = 10_000;
+
+/// Rows per round trip when preloading.
+const PRELOAD_FETCH: i32 = 50_000;
+
+/// The namespace UUIDs of `entity_type` are generated in.
+pub fn entity_namespace(entity_type: &str) -> Uuid {
+    Uuid::new_v5(&ROOT_NAMESPACE, entity_type.as_bytes())
+}
+
+/// The UUID a never-migrated row of `entity_type` gets.
+pub fn deterministic_id(entity_type: &str, legacy_id: i64) -> Uuid {
+    Uuid::new_v5(
+        &entity_namespace(entity_type),
+        legacy_id.to_string().as_bytes(),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Mapping {
+    new_id: Uuid,
+    /// Index into `MappingStore::batches`.
+    batch: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct MappingStore {
+    entities: HashMap<String, HashMap<i64, Mapping>>,
+    batches: Vec<String>,
+    batch_index: HashMap<String, u32>,
+    /// Entity types whose every mapping has been loaded.
+    complete: BTreeSet<String>,
+}
+
+impl MappingStore {
+    pub fn new() -> Self {
+        MappingStore::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.values().map(HashMap::len).sum()
+    }
+
+    /// Cached mappings per entity type.
+    pub fn counts(&self) -> BTreeMap<&str, usize> {
+        self.entities
+            .iter()
+            .map(|(entity, ids)| (entity.as_str(), ids.len()))
+            .collect()
+    }
+
+    pub fn get(&self, entity_type: &str, legacy_id: i64) -> Option<Uuid> {
+        self.entities
+            .get(entity_type)?
+            .get(&legacy_id)
+            .map(|m| m.new_id)
+    }
+
+    /// The `migration_batch` that mapped `legacy_id`.
+    pub fn batch(&self, entity_type: &str, legacy_id: i64) -> Option<&str> {
+        self.entities
+            .get(entity_type)?
+            .get(&legacy_id)
+            .map(|m| self.batches[m.batch as usize].as_str())
+    }
+
+    /// The mapped UUID, or the deterministic one for an unmapped row.
+    pub fn assign(&self, entity_type: &str, legacy_id: i64) -> Uuid {
+        self.get(entity_type, legacy_id)
+            .unwrap_or_else(|| deterministic_id(entity_type, legacy_id))
+    }
+
+    /// The cached mapping with UUID `new_id`: entity type, legacy ID and
+    /// migration batch.
+    pub fn reverse(&self, new_id: Uuid) -> Option<(&str, i64, &str)> {
+        self.entities.iter().find_map(|(entity, ids)| {
+            ids.iter()
+                .find(|(_, m)| m.new_id == new_id)
+                .map(|(legacy, m)| {
+                    (
+                        entity.as_str(),
+                        *legacy,
+                        self.batches[m.batch as usize].as_str(),
+                    )
+                })
+        })
+    }
+
+    pub fn insert(&mut self, entity_type: &str, legacy_id: i64, new_id: Uuid, batch: &str) {
+        let batch = self.intern(batch);
+        self.entities
+            .entry(entity_type.to_string())
+            .or_default()
+            .insert(legacy_id, Mapping { new_id, batch });
+    }
+
+    fn intern(&mut self, batch: &str) -> u32 {
+        if let Some(&i) = self.batch_index.get(batch) {
+            return i;
+        }
+        let i = self.batches.len() as u32;
+        self.batches.push(batch.to_string());
+        self.batch_index.insert(batch.to_string(), i);
+        i
+    }
+
+    /// Load every mapping of `entity_types` in one streamed query; returns
+    /// the number of rows read.
+    pub fn preload(&mut self, client: &mut Client, entity_types: &[&str]) -> Result<usize, String> {
+        let wanted: Vec<&str> = entity_types
+            .iter()
+            .copied()
+            .filter(|e| !self.complete.contains(*e))
+            .collect();
+        if wanted.is_empty() {
+            return Ok(0);
+        }
+        let failed =
+            |e: postgres::Error| format!("cannot preload migration_mappings: {}", describe(&e));
+        let mut tx = client.transaction().map_err(failed)?;
+        let cursor = tx
+            .bind(
+                "SELECT entity_type::text, legacy_id::bigint, new_id, migration_batch::text
+                 FROM migration_mappings
+                 WHERE entity_type = ANY($1) AND legacy_id IS NOT NULL AND new_id IS NOT NULL",
+                &[&wanted],
+            )
+            .map_err(failed)?;
+        let mut read = 0;
+        loop {
+            let rows = tx.query_portal(&cursor, PRELOAD_FETCH).map_err(failed)?;
+            if rows.is_empty() {
+                break;
+            }
+            read += rows.len();
+            for row in &rows {
+                let batch: Option<&str> = row.get(3);
+                self.insert(
+                    row.get(0),
+                    row.get(1),
+                    row.get(2),
+                    batch.unwrap_or_default(),
+                );
+            }
+        }
+        tx.commit().map_err(failed)?;
+        for entity in wanted {
+            self.entities.entry(entity.to_string()).or_default();
+            self.complete.insert(entity.to_string());
+        }
+        Ok(read)
+    }
+
+    /// Fetch the uncached IDs among `legacy_ids`, a chunk per query; returns
+    /// the number found. Preloaded entity types are never queried again.
+    pub fn resolve(
+        &mut self,
+        client: &mut Client,
+        entity_type: &str,
+        legacy_ids: impl IntoIterator<Item = i64>,
+    ) -> Result<usize, String> {
+        if self.complete.contains(entity_type) {
+            return Ok(0);
+        }
+        let cached = self.entities.get(entity_type);
+        let missing: Vec<i64> = legacy_ids
+            .into_iter()
+            .filter(|id| cached.is_none_or(|c| !c.contains_key(id)))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let mut found = 0;
+        for chunk in missing.chunks(MISS_CHUNK) {
+            let rows = client
+                .query(
+                    "SELECT legacy_id::bigint, new_id, migration_batch::text
+                     FROM migration_mappings
+                     WHERE entity_type = $1 AND legacy_id = ANY($2::bigint[]) AND new_id IS NOT NULL",
+                    &[&entity_type, &chunk],
+                )
+                .map_err(|e| format!("cannot resolve {} mappings: {}", entity_type, describe(&e)))?;
+            found += rows.len();
+            for row in &rows {
+                let batch: Option<&str> = row.get(2);
+                self.insert(
+                    entity_type,
+                    row.get(0),
+                    row.get(1),
+                    batch.unwrap_or_default(),
+                );
+            }
+        }
+        Ok(found)
+    }
+
+    /// Fetch the mapping with UUID `new_id` unless it is cached; returns
+    /// whether one exists.
+    pub fn resolve_new_id(&mut self, client: &mut Client, new_id: Uuid) -> Result<bool, String> {
+        if self.reverse(new_id).is_some() {
+            return Ok(true);
+        }
+        let row = client
+            .query_opt(
+                "SELECT entity_type::text, legacy_id::bigint, migration_batch::text
+                 FROM migration_mappings
+                 WHERE new_id = $1 AND legacy_id IS NOT NULL
+                 LIMIT 1",
+                &[&new_id],
+            )
+            .map_err(|e| format!("cannot read migration_mappings: {}", describe(&e)))?;
+        let Some(row) = row else {
+            return Ok(false);
+        };
+        let batch: Option<&str> = row.get(2);
+        self.insert(row.get(0), row.get(1), new_id, batch.unwrap_or_default());
+        Ok(true)
+    }
+
+    /// Every entity type with at least one row in `migration_mappings`.
+    pub fn entity_types(client: &mut Client) -> Result<Vec<String>, String> {
+        let rows = client
+            .query(
+                "SELECT DISTINCT entity_type::text FROM migration_mappings ORDER BY 1",
+                &[],
+            )
+            .map_err(|e| format!("cannot read migration_mappings: {}", describe(&e)))?;
+        Ok(rows.iter().map(|r| r.get(0)).collect())
+    }
+
+    /// Insert every cached mapping into `migration_mappings`, keeping rows
+    /// that already exist; returns the number inserted.
+    pub fn save(&self, client: &mut Client) -> Result<u64, String> {
+        let failed =
+            |e: postgres::Error| format!("cannot write migration_mappings: {}", describe(&e));
+        let mut tx = client.transaction().map_err(failed)?;
+        let mut inserted = 0;
+        for (entity, ids) in &self.entities {
+            let rows: Vec<(&i64, &Mapping)> = ids.iter().collect();
+            for chunk in rows.chunks(MISS_CHUNK) {
+                let legacy_ids: Vec<i64> = chunk.iter().map(|(id, _)| **id).collect();
+                let new_ids: Vec<Uuid> = chunk.iter().map(|(_, m)| m.new_id).collect();
+                let batches: Vec<&str> = chunk
+                    .iter()
+                    .map(|(_, m)| self.batches[m.batch as usize].as_str())
+                    .collect();
+                inserted += tx
+                    .execute(
+                        "INSERT INTO migration_mappings
+                           (entity_type, legacy_id, new_id, migrated_at, migration_batch)
+                         SELECT $1::text, m.legacy_id, m.new_id, NOW(), NULLIF(m.batch, '')
+                         FROM UNNEST($2::bigint[], $3::uuid[], $4::text[]) AS m(legacy_id, new_id, batch)
+                         ON CONFLICT (entity_type, legacy_id) DO NOTHING",
+                        &[entity, &legacy_ids, &new_ids, &batches],
+                    )
+                    .map_err(failed)?;
+            }
+        }
+        tx.commit().map_err(failed)?;
+        Ok(inserted)
+    }
+
+    /// Write every cached mapping to `path` (see [`file`] for the format).
+    pub fn export(&self, path: &Path) -> Result<usize, String> {
+        file::write(self, path)
+    }
+
+    /// Read a store written by [`MappingStore::export`]. Its entity types
+    /// count as fully loaded: an offline run never asks the database.
+    pub fn import(path: &Path) -> Result<Self, String> {
+        file::read(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every UUID already issued depends on these; they must never change.
+    #[test]
+    fn namespaces_are_pinned() {
+        assert_eq!(
+            ROOT_NAMESPACE,
+            Uuid::new_v5(
+                &Uuid::NAMESPACE_URL,
+                b"migration_generator:migration_mappings"
+            )
+        );
+        assert_eq!(
+            entity_namespace("office").to_string(),
+            "f0afffd3-d1b6-5847-a68b-26e242209bc7"
+        );
+        assert_eq!(
+            deterministic_id("office", 1).to_string(),
+            "685040db-3b16-5033-ac96-3126444d3a6b"
+        );
+        assert_ne!(
+            deterministic_id("office", 1),
+            deterministic_id("patient", 1)
+        );
+    }
+
+    #[test]
+    fn assign_prefers_the_mapping() {
+        let mut store = MappingStore::new();
+        let mapped = Uuid::from_u128(7);
+        store.insert("office", 1, mapped, "batch-1");
+        assert_eq!(store.assign("office", 1), mapped);
+        assert_eq!(store.assign("office", 2), deterministic_id("office", 2));
+        assert_eq!(store.batch("office", 1), Some("batch-1"));
+        assert_eq!(store.reverse(mapped), Some(("office", 1, "batch-1")));
+        assert_eq!(store.batch("office", 2), None);
+    }
+}
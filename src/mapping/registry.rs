@@ -0,0 +1,100 @@
+//! Namespace registry: one `migration_mappings.entity_type` per legacy
+//! `dispatch_*` table.
+//!
+//! Every target UUID is `uuid5(namespace(entity_type), legacy_id)`, so the
+//! entity type a table migrates under must never change: a second name for
+//! the same table would mint a second set of UUIDs. Entity types follow the
+//! table name without its `dispatch_` prefix, except where the TypeScript
+//! migrations already wrote mappings under another name (`dispatch_instruction`
+//! rows are `order`s, which leaves `dispatch_order` as `course_order`).
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::entity_namespace;
+
+/// `(source table, entity type)`, sorted by table.
+const DISPATCH_TABLES: &[(&str, &str)] = &[
+    ("dispatch_action", "action"),
+    ("dispatch_agent", "agent"),
+    ("dispatch_bracket", "bracket"),
+    ("dispatch_category", "category"),
+    ("dispatch_client", "client"),
+    ("dispatch_client3d", "client3d"),
+    ("dispatch_comment", "comment"),
+    ("dispatch_course", "course"),
+    ("dispatch_discount", "discount"),
+    ("dispatch_doctorsetting", "doctorsetting"),
+    ("dispatch_doctorsetting_masters", "doctorsetting_masters"),
+    ("dispatch_doctorsetting_sales", "doctorsetting_sales"),
+    ("dispatch_event", "event"),
+    ("dispatch_file", "file"),
+    ("dispatch_globalsetting", "globalsetting"),
+    ("dispatch_instance", "instance"),
+    ("dispatch_instruction", "order"),
+    ("dispatch_jaw", "jaw"),
+    ("dispatch_language", "language"),
+    ("dispatch_link", "link"),
+    ("dispatch_master", "master"),
+    ("dispatch_note", "note"),
+    ("dispatch_notification", "notification"),
+    ("dispatch_offer", "offer"),
+    ("dispatch_office", "office"),
+    ("dispatch_office_doctors", "office_doctors"),
+    ("dispatch_operation", "operation"),
+    ("dispatch_order", "course_order"),
+    ("dispatch_patient", "patient"),
+    ("dispatch_payment", "payment"),
+    ("dispatch_plan", "plan"),
+    ("dispatch_product", "product"),
+    ("dispatch_product_roles", "product_roles"),
+    ("dispatch_product_successors", "product_successors"),
+    ("dispatch_project", "project"),
+    ("dispatch_purchase", "purchase"),
+    ("dispatch_reading", "reading"),
+    ("dispatch_record", "record"),
+    ("dispatch_record_attachments", "record_attachments"),
+    ("dispatch_record_roles", "record_roles"),
+    ("dispatch_role", "role"),
+    ("dispatch_role_permissions", "role_permissions"),
+    ("dispatch_state", "state"),
+    ("dispatch_storage", "storage"),
+    ("dispatch_task", "task"),
+    ("dispatch_template", "template"),
+    ("dispatch_template_edit_roles", "template_edit_roles"),
+    ("dispatch_template_predecessors", "template_predecessors"),
+    ("dispatch_template_products", "template_products"),
+    ("dispatch_template_view_groups", "template_view_groups"),
+    ("dispatch_template_view_roles", "template_view_roles"),
+    ("dispatch_usersetting", "usersetting"),
+    ("dispatch_ware", "ware"),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Registered {
+    pub source_table: &'static str,
+    pub entity_type: &'static str,
+    pub namespace: Uuid,
+}
+
+pub fn all() -> impl Iterator<Item = Registered> {
+    DISPATCH_TABLES.iter().map(|&(table, entity)| Registered {
+        source_table: table,
+        entity_type: entity,
+        namespace: entity_namespace(entity),
+    })
+}
+
+/// The registered entity type of a source table (with or without a schema).
+pub fn entity_type(source_table: &str) -> Option<&'static str> {
+    let table = source_table.rsplit('.').next().unwrap_or(source_table);
+    DISPATCH_TABLES
+        .binary_search_by_key(&table, |&(t, _)| t)
+        .ok()
+        .map(|i| DISPATCH_TABLES[i].1)
+}
+
+/// Look `name` up as an entity type or a source table.
+pub fn find(name: &str) -> Option<Registered> {
+    all().find(|r| r.entity_type == name || r.source_table == name)
+}
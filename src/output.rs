@@ -0,0 +1,57 @@
+//! Human, quiet and JSON output modes shared by all subcommands.
+
+use std::fmt::Display;
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Output {
+    quiet: bool,
+    json: bool,
+}
+
+impl Output {
+    pub fn new(quiet: bool, json: bool) -> Self {
+        Output { quiet, json }
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.json
+    }
+
+    /// Progress and summary lines; suppressed by `--quiet` and `--json`.
+    pub fn line(&self, msg: impl Display) {
+        if !self.quiet && !self.json {
+            // A closed pipe (e.g. `| head`) is not worth a panic.
+            let _ = writeln!(io::stdout().lock(), "{}", msg);
+        }
+    }
+
+    /// Pre-formatted text such as diffs, printed verbatim; suppressed like
+    /// [`Output::line`].
+    pub fn raw(&self, text: impl Display) {
+        if !self.quiet && !self.json {
+            let _ = write!(io::stdout().lock(), "{}", text);
+        }
+    }
+
+    /// Problems that should be seen even with `--quiet`.
+    pub fn warn(&self, msg: impl Display) {
+        if !self.json {
+            eprintln!("{}", msg);
+        }
+    }
+
+    /// Machine-readable result; only printed with `--json`.
+    pub fn report<T: Serialize>(&self, value: &T) {
+        if self.json {
+            match serde_json::to_string_pretty(value) {
+                Ok(text) => {
+                    let _ = writeln!(io::stdout().lock(), "{}", text);
+                }
+                Err(e) => eprintln!("Error serializing report: {}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,895 @@
+//! Entity dependency graph and staged execution plan.
+//!
+//! Edges come from the entity specs (`depends_on` and FK `lookups`) and, when
+//! a schema is available, from foreign keys between the specs' source tables.
+//! Cycles are broken by deferring a nullable FK: the entity is loaded with the
+//! column NULL and the column is backfilled once every stage has run. The
+//! acyclic remainder is layered so that every entity in a stage depends only
+//! on earlier stages and the stage can run in parallel.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use crate::schema::Schema;
+use crate::spec::EntitySpec;
+use crate::suggest::entity_stem;
+
+/// Bumped whenever the serialized plan changes incompatibly.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Node {
+    pub name: String,
+    pub source_table: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_table: Option<String>,
+    /// The spec's hard-coded `dependency_order`, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dependency_order: Option<u32>,
+}
+
+/// Why `from` must run after `to`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Reason {
+    /// Foreign key between the source tables.
+    ForeignKey {
+        columns: Vec<String>,
+        nullable: bool,
+    },
+    /// `lookups` entry in the spec.
+    Lookup { column: String, required: bool },
+    /// `depends_on` entry in the spec.
+    DependsOn,
+}
+
+impl Reason {
+    /// Whether the dependency can be satisfied after the row is inserted.
+    fn deferrable(&self) -> bool {
+        match self {
+            Reason::ForeignKey { nullable, .. } => *nullable,
+            Reason::Lookup { required, .. } => !required,
+            Reason::DependsOn => false,
+        }
+    }
+
+    fn columns(&self) -> Vec<String> {
+        match self {
+            Reason::ForeignKey { columns, .. } => columns.clone(),
+            Reason::Lookup { column, .. } => vec![column.clone()],
+            Reason::DependsOn => Vec::new(),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Reason::ForeignKey { columns, .. } => columns.join(", "),
+            Reason::Lookup { column, .. } => column.clone(),
+            Reason::DependsOn => "depends_on".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub reasons: Vec<Reason>,
+    /// Broken to resolve a cycle; satisfied by a backfill instead of ordering.
+    pub deferred: bool,
+}
+
+/// One dependency cycle, listed so that each entity depends on the next and
+/// the last on the first, with the edge proposed to break it.
+#[derive(Debug, Serialize)]
+pub struct Cycle {
+    pub entities: Vec<String>,
+    #[serde(rename = "break")]
+    pub resolution: CycleBreak,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CycleBreak {
+    pub from: String,
+    pub to: String,
+    pub columns: Vec<String>,
+    /// False when no nullable edge exists and the break needs manual work
+    /// (e.g. a DEFERRABLE constraint or splitting the table).
+    pub safe: bool,
+    pub proposal: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stage {
+    pub stage: usize,
+    pub entities: Vec<PlannedEntity>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlannedEntity {
+    #[serde(flatten)]
+    pub node: Node,
+    pub depends_on: Vec<String>,
+}
+
+/// Column to fill in after all stages, for an edge broken out of a cycle.
+#[derive(Debug, Serialize)]
+pub struct Backfill {
+    pub entity: String,
+    pub columns: Vec<String>,
+    pub references: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Plan {
+    pub format_version: u32,
+    pub stages: Vec<Stage>,
+    pub backfill: Vec<Backfill>,
+    pub cycles: Vec<Cycle>,
+    pub edges: Vec<Edge>,
+    /// Tables referenced by a node's foreign keys that have no node.
+    pub external: Vec<ExternalReference>,
+    /// Specs whose `dependency_order` is not after an entity they depend on.
+    pub order_conflicts: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExternalReference {
+    pub entity: String,
+    pub columns: Vec<String>,
+    pub table: String,
+}
+
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    /// `(from, to)` node indexes → reasons; `from` depends on `to`.
+    edges: BTreeMap<(usize, usize), Vec<Reason>>,
+    external: Vec<ExternalReference>,
+}
+
+impl Graph {
+    /// Build the graph from specs, or from every schema table when `specs`
+    /// is empty.
+    pub fn build(specs: &[EntitySpec], schema: Option<&Schema>) -> Result<Graph, String> {
+        let mut nodes: Vec<Node> = specs
+            .iter()
+            .map(|s| Node {
+                name: s.name.clone(),
+                source_table: s.source_table.clone(),
+                target_table: Some(s.target_table.clone()),
+                dependency_order: Some(s.dependency_order),
+            })
+            .collect();
+        if specs.is_empty()
+            && let Some(schema) = schema
+        {
+            nodes = schema
+                .tables
+                .keys()
+                .map(|t| Node {
+                    name: entity_stem(t).to_string(),
+                    source_table: t.clone(),
+                    target_table: None,
+                    dependency_order: None,
+                })
+                .collect();
+        }
+
+        let by_name: BTreeMap<&str, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.name.as_str(), i))
+            .collect();
+        let by_entity_type: BTreeMap<&str, usize> = specs
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.entity_type(), i))
+            .collect();
+        let by_table: BTreeMap<&str, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.source_table.as_str(), i))
+            .collect();
+
+        let mut graph = Graph {
+            nodes: Vec::new(),
+            edges: BTreeMap::new(),
+            external: Vec::new(),
+        };
+
+        for (i, spec) in specs.iter().enumerate() {
+            for dep in &spec.depends_on {
+                let to = by_name.get(dep.as_str()).ok_or_else(|| {
+                    format!(
+                        "{}: depends_on '{}' is not a known entity",
+                        spec.origin.display(),
+                        dep
+                    )
+                })?;
+                graph.add(i, *to, Reason::DependsOn);
+            }
+            for lookup in &spec.lookups {
+                let to = by_entity_type
+                    .get(lookup.entity.as_str())
+                    .or_else(|| by_name.get(lookup.entity.as_str()));
+                match to {
+                    Some(&to) => graph.add(
+                        i,
+                        to,
+                        Reason::Lookup {
+                            column: lookup.source.clone(),
+                            required: lookup.required,
+                        },
+                    ),
+                    None => graph.external.push(ExternalReference {
+                        entity: spec.name.clone(),
+                        columns: vec![lookup.source.clone()],
+                        table: format!("entity '{}'", lookup.entity),
+                    }),
+                }
+            }
+        }
+
+        if let Some(schema) = schema {
+            for (i, node) in nodes.iter().enumerate() {
+                let Some(table) = schema.table(&node.source_table) else {
+                    continue;
+                };
+                for fk in &table.foreign_keys {
+                    let nullable = fk.columns.iter().all(|c| {
+                        table
+                            .columns
+                            .iter()
+                            .any(|col| &col.name == c && col.nullable)
+                    });
+                    match by_table.get(fk.references_table.as_str()) {
+                        Some(&to) => graph.add(
+                            i,
+                            to,
+                            Reason::ForeignKey {
+                                columns: fk.columns.clone(),
+                                nullable,
+                            },
+                        ),
+                        None => graph.external.push(ExternalReference {
+                            entity: node.name.clone(),
+                            columns: fk.columns.clone(),
+                            table: fk.references_table.clone(),
+                        }),
+                    }
+                }
+            }
+        }
+        graph.nodes = nodes;
+        Ok(graph)
+    }
+
+    fn add(&mut self, from: usize, to: usize, reason: Reason) {
+        let reasons = self.edges.entry((from, to)).or_default();
+        // A spec lookup and the schema FK usually describe the same column.
+        let duplicate = reasons.iter().any(|r| {
+            !matches!(r, Reason::DependsOn)
+                && !matches!(reason, Reason::DependsOn)
+                && r.columns() == reason.columns()
+        });
+        if !duplicate && !reasons.contains(&reason) {
+            reasons.push(reason);
+        }
+    }
+
+    /// The nodes with an edge to node `name`, i.e. that depend on it, with
+    /// the reasons; a self-reference is included.
+    pub fn dependents(&self, name: &str) -> Vec<(&Node, &[Reason])> {
+        let Some(to) = self.nodes.iter().position(|n| n.name == name) else {
+            return Vec::new();
+        };
+        self.edges
+            .iter()
+            .filter(|((_, t), _)| *t == to)
+            .map(|((from, _), reasons)| (&self.nodes[*from], reasons.as_slice()))
+            .collect()
+    }
+
+    /// Break cycles and layer the graph into parallel stages.
+    pub fn plan(self) -> Plan {
+        let n = self.nodes.len();
+        let sccs = strongly_connected(n, &self.edges);
+        let mut deferred: BTreeSet<(usize, usize)> = BTreeSet::new();
+        let mut cycles = Vec::new();
+
+        for scc in &sccs {
+            let cyclic = scc.len() > 1 || self.edges.contains_key(&(scc[0], scc[0]));
+            if !cyclic {
+                continue;
+            }
+            let members: BTreeSet<usize> = scc.iter().copied().collect();
+            while let Some(cycle) = find_cycle(&members, &self.edges, &deferred) {
+                // Prefer a deferrable edge; among those, the one with the
+                // fewest columns to backfill.
+                let pairs: Vec<(usize, usize)> = cycle
+                    .iter()
+                    .zip(cycle.iter().cycle().skip(1))
+                    .map(|(&a, &b)| (a, b))
+                    .collect();
+                let choice = pairs
+                    .iter()
+                    .filter(|p| self.edges[p].iter().all(Reason::deferrable))
+                    .min_by_key(|p| {
+                        (
+                            self.edges[p]
+                                .iter()
+                                .map(|r| r.columns().len())
+                                .sum::<usize>(),
+                            self.nodes[p.0].name.clone(),
+                        )
+                    })
+                    .copied();
+                let safe = choice.is_some();
+                let (from, to) = choice.unwrap_or(pairs[0]);
+                deferred.insert((from, to));
+                cycles.push(Cycle {
+                    entities: cycle.iter().map(|&i| self.nodes[i].name.clone()).collect(),
+                    resolution: self.describe_break(from, to, safe),
+                });
+            }
+        }
+
+        let stages = self.layer(&deferred);
+        let backfill = deferred
+            .iter()
+            .map(|&(from, to)| Backfill {
+                entity: self.nodes[from].name.clone(),
+                columns: self.edges[&(from, to)]
+                    .iter()
+                    .flat_map(Reason::columns)
+                    .collect(),
+                references: self.nodes[to].name.clone(),
+            })
+            .collect();
+
+        let mut order_conflicts = Vec::new();
+        for (&(from, to), _) in self.edges.iter().filter(|(k, _)| !deferred.contains(k)) {
+            let (a, b) = (&self.nodes[from], &self.nodes[to]);
+            if let (Some(oa), Some(ob)) = (a.dependency_order, b.dependency_order)
+                && oa <= ob
+            {
+                order_conflicts.push(format!(
+                    "{} (dependency_order {}) depends on {} (dependency_order {})",
+                    a.name, oa, b.name, ob
+                ));
+            }
+        }
+
+        let edges = self
+            .edges
+            .iter()
+            .map(|(&(from, to), reasons)| Edge {
+                from: self.nodes[from].name.clone(),
+                to: self.nodes[to].name.clone(),
+                reasons: reasons.clone(),
+                deferred: deferred.contains(&(from, to)),
+            })
+            .collect();
+
+        Plan {
+            format_version: FORMAT_VERSION,
+            stages,
+            backfill,
+            cycles,
+            edges,
+            external: self.external,
+            order_conflicts,
+        }
+    }
+
+    fn describe_break(&self, from: usize, to: usize, safe: bool) -> CycleBreak {
+        let (a, b) = (&self.nodes[from], &self.nodes[to]);
+        let columns: Vec<String> = self.edges[&(from, to)]
+            .iter()
+            .flat_map(Reason::columns)
+            .collect();
+        let proposal = if !safe {
+            format!(
+                "{} requires {} and the reference is NOT NULL; make the constraint DEFERRABLE \
+                 and load both in one transaction, or split the table",
+                a.name, b.name
+            )
+        } else if from == to {
+            format!(
+                "insert {} with {} NULL, then backfill it from migration_mappings once all rows are mapped",
+                a.name,
+                columns.join(", ")
+            )
+        } else {
+            format!(
+                "load {} before {} with {} NULL, then backfill it after {} is mapped",
+                a.name,
+                b.name,
+                columns.join(", "),
+                b.name
+            )
+        };
+        CycleBreak {
+            from: a.name.clone(),
+            to: b.name.clone(),
+            columns,
+            safe,
+            proposal,
+        }
+    }
+
+    /// Kahn's algorithm, one layer at a time, ignoring `deferred` edges.
+    fn layer(&self, deferred: &BTreeSet<(usize, usize)>) -> Vec<Stage> {
+        let n = self.nodes.len();
+        let live: Vec<(usize, usize)> = self
+            .edges
+            .keys()
+            .filter(|k| k.0 != k.1 && !deferred.contains(k))
+            .copied()
+            .collect();
+        let mut pending: Vec<usize> = vec![0; n];
+        for &(from, _) in &live {
+            pending[from] += 1;
+        }
+        let mut done = vec![false; n];
+        let mut stages = Vec::new();
+        loop {
+            let mut ready: Vec<usize> = (0..n).filter(|&i| !done[i] && pending[i] == 0).collect();
+            if ready.is_empty() {
+                break;
+            }
+            ready.sort_by_key(|&i| {
+                (
+                    self.nodes[i].dependency_order.unwrap_or(0),
+                    self.nodes[i].name.clone(),
+                )
+            });
+            for &i in &ready {
+                done[i] = true;
+            }
+            for &(from, to) in &live {
+                if ready.contains(&to) {
+                    pending[from] -= 1;
+                }
+            }
+            let entities = ready
+                .iter()
+                .map(|&i| PlannedEntity {
+                    node: self.nodes[i].clone(),
+                    depends_on: live
+                        .iter()
+                        .filter(|(from, _)| *from == i)
+                        .map(|&(_, to)| self.nodes[to].name.clone())
+                        .collect(),
+                })
+                .collect();
+            stages.push(Stage {
+                stage: stages.len() + 1,
+                entities,
+            });
+        }
+        stages
+    }
+}
+
+/// Tarjan's algorithm; returns the components in reverse topological order.
+fn strongly_connected(n: usize, edges: &BTreeMap<(usize, usize), Vec<Reason>>) -> Vec<Vec<usize>> {
+    struct State {
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next: usize,
+        out: Vec<Vec<usize>>,
+    }
+    fn visit(v: usize, adj: &[Vec<usize>], s: &mut State) {
+        s.index[v] = Some(s.next);
+        s.low[v] = s.next;
+        s.next += 1;
+        s.stack.push(v);
+        s.on_stack[v] = true;
+        for &w in &adj[v] {
+            match s.index[w] {
+                None => {
+                    visit(w, adj, s);
+                    s.low[v] = s.low[v].min(s.low[w]);
+                }
+                Some(iw) if s.on_stack[w] => s.low[v] = s.low[v].min(iw),
+                _ => {}
+            }
+        }
+        if Some(s.low[v]) == s.index[v] {
+            let mut component = Vec::new();
+            while let Some(w) = s.stack.pop() {
+                s.on_stack[w] = false;
+                component.push(w);
+                if w == v {
+                    break;
+                }
+            }
+            s.out.push(component);
+        }
+    }
+
+    let mut adj = vec![Vec::new(); n];
+    for &(from, to) in edges.keys() {
+        adj[from].push(to);
+    }
+    let mut state = State {
+        index: vec![None; n],
+        low: vec![0; n],
+        on_stack: vec![false; n],
+        stack: Vec::new(),
+        next: 0,
+        out: Vec::new(),
+    };
+    for v in 0..n {
+        if state.index[v].is_none() {
+            visit(v, &adj, &mut state);
+        }
+    }
+    state.out
+}
+
+/// Any cycle among `members` that avoids `deferred` edges, as a node list
+/// where each node depends on the next (wrapping around).
+fn find_cycle(
+    members: &BTreeSet<usize>,
+    edges: &BTreeMap<(usize, usize), Vec<Reason>>,
+    deferred: &BTreeSet<(usize, usize)>,
+) -> Option<Vec<usize>> {
+    let next = |v: usize| {
+        edges
+            .keys()
+            .filter(move |&&(from, to)| from == v && members.contains(&to))
+            .filter(|k| !deferred.contains(k))
+            .map(|&(_, to)| to)
+    };
+    // 0 = unvisited, 1 = on the current path, 2 = finished.
+    let mut color: BTreeMap<usize, u8> = BTreeMap::new();
+    for &start in members {
+        if color.get(&start).copied().unwrap_or(0) != 0 {
+            continue;
+        }
+        let mut path = vec![start];
+        let mut iters = vec![next(start).collect::<Vec<_>>().into_iter()];
+        color.insert(start, 1);
+        while let Some(it) = iters.last_mut() {
+            match it.next() {
+                Some(w) => match color.get(&w).copied().unwrap_or(0) {
+                    1 => {
+                        let pos = path.iter().position(|&p| p == w).unwrap_or(0);
+                        return Some(path[pos..].to_vec());
+                    }
+                    0 => {
+                        color.insert(w, 1);
+                        path.push(w);
+                        iters.push(next(w).collect::<Vec<_>>().into_iter());
+                    }
+                    _ => {}
+                },
+                None => {
+                    if let Some(v) = path.pop() {
+                        color.insert(v, 2);
+                    }
+                    iters.pop();
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Graphviz rendering; arrows point from an entity to what it depends on.
+pub fn to_dot(plan: &Plan) -> String {
+    let mut out = String::from("digraph migration_plan {\n  rankdir=BT;\n  node [shape=box];\n");
+    for stage in &plan.stages {
+        out.push_str(&format!(
+            "  subgraph stage_{} {{\n    rank=same;\n",
+            stage.stage
+        ));
+        for e in &stage.entities {
+            out.push_str(&format!(
+                "    \"{}\" [label=\"{}\\n{}\"];\n",
+                e.node.name, e.node.name, e.node.source_table
+            ));
+        }
+        out.push_str("  }\n");
+    }
+    for edge in &plan.edges {
+        let label = edge_label(edge);
+        let style = if edge.deferred {
+            ", style=dashed, color=red"
+        } else {
+            ""
+        };
+        out.push_str(&format!(
+            "  \"{}\" -> \"{}\" [label=\"{}\"{}];\n",
+            edge.from, edge.to, label, style
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Mermaid flowchart rendering; deferred edges are dotted.
+pub fn to_mermaid(plan: &Plan) -> String {
+    let mut out = String::from("flowchart BT\n");
+    for stage in &plan.stages {
+        out.push_str(&format!(
+            "  subgraph stage_{}[\"Stage {}\"]\n",
+            stage.stage, stage.stage
+        ));
+        for e in &stage.entities {
+            out.push_str(&format!(
+                "    {}[\"{}<br/>{}\"]\n",
+                e.node.name, e.node.name, e.node.source_table
+            ));
+        }
+        out.push_str("  end\n");
+    }
+    for edge in &plan.edges {
+        let arrow = if edge.deferred { "-.->" } else { "-->" };
+        out.push_str(&format!(
+            "  {} {}|{}| {}\n",
+            edge.from,
+            arrow,
+            edge_label(edge),
+            edge.to
+        ));
+    }
+    out
+}
+
+fn edge_label(edge: &Edge) -> String {
+    let labels: Vec<String> = edge.reasons.iter().map(Reason::label).collect();
+    let mut label = labels.join(", ");
+    if edge.deferred {
+        label.push_str(" (backfill)");
+    }
+    label
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fk(columns: &[&str], nullable: bool) -> Reason {
+        Reason::ForeignKey {
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            nullable,
+        }
+    }
+
+    fn lookup(column: &str, required: bool) -> Reason {
+        Reason::Lookup {
+            column: column.to_string(),
+            required,
+        }
+    }
+
+    /// `(name, dependency_order)`.
+    type Nodes<'a> = &'a [(&'a str, Option<u32>)];
+    /// `(from, to, reason)` by node name; `from` depends on `to`.
+    type Edges<'a> = &'a [(&'a str, &'a str, Reason)];
+    /// Node count, `(from, to)` edges and the components, each sorted.
+    type SccCase<'a> = (usize, &'a [(usize, usize)], &'a [&'a [usize]]);
+
+    fn graph(nodes: Nodes, edges: Edges) -> Graph {
+        let nodes: Vec<Node> = nodes
+            .iter()
+            .map(|&(name, order)| Node {
+                name: name.to_string(),
+                source_table: name.to_string(),
+                target_table: None,
+                dependency_order: order,
+            })
+            .collect();
+        let index = |name: &str| nodes.iter().position(|n| n.name == name).unwrap();
+        let mut graph = Graph {
+            edges: BTreeMap::new(),
+            external: Vec::new(),
+            nodes: nodes.clone(),
+        };
+        for (from, to, reason) in edges {
+            graph.add(index(from), index(to), reason.clone());
+        }
+        graph
+    }
+
+    fn stages(plan: &Plan) -> Vec<Vec<&str>> {
+        plan.stages
+            .iter()
+            .map(|s| s.entities.iter().map(|e| e.node.name.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn strongly_connected_components() {
+        let cases: [SccCase; 6] = [
+            (3, &[], &[&[0], &[1], &[2]]),
+            // Reverse topological: a component comes before its dependents.
+            (3, &[(0, 1), (1, 2)], &[&[2], &[1], &[0]]),
+            (3, &[(2, 0), (0, 1), (1, 0)], &[&[0, 1], &[2]]),
+            (2, &[(0, 0)], &[&[0], &[1]]),
+            (
+                4,
+                &[(0, 1), (1, 0), (1, 2), (2, 3), (3, 2)],
+                &[&[2, 3], &[0, 1]],
+            ),
+            (4, &[(0, 1), (1, 2), (2, 3), (3, 0)], &[&[0, 1, 2, 3]]),
+        ];
+        for (n, edges, expected) in cases {
+            let edges: BTreeMap<(usize, usize), Vec<Reason>> = edges
+                .iter()
+                .map(|&e| (e, vec![Reason::DependsOn]))
+                .collect();
+            let mut found = strongly_connected(n, &edges);
+            for component in &mut found {
+                component.sort();
+            }
+            assert_eq!(found, expected, "{:?}", edges.keys());
+        }
+    }
+
+    #[test]
+    fn cycle_breaks() {
+        struct Case {
+            edges: Vec<(&'static str, &'static str, Reason)>,
+            /// `(from, to, safe)` of each break.
+            breaks: Vec<(&'static str, &'static str, bool)>,
+            backfill: Vec<(&'static str, Vec<&'static str>, &'static str)>,
+            stages: Vec<Vec<&'static str>>,
+        }
+        let cases = [
+            // Only the nullable side can be deferred.
+            Case {
+                edges: vec![
+                    ("a", "b", fk(&["b_id"], true)),
+                    ("b", "a", lookup("a_id", true)),
+                ],
+                breaks: vec![("a", "b", true)],
+                backfill: vec![("a", vec!["b_id"], "b")],
+                stages: vec![vec!["a", "c"], vec!["b"]],
+            },
+            // Among deferrable edges the one with fewer columns wins.
+            Case {
+                edges: vec![
+                    ("a", "b", fk(&["b_id", "b_kind"], true)),
+                    ("b", "a", lookup("a_id", false)),
+                ],
+                breaks: vec![("b", "a", true)],
+                backfill: vec![("b", vec!["a_id"], "a")],
+                stages: vec![vec!["b", "c"], vec!["a"]],
+            },
+            // Nothing deferrable: break anyway, flagged unsafe.
+            Case {
+                edges: vec![
+                    ("a", "b", Reason::DependsOn),
+                    ("b", "a", fk(&["a_id"], false)),
+                ],
+                breaks: vec![("a", "b", false)],
+                backfill: vec![("a", vec![], "b")],
+                stages: vec![vec!["a", "c"], vec!["b"]],
+            },
+            // A self-reference is a cycle of one.
+            Case {
+                edges: vec![("c", "c", fk(&["parent_id"], true))],
+                breaks: vec![("c", "c", true)],
+                backfill: vec![("c", vec!["parent_id"], "c")],
+                stages: vec![vec!["a", "b", "c"]],
+            },
+            // Two cycles through one node need two breaks.
+            Case {
+                edges: vec![
+                    ("a", "b", fk(&["b_id"], true)),
+                    ("b", "a", fk(&["a_id"], false)),
+                    ("a", "c", fk(&["c_id"], true)),
+                    ("c", "a", fk(&["a_id"], false)),
+                ],
+                breaks: vec![("a", "b", true), ("a", "c", true)],
+                backfill: vec![("a", vec!["b_id"], "b"), ("a", vec!["c_id"], "c")],
+                stages: vec![vec!["a"], vec!["b", "c"]],
+            },
+        ];
+        for case in cases {
+            let plan = graph(&[("a", None), ("b", None), ("c", None)], &case.edges).plan();
+            let label = format!("{:?}", case.edges);
+            let breaks: Vec<(&str, &str, bool)> = plan
+                .cycles
+                .iter()
+                .map(|c| {
+                    let r = &c.resolution;
+                    (r.from.as_str(), r.to.as_str(), r.safe)
+                })
+                .collect();
+            assert_eq!(breaks, case.breaks, "{}", label);
+            let backfill: Vec<(&str, Vec<&str>, &str)> = plan
+                .backfill
+                .iter()
+                .map(|b| {
+                    (
+                        b.entity.as_str(),
+                        b.columns.iter().map(String::as_str).collect(),
+                        b.references.as_str(),
+                    )
+                })
+                .collect();
+            assert_eq!(backfill, case.backfill, "{}", label);
+            assert_eq!(stages(&plan), case.stages, "{}", label);
+        }
+    }
+
+    #[test]
+    fn layering() {
+        let diamond = [
+            ("b", "a", Reason::DependsOn),
+            ("c", "a", Reason::DependsOn),
+            ("d", "b", Reason::DependsOn),
+            ("d", "c", Reason::DependsOn),
+        ];
+        let cases: [(Nodes, Edges, Vec<Vec<&str>>); 4] = [
+            (&[("b", None), ("a", None)], &[], vec![vec!["a", "b"]]),
+            (
+                &[("a", None), ("b", None), ("c", None), ("d", None)],
+                &diamond,
+                vec![vec!["a"], vec!["b", "c"], vec!["d"]],
+            ),
+            // dependency_order sorts within a stage, never across one.
+            (
+                &[
+                    ("a", Some(9)),
+                    ("b", Some(2)),
+                    ("c", Some(1)),
+                    ("d", Some(0)),
+                ],
+                &diamond,
+                vec![vec!["a"], vec!["c", "b"], vec!["d"]],
+            ),
+            // A stage waits for its slowest dependency.
+            (
+                &[("a", None), ("b", None), ("c", None), ("d", None)],
+                &[
+                    ("b", "a", Reason::DependsOn),
+                    ("c", "b", Reason::DependsOn),
+                    ("d", "a", Reason::DependsOn),
+                    ("d", "c", Reason::DependsOn),
+                ],
+                vec![vec!["a"], vec!["b"], vec!["c"], vec!["d"]],
+            ),
+        ];
+        for (nodes, edges, expected) in cases {
+            let plan = graph(nodes, edges).plan();
+            assert_eq!(stages(&plan), expected, "{:?}", edges);
+            assert!(plan.cycles.is_empty());
+        }
+
+        let plan = graph(
+            &[("a", None), ("b", None), ("c", None), ("d", None)],
+            &diamond,
+        )
+        .plan();
+        let d = &plan.stages[2].entities[0];
+        assert_eq!(d.depends_on, ["b", "c"]);
+    }
+
+    #[test]
+    fn order_conflicts() {
+        let plan = graph(
+            &[("a", Some(2)), ("b", Some(2)), ("c", Some(1))],
+            &[
+                ("a", "b", Reason::DependsOn),
+                ("c", "a", fk(&["a_id"], false)),
+            ],
+        )
+        .plan();
+        assert_eq!(
+            plan.order_conflicts,
+            [
+                "a (dependency_order 2) depends on b (dependency_order 2)",
+                "c (dependency_order 1) depends on a (dependency_order 2)",
+            ]
+        );
+    }
+}
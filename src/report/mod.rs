@@ -0,0 +1,717 @@
+//! Migration reports built from run metadata, replacing the hand-written
+//! `*_MIGRATION_REPORT.md` files and the plain-text
+//! `MigrationReportGenerator` (`src/reporting/report-generator.ts`).
+//!
+//! The inputs are the rows of `migration_control` (one per run, as written
+//! by the executor or by the TypeScript scripts), `migration_execution_logs`
+//! (warnings and errors only) and `migration_validation_reports`, read from
+//! the target or from JSON exports of them (see [`EXPORT_QUERY`]). They are
+//! summarized into a [`Report`]: per-entity success rates, a timeline of
+//! runs, errors grouped by message, the latest validation per entity and,
+//! given the JSON of an earlier report, what changed since. [`render`] turns
+//! it into Markdown or self-contained HTML.
+
+pub mod render;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use postgres::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::engine::describe;
+
+/// Run with `psql -At -f` and save the single JSON value it returns;
+/// `report --from` reads it back. Drop the entry of a table the database
+/// does not have. Rows are left unordered: `migration_control` names its
+/// start column differently per layout, and [`build`] sorts by time anyway.
+pub const EXPORT_QUERY: &str = r#"SELECT json_build_object(
+  'migration_control', (SELECT json_agg(c) FROM migration_control c),
+  'migration_execution_logs', (
+    SELECT json_agg(l)
+    FROM migration_execution_logs l
+    WHERE l.log_level IN ('error', 'warn')
+  ),
+  'migration_validation_reports', (SELECT json_agg(v) FROM migration_validation_reports v)
+);"#;
+
+/// Runs shown in the timeline, the most recent ones.
+pub const TIMELINE: usize = 50;
+
+/// Characters of an error message kept for grouping.
+const MESSAGE: usize = 160;
+
+/// A `migration_control` row; the aliases are the columns of the TypeScript
+/// scripts' layout.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ControlRow {
+    #[serde(alias = "script_name", alias = "entity_type")]
+    pub table_name: String,
+    #[serde(default, alias = "operation_type")]
+    pub operation: Option<String>,
+    pub status: String,
+    #[serde(default)]
+    pub records_processed: Option<i64>,
+    #[serde(default)]
+    pub records_failed: Option<i64>,
+    #[serde(default, alias = "start_time")]
+    pub started_at: Option<String>,
+    #[serde(default, alias = "end_time")]
+    pub completed_at: Option<String>,
+    #[serde(default)]
+    pub error_message: Option<String>,
+    /// The TypeScript layout's error column: a message string or an object
+    /// holding one under `message` or `error`.
+    #[serde(default)]
+    pub error_details: Option<Value>,
+}
+
+impl ControlRow {
+    fn error(&self) -> Option<&str> {
+        if let Some(message) = &self.error_message {
+            return Some(message);
+        }
+        match self.error_details.as_ref()? {
+            Value::String(message) => Some(message),
+            details => ["message", "error"]
+                .iter()
+                .find_map(|key| details.get(key).and_then(Value::as_str)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogRow {
+    #[serde(default)]
+    pub entity_type: Option<String>,
+    pub log_level: String,
+    pub message: String,
+    #[serde(default)]
+    pub timestamp: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ValidationRow {
+    pub validation_type: String,
+    pub target_entity: String,
+    pub validation_passed: bool,
+    #[serde(default)]
+    pub discrepancies_found: i64,
+    #[serde(default)]
+    pub generated_at: Option<String>,
+    #[serde(default)]
+    pub metadata: Option<Value>,
+}
+
+/// The rows a report is built from.
+#[derive(Debug, Default)]
+pub struct Inputs {
+    /// Where the rows came from, for the report's header.
+    pub sources: Vec<String>,
+    pub control: Vec<ControlRow>,
+    pub logs: Vec<LogRow>,
+    pub validations: Vec<ValidationRow>,
+}
+
+impl Inputs {
+    /// The tables of the target that exist; the others are skipped.
+    pub fn read(target: &mut Client, label: &str) -> Result<Inputs, String> {
+        let mut inputs = Inputs::default();
+        let queries = [
+            (
+                "migration_control",
+                "SELECT coalesce(json_agg(c), '[]')::text FROM migration_control c",
+            ),
+            (
+                "migration_execution_logs",
+                "SELECT coalesce(json_agg(l), '[]')::text
+                 FROM migration_execution_logs l WHERE l.log_level IN ('error', 'warn')",
+            ),
+            (
+                "migration_validation_reports",
+                "SELECT coalesce(json_agg(v), '[]')::text FROM migration_validation_reports v",
+            ),
+        ];
+        let mut found = Vec::new();
+        for (table, sql) in queries {
+            let present: bool = target
+                .query_one("SELECT to_regclass($1) IS NOT NULL", &[&table])
+                .map_err(|e| format!("cannot inspect {}: {}", table, describe(&e)))?
+                .get(0);
+            if !present {
+                continue;
+            }
+            let json: String = target
+                .query_one(sql, &[])
+                .map_err(|e| format!("cannot read {}: {}", table, describe(&e)))?
+                .get(0);
+            let rows: Value =
+                serde_json::from_str(&json).map_err(|e| format!("cannot read {}: {}", table, e))?;
+            inputs.add(table, rows)?;
+            found.push(table);
+        }
+        if found.is_empty() {
+            return Err(format!(
+                "{} has none of migration_control, migration_execution_logs and migration_validation_reports",
+                label
+            ));
+        }
+        inputs
+            .sources
+            .push(format!("{} ({})", label, found.join(", ")));
+        Ok(inputs)
+    }
+
+    /// Add a JSON export: the object [`EXPORT_QUERY`] returns, or an array of
+    /// the rows of one table, recognized by its columns.
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        let value: Value = serde_json::from_str(&text)
+            .map_err(|e| format!("{} is not JSON: {}", path.display(), e))?;
+        let invalid = |e: String| format!("invalid export {}: {}", path.display(), e);
+        let mut tables = Vec::new();
+        match value {
+            Value::Object(map) if map.contains_key("generated_at") => {
+                return Err(format!(
+                    "{} is a report, not an export; pass it to --previous",
+                    path.display()
+                ));
+            }
+            Value::Object(map) => {
+                for (table, rows) in map {
+                    self.add(&table, rows).map_err(invalid)?;
+                    tables.push(table);
+                }
+            }
+            Value::Array(rows) => {
+                let table = rows
+                    .first()
+                    .and_then(Value::as_object)
+                    .and_then(|row| {
+                        if row.contains_key("validation_type") {
+                            Some("migration_validation_reports")
+                        } else if row.contains_key("log_level") {
+                            Some("migration_execution_logs")
+                        } else if row.contains_key("status") {
+                            Some("migration_control")
+                        } else {
+                            None
+                        }
+                    })
+                    .ok_or_else(|| {
+                        invalid("expected rows of migration_control, migration_execution_logs or migration_validation_reports".into())
+                    })?;
+                self.add(table, Value::Array(rows)).map_err(invalid)?;
+                tables.push(table.to_string());
+            }
+            _ => return Err(invalid("expected an object or an array".into())),
+        }
+        self.sources
+            .push(format!("{} ({})", path.display(), tables.join(", ")));
+        Ok(())
+    }
+
+    fn add(&mut self, table: &str, rows: Value) -> Result<(), String> {
+        if rows.is_null() {
+            return Ok(());
+        }
+        let bad = |e: serde_json::Error| format!("{}: {}", table, e);
+        match table {
+            "migration_control" => self
+                .control
+                .extend(serde_json::from_value::<Vec<ControlRow>>(rows).map_err(bad)?),
+            "migration_execution_logs" => self.logs.extend(
+                serde_json::from_value::<Vec<LogRow>>(rows)
+                    .map_err(bad)?
+                    .into_iter()
+                    .filter(|l| l.log_level == "error" || l.log_level == "warn"),
+            ),
+            "migration_validation_reports" => self
+                .validations
+                .extend(serde_json::from_value::<Vec<ValidationRow>>(rows).map_err(bad)?),
+            other => return Err(format!("unknown table '{}'", other)),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    pub title: String,
+    pub generated_at: String,
+    pub sources: Vec<String>,
+    pub totals: Totals,
+    pub entities: Vec<EntityStats>,
+    /// The most recent runs, oldest first.
+    pub timeline: Vec<RunSpan>,
+    pub errors: Vec<ErrorGroup>,
+    pub validation: Vec<ValidationStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changes: Option<Changes>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Totals {
+    pub entities: usize,
+    pub runs: u64,
+    pub failed_runs: u64,
+    pub records_processed: u64,
+    pub records_failed: u64,
+    /// Percentage of processed records that were not rejected.
+    pub success_rate: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityStats {
+    pub entity: String,
+    pub runs: u64,
+    pub completed: u64,
+    pub failed: u64,
+    /// Runs neither completed nor failed: stopped, cancelled or still
+    /// running.
+    pub other: u64,
+    pub records_processed: u64,
+    pub records_failed: u64,
+    pub success_rate: Option<f64>,
+    pub last_status: String,
+    pub last_run_at: Option<String>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunSpan {
+    pub entity: String,
+    pub operation: Option<String>,
+    pub status: String,
+    pub started_at: Option<String>,
+    pub completed_at: Option<String>,
+    pub duration_ms: Option<u64>,
+    pub records_processed: u64,
+    pub records_failed: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorGroup {
+    pub entity: String,
+    /// `run`, `log error`, `log warn` or `validation`.
+    pub origin: String,
+    /// The message with numbers replaced by `N`.
+    pub message: String,
+    pub occurrences: u64,
+    pub last_seen: Option<String>,
+}
+
+impl ErrorGroup {
+    fn key(&self) -> String {
+        format!("{} [{}] {}", self.entity, self.origin, self.message)
+    }
+}
+
+/// The latest validation run of an entity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationStats {
+    pub entity: String,
+    pub run: Option<String>,
+    pub checks: u64,
+    pub passed: u64,
+    pub failed: u64,
+    pub discrepancies: u64,
+    pub validated_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Changes {
+    pub previous_generated_at: String,
+    pub entities: Vec<EntityChange>,
+    pub new_errors: Vec<String>,
+    pub resolved_errors: Vec<String>,
+    pub validation: Vec<ValidationChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityChange {
+    pub entity: String,
+    /// `added`, `removed` or `changed`.
+    pub change: String,
+    pub runs_before: u64,
+    pub runs_after: u64,
+    pub records_before: u64,
+    pub records_after: u64,
+    pub success_rate_before: Option<f64>,
+    pub success_rate_after: Option<f64>,
+    pub status_before: Option<String>,
+    pub status_after: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationChange {
+    pub entity: String,
+    pub failed_before: Option<u64>,
+    pub failed_after: Option<u64>,
+}
+
+/// A timestamp of `to_json` or of a text cast, with or without an offset;
+/// one without is taken as UTC.
+pub fn parse_time(text: &str) -> Option<DateTime<FixedOffset>> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(text) {
+        return Some(t);
+    }
+    if let Ok(t) = DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f%#z") {
+        return Some(t);
+    }
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(text, f).ok())
+        .map(|t| t.and_utc().fixed_offset())
+}
+
+fn later(a: &Option<String>, b: &Option<String>) -> bool {
+    match (
+        a.as_deref().and_then(parse_time),
+        b.as_deref().and_then(parse_time),
+    ) {
+        (Some(a), Some(b)) => a > b,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+/// Rows the executor's "N row(s) rejected by the target" message counts.
+fn rejected(message: Option<&str>) -> u64 {
+    message
+        .filter(|m| m.ends_with("row(s) rejected by the target"))
+        .and_then(|m| m.split_whitespace().next())
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0)
+}
+
+/// `message` with digit runs replaced, so messages differing only in IDs
+/// and counts group together.
+fn normalize(message: &str) -> String {
+    let mut normalized = String::new();
+    let mut in_number = false;
+    for c in message.trim().chars() {
+        if c.is_ascii_digit() {
+            if !in_number {
+                normalized.push('N');
+            }
+            in_number = true;
+        } else {
+            normalized.push(c);
+            in_number = false;
+        }
+        if normalized.chars().count() >= MESSAGE {
+            normalized.push('…');
+            break;
+        }
+    }
+    normalized
+}
+
+fn rate(processed: u64, failed: u64) -> Option<f64> {
+    (processed > 0).then(|| processed.saturating_sub(failed) as f64 * 100.0 / processed as f64)
+}
+
+pub fn build(title: &str, inputs: &Inputs) -> Report {
+    let spans: Vec<RunSpan> = inputs
+        .control
+        .iter()
+        .map(|row| {
+            let duration_ms = match (
+                row.started_at.as_deref().and_then(parse_time),
+                row.completed_at.as_deref().and_then(parse_time),
+            ) {
+                (Some(start), Some(end)) if end >= start => {
+                    Some((end - start).num_milliseconds() as u64)
+                }
+                _ => None,
+            };
+            RunSpan {
+                entity: row.table_name.clone(),
+                operation: row.operation.clone(),
+                status: row.status.clone(),
+                started_at: row.started_at.clone(),
+                completed_at: row.completed_at.clone(),
+                duration_ms,
+                records_processed: row.records_processed.unwrap_or(0).max(0) as u64,
+                records_failed: row
+                    .records_failed
+                    .map(|n| n.max(0) as u64)
+                    .unwrap_or_else(|| rejected(row.error())),
+            }
+        })
+        .collect();
+
+    let mut entities: BTreeMap<&str, EntityStats> = BTreeMap::new();
+    for span in &spans {
+        let stats = entities.entry(&span.entity).or_insert_with(|| EntityStats {
+            entity: span.entity.clone(),
+            runs: 0,
+            completed: 0,
+            failed: 0,
+            other: 0,
+            records_processed: 0,
+            records_failed: 0,
+            success_rate: None,
+            last_status: span.status.clone(),
+            last_run_at: None,
+            duration_ms: 0,
+        });
+        stats.runs += 1;
+        match span.status.as_str() {
+            "completed" => stats.completed += 1,
+            "failed" => stats.failed += 1,
+            _ => stats.other += 1,
+        }
+        stats.records_processed += span.records_processed;
+        stats.records_failed += span.records_failed;
+        stats.duration_ms += span.duration_ms.unwrap_or(0);
+        if stats.last_run_at.is_none() || !later(&stats.last_run_at, &span.started_at) {
+            stats.last_run_at = span.started_at.clone();
+            stats.last_status = span.status.clone();
+        }
+    }
+    let mut entities: Vec<EntityStats> = entities.into_values().collect();
+    for stats in &mut entities {
+        stats.success_rate = rate(stats.records_processed, stats.records_failed);
+    }
+
+    let mut totals = Totals {
+        entities: entities.len(),
+        ..Totals::default()
+    };
+    for stats in &entities {
+        totals.runs += stats.runs;
+        totals.failed_runs += stats.failed;
+        totals.records_processed += stats.records_processed;
+        totals.records_failed += stats.records_failed;
+    }
+    totals.success_rate = rate(totals.records_processed, totals.records_failed);
+
+    let mut timeline = spans.clone();
+    // Runs without a start time sort first and so drop out first.
+    timeline.sort_by_key(|run| run.started_at.as_deref().and_then(parse_time));
+    let skip = timeline.len().saturating_sub(TIMELINE);
+    timeline.drain(..skip);
+
+    let mut errors: BTreeMap<(String, String, String), ErrorGroup> = BTreeMap::new();
+    let mut note = |entity: &str, origin: &str, message: &str, at: &Option<String>| {
+        let message = normalize(message);
+        let group = errors
+            .entry((entity.to_string(), origin.to_string(), message.clone()))
+            .or_insert_with(|| ErrorGroup {
+                entity: entity.to_string(),
+                origin: origin.to_string(),
+                message,
+                occurrences: 0,
+                last_seen: None,
+            });
+        group.occurrences += 1;
+        if later(at, &group.last_seen) {
+            group.last_seen = at.clone();
+        }
+    };
+    for row in &inputs.control {
+        if let Some(message) = row.error() {
+            note(&row.table_name, "run", message, &row.started_at);
+        }
+    }
+    for row in &inputs.logs {
+        note(
+            row.entity_type.as_deref().unwrap_or("-"),
+            &format!("log {}", row.log_level),
+            &row.message,
+            &row.timestamp,
+        );
+    }
+
+    let validation = latest_validations(&inputs.validations);
+    for row in &inputs.validations {
+        if !row.validation_passed {
+            let check = row
+                .metadata
+                .as_ref()
+                .and_then(|m| m.get("check"))
+                .and_then(Value::as_str)
+                .unwrap_or(&row.validation_type);
+            note(
+                &validation_entity(row),
+                "validation",
+                &format!("{} failed", check),
+                &row.generated_at,
+            );
+        }
+    }
+    let mut errors: Vec<ErrorGroup> = errors.into_values().collect();
+    errors.sort_by(|a, b| {
+        b.occurrences
+            .cmp(&a.occurrences)
+            .then_with(|| a.key().cmp(&b.key()))
+    });
+
+    Report {
+        title: title.to_string(),
+        generated_at: Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        sources: inputs.sources.clone(),
+        totals,
+        entities,
+        timeline,
+        errors,
+        validation,
+        changes: None,
+    }
+}
+
+/// The spec name `validate` records in `metadata`, else the target table.
+fn validation_entity(row: &ValidationRow) -> String {
+    row.metadata
+        .as_ref()
+        .and_then(|m| m.get("entity"))
+        .and_then(Value::as_str)
+        .unwrap_or(&row.target_entity)
+        .to_string()
+}
+
+/// Per entity, the rows of its most recent validation run: those sharing
+/// the newest `metadata.run`, or the newest `generated_at` without one.
+fn latest_validations(rows: &[ValidationRow]) -> Vec<ValidationStats> {
+    let mut runs: BTreeMap<String, Vec<&ValidationRow>> = BTreeMap::new();
+    for row in rows {
+        runs.entry(validation_entity(row)).or_default().push(row);
+    }
+    let run_of = |row: &ValidationRow| {
+        row.metadata
+            .as_ref()
+            .and_then(|m| m.get("run"))
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .or_else(|| row.generated_at.clone())
+    };
+    runs.into_iter()
+        .map(|(entity, rows)| {
+            let newest = rows
+                .iter()
+                .copied()
+                .reduce(|a, b| {
+                    if later(&a.generated_at, &b.generated_at) {
+                        a
+                    } else {
+                        b
+                    }
+                })
+                .expect("an entity has rows");
+            let run = run_of(newest);
+            let latest: Vec<&&ValidationRow> = rows.iter().filter(|r| run_of(r) == run).collect();
+            ValidationStats {
+                entity,
+                run: run.clone(),
+                checks: latest.len() as u64,
+                passed: latest.iter().filter(|r| r.validation_passed).count() as u64,
+                failed: latest.iter().filter(|r| !r.validation_passed).count() as u64,
+                discrepancies: latest
+                    .iter()
+                    .map(|r| r.discrepancies_found.max(0) as u64)
+                    .sum(),
+                validated_at: newest.generated_at.clone(),
+            }
+        })
+        .collect()
+}
+
+/// A report written with `--format json`.
+pub fn load_previous(path: &Path) -> Result<Report, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    serde_json::from_str(&text).map_err(|e| {
+        format!(
+            "{} is not a report written with --format json: {}",
+            path.display(),
+            e
+        )
+    })
+}
+
+/// What changed between `previous` and `current`.
+pub fn compare(previous: &Report, current: &Report) -> Changes {
+    let before: BTreeMap<&str, &EntityStats> = previous
+        .entities
+        .iter()
+        .map(|e| (e.entity.as_str(), e))
+        .collect();
+    let after: BTreeMap<&str, &EntityStats> = current
+        .entities
+        .iter()
+        .map(|e| (e.entity.as_str(), e))
+        .collect();
+    let names: BTreeSet<&str> = before.keys().chain(after.keys()).copied().collect();
+    let entities = names
+        .into_iter()
+        .filter_map(|name| {
+            let (b, a) = (before.get(name), after.get(name));
+            let change = match (b, a) {
+                (None, Some(_)) => "added",
+                (Some(_), None) => "removed",
+                (Some(b), Some(a))
+                    if b.runs != a.runs
+                        || b.records_processed != a.records_processed
+                        || b.records_failed != a.records_failed
+                        || b.last_status != a.last_status =>
+                {
+                    "changed"
+                }
+                _ => return None,
+            };
+            Some(EntityChange {
+                entity: name.to_string(),
+                change: change.to_string(),
+                runs_before: b.map_or(0, |s| s.runs),
+                runs_after: a.map_or(0, |s| s.runs),
+                records_before: b.map_or(0, |s| s.records_processed),
+                records_after: a.map_or(0, |s| s.records_processed),
+                success_rate_before: b.and_then(|s| s.success_rate),
+                success_rate_after: a.and_then(|s| s.success_rate),
+                status_before: b.map(|s| s.last_status.clone()),
+                status_after: a.map(|s| s.last_status.clone()),
+            })
+        })
+        .collect();
+
+    let keys = |report: &Report| -> BTreeSet<String> {
+        report.errors.iter().map(ErrorGroup::key).collect()
+    };
+    let (old, new) = (keys(previous), keys(current));
+    let validated: BTreeSet<&str> = previous
+        .validation
+        .iter()
+        .chain(&current.validation)
+        .map(|v| v.entity.as_str())
+        .collect();
+    let failed = |report: &Report, entity: &str| {
+        report
+            .validation
+            .iter()
+            .find(|v| v.entity == entity)
+            .map(|v| v.failed)
+    };
+    Changes {
+        previous_generated_at: previous.generated_at.clone(),
+        entities,
+        new_errors: new.difference(&old).cloned().collect(),
+        resolved_errors: old.difference(&new).cloned().collect(),
+        validation: validated
+            .into_iter()
+            .filter_map(|entity| {
+                let (b, a) = (failed(previous, entity), failed(current, entity));
+                (b != a).then(|| ValidationChange {
+                    entity: entity.to_string(),
+                    failed_before: b,
+                    failed_after: a,
+                })
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,489 @@
+//! Markdown and self-contained HTML renderings of a [`Report`].
+
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use super::{Changes, Report, RunSpan};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    #[default]
+    Markdown,
+    /// One file with its styles inline, for attaching or publishing as is.
+    Html,
+    /// The report itself; what `--previous` reads back.
+    Json,
+}
+
+/// Errors listed per report; the JSON keeps all of them.
+const ERRORS: usize = 25;
+
+/// Rounded down, so a rate short of 100% never shows as 100.00%.
+fn percent(rate: Option<f64>) -> String {
+    rate.map_or_else(
+        || "-".to_string(),
+        |r| format!("{:.2}%", (r * 100.0).floor() / 100.0),
+    )
+}
+
+fn duration(ms: Option<u64>) -> String {
+    match ms {
+        None => "-".to_string(),
+        Some(ms) if ms < 1000 => format!("{}ms", ms),
+        Some(ms) if ms < 60_000 => format!("{:.1}s", ms as f64 / 1000.0),
+        Some(ms) => format!("{}m{:02}s", ms / 60_000, ms / 1000 % 60),
+    }
+}
+
+fn or_dash(value: &Option<String>) -> &str {
+    value.as_deref().unwrap_or("-")
+}
+
+fn count(value: Option<u64>) -> String {
+    value.map_or_else(|| "-".to_string(), |n| n.to_string())
+}
+
+/// `text` safe inside a Markdown table cell.
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+pub fn markdown(report: &Report) -> String {
+    let mut md = String::new();
+    let totals = &report.totals;
+    let _ = writeln!(md, "# {}\n", report.title);
+    let _ = writeln!(md, "Generated {} from:\n", report.generated_at);
+    for source in &report.sources {
+        let _ = writeln!(md, "- {}", source);
+    }
+    let _ = writeln!(
+        md,
+        "\n## Summary\n\n| Entities | Runs | Failed runs | Records | Rejected | Success rate |\n|---:|---:|---:|---:|---:|---:|\n| {} | {} | {} | {} | {} | {} |",
+        totals.entities,
+        totals.runs,
+        totals.failed_runs,
+        totals.records_processed,
+        totals.records_failed,
+        percent(totals.success_rate)
+    );
+
+    if let Some(changes) = &report.changes {
+        markdown_changes(&mut md, changes);
+    }
+
+    let _ = writeln!(md, "\n## Entities\n");
+    if report.entities.is_empty() {
+        let _ = writeln!(md, "No runs recorded.");
+    } else {
+        let _ = writeln!(
+            md,
+            "| Entity | Runs | Completed | Failed | Other | Records | Rejected | Success rate | Last status | Last run | Time |\n|---|---:|---:|---:|---:|---:|---:|---:|---|---|---:|"
+        );
+        for e in &report.entities {
+            let _ = writeln!(
+                md,
+                "| {} | {} | {} | {} | {} | {} | {} | {} | {} | {} | {} |",
+                cell(&e.entity),
+                e.runs,
+                e.completed,
+                e.failed,
+                e.other,
+                e.records_processed,
+                e.records_failed,
+                percent(e.success_rate),
+                e.last_status,
+                or_dash(&e.last_run_at),
+                duration(Some(e.duration_ms))
+            );
+        }
+    }
+
+    if !report.timeline.is_empty() {
+        let _ = writeln!(
+            md,
+            "\n## Timeline\n\n| Started | Completed | Entity | Operation | Status | Records | Rejected | Time |\n|---|---|---|---|---|---:|---:|---:|"
+        );
+        for run in &report.timeline {
+            let _ = writeln!(
+                md,
+                "| {} | {} | {} | {} | {} | {} | {} | {} |",
+                or_dash(&run.started_at),
+                or_dash(&run.completed_at),
+                cell(&run.entity),
+                or_dash(&run.operation),
+                run.status,
+                run.records_processed,
+                run.records_failed,
+                duration(run.duration_ms)
+            );
+        }
+    }
+
+    if !report.validation.is_empty() {
+        let _ = writeln!(
+            md,
+            "\n## Validation\n\n| Entity | Run | Checks | Passed | Failed | Discrepancies | Validated |\n|---|---|---:|---:|---:|---:|---|"
+        );
+        for v in &report.validation {
+            let _ = writeln!(
+                md,
+                "| {} | {} | {} | {} | {} | {} | {} |",
+                cell(&v.entity),
+                or_dash(&v.run),
+                v.checks,
+                v.passed,
+                v.failed,
+                v.discrepancies,
+                or_dash(&v.validated_at)
+            );
+        }
+    }
+
+    let _ = writeln!(md, "\n## Errors\n");
+    if report.errors.is_empty() {
+        let _ = writeln!(md, "None recorded.");
+    } else {
+        let _ = writeln!(
+            md,
+            "| Entity | Origin | Message | Occurrences | Last seen |\n|---|---|---|---:|---|"
+        );
+        for e in report.errors.iter().take(ERRORS) {
+            let _ = writeln!(
+                md,
+                "| {} | {} | {} | {} | {} |",
+                cell(&e.entity),
+                e.origin,
+                cell(&e.message),
+                e.occurrences,
+                or_dash(&e.last_seen)
+            );
+        }
+        if report.errors.len() > ERRORS {
+            let _ = writeln!(
+                md,
+                "\n…and {} more; see the JSON report.",
+                report.errors.len() - ERRORS
+            );
+        }
+    }
+    md
+}
+
+fn markdown_changes(md: &mut String, changes: &Changes) {
+    let _ = writeln!(md, "\n## Changes since {}\n", changes.previous_generated_at);
+    if changes.entities.is_empty()
+        && changes.new_errors.is_empty()
+        && changes.resolved_errors.is_empty()
+        && changes.validation.is_empty()
+    {
+        let _ = writeln!(md, "Nothing changed.");
+        return;
+    }
+    if !changes.entities.is_empty() {
+        let _ = writeln!(
+            md,
+            "| Entity | Change | Runs | Records | Success rate | Last status |\n|---|---|---|---|---|---|"
+        );
+        for c in &changes.entities {
+            let _ = writeln!(
+                md,
+                "| {} | {} | {} → {} | {} → {} | {} → {} | {} → {} |",
+                cell(&c.entity),
+                c.change,
+                c.runs_before,
+                c.runs_after,
+                c.records_before,
+                c.records_after,
+                percent(c.success_rate_before),
+                percent(c.success_rate_after),
+                or_dash(&c.status_before),
+                or_dash(&c.status_after)
+            );
+        }
+    }
+    for v in &changes.validation {
+        let _ = writeln!(
+            md,
+            "\n- Validation of {}: {} → {} failed check(s)",
+            v.entity,
+            count(v.failed_before),
+            count(v.failed_after)
+        );
+    }
+    for (label, errors) in [
+        ("New errors", &changes.new_errors),
+        ("Resolved errors", &changes.resolved_errors),
+    ] {
+        if !errors.is_empty() {
+            let _ = writeln!(md, "\n{}:\n", label);
+            for e in errors {
+                let _ = writeln!(md, "- {}", e);
+            }
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const STYLE: &str = "body{font:14px/1.45 system-ui,sans-serif;margin:2em auto;max-width:1200px;padding:0 1em;color:#222}
+h1{margin-bottom:.2em}h2{margin-top:1.8em;border-bottom:1px solid #ddd}
+table{border-collapse:collapse;width:100%;margin:.6em 0}
+th,td{border:1px solid #ddd;padding:.3em .6em;text-align:left;vertical-align:top}
+th{background:#f5f5f5}td.n{text-align:right;font-variant-numeric:tabular-nums}
+.muted{color:#777}.completed{color:#1a7f37}.failed{color:#cf222e}.other{color:#9a6700}
+.cards{display:flex;gap:1em;flex-wrap:wrap}.card{border:1px solid #ddd;border-radius:6px;padding:.6em 1em;min-width:8em}
+.card b{display:block;font-size:1.5em}
+.track{position:relative;height:14px;background:#f5f5f5;min-width:240px}
+.bar{position:absolute;top:2px;height:10px;min-width:2px;border-radius:2px}
+.bar.completed{background:#2da44e}.bar.failed{background:#cf222e}.bar.other{background:#d4a72c}";
+
+fn status_class(status: &str) -> &'static str {
+    match status {
+        "completed" => "completed",
+        "failed" => "failed",
+        _ => "other",
+    }
+}
+
+/// Position and width in percent of `run` on a track spanning the timeline.
+fn span(run: &RunSpan, timeline: &[RunSpan]) -> Option<(f64, f64)> {
+    let times: Vec<i64> = timeline
+        .iter()
+        .flat_map(|r| [&r.started_at, &r.completed_at])
+        .filter_map(|t| t.as_deref().and_then(super::parse_time))
+        .map(|t| t.timestamp_millis())
+        .collect();
+    let (first, last) = (*times.iter().min()?, *times.iter().max()?);
+    let start = run.started_at.as_deref().and_then(super::parse_time)?;
+    let start = start.timestamp_millis();
+    let end = run
+        .completed_at
+        .as_deref()
+        .and_then(super::parse_time)
+        .map_or(start, |t| t.timestamp_millis());
+    let total = (last - first).max(1) as f64;
+    Some((
+        (start - first) as f64 * 100.0 / total,
+        (end - start).max(0) as f64 * 100.0 / total,
+    ))
+}
+
+pub fn html(report: &Report) -> String {
+    let mut h = String::new();
+    let totals = &report.totals;
+    let _ = writeln!(
+        h,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n{STYLE}\n</style>\n</head>\n<body>\n<h1>{title}</h1>\n<p class=\"muted\">Generated {} from {}</p>",
+        escape(&report.generated_at),
+        escape(&report.sources.join("; ")),
+        title = escape(&report.title),
+    );
+    let _ = writeln!(h, "<div class=\"cards\">");
+    for (label, value) in [
+        ("Entities", totals.entities.to_string()),
+        ("Runs", totals.runs.to_string()),
+        ("Failed runs", totals.failed_runs.to_string()),
+        ("Records", totals.records_processed.to_string()),
+        ("Rejected", totals.records_failed.to_string()),
+        ("Success rate", percent(totals.success_rate)),
+    ] {
+        let _ = writeln!(h, "<div class=\"card\"><b>{}</b>{}</div>", value, label);
+    }
+    let _ = writeln!(h, "</div>");
+
+    if let Some(changes) = &report.changes {
+        html_changes(&mut h, changes);
+    }
+
+    let _ = writeln!(h, "<h2>Entities</h2>");
+    if report.entities.is_empty() {
+        let _ = writeln!(h, "<p class=\"muted\">No runs recorded.</p>");
+    } else {
+        let _ = writeln!(
+            h,
+            "<table>\n<tr><th>Entity</th><th>Runs</th><th>Completed</th><th>Failed</th><th>Other</th><th>Records</th><th>Rejected</th><th>Success rate</th><th>Last status</th><th>Last run</th><th>Time</th></tr>"
+        );
+        for e in &report.entities {
+            let _ = writeln!(
+                h,
+                "<tr><td>{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"{}\">{}</td><td>{}</td><td class=\"n\">{}</td></tr>",
+                escape(&e.entity),
+                e.runs,
+                e.completed,
+                e.failed,
+                e.other,
+                e.records_processed,
+                e.records_failed,
+                percent(e.success_rate),
+                status_class(&e.last_status),
+                escape(&e.last_status),
+                escape(or_dash(&e.last_run_at)),
+                duration(Some(e.duration_ms))
+            );
+        }
+        let _ = writeln!(h, "</table>");
+    }
+
+    if !report.timeline.is_empty() {
+        let _ = writeln!(
+            h,
+            "<h2>Timeline</h2>\n<table>\n<tr><th>Started</th><th>Entity</th><th>Operation</th><th>Status</th><th>Records</th><th>Rejected</th><th>Time</th><th></th></tr>"
+        );
+        for run in &report.timeline {
+            let bar = span(run, &report.timeline).map_or_else(String::new, |(left, width)| {
+                format!(
+                    "<div class=\"bar {}\" style=\"left:{:.2}%;width:{:.2}%\"></div>",
+                    status_class(&run.status),
+                    left,
+                    width
+                )
+            });
+            let _ = writeln!(
+                h,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"{}\">{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td><td><div class=\"track\">{}</div></td></tr>",
+                escape(or_dash(&run.started_at)),
+                escape(&run.entity),
+                escape(or_dash(&run.operation)),
+                status_class(&run.status),
+                escape(&run.status),
+                run.records_processed,
+                run.records_failed,
+                duration(run.duration_ms),
+                bar
+            );
+        }
+        let _ = writeln!(h, "</table>");
+    }
+
+    if !report.validation.is_empty() {
+        let _ = writeln!(
+            h,
+            "<h2>Validation</h2>\n<table>\n<tr><th>Entity</th><th>Run</th><th>Checks</th><th>Passed</th><th>Failed</th><th>Discrepancies</th><th>Validated</th></tr>"
+        );
+        for v in &report.validation {
+            let _ = writeln!(
+                h,
+                "<tr><td>{}</td><td>{}</td><td class=\"n\">{}</td><td class=\"n completed\">{}</td><td class=\"n{}\">{}</td><td class=\"n\">{}</td><td>{}</td></tr>",
+                escape(&v.entity),
+                escape(or_dash(&v.run)),
+                v.checks,
+                v.passed,
+                if v.failed > 0 { " failed" } else { "" },
+                v.failed,
+                v.discrepancies,
+                escape(or_dash(&v.validated_at))
+            );
+        }
+        let _ = writeln!(h, "</table>");
+    }
+
+    let _ = writeln!(h, "<h2>Errors</h2>");
+    if report.errors.is_empty() {
+        let _ = writeln!(h, "<p class=\"muted\">None recorded.</p>");
+    } else {
+        let _ = writeln!(
+            h,
+            "<table>\n<tr><th>Entity</th><th>Origin</th><th>Message</th><th>Occurrences</th><th>Last seen</th></tr>"
+        );
+        for e in report.errors.iter().take(ERRORS) {
+            let _ = writeln!(
+                h,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"n\">{}</td><td>{}</td></tr>",
+                escape(&e.entity),
+                escape(&e.origin),
+                escape(&e.message),
+                e.occurrences,
+                escape(or_dash(&e.last_seen))
+            );
+        }
+        let _ = writeln!(h, "</table>");
+        if report.errors.len() > ERRORS {
+            let _ = writeln!(
+                h,
+                "<p class=\"muted\">…and {} more; see the JSON report.</p>",
+                report.errors.len() - ERRORS
+            );
+        }
+    }
+    let _ = writeln!(h, "</body>\n</html>");
+    h
+}
+
+fn html_changes(h: &mut String, changes: &Changes) {
+    let _ = writeln!(
+        h,
+        "<h2>Changes since {}</h2>",
+        escape(&changes.previous_generated_at)
+    );
+    if changes.entities.is_empty()
+        && changes.new_errors.is_empty()
+        && changes.resolved_errors.is_empty()
+        && changes.validation.is_empty()
+    {
+        let _ = writeln!(h, "<p class=\"muted\">Nothing changed.</p>");
+        return;
+    }
+    if !changes.entities.is_empty() {
+        let _ = writeln!(
+            h,
+            "<table>\n<tr><th>Entity</th><th>Change</th><th>Runs</th><th>Records</th><th>Success rate</th><th>Last status</th></tr>"
+        );
+        for c in &changes.entities {
+            let _ = writeln!(
+                h,
+                "<tr><td>{}</td><td>{}</td><td>{} → {}</td><td>{} → {}</td><td>{} → {}</td><td>{} → {}</td></tr>",
+                escape(&c.entity),
+                c.change,
+                c.runs_before,
+                c.runs_after,
+                c.records_before,
+                c.records_after,
+                percent(c.success_rate_before),
+                percent(c.success_rate_after),
+                escape(or_dash(&c.status_before)),
+                escape(or_dash(&c.status_after))
+            );
+        }
+        let _ = writeln!(h, "</table>");
+    }
+    if !changes.validation.is_empty() {
+        let _ = writeln!(h, "<ul>");
+        for v in &changes.validation {
+            let _ = writeln!(
+                h,
+                "<li>Validation of {}: {} → {} failed check(s)</li>",
+                escape(&v.entity),
+                count(v.failed_before),
+                count(v.failed_after)
+            );
+        }
+        let _ = writeln!(h, "</ul>");
+    }
+    for (label, class, errors) in [
+        ("New errors", "failed", &changes.new_errors),
+        ("Resolved errors", "completed", &changes.resolved_errors),
+    ] {
+        if !errors.is_empty() {
+            let _ = writeln!(h, "<p>{}:</p>\n<ul class=\"{}\">", label, class);
+            for e in errors {
+                let _ = writeln!(h, "<li>{}</li>", escape(e));
+            }
+            let _ = writeln!(h, "</ul>");
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! Scaffold a migration, validation and rollback artifact from an entity spec.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::plan::Plan;
+use crate::spec::EntitySpec;
+use crate::template::{Loader, TemplateError};
+
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("migrate.ts.tmpl", include_str!("templates/migrate.ts.tmpl")),
+    (
+        "validate.ts.tmpl",
+        include_str!("templates/validate.ts.tmpl"),
+    ),
+    (
+        "rollback.sql.tmpl",
+        include_str!("templates/rollback.sql.tmpl"),
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ArtifactKind {
+    Migrate,
+    Validate,
+    Rollback,
+}
+
+impl ArtifactKind {
+    pub const ALL: [ArtifactKind; 3] = [
+        ArtifactKind::Migrate,
+        ArtifactKind::Validate,
+        ArtifactKind::Rollback,
+    ];
+
+    pub fn file_name(self, spec: &EntitySpec) -> String {
+        let stem = spec.name.replace('_', "-");
+        match self {
+            ArtifactKind::Migrate => format!("migrate-{}.ts", stem),
+            ArtifactKind::Validate => format!("validate-{}-migration.ts", stem),
+            ArtifactKind::Rollback => format!("rollback-{}.sql", stem),
+        }
+    }
+
+    pub fn template_name(self) -> &'static str {
+        match self {
+            ArtifactKind::Migrate => "migrate.ts.tmpl",
+            ArtifactKind::Validate => "validate.ts.tmpl",
+            ArtifactKind::Rollback => "rollback.sql.tmpl",
+        }
+    }
+}
+
+pub struct Artifact {
+    pub file_name: String,
+    pub contents: String,
+}
+
+/// File name of the combined rollback covering every spec.
+pub const ROLLBACK_ALL: &str = "rollback-all.sql";
+
+/// A column the forward run loads NULL and backfills once the entity it
+/// references is mapped. Rolling that entity back sets it to NULL again.
+#[derive(Debug, Clone, Serialize)]
+pub struct Restore {
+    pub table: String,
+    pub column: String,
+}
+
+/// What the rollback scripts need from the dependency plan.
+#[derive(Debug, Default)]
+pub struct RollbackPlan {
+    /// Spec names, dependants before the entities they reference.
+    pub order: Vec<String>,
+    /// Backfilled columns, keyed by the spec name they reference.
+    pub restore: BTreeMap<String, Vec<Restore>>,
+    /// Entities that must be rolled back before the key entity.
+    pub dependents: BTreeMap<String, Vec<String>>,
+}
+
+impl RollbackPlan {
+    pub fn new(specs: &[EntitySpec], plan: &Plan) -> Self {
+        let mut rollback = RollbackPlan {
+            order: plan
+                .stages
+                .iter()
+                .rev()
+                .flat_map(|s| s.entities.iter().map(|e| e.node.name.clone()))
+                .collect(),
+            ..RollbackPlan::default()
+        };
+        for fill in &plan.backfill {
+            let Some(spec) = specs.iter().find(|s| s.name == fill.entity) else {
+                continue;
+            };
+            // Plan columns are source columns; the rollback works on the target.
+            for column in &fill.columns {
+                let target = spec
+                    .lookups
+                    .iter()
+                    .find(|l| &l.source == column)
+                    .map(|l| &l.target)
+                    .or_else(|| {
+                        spec.columns
+                            .iter()
+                            .find(|c| &c.source == column)
+                            .map(|c| &c.target)
+                    });
+                if let Some(target) = target {
+                    rollback
+                        .restore
+                        .entry(fill.references.clone())
+                        .or_default()
+                        .push(Restore {
+                            table: spec.target_table.clone(),
+                            column: target.clone(),
+                        });
+                }
+            }
+        }
+        for edge in plan.edges.iter().filter(|e| !e.deferred && e.from != e.to) {
+            rollback
+                .dependents
+                .entry(edge.to.clone())
+                .or_default()
+                .push(edge.from.clone());
+        }
+        rollback
+    }
+}
+
+/// Template loader over the built-in templates, with overrides read from
+/// `override_dir` (normally the project's `templates/`).
+pub fn loader(override_dir: &Path) -> Loader {
+    Loader::new(BUILTIN_TEMPLATES).with_override_dir(override_dir)
+}
+
+/// Render one artifact; the spec's file name is recorded in the header.
+pub fn render(
+    loader: &Loader,
+    spec: &EntitySpec,
+    kind: ArtifactKind,
+    rollback: &RollbackPlan,
+) -> Result<Artifact, TemplateError> {
+    let template = loader.load(kind.template_name())?;
+    let file_name = kind.file_name(spec);
+    let mut ctx = context(spec);
+    if kind == ArtifactKind::Rollback
+        && let Some(ctx) = ctx.as_object_mut()
+    {
+        ctx.insert("file_name".into(), json!(file_name));
+        ctx.insert("entities".into(), json!([rollback_entity(spec, rollback)]));
+        ctx.insert(
+            "dependents".into(),
+            json!(
+                rollback
+                    .dependents
+                    .get(&spec.name)
+                    .cloned()
+                    .unwrap_or_default()
+            ),
+        );
+    }
+    let contents = template.render(&ctx)?;
+    Ok(Artifact {
+        file_name,
+        contents,
+    })
+}
+
+/// Render one rollback covering every spec in `rollback.order`, in a single
+/// transaction.
+pub fn render_rollback_all(
+    loader: &Loader,
+    specs: &[EntitySpec],
+    rollback: &RollbackPlan,
+) -> Result<Artifact, TemplateError> {
+    let template = loader.load(ArtifactKind::Rollback.template_name())?;
+    let entities: Vec<Value> = rollback
+        .order
+        .iter()
+        .filter_map(|name| specs.iter().find(|s| &s.name == name))
+        .map(|spec| rollback_entity(spec, rollback))
+        .collect();
+    let origins: Vec<String> = specs.iter().map(origin_name).collect();
+    let ctx = json!({
+        "name": "all",
+        "file_name": ROLLBACK_ALL,
+        "header": format!(
+            "Generated by migration_generator scaffold from {}. Edit the specs and regenerate instead of changing this file.",
+            origins.join(", ")
+        ),
+        "entities": entities,
+        "dependents": [],
+    });
+    Ok(Artifact {
+        file_name: ROLLBACK_ALL.to_string(),
+        contents: template.render(&ctx)?,
+    })
+}
+
+/// The spec's file name, so the header reads the same whatever path or
+/// working directory the spec was loaded from.
+fn origin_name(spec: &EntitySpec) -> String {
+    spec.origin
+        .file_name()
+        .unwrap_or(spec.origin.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn rollback_entity(spec: &EntitySpec, rollback: &RollbackPlan) -> Value {
+    json!({
+        "name": spec.name,
+        "entity_type": spec.entity_type(),
+        "source_table": spec.source_table,
+        "target_table": spec.target_table,
+        "target_key": spec.target_key,
+        "restore": rollback.restore.get(&spec.name).cloned().unwrap_or_default(),
+    })
+}
+
+/// The template context: the spec's own fields plus a few derived values.
+pub fn context(spec: &EntitySpec) -> Value {
+    let mut ctx = serde_json::to_value(spec).unwrap_or_else(|_| json!({}));
+    let class_base: String = spec
+        .name
+        .split(['_', '-'])
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            let mut chars = w.chars();
+            chars
+                .next()
+                .map(|c| c.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let mut seen = HashSet::new();
+    let select: Vec<String> = std::iter::once(&spec.source_key)
+        .chain(spec.columns.iter().map(|c| &c.source))
+        .chain(spec.lookups.iter().map(|l| &l.source))
+        .filter(|c| seen.insert(c.as_str()))
+        .map(|c| format!("s.{}", c))
+        .collect();
+
+    // The entity's own mappings keep re-runs on the UUIDs already issued.
+    let mut entities: Vec<&str> = vec![spec.entity_type()];
+    for lookup in &spec.lookups {
+        if !entities.contains(&lookup.entity.as_str()) {
+            entities.push(&lookup.entity);
+        }
+    }
+
+    let derived = json!({
+        "header": format!(
+            "Generated by migration_generator scaffold from {}. Edit the spec and regenerate instead of changing this file.",
+            origin_name(spec)
+        ),
+        "entity_type": spec.entity_type(),
+        "class_name": format!("{}MigrationService", class_base),
+        "class_base": class_base,
+        "select_columns": select,
+        "target_columns": target_columns(spec),
+        "lookup_entities": entities,
+    });
+    if let (Some(ctx), Value::Object(derived)) = (ctx.as_object_mut(), derived) {
+        ctx.extend(derived);
+    }
+    ctx
+}
+
+/// Target columns in insert order: legacy ID first, then mapped columns, then
+/// resolved foreign keys.
+pub fn target_columns(spec: &EntitySpec) -> Vec<&str> {
+    std::iter::once(spec.legacy_id_column.as_str())
+        .chain(spec.columns.iter().map(|c| c.target.as_str()))
+        .chain(spec.lookups.iter().map(|l| l.target.as_str()))
+        .collect()
+}
@@ -0,0 +1,304 @@
+//! Schema from a JSON export of `information_schema`.
+//!
+//! The expected shape is what [`EXPORT_QUERY`] produces:
+//! `{"columns": [...], "constraints": [...], "enums": [...]}`. A bare array of
+//! `information_schema.columns` rows (e.g. from a dashboard export) is also
+//! accepted; it yields columns without constraints. Unique indexes that are
+//! not backed by a constraint are invisible to `information_schema`; use a
+//! pg_dump when those matter.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use super::{
+    CheckConstraint, Column, EnumType, ForeignKey, Schema, UniqueConstraint, normalize_type,
+    table_key,
+};
+
+/// Run with `psql -At -f` (or paste into the Supabase SQL editor) and save
+/// the single JSON value it returns.
+pub const EXPORT_QUERY: &str = r#"SELECT json_build_object(
+  'columns', (
+    SELECT json_agg(c ORDER BY c.table_schema, c.table_name, c.ordinal_position)
+    FROM (
+      SELECT table_schema, table_name, column_name, ordinal_position, data_type,
+             udt_schema, udt_name, is_nullable, column_default,
+             character_maximum_length, numeric_precision, numeric_scale
+      FROM information_schema.columns
+      WHERE table_schema NOT IN ('pg_catalog', 'information_schema')
+    ) c
+  ),
+  'constraints', (
+    SELECT json_agg(k ORDER BY k.table_schema, k.table_name, k.constraint_name, k.position)
+    FROM (
+      SELECT tc.table_schema, tc.table_name, tc.constraint_name, tc.constraint_type,
+             kcu.column_name, kcu.ordinal_position AS position,
+             ccu.table_schema AS foreign_table_schema,
+             ccu.table_name AS foreign_table_name,
+             ccu.column_name AS foreign_column_name,
+             rc.delete_rule, cc.check_clause
+      FROM information_schema.table_constraints tc
+      LEFT JOIN information_schema.key_column_usage kcu
+        ON kcu.constraint_schema = tc.constraint_schema
+       AND kcu.constraint_name = tc.constraint_name
+       AND kcu.table_name = tc.table_name
+      LEFT JOIN information_schema.referential_constraints rc
+        ON rc.constraint_schema = tc.constraint_schema
+       AND rc.constraint_name = tc.constraint_name
+      LEFT JOIN information_schema.key_column_usage ccu
+        ON ccu.constraint_schema = rc.unique_constraint_schema
+       AND ccu.constraint_name = rc.unique_constraint_name
+       AND ccu.ordinal_position = kcu.position_in_unique_constraint
+      LEFT JOIN information_schema.check_constraints cc
+        ON cc.constraint_schema = tc.constraint_schema
+       AND cc.constraint_name = tc.constraint_name
+      WHERE tc.table_schema NOT IN ('pg_catalog', 'information_schema')
+        AND NOT (tc.constraint_type = 'CHECK' AND cc.check_clause LIKE '% IS NOT NULL')
+    ) k
+  ),
+  'enums', (
+    SELECT json_agg(e ORDER BY e.type_schema, e.type_name, e.sort_order)
+    FROM (
+      SELECT n.nspname AS type_schema, t.typname AS type_name,
+             en.enumlabel AS enum_label, en.enumsortorder AS sort_order
+      FROM pg_type t
+      JOIN pg_enum en ON en.enumtypid = t.oid
+      JOIN pg_namespace n ON n.oid = t.typnamespace
+    ) e
+  )
+);"#;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Export {
+    Full {
+        #[serde(default)]
+        columns: Option<Vec<ColumnRow>>,
+        #[serde(default)]
+        constraints: Option<Vec<ConstraintRow>>,
+        #[serde(default)]
+        enums: Option<Vec<EnumRow>>,
+    },
+    Columns(Vec<ColumnRow>),
+}
+
+#[derive(Deserialize)]
+struct ColumnRow {
+    #[serde(default)]
+    table_schema: Option<String>,
+    table_name: String,
+    column_name: String,
+    #[serde(default)]
+    ordinal_position: Option<i64>,
+    data_type: String,
+    #[serde(default)]
+    udt_name: Option<String>,
+    #[serde(default)]
+    is_nullable: Option<String>,
+    #[serde(default)]
+    column_default: Option<String>,
+    #[serde(default)]
+    character_maximum_length: Option<i64>,
+    #[serde(default)]
+    numeric_precision: Option<i64>,
+    #[serde(default)]
+    numeric_scale: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct ConstraintRow {
+    #[serde(default)]
+    table_schema: Option<String>,
+    table_name: String,
+    constraint_name: String,
+    constraint_type: String,
+    #[serde(default)]
+    column_name: Option<String>,
+    #[serde(default)]
+    foreign_table_schema: Option<String>,
+    #[serde(default)]
+    foreign_table_name: Option<String>,
+    #[serde(default)]
+    foreign_column_name: Option<String>,
+    #[serde(default)]
+    delete_rule: Option<String>,
+    #[serde(default)]
+    check_clause: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EnumRow {
+    #[serde(default, alias = "nspname")]
+    type_schema: Option<String>,
+    #[serde(alias = "typname")]
+    type_name: String,
+    #[serde(alias = "enumlabel")]
+    enum_label: String,
+    #[serde(default, alias = "enumsortorder")]
+    sort_order: Option<f64>,
+}
+
+pub fn parse(text: &str) -> Result<Schema, String> {
+    let export: Export = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let (columns, constraints, enums) = match export {
+        Export::Full {
+            columns,
+            constraints,
+            enums,
+        } => (
+            columns.unwrap_or_default(),
+            constraints.unwrap_or_default(),
+            enums.unwrap_or_default(),
+        ),
+        Export::Columns(columns) => (columns, Vec::new(), Vec::new()),
+    };
+
+    let mut schema = Schema::default();
+
+    let mut enums = enums;
+    enums.sort_by(|a, b| {
+        a.sort_order
+            .unwrap_or(0.0)
+            .total_cmp(&b.sort_order.unwrap_or(0.0))
+    });
+    for row in enums {
+        let key = table_key(row.type_schema.as_deref(), &row.type_name);
+        schema
+            .enums
+            .entry(key.clone())
+            .or_insert_with(|| EnumType {
+                name: key,
+                values: Vec::new(),
+            })
+            .values
+            .push(row.enum_label);
+    }
+
+    let mut columns = columns;
+    columns.sort_by_key(|c| c.ordinal_position.unwrap_or(0));
+    for row in columns {
+        let key = table_key(row.table_schema.as_deref(), &row.table_name);
+        let column = Column {
+            data_type: column_type(&row),
+            nullable: row.is_nullable.as_deref() != Some("NO"),
+            default: row.column_default,
+            name: row.column_name,
+        };
+        schema.table_mut(&key).columns.push(column);
+    }
+
+    // One row per constraint column; group them back into constraints while
+    // keeping the export's column order.
+    let mut grouped: BTreeMap<(String, String), Vec<ConstraintRow>> = BTreeMap::new();
+    for row in constraints {
+        let key = table_key(row.table_schema.as_deref(), &row.table_name);
+        grouped
+            .entry((key, row.constraint_name.clone()))
+            .or_default()
+            .push(row);
+    }
+    for ((key, name), rows) in grouped {
+        let columns: Vec<String> = dedup(rows.iter().filter_map(|r| r.column_name.clone()));
+        let first = &rows[0];
+        let table = schema.table_mut(&key);
+        match first.constraint_type.as_str() {
+            "PRIMARY KEY" => {
+                for column in &mut table.columns {
+                    if columns.contains(&column.name) {
+                        column.nullable = false;
+                    }
+                }
+                table.primary_key = columns;
+            }
+            "UNIQUE" => table.unique.push(UniqueConstraint {
+                name: Some(name),
+                columns,
+            }),
+            "FOREIGN KEY" => {
+                let Some(foreign_table) = &first.foreign_table_name else {
+                    return Err(format!(
+                        "foreign key '{}' on '{}' has no foreign_table_name",
+                        name, key
+                    ));
+                };
+                table.foreign_keys.push(ForeignKey {
+                    name: Some(name),
+                    columns,
+                    references_table: table_key(
+                        first.foreign_table_schema.as_deref(),
+                        foreign_table,
+                    ),
+                    references_columns: dedup(
+                        rows.iter().filter_map(|r| r.foreign_column_name.clone()),
+                    ),
+                    on_delete: first.delete_rule.clone().filter(|rule| rule != "NO ACTION"),
+                });
+            }
+            "CHECK" => {
+                let expression = first.check_clause.clone().unwrap_or_default();
+                // PostgreSQL reports NOT NULL as `CHECK (x IS NOT NULL)`.
+                if !expression.is_empty() && !expression.ends_with(" IS NOT NULL") {
+                    table.checks.push(CheckConstraint {
+                        name: Some(name),
+                        expression: strip_parens(&expression).to_string(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(schema)
+}
+
+fn column_type(row: &ColumnRow) -> String {
+    let udt = row.udt_name.as_deref().unwrap_or("");
+    let base = match row.data_type.as_str() {
+        "USER-DEFINED" => udt.to_string(),
+        "ARRAY" => format!("{}[]", udt.strip_prefix('_').unwrap_or(udt)),
+        "character varying" | "character" => match row.character_maximum_length {
+            Some(n) => format!("{}({})", row.data_type, n),
+            None => row.data_type.clone(),
+        },
+        "numeric" => match (row.numeric_precision, row.numeric_scale) {
+            (Some(p), Some(s)) => format!("numeric({},{})", p, s),
+            (Some(p), None) => format!("numeric({})", p),
+            _ => "numeric".to_string(),
+        },
+        other => other.to_string(),
+    };
+    normalize_type(&base)
+}
+
+/// `check_clause` wraps the expression in one more pair of parentheses than
+/// the `CHECK (...)` body pg_dump prints.
+fn strip_parens(expression: &str) -> &str {
+    let Some(inner) = expression
+        .strip_prefix('(')
+        .and_then(|e| e.strip_suffix(')'))
+    else {
+        return expression;
+    };
+    let mut depth = 0i32;
+    for c in inner.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if depth < 0 {
+            return expression;
+        }
+    }
+    inner
+}
+
+fn dedup(values: impl Iterator<Item = String>) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for v in values {
+        if !out.contains(&v) {
+            out.push(v);
+        }
+    }
+    out
+}
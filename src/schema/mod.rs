@@ -0,0 +1,225 @@
+//! Typed database schema model shared by introspection, mapping suggestions,
+//! planning and auditing.
+//!
+//! Schemas come from a `pg_dump --schema-only` file ([`pgdump`]), a JSON export
+//! of `information_schema` ([`catalog`]) or a cached snapshot ([`snapshot`]).
+
+pub mod catalog;
+pub mod pgdump;
+pub mod snapshot;
+mod sql;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+    /// Keyed by table name; tables outside `public` are keyed `schema.name`.
+    pub tables: BTreeMap<String, Table>,
+    pub enums: BTreeMap<String, EnumType>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Table {
+    pub name: String,
+    pub columns: Vec<Column>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub primary_key: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub foreign_keys: Vec<ForeignKey>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unique: Vec<UniqueConstraint>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<CheckConstraint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Column {
+    pub name: String,
+    /// Normalized PostgreSQL type, e.g. `integer`, `character varying(255)`,
+    /// `timestamp with time zone`, `text[]`, or an enum type name.
+    pub data_type: String,
+    pub nullable: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForeignKey {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub columns: Vec<String>,
+    pub references_table: String,
+    pub references_columns: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_delete: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UniqueConstraint {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub columns: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckConstraint {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub expression: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnumType {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+impl Schema {
+    pub fn table(&self, name: &str) -> Option<&Table> {
+        self.tables.get(name)
+    }
+
+    pub fn column_count(&self) -> usize {
+        self.tables.values().map(|t| t.columns.len()).sum()
+    }
+
+    pub fn foreign_key_count(&self) -> usize {
+        self.tables.values().map(|t| t.foreign_keys.len()).sum()
+    }
+
+    /// Get or create the table `key`, as the DDL and catalog readers see
+    /// constraints before or after the table definition.
+    fn table_mut(&mut self, key: &str) -> &mut Table {
+        self.tables.entry(key.to_string()).or_insert_with(|| Table {
+            name: key.to_string(),
+            ..Table::default()
+        })
+    }
+}
+
+/// Table key for `schema.name`: bare for `public`, qualified otherwise.
+pub fn table_key(schema: Option<&str>, name: &str) -> String {
+    match schema {
+        None | Some("public") | Some("") => name.to_string(),
+        Some(schema) => format!("{}.{}", schema, name),
+    }
+}
+
+/// Normalize PostgreSQL type spellings to the names `format_type` reports.
+pub fn normalize_type(raw: &str) -> String {
+    let lowered = raw.trim().to_ascii_lowercase();
+    let collapsed = lowered.split_whitespace().collect::<Vec<_>>().join(" ");
+    let (base, array) = match collapsed.strip_suffix("[]") {
+        Some(base) => (base.trim_end().to_string(), "[]"),
+        None => (collapsed, ""),
+    };
+    let (name, args) = match base.find('(') {
+        Some(i) => (base[..i].trim_end().to_string(), base[i..].replace(' ', "")),
+        None => (base, String::new()),
+    };
+    // `timestamp(3) with time zone` keeps its precision after the type name.
+    let (name, args) = match name.split_once(' ') {
+        Some((head, tail)) if !args.is_empty() && head == "timestamp" => {
+            (format!("timestamp {}", tail), args)
+        }
+        _ => (name, args),
+    };
+    let name = name.strip_prefix("public.").unwrap_or(&name).to_string();
+    let canonical = match name.as_str() {
+        "int" | "int4" | "serial" | "serial4" => "integer",
+        "int8" | "bigserial" | "serial8" => "bigint",
+        "int2" | "smallserial" | "serial2" => "smallint",
+        "bool" => "boolean",
+        "varchar" => "character varying",
+        "char" | "bpchar" => "character",
+        "float8" | "double" => "double precision",
+        "float4" => "real",
+        "decimal" => "numeric",
+        "timestamp" | "timestamp without time zone" => "timestamp without time zone",
+        "timestamptz" | "timestamp with time zone" => "timestamp with time zone",
+        "time" => "time without time zone",
+        "timetz" => "time with time zone",
+        other => other,
+    };
+    format!("{}{}{}", canonical, args, array)
+}
+
+/// Serial pseudo-types imply a sequence default.
+fn is_serial(raw: &str) -> bool {
+    matches!(
+        raw.trim().to_ascii_lowercase().as_str(),
+        "serial" | "serial4" | "bigserial" | "serial8" | "smallserial" | "serial2"
+    )
+}
+
+#[derive(Debug)]
+pub struct SchemaError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum InputFormat {
+    /// Detect from the file extension and contents
+    Auto,
+    /// `pg_dump --schema-only` output or other DDL
+    PgDump,
+    /// JSON export of information_schema columns and constraints
+    CatalogJson,
+    /// A snapshot written by `introspect --output`
+    Snapshot,
+}
+
+/// Read a schema from any supported input.
+pub fn load(path: &Path, format: InputFormat) -> Result<Schema, SchemaError> {
+    let text = fs::read_to_string(path).map_err(|e| SchemaError {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    })?;
+    parse(path, &text, format)
+}
+
+/// Parse `text` read from `path`; `path` is only used for detection and errors.
+pub fn parse(path: &Path, text: &str, format: InputFormat) -> Result<Schema, SchemaError> {
+    let err = |message: String| SchemaError {
+        path: path.to_path_buf(),
+        message,
+    };
+    match resolve_format(path, text, format) {
+        InputFormat::PgDump => pgdump::parse(text).map_err(err),
+        InputFormat::CatalogJson => catalog::parse(text).map_err(err),
+        InputFormat::Snapshot | InputFormat::Auto => snapshot::Snapshot::from_json(text)
+            .map(|s| s.schema)
+            .map_err(err),
+    }
+}
+
+/// Resolve [`InputFormat::Auto`] from the file extension and contents.
+pub fn resolve_format(path: &Path, text: &str, format: InputFormat) -> InputFormat {
+    if format != InputFormat::Auto {
+        return format;
+    }
+    let is_json =
+        path.extension().is_some_and(|e| e == "json") || text.trim_start().starts_with(['{', '[']);
+    if !is_json {
+        InputFormat::PgDump
+    } else if snapshot::looks_like_snapshot(text) {
+        InputFormat::Snapshot
+    } else {
+        InputFormat::CatalogJson
+    }
+}
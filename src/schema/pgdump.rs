@@ -0,0 +1,470 @@
+//! Schema from `pg_dump --schema-only` output or hand-written DDL.
+//!
+//! Only the statements that shape the table model are interpreted: `CREATE
+//! TABLE`, `CREATE TYPE ... AS ENUM`, `ALTER TABLE ... ADD CONSTRAINT`,
+//! `ALTER TABLE ... ALTER COLUMN ... SET DEFAULT` and `CREATE UNIQUE INDEX`.
+//! Everything else (functions, grants, sequences, data) is skipped.
+
+use super::sql::{self, Kind, Token};
+use super::{
+    CheckConstraint, Column, EnumType, ForeignKey, Schema, Table, UniqueConstraint, is_serial,
+    normalize_type, table_key,
+};
+
+pub fn parse(text: &str) -> Result<Schema, String> {
+    let tokens = sql::tokenize(text)?;
+    let mut schema = Schema::default();
+    for stmt in sql::statements(&tokens) {
+        let mut p = Parser {
+            src: text,
+            tokens: stmt,
+            pos: 0,
+        };
+        p.statement(&mut schema)
+            .map_err(|e| format!("line {}: {}", line_of(text, stmt[0].start), e))?;
+    }
+    Ok(schema)
+}
+
+fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset].matches('\n').count() + 1
+}
+
+struct Parser<'s, 't> {
+    src: &'s str,
+    tokens: &'t [Token<'s>],
+    pos: usize,
+}
+
+/// Words that end a column's type and start its constraints.
+const COLUMN_STOP: &[&str] = &[
+    "not",
+    "null",
+    "default",
+    "primary",
+    "references",
+    "unique",
+    "check",
+    "constraint",
+    "collate",
+    "generated",
+];
+
+impl<'s, 't> Parser<'s, 't> {
+    fn peek(&self) -> Option<&'t Token<'s>> {
+        self.tokens.get(self.pos)
+    }
+
+    fn at_word(&self, word: &str) -> bool {
+        self.peek().is_some_and(|t| t.is_word(word))
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        if self.at_word(word) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_words(&mut self, words: &[&str]) -> bool {
+        let matched = words
+            .iter()
+            .enumerate()
+            .all(|(i, w)| self.tokens.get(self.pos + i).is_some_and(|t| t.is_word(w)));
+        if matched {
+            self.pos += words.len();
+        }
+        matched
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(t) if matches!(t.kind, Kind::Word | Kind::Quoted) => {
+                self.pos += 1;
+                Ok(t.ident())
+            }
+            Some(t) => Err(format!("expected identifier, found '{}'", t.text)),
+            None => Err("unexpected end of statement".into()),
+        }
+    }
+
+    /// `name` or `schema.name`, returned as a [`table_key`].
+    fn qualified_name(&mut self) -> Result<String, String> {
+        let first = self.ident()?;
+        if self.peek().is_some_and(|t| t.is_punct('.')) {
+            self.pos += 1;
+            let name = self.ident()?;
+            Ok(table_key(Some(&first), &name))
+        } else {
+            Ok(table_key(None, &first))
+        }
+    }
+
+    /// Tokens inside the parenthesis group starting at the cursor.
+    fn paren_group(&mut self) -> Result<&'t [Token<'s>], String> {
+        if !self.peek().is_some_and(|t| t.is_punct('(')) {
+            return Err(format!(
+                "expected '(', found '{}'",
+                self.peek().map_or("end of statement", |t| t.text)
+            ));
+        }
+        let close = sql::matching_paren(self.tokens, self.pos).ok_or("unbalanced parentheses")?;
+        let inner = &self.tokens[self.pos + 1..close];
+        self.pos = close + 1;
+        Ok(inner)
+    }
+
+    fn ident_list(&mut self) -> Result<Vec<String>, String> {
+        let inner = self.paren_group()?;
+        sql::split_top_level(inner)
+            .into_iter()
+            .map(|part| match part {
+                [t] if matches!(t.kind, Kind::Word | Kind::Quoted) => Ok(t.ident()),
+                _ => Err(format!(
+                    "expected column name, found '{}'",
+                    sql::source_text(self.src, part)
+                )),
+            })
+            .collect()
+    }
+
+    fn statement(&mut self, schema: &mut Schema) -> Result<(), String> {
+        if self.eat_word("create") {
+            self.eat_words(&["or", "replace"]);
+            for modifier in ["unlogged", "temporary", "temp"] {
+                self.eat_word(modifier);
+            }
+            if self.eat_word("table") {
+                return self.create_table(schema);
+            }
+            if self.eat_word("type") {
+                return self.create_type(schema);
+            }
+            if self.eat_words(&["unique", "index"]) {
+                return self.create_unique_index(schema);
+            }
+        } else if self.eat_words(&["alter", "table"]) {
+            return self.alter_table(schema);
+        }
+        Ok(())
+    }
+
+    fn create_table(&mut self, schema: &mut Schema) -> Result<(), String> {
+        self.eat_words(&["if", "not", "exists"]);
+        let key = self.qualified_name()?;
+        // `CREATE TABLE x AS SELECT` and `PARTITION OF` carry no column list.
+        if !self.peek().is_some_and(|t| t.is_punct('(')) {
+            return Ok(());
+        }
+        let body = self.paren_group()?;
+        let table = schema.table_mut(&key);
+        for element in sql::split_top_level(body) {
+            let mut p = Parser {
+                src: self.src,
+                tokens: element,
+                pos: 0,
+            };
+            if p.at_word("constraint")
+                || p.at_word("primary")
+                || p.at_word("foreign")
+                || p.at_word("unique")
+                || p.at_word("check")
+                || p.at_word("exclude")
+            {
+                p.table_constraint(table)?;
+            } else if p.at_word("like") {
+                continue;
+            } else {
+                let column = p.column(&key, table)?;
+                table.columns.push(column);
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse one column definition, recording inline constraints on `table`.
+    fn column(&mut self, key: &str, table: &mut Table) -> Result<Column, String> {
+        let name = self.ident()?;
+        let type_start = self.pos;
+        while let Some(t) = self.peek() {
+            if COLUMN_STOP.iter().any(|w| t.is_word(w)) {
+                break;
+            }
+            if t.is_punct('(') {
+                self.paren_group()?;
+            } else {
+                self.pos += 1;
+            }
+        }
+        let raw_type = sql::source_text(self.src, &self.tokens[type_start..self.pos]);
+        if raw_type.is_empty() {
+            return Err(format!("column '{}' on '{}' has no type", name, key));
+        }
+        let mut column = Column {
+            name: name.clone(),
+            data_type: normalize_type(raw_type),
+            nullable: true,
+            default: is_serial(raw_type).then(|| {
+                format!(
+                    "nextval('{}_{}_seq'::regclass)",
+                    key.rsplit('.').next().unwrap_or(key),
+                    name
+                )
+            }),
+        };
+        if is_serial(raw_type) {
+            column.nullable = false;
+        }
+
+        let mut constraint_name = None;
+        while self.peek().is_some() {
+            if self.eat_word("constraint") {
+                constraint_name = Some(self.ident()?);
+                continue;
+            }
+            if self.eat_words(&["not", "null"]) {
+                column.nullable = false;
+            } else if self.eat_word("null") {
+                column.nullable = true;
+            } else if self.eat_word("default") {
+                column.default = Some(self.expression_until(COLUMN_STOP));
+            } else if self.eat_words(&["primary", "key"]) {
+                column.nullable = false;
+                table.primary_key = vec![name.clone()];
+            } else if self.eat_word("unique") {
+                self.eat_words(&["nulls", "not", "distinct"]);
+                table.unique.push(UniqueConstraint {
+                    name: constraint_name.take(),
+                    columns: vec![name.clone()],
+                });
+            } else if self.eat_word("references") {
+                let mut fk = self.references(vec![name.clone()])?;
+                fk.name = constraint_name.take();
+                table.foreign_keys.push(fk);
+            } else if self.eat_word("check") {
+                let expression = self.check_expression()?;
+                table.checks.push(CheckConstraint {
+                    name: constraint_name.take(),
+                    expression,
+                });
+            } else if self.eat_word("collate") {
+                self.qualified_name()?;
+            } else {
+                // GENERATED ... AS IDENTITY / STORED and anything newer.
+                self.pos += 1;
+                if self.peek().is_some_and(|t| t.is_punct('(')) {
+                    self.paren_group()?;
+                }
+            }
+        }
+        Ok(column)
+    }
+
+    /// Source text up to the next top-level stop word.
+    fn expression_until(&mut self, stops: &[&str]) -> String {
+        let start = self.pos;
+        while let Some(t) = self.peek() {
+            if stops.iter().any(|w| t.is_word(w)) {
+                break;
+            }
+            if t.is_punct('(') {
+                if self.paren_group().is_err() {
+                    self.pos = self.tokens.len();
+                }
+            } else {
+                self.pos += 1;
+            }
+        }
+        sql::source_text(self.src, &self.tokens[start..self.pos]).to_string()
+    }
+
+    fn check_expression(&mut self) -> Result<String, String> {
+        let inner = self.paren_group()?;
+        self.eat_words(&["no", "inherit"]);
+        self.eat_words(&["not", "valid"]);
+        Ok(sql::source_text(self.src, inner).to_string())
+    }
+
+    /// `REFERENCES table [(cols)] [MATCH ...] [ON DELETE action] [ON UPDATE action]`
+    fn references(&mut self, columns: Vec<String>) -> Result<ForeignKey, String> {
+        let references_table = self.qualified_name()?;
+        let references_columns = if self.peek().is_some_and(|t| t.is_punct('(')) {
+            self.ident_list()?
+        } else {
+            Vec::new()
+        };
+        let mut on_delete = None;
+        loop {
+            if self.eat_word("match") {
+                self.pos += 1;
+            } else if self.eat_words(&["on", "delete"]) {
+                on_delete = Some(self.referential_action());
+            } else if self.eat_words(&["on", "update"]) {
+                self.referential_action();
+            } else if self.eat_word("deferrable")
+                || self.eat_words(&["not", "deferrable"])
+                || self.eat_words(&["initially", "deferred"])
+                || self.eat_words(&["initially", "immediate"])
+                || self.eat_words(&["not", "valid"])
+            {
+            } else {
+                break;
+            }
+        }
+        Ok(ForeignKey {
+            name: None,
+            columns,
+            references_table,
+            references_columns,
+            on_delete,
+        })
+    }
+
+    fn referential_action(&mut self) -> String {
+        for action in [
+            &["no", "action"][..],
+            &["set", "null"],
+            &["set", "default"],
+            &["cascade"],
+            &["restrict"],
+        ] {
+            if self.eat_words(action) {
+                // `SET NULL (col)` on PostgreSQL 15+.
+                if self.peek().is_some_and(|t| t.is_punct('(')) {
+                    let _ = self.paren_group();
+                }
+                return action.join(" ").to_ascii_uppercase();
+            }
+        }
+        String::new()
+    }
+
+    /// Table-level constraint inside `CREATE TABLE` or after `ADD`.
+    fn table_constraint(&mut self, table: &mut Table) -> Result<(), String> {
+        let name = if self.eat_word("constraint") {
+            Some(self.ident()?)
+        } else {
+            None
+        };
+        if self.eat_words(&["primary", "key"]) {
+            table.primary_key = self.ident_list()?;
+            for column in &mut table.columns {
+                if table.primary_key.contains(&column.name) {
+                    column.nullable = false;
+                }
+            }
+        } else if self.eat_words(&["foreign", "key"]) {
+            let columns = self.ident_list()?;
+            if !self.eat_word("references") {
+                return Err("expected REFERENCES after FOREIGN KEY".into());
+            }
+            let mut fk = self.references(columns)?;
+            fk.name = name;
+            table.foreign_keys.push(fk);
+        } else if self.eat_word("unique") {
+            self.eat_words(&["nulls", "not", "distinct"]);
+            let columns = self.ident_list()?;
+            table.unique.push(UniqueConstraint { name, columns });
+        } else if self.eat_word("check") {
+            let expression = self.check_expression()?;
+            table.checks.push(CheckConstraint { name, expression });
+        }
+        // EXCLUDE constraints have no place in the model.
+        Ok(())
+    }
+
+    fn create_type(&mut self, schema: &mut Schema) -> Result<(), String> {
+        let name = self.qualified_name()?;
+        if !self.eat_words(&["as", "enum"]) {
+            return Ok(());
+        }
+        let inner = self.paren_group()?;
+        let values = inner
+            .iter()
+            .filter(|t| t.kind == Kind::Str)
+            .map(|t| t.string_value())
+            .collect();
+        schema.enums.insert(name.clone(), EnumType { name, values });
+        Ok(())
+    }
+
+    fn create_unique_index(&mut self, schema: &mut Schema) -> Result<(), String> {
+        self.eat_word("concurrently");
+        self.eat_words(&["if", "not", "exists"]);
+        let name = if self.at_word("on") {
+            None
+        } else {
+            Some(self.ident()?)
+        };
+        if !self.eat_word("on") {
+            return Ok(());
+        }
+        self.eat_word("only");
+        let key = self.qualified_name()?;
+        if self.eat_word("using") {
+            self.pos += 1;
+        }
+        // Expression indexes are not column constraints; partial ones
+        // (`WHERE ...`) only hold for a subset of rows.
+        let Ok(columns) = self.ident_list() else {
+            return Ok(());
+        };
+        if self.at_word("where") {
+            return Ok(());
+        }
+        schema
+            .table_mut(&key)
+            .unique
+            .push(UniqueConstraint { name, columns });
+        Ok(())
+    }
+
+    fn alter_table(&mut self, schema: &mut Schema) -> Result<(), String> {
+        self.eat_words(&["if", "exists"]);
+        self.eat_word("only");
+        let key = self.qualified_name()?;
+        let actions: Vec<&'t [Token<'s>]> = sql::split_top_level(&self.tokens[self.pos..]);
+        for action in actions {
+            let mut p = Parser {
+                src: self.src,
+                tokens: action,
+                pos: 0,
+            };
+            if p.eat_word("add") {
+                if p.at_word("constraint")
+                    || p.at_word("primary")
+                    || p.at_word("foreign")
+                    || p.at_word("unique")
+                    || p.at_word("check")
+                {
+                    p.table_constraint(schema.table_mut(&key))?;
+                } else {
+                    p.eat_word("column");
+                    p.eat_words(&["if", "not", "exists"]);
+                    let table = schema.table_mut(&key);
+                    let column = p.column(&key, table)?;
+                    table.columns.push(column);
+                }
+            } else if p.eat_word("alter") {
+                p.eat_word("column");
+                let column = p.ident()?;
+                let table = schema.table_mut(&key);
+                let Some(target) = table.columns.iter_mut().find(|c| c.name == column) else {
+                    continue;
+                };
+                if p.eat_words(&["set", "default"]) {
+                    target.default = Some(p.expression_until(&[]));
+                } else if p.eat_words(&["drop", "default"]) {
+                    target.default = None;
+                } else if p.eat_words(&["set", "not", "null"]) {
+                    target.nullable = false;
+                } else if p.eat_words(&["drop", "not", "null"]) {
+                    target.nullable = true;
+                }
+            }
+        }
+        Ok(())
+    }
+}
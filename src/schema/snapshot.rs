@@ -0,0 +1,82 @@
+//! Versioned on-disk snapshot of an introspected schema.
+//!
+//! Snapshots double as a cache: the fingerprint of the input they were built
+//! from is stored alongside the model, so re-running `introspect` on an
+//! unchanged dump is a no-op and later commands can load the snapshot instead
+//! of re-parsing DDL.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::Schema;
+
+/// Bumped whenever the serialized model changes incompatibly.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub format_version: u32,
+    /// `migration_generator <version>`.
+    pub generated_by: String,
+    /// Path of the dump or catalog export the snapshot was built from.
+    pub source: String,
+    /// RFC 3339 UTC timestamp.
+    pub created_at: String,
+    /// `sha256:<hex>` of the input file contents.
+    pub fingerprint: String,
+    pub schema: Schema,
+}
+
+impl Snapshot {
+    pub fn new(source: String, fingerprint: String, schema: Schema) -> Self {
+        Snapshot {
+            format_version: FORMAT_VERSION,
+            generated_by: generated_by(),
+            source,
+            created_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            fingerprint,
+            schema,
+        }
+    }
+
+    pub fn from_json(text: &str) -> Result<Snapshot, String> {
+        let snapshot: Snapshot = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        if snapshot.format_version != FORMAT_VERSION {
+            return Err(format!(
+                "snapshot format version {} is not supported (expected {}); re-run `introspect --force`",
+                snapshot.format_version, FORMAT_VERSION
+            ));
+        }
+        Ok(snapshot)
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut text = serde_json::to_string_pretty(self).unwrap_or_default();
+        text.push('\n');
+        text
+    }
+
+    /// Whether this snapshot was built from `fingerprint` by this version of
+    /// the tool, so re-parsing the input would produce the same model.
+    pub fn is_current(&self, fingerprint: &str) -> bool {
+        self.fingerprint == fingerprint && self.generated_by == generated_by()
+    }
+}
+
+/// Cheap check used by format detection before a full parse.
+pub fn looks_like_snapshot(text: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(text)
+        .ok()
+        .and_then(|v| v.get("format_version").cloned())
+        .is_some()
+}
+
+pub fn fingerprint(contents: &[u8]) -> String {
+    let digest = Sha256::digest(contents);
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256:{}", hex)
+}
+
+fn generated_by() -> String {
+    format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
@@ -0,0 +1,222 @@
+//! Just enough of a PostgreSQL lexer to split DDL into statements and walk
+//! their tokens. Comments are dropped; string, quoted-identifier and
+//! dollar-quoted bodies are kept intact so function definitions do not leak
+//! stray semicolons.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Unquoted identifier or keyword.
+    Word,
+    /// `"Quoted"` identifier.
+    Quoted,
+    /// `'string'` or `$tag$string$tag$`.
+    Str,
+    Number,
+    /// Single punctuation character such as `(`, `,` or `.`.
+    Punct,
+    /// Any other operator run, e.g. `::` or `>=`.
+    Op,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Token<'a> {
+    pub kind: Kind,
+    pub text: &'a str,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Token<'_> {
+    pub fn is_word(&self, word: &str) -> bool {
+        self.kind == Kind::Word && self.text.eq_ignore_ascii_case(word)
+    }
+
+    pub fn is_punct(&self, c: char) -> bool {
+        self.kind == Kind::Punct && self.text.len() == 1 && self.text.starts_with(c)
+    }
+
+    /// Identifier value: unquoted words fold to lower case.
+    pub fn ident(&self) -> String {
+        match self.kind {
+            Kind::Quoted => self.text[1..self.text.len() - 1].replace("\"\"", "\""),
+            _ => self.text.to_ascii_lowercase(),
+        }
+    }
+
+    /// String literal value with quotes removed.
+    pub fn string_value(&self) -> String {
+        if self.text.starts_with('\'') {
+            self.text[1..self.text.len() - 1].replace("''", "'")
+        } else {
+            let tag_end = self.text[1..].find('$').map_or(0, |i| i + 2);
+            self.text[tag_end..self.text.len() - tag_end].to_string()
+        }
+    }
+}
+
+pub fn tokenize(src: &str) -> Result<Vec<Token<'_>>, String> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let start = i;
+        let kind = match c {
+            b if b.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let end = src[i + 2..]
+                    .find("*/")
+                    .ok_or_else(|| format!("unterminated comment at byte {}", i))?;
+                i += end + 4;
+                continue;
+            }
+            b'\'' => {
+                i = scan_quoted(bytes, i, b'\'')
+                    .ok_or_else(|| format!("unterminated string at byte {}", start))?;
+                Kind::Str
+            }
+            b'"' => {
+                i = scan_quoted(bytes, i, b'"')
+                    .ok_or_else(|| format!("unterminated identifier at byte {}", start))?;
+                Kind::Quoted
+            }
+            b'$' if dollar_tag(src, i).is_some() => {
+                let tag = dollar_tag(src, i).unwrap_or("$$");
+                let body = i + tag.len();
+                let end = src[body..]
+                    .find(tag)
+                    .ok_or_else(|| format!("unterminated {} string at byte {}", tag, start))?;
+                i = body + end + tag.len();
+                Kind::Str
+            }
+            b if b.is_ascii_alphabetic() || b == b'_' || b >= 0x80 => {
+                while i < bytes.len()
+                    && (bytes[i].is_ascii_alphanumeric()
+                        || bytes[i] == b'_'
+                        || bytes[i] == b'$'
+                        || bytes[i] >= 0x80)
+                {
+                    i += 1;
+                }
+                Kind::Word
+            }
+            b if b.is_ascii_digit() => {
+                while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'.') {
+                    i += 1;
+                }
+                Kind::Number
+            }
+            b'(' | b')' | b',' | b';' | b'.' | b'[' | b']' => {
+                i += 1;
+                Kind::Punct
+            }
+            _ => {
+                while i < bytes.len() && b"+-*/<>=~!@#%^&|`?:".contains(&bytes[i]) {
+                    i += 1;
+                }
+                if i == start {
+                    i += 1;
+                }
+                Kind::Op
+            }
+        };
+        tokens.push(Token {
+            kind,
+            text: &src[start..i],
+            start,
+            end: i,
+        });
+    }
+    Ok(tokens)
+}
+
+fn scan_quoted(bytes: &[u8], start: usize, quote: u8) -> Option<usize> {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return Some(i + 1);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// `$$` or `$tag$` at byte `i`.
+fn dollar_tag(src: &str, i: usize) -> Option<&str> {
+    let rest = &src[i + 1..];
+    let end = rest.find('$')?;
+    let tag = &rest[..end];
+    if tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !tag.starts_with(|c: char| c.is_ascii_digit())
+    {
+        Some(&src[i..i + end + 2])
+    } else {
+        None
+    }
+}
+
+/// Split a token stream into statements at top-level semicolons.
+pub fn statements<'t, 'a>(tokens: &'t [Token<'a>]) -> Vec<&'t [Token<'a>]> {
+    tokens
+        .split(|t| t.is_punct(';'))
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Split `tokens` at commas that are not nested inside parentheses.
+pub fn split_top_level<'t, 'a>(tokens: &'t [Token<'a>]) -> Vec<&'t [Token<'a>]> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0;
+    for (i, t) in tokens.iter().enumerate() {
+        if t.is_punct('(') || t.is_punct('[') {
+            depth += 1;
+        } else if t.is_punct(')') || t.is_punct(']') {
+            depth -= 1;
+        } else if depth == 0 && t.is_punct(',') {
+            parts.push(&tokens[start..i]);
+            start = i + 1;
+        }
+    }
+    if start < tokens.len() {
+        parts.push(&tokens[start..]);
+    }
+    parts
+}
+
+/// Index of the `)` matching the `(` at `open`.
+pub fn matching_paren(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, t) in tokens.iter().enumerate().skip(open) {
+        if t.is_punct('(') {
+            depth += 1;
+        } else if t.is_punct(')') {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// Original source text spanned by `tokens`.
+pub fn source_text<'a>(src: &'a str, tokens: &[Token]) -> &'a str {
+    match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => &src[first.start..last.end],
+        _ => "",
+    }
+}
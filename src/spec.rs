@@ -0,0 +1,440 @@
+//! Declarative entity specs.
+//!
+//! A spec describes how one legacy `dispatch_*` table maps onto its UUID-keyed
+//! target table. It mirrors the `MigrationEntity` interface used by
+//! `src/full-migration/full-migration-orchestrator.ts`, plus the column map and
+//! foreign-key lookups that the hand-written migration scripts encode inline.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::mapping::registry;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EntitySpec {
+    pub name: String,
+    pub source_table: String,
+    pub target_table: String,
+    /// `migration_mappings.entity_type`; defaults to the registered entity
+    /// type of `source_table` (see `mapping::registry`), else `name`.
+    #[serde(default)]
+    pub entity_type: Option<String>,
+    #[serde(default = "default_source_key")]
+    pub source_key: String,
+    /// Target column that stores the legacy primary key, e.g. `legacy_office_id`.
+    pub legacy_id_column: String,
+    #[serde(default = "default_target_key")]
+    pub target_key: String,
+    pub dependency_order: u32,
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    #[serde(default)]
+    pub estimated_records: Option<u64>,
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Extra SQL predicate applied to the source table, e.g. `valid = true`.
+    #[serde(default)]
+    pub filter: Option<String>,
+    #[serde(default)]
+    pub columns: Vec<ColumnMap>,
+    #[serde(default)]
+    pub lookups: Vec<ForeignKeyLookup>,
+    /// How rows that already exist in the target are updated; without it the
+    /// source overwrites every mapped column.
+    #[serde(default)]
+    pub conflicts: Option<ConflictPolicies>,
+    /// What happens to target rows whose legacy row was deleted; without it
+    /// they are only reported.
+    #[serde(default)]
+    pub deletes: Option<DeletePolicy>,
+    /// File the spec was loaded from.
+    #[serde(skip)]
+    pub origin: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnMap {
+    pub source: String,
+    pub target: String,
+    #[serde(default)]
+    pub transform: Option<Transform>,
+    /// Value used when the source column is NULL.
+    #[serde(default)]
+    pub default: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+    Trim,
+    Lowercase,
+    Uppercase,
+    NullIfEmpty,
+    Json,
+}
+
+/// A legacy integer FK that is resolved to a UUID through `migration_mappings`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ForeignKeyLookup {
+    pub source: String,
+    pub target: String,
+    /// `migration_mappings.entity_type` of the referenced entity.
+    pub entity: String,
+    /// Skip the row instead of inserting NULL when the mapping is missing.
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConflictPolicies {
+    /// Policy of every column without an entry in `columns`.
+    #[serde(default)]
+    pub default: ConflictPolicy,
+    /// Target column compared by `newest-updated-at`; it must be mapped.
+    #[serde(default = "default_updated_at")]
+    pub updated_at: String,
+    /// Policies by target column.
+    #[serde(default)]
+    pub columns: BTreeMap<String, ConflictPolicy>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    #[default]
+    SourceWins,
+    TargetWins,
+    /// The side whose `updated_at` is newer wins.
+    NewestUpdatedAt,
+    /// JSON objects are merged key by key and arrays are united, the source
+    /// winning where both sides set a scalar.
+    Merge,
+    /// The target keeps its value and the row is queued for `resolve`.
+    Manual,
+}
+
+impl ConflictPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConflictPolicy::SourceWins => "source-wins",
+            ConflictPolicy::TargetWins => "target-wins",
+            ConflictPolicy::NewestUpdatedAt => "newest-updated-at",
+            ConflictPolicy::Merge => "merge",
+            ConflictPolicy::Manual => "manual",
+        }
+    }
+}
+
+impl ConflictPolicies {
+    pub fn policy(&self, target_column: &str) -> ConflictPolicy {
+        self.columns
+            .get(target_column)
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    fn uses(&self, policy: ConflictPolicy) -> bool {
+        self.default == policy || self.columns.values().any(|p| *p == policy)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeletePolicy {
+    #[serde(default)]
+    pub policy: DeleteMode,
+    /// `soft`: timestamp column set to the time of the delete.
+    #[serde(default)]
+    pub deleted_at: Option<String>,
+    /// `soft`: boolean column set to false.
+    #[serde(default)]
+    pub is_active: Option<String>,
+    /// `archive`: shadow table the rows are moved to; defaults to
+    /// `<target_table>_archive`.
+    #[serde(default)]
+    pub archive_table: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeleteMode {
+    /// Delete the target row.
+    Hard,
+    /// Keep the row and mark it with `deleted_at` and/or `is_active`.
+    Soft,
+    /// Move the row to the archive table.
+    Archive,
+    /// Change nothing; list the rows.
+    #[default]
+    Report,
+}
+
+impl DeleteMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeleteMode::Hard => "hard",
+            DeleteMode::Soft => "soft",
+            DeleteMode::Archive => "archive",
+            DeleteMode::Report => "report",
+        }
+    }
+
+    /// Whether the policy takes the row out of the target table.
+    pub fn removes(self) -> bool {
+        matches!(self, DeleteMode::Hard | DeleteMode::Archive)
+    }
+}
+
+impl DeletePolicy {
+    pub fn archive_table(&self, spec: &EntitySpec) -> String {
+        self.archive_table
+            .clone()
+            .unwrap_or_else(|| format!("{}_archive", spec.target_table))
+    }
+}
+
+fn default_updated_at() -> String {
+    "updated_at".to_string()
+}
+
+fn default_source_key() -> String {
+    "id".to_string()
+}
+
+fn default_target_key() -> String {
+    "id".to_string()
+}
+
+fn default_batch_size() -> usize {
+    500
+}
+
+impl EntitySpec {
+    pub fn entity_type(&self) -> &str {
+        self.entity_type
+            .as_deref()
+            .or_else(|| registry::entity_type(&self.source_table))
+            .unwrap_or(&self.name)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("`name` must not be empty".into());
+        }
+        for (field, value) in [
+            ("source_table", &self.source_table),
+            ("target_table", &self.target_table),
+            ("source_key", &self.source_key),
+            ("target_key", &self.target_key),
+            ("legacy_id_column", &self.legacy_id_column),
+        ] {
+            if !is_identifier(value) {
+                return Err(format!(
+                    "`{}` is not a valid SQL identifier: '{}'",
+                    field, value
+                ));
+            }
+        }
+        if let (Some(declared), Some(registered)) =
+            (&self.entity_type, registry::entity_type(&self.source_table))
+            && declared != registered
+        {
+            return Err(format!(
+                "`entity_type` '{}' differs from '{}', the registered entity type of {}; \
+                 its UUIDs would not match earlier runs",
+                declared, registered, self.source_table
+            ));
+        }
+        if self.batch_size == 0 {
+            return Err("`batch_size` must be greater than zero".into());
+        }
+        if self.columns.is_empty() && self.lookups.is_empty() {
+            return Err("at least one entry in `columns` or `lookups` is required".into());
+        }
+
+        let mut targets = HashSet::new();
+        targets.insert(self.legacy_id_column.as_str());
+        let sources = self
+            .columns
+            .iter()
+            .map(|c| (&c.source, &c.target))
+            .chain(self.lookups.iter().map(|l| (&l.source, &l.target)));
+        for (source, target) in sources {
+            if !is_identifier(source) || !is_identifier(target) {
+                return Err(format!(
+                    "invalid column mapping '{}' -> '{}'",
+                    source, target
+                ));
+            }
+            if !targets.insert(target.as_str()) {
+                return Err(format!(
+                    "target column '{}' is mapped more than once",
+                    target
+                ));
+            }
+        }
+        if let Some(conflicts) = &self.conflicts {
+            for column in conflicts.columns.keys() {
+                if column == &self.legacy_id_column || !targets.contains(column.as_str()) {
+                    return Err(format!(
+                        "`conflicts.columns` names '{}', which is not a mapped target column",
+                        column
+                    ));
+                }
+            }
+            if conflicts.uses(ConflictPolicy::NewestUpdatedAt)
+                && !self
+                    .columns
+                    .iter()
+                    .any(|c| c.target == conflicts.updated_at)
+            {
+                return Err(format!(
+                    "`newest-updated-at` compares '{}', which is not a mapped target column; \
+                     set `conflicts.updated_at`",
+                    conflicts.updated_at
+                ));
+            }
+        }
+        if let Some(deletes) = &self.deletes {
+            for (field, value) in [
+                ("deletes.deleted_at", &deletes.deleted_at),
+                ("deletes.is_active", &deletes.is_active),
+                ("deletes.archive_table", &deletes.archive_table),
+            ] {
+                if let Some(value) = value
+                    && !is_identifier(value)
+                {
+                    return Err(format!(
+                        "`{}` is not a valid SQL identifier: '{}'",
+                        field, value
+                    ));
+                }
+            }
+            if deletes.policy == DeleteMode::Soft
+                && deletes.deleted_at.is_none()
+                && deletes.is_active.is_none()
+            {
+                return Err(
+                    "a `soft` delete policy needs `deletes.deleted_at`, `deletes.is_active` or both"
+                        .into(),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Accepts `name` and `schema.name`.
+pub fn is_identifier(s: &str) -> bool {
+    !s.is_empty()
+        && s.split('.').all(|part| {
+            let mut chars = part.chars();
+            matches!(chars.next(), Some(c) if c == '_' || c.is_ascii_alphabetic())
+                && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+        })
+}
+
+/// `value` as a quoted SQL string literal.
+pub fn literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[derive(Debug)]
+pub struct SpecError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for SpecError {}
+
+/// Load a single spec file (`.toml`, `.yaml` or `.yml`).
+pub fn load_file(path: &Path) -> Result<EntitySpec, SpecError> {
+    let err = |message: String| SpecError {
+        path: path.to_path_buf(),
+        message,
+    };
+    let text = fs::read_to_string(path).map_err(|e| err(e.to_string()))?;
+    let mut spec: EntitySpec = match extension(path) {
+        Some("toml") => toml::from_str(&text).map_err(|e| err(e.to_string()))?,
+        Some("yaml" | "yml") => serde_yaml::from_str(&text).map_err(|e| err(e.to_string()))?,
+        _ => return Err(err("expected a .toml, .yaml or .yml file".into())),
+    };
+    spec.validate().map_err(err)?;
+    spec.origin = path.to_path_buf();
+    Ok(spec)
+}
+
+/// The spec named `name`, or an error listing the known names.
+pub fn find<'a>(specs: &'a [EntitySpec], name: &str) -> Result<&'a EntitySpec, String> {
+    specs.iter().find(|s| s.name == name).ok_or_else(|| {
+        let names: Vec<&str> = specs.iter().map(|s| s.name.as_str()).collect();
+        format!(
+            "no entity spec named '{}' (known: {})",
+            name,
+            if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.join(", ")
+            }
+        )
+    })
+}
+
+/// Load every spec under `paths`, expanding directories one level deep, and
+/// return them sorted by dependency order.
+pub fn load_all(paths: &[PathBuf]) -> Result<Vec<EntitySpec>, SpecError> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let entries = fs::read_dir(path).map_err(|e| SpecError {
+                path: path.clone(),
+                message: e.to_string(),
+            })?;
+            let mut found: Vec<PathBuf> = entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| matches!(extension(p), Some("toml" | "yaml" | "yml")))
+                .collect();
+            found.sort();
+            files.extend(found);
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    let mut specs = Vec::with_capacity(files.len());
+    let mut names = HashSet::new();
+    for file in &files {
+        let spec = load_file(file)?;
+        if !names.insert(spec.name.clone()) {
+            return Err(SpecError {
+                path: file.clone(),
+                message: format!("entity '{}' is defined more than once", spec.name),
+            });
+        }
+        specs.push(spec);
+    }
+    specs.sort_by(|a, b| {
+        a.dependency_order
+            .cmp(&b.dependency_order)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(specs)
+}
+
+fn extension(path: &Path) -> Option<&str> {
+    path.extension().and_then(|e| e.to_str())
+}
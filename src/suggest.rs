@@ -0,0 +1,412 @@
+//! Source → target column mapping suggestions.
+//!
+//! Pairs each legacy table with its target table and proposes a source for
+//! every target column using the conventions the hand-written migrations
+//! follow: the legacy integer key lands in `legacy_<entity>_id`, integer FKs
+//! become UUID FKs resolved through `migration_mappings`, and
+//! `dispatch_<entity>` becomes the plural `<entities>`.
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::Serialize;
+
+use crate::schema::{Column, Schema, Table};
+
+/// Legacy column names that were renamed in the target schema.
+const SYNONYMS: &[(&str, &str)] = &[
+    ("birthdate", "date_of_birth"),
+    ("sex", "gender"),
+    ("date_joined", "created_at"),
+    ("created", "created_at"),
+    ("updated", "updated_at"),
+    ("modified", "updated_at"),
+    ("last_login", "last_login_at"),
+    ("password", "password_hash"),
+    ("suffix", "patient_suffix"),
+];
+
+#[derive(Debug, Serialize)]
+pub struct Suggestions {
+    pub pairs: Vec<TablePair>,
+    /// Source tables with no plausible target table.
+    pub unpaired: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TablePair {
+    pub source: String,
+    pub target: String,
+    /// `migration_mappings.entity_type` for rows of this table.
+    pub entity: String,
+    /// Why the tables were paired.
+    pub paired_by: String,
+    pub columns: Vec<ColumnSuggestion>,
+    pub unmapped_source: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ColumnSuggestion {
+    pub target: String,
+    pub target_type: String,
+    /// Target column default, used when nothing is mapped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_default: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_type: Option<String>,
+    pub kind: MatchKind,
+    /// `migration_mappings.entity_type` the value is looked up under.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lookup_entity: Option<String>,
+    /// NOT NULL in the target with no source and no default.
+    pub required: bool,
+    /// Description of a lossy conversion, e.g. `text → character varying(10)`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub narrowing: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchKind {
+    /// Target primary key filled by the database.
+    Generated,
+    /// Legacy primary key stored for traceability.
+    LegacyId,
+    /// Integer FK translated to a UUID through `migration_mappings`.
+    Lookup,
+    Exact,
+    Renamed,
+    Unmapped,
+}
+
+impl TablePair {
+    pub fn required_unmapped(&self) -> impl Iterator<Item = &ColumnSuggestion> {
+        self.columns.iter().filter(|c| c.required)
+    }
+
+    pub fn narrowing(&self) -> impl Iterator<Item = &ColumnSuggestion> {
+        self.columns.iter().filter(|c| c.narrowing.is_some())
+    }
+}
+
+/// Entity name for a legacy table: `dispatch_patient` → `patient`.
+pub fn entity_stem(table: &str) -> &str {
+    let name = table.rsplit('.').next().unwrap_or(table);
+    name.strip_prefix("dispatch_").unwrap_or(name)
+}
+
+fn plural(word: &str) -> String {
+    if let Some(base) = word.strip_suffix('y')
+        && !base.ends_with(['a', 'e', 'i', 'o', 'u'])
+    {
+        format!("{}ies", base)
+    } else if word.ends_with(['s', 'x']) || word.ends_with("ch") || word.ends_with("sh") {
+        format!("{}es", word)
+    } else {
+        format!("{}s", word)
+    }
+}
+
+/// Pair every source table (or only `only`, when given) with a target table
+/// and suggest column mappings. `explicit` pairs take precedence over the
+/// naming conventions.
+pub fn suggest(
+    source: &Schema,
+    target: &Schema,
+    explicit: &BTreeMap<String, String>,
+    only: &[String],
+) -> Suggestions {
+    let mut suggestions = Suggestions {
+        pairs: Vec::new(),
+        unpaired: Vec::new(),
+    };
+    let pairing: BTreeMap<&str, (String, String)> = source
+        .tables
+        .keys()
+        .filter_map(|s| pair_table(s, target, explicit).map(|p| (s.as_str(), p)))
+        .collect();
+
+    for (name, table) in &source.tables {
+        if !only.is_empty() && !only.contains(name) {
+            continue;
+        }
+        match pairing.get(name.as_str()) {
+            Some((target_name, paired_by)) => {
+                let target_table = &target.tables[target_name];
+                suggestions.pairs.push(map_columns(
+                    table,
+                    target_table,
+                    target,
+                    &pairing,
+                    paired_by.clone(),
+                ));
+            }
+            None => suggestions.unpaired.push(name.clone()),
+        }
+    }
+    suggestions
+}
+
+fn pair_table(
+    source: &str,
+    target: &Schema,
+    explicit: &BTreeMap<String, String>,
+) -> Option<(String, String)> {
+    if let Some(t) = explicit.get(source) {
+        return target
+            .table(t)
+            .map(|_| (t.clone(), "explicit --pair".to_string()));
+    }
+    let stem = entity_stem(source);
+    for candidate in [plural(stem), stem.to_string(), source.to_string()] {
+        if target.table(&candidate).is_some() {
+            return Some((candidate, "name".to_string()));
+        }
+    }
+    let legacy = format!("legacy_{}_id", stem);
+    let mut by_legacy = target
+        .tables
+        .values()
+        .filter(|t| t.columns.iter().any(|c| c.name == legacy));
+    match (by_legacy.next(), by_legacy.next()) {
+        (Some(t), None) => Some((t.name.clone(), format!("`{}` column", legacy))),
+        _ => None,
+    }
+}
+
+fn map_columns(
+    source: &Table,
+    target: &Table,
+    target_schema: &Schema,
+    pairing: &BTreeMap<&str, (String, String)>,
+    paired_by: String,
+) -> TablePair {
+    let entity = entity_stem(&source.name).to_string();
+    let source_pk = match source.primary_key.as_slice() {
+        [pk] => source.columns.iter().find(|c| &c.name == pk),
+        _ => None,
+    };
+    let mut used: HashSet<&str> = HashSet::new();
+    let mut columns = Vec::with_capacity(target.columns.len());
+
+    for tc in &target.columns {
+        let is_target_pk = target.primary_key.len() == 1 && target.primary_key[0] == tc.name;
+        let mut suggestion = ColumnSuggestion {
+            target: tc.name.clone(),
+            target_type: tc.data_type.clone(),
+            target_default: tc.default.clone(),
+            source: None,
+            source_type: None,
+            kind: MatchKind::Unmapped,
+            lookup_entity: None,
+            required: false,
+            narrowing: None,
+        };
+        let unused = |name: &str| !used.contains(name);
+        let legacy_column = format!("legacy_{}_id", entity);
+        let found: Option<(&Column, MatchKind)> = if let Some(pk) = source_pk
+            .filter(|pk| unused(&pk.name) && (tc.name == legacy_column || tc.name == "legacy_id"))
+        {
+            Some((pk, MatchKind::LegacyId))
+        } else if is_target_pk && tc.data_type == "uuid" {
+            suggestion.kind = MatchKind::Generated;
+            None
+        } else if let Some((sc, ref_entity)) =
+            lookup_source(source, tc, target, pairing).filter(|(sc, _)| unused(&sc.name))
+        {
+            suggestion.lookup_entity = Some(ref_entity);
+            Some((sc, MatchKind::Lookup))
+        } else if let Some(sc) = source
+            .columns
+            .iter()
+            .find(|sc| sc.name == tc.name && unused(&sc.name))
+        {
+            Some((sc, MatchKind::Exact))
+        } else {
+            source
+                .columns
+                .iter()
+                .find(|sc| unused(&sc.name) && is_rename(&sc.name, &tc.name))
+                .map(|sc| (sc, MatchKind::Renamed))
+        };
+
+        if let Some((sc, kind)) = found {
+            suggestion.source = Some(sc.name.clone());
+            suggestion.source_type = Some(sc.data_type.clone());
+            suggestion.kind = kind;
+            if kind != MatchKind::Lookup {
+                suggestion.narrowing = narrowing(&sc.data_type, &tc.data_type, target_schema);
+            }
+            used.insert(&sc.name);
+        }
+        if suggestion.kind == MatchKind::Unmapped {
+            suggestion.required = !tc.nullable && tc.default.is_none();
+        }
+        columns.push(suggestion);
+    }
+
+    let unmapped_source = source
+        .columns
+        .iter()
+        .filter(|c| !used.contains(c.name.as_str()))
+        .map(|c| c.name.clone())
+        .collect();
+    TablePair {
+        source: source.name.clone(),
+        target: target.name.clone(),
+        entity,
+        paired_by,
+        columns,
+        unmapped_source,
+    }
+}
+
+/// Source integer FK column that feeds UUID column `tc` through
+/// `migration_mappings`, with the referenced entity name.
+fn lookup_source<'a>(
+    source: &'a Table,
+    tc: &Column,
+    target: &Table,
+    pairing: &BTreeMap<&str, (String, String)>,
+) -> Option<(&'a Column, String)> {
+    if tc.data_type != "uuid" || !tc.name.ends_with("_id") {
+        return None;
+    }
+    // The table the target column points at, if it is a declared FK.
+    let target_ref = target
+        .foreign_keys
+        .iter()
+        .find(|fk| fk.columns.len() == 1 && fk.columns[0] == tc.name)
+        .map(|fk| fk.references_table.as_str());
+
+    source.columns.iter().find_map(|sc| {
+        if !is_integer(&sc.data_type) {
+            return None;
+        }
+        let declared = source
+            .foreign_keys
+            .iter()
+            .find(|fk| fk.columns.len() == 1 && fk.columns[0] == sc.name)
+            .map(|fk| fk.references_table.as_str());
+        let ref_entity = match declared {
+            Some(table) => entity_stem(table).to_string(),
+            None => sc.name.strip_suffix("_id")?.to_string(),
+        };
+        let prefix = sc.name.strip_suffix("_id");
+        let by_name = tc.name == sc.name
+            || [Some(ref_entity.as_str()), prefix]
+                .into_iter()
+                .flatten()
+                .any(|stem| {
+                    tc.name == format!("{}_id", stem) || tc.name.ends_with(&format!("_{}_id", stem))
+                });
+        let by_fk = match (declared, target_ref) {
+            (Some(src_ref), Some(tgt_ref)) => pairing
+                .get(src_ref)
+                .is_some_and(|(paired, _)| paired == tgt_ref),
+            _ => false,
+        };
+        (by_name || by_fk).then_some((sc, ref_entity))
+    })
+}
+
+fn is_rename(source: &str, target: &str) -> bool {
+    let squash = |s: &str| s.replace('_', "");
+    SYNONYMS.contains(&(source, target))
+        || squash(source) == squash(target)
+        || target.strip_prefix("is_") == Some(source)
+        || source.strip_prefix("is_") == Some(target)
+}
+
+fn is_integer(data_type: &str) -> bool {
+    matches!(data_type, "integer" | "bigint" | "smallint")
+}
+
+/// Rough shape of a normalized type, enough to spot lossy conversions.
+#[derive(Debug, PartialEq)]
+enum Shape {
+    Int(u32),
+    Numeric(Option<(u32, u32)>),
+    Float(u32),
+    Text(Option<u32>),
+    Timestamp { tz: bool },
+    Date,
+    Enum,
+    Other,
+}
+
+fn shape(data_type: &str, schema: &Schema) -> Shape {
+    let (name, args) = match data_type.split_once('(') {
+        Some((name, rest)) => (
+            name.trim(),
+            rest.trim_end_matches(|c: char| c != ')')
+                .trim_end_matches(')')
+                .split(',')
+                .filter_map(|a| a.trim().parse::<u32>().ok())
+                .collect::<Vec<_>>(),
+        ),
+        None => (data_type, Vec::new()),
+    };
+    match name {
+        "smallint" => Shape::Int(16),
+        "integer" => Shape::Int(32),
+        "bigint" => Shape::Int(64),
+        "numeric" => Shape::Numeric(match args.as_slice() {
+            [p] => Some((*p, 0)),
+            [p, s] => Some((*p, *s)),
+            _ => None,
+        }),
+        "real" => Shape::Float(32),
+        "double precision" => Shape::Float(64),
+        "text" => Shape::Text(None),
+        "character varying" | "character" => Shape::Text(args.first().copied()),
+        "timestamp with time zone" => Shape::Timestamp { tz: true },
+        "timestamp without time zone" => Shape::Timestamp { tz: false },
+        "date" => Shape::Date,
+        _ if schema.enums.contains_key(name) => Shape::Enum,
+        _ => Shape::Other,
+    }
+}
+
+/// Decimal digits an integer of `bits` can hold.
+fn int_digits(bits: u32) -> u32 {
+    match bits {
+        16 => 5,
+        32 => 10,
+        _ => 19,
+    }
+}
+
+/// Digits left of the point in `numeric(p, s)`; negative when the scale
+/// exceeds the precision, as PG 15 allows.
+fn integer_digits(precision: u32, scale: u32) -> i64 {
+    i64::from(precision) - i64::from(scale)
+}
+
+/// Describe the conversion if `from` can hold values `to` cannot.
+fn narrowing(from: &str, to: &str, target: &Schema) -> Option<String> {
+    // Enum types live in the target schema; the source only needs its own
+    // names, which normalization already gives us.
+    let from_shape = shape(from, &Schema::default());
+    let to_shape = shape(to, target);
+    let lossy = match (&from_shape, &to_shape) {
+        (Shape::Int(a), Shape::Int(b)) => b < a,
+        (Shape::Int(a), Shape::Numeric(Some((p, s)))) => {
+            integer_digits(*p, *s) < i64::from(int_digits(*a))
+        }
+        (Shape::Numeric(_) | Shape::Float(_), Shape::Int(_)) => true,
+        (Shape::Numeric(None) | Shape::Float(_), Shape::Numeric(Some(_))) => true,
+        (Shape::Numeric(Some((p1, s1))), Shape::Numeric(Some((p2, s2)))) => {
+            integer_digits(*p2, *s2) < integer_digits(*p1, *s1) || s2 < s1
+        }
+        (Shape::Float(a), Shape::Float(b)) => b < a,
+        (Shape::Text(None), Shape::Text(Some(_))) => true,
+        (Shape::Text(Some(a)), Shape::Text(Some(b))) => b < a,
+        (Shape::Text(_), Shape::Enum) => true,
+        (Shape::Timestamp { tz: true }, Shape::Timestamp { tz: false }) => true,
+        (Shape::Timestamp { .. }, Shape::Date) => true,
+        _ => false,
+    };
+    lossy.then(|| format!("{} → {}", from, to))
+}
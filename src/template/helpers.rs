@@ -0,0 +1,451 @@
+//! Helpers callable from templates.
+//!
+//! The domain helpers expand to the idioms that are otherwise hand-written in
+//! `src/lib/uuid-mapper.ts` and `src/lib/batch-processor.ts`. They read the
+//! entity being rendered from the template's root context, and the TypeScript
+//! they emit assumes the naming used by the built-in migrate template: source
+//! rows are `row`, resolved mappings live in `lookups`, and the legacy IDs to
+//! resolve are collected in `wanted`.
+
+use serde_json::Value;
+
+use crate::mapping;
+
+pub fn call(name: &str, args: &[Value], root: &Value) -> Result<Value, String> {
+    let text = match name {
+        "pascal" => words(str_arg(name, args, 0)?).map(capitalize).collect(),
+        "title" => words(str_arg(name, args, 0)?)
+            .map(capitalize)
+            .collect::<Vec<_>>()
+            .join(" "),
+        "kebab" => words(str_arg(name, args, 0)?)
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>()
+            .join("-"),
+        "join" => {
+            arity(name, args, 2)?;
+            list_arg(name, args, 0)?
+                .iter()
+                .map(super::display)
+                .collect::<Vec<_>>()
+                .join(str_arg(name, args, 1)?)
+        }
+        "js_array" => {
+            let items: Vec<String> = list_arg(name, args, 0)?
+                .iter()
+                .map(|v| js_string(&super::display(v)))
+                .collect();
+            format!("[{}]", items.join(", "))
+        }
+        "json" => {
+            arity(name, args, 1)?;
+            args[0].to_string()
+        }
+        "legacy_fk" => legacy_fk(str_arg(name, args, 0)?, str_arg(name, args, 1)?),
+        "uuid_lookup" => uuid_lookup(str_arg(name, args, 0)?),
+        "mapped_id" => mapped_id(str_arg(name, args, 0)?, str_arg(name, args, 1)?),
+        "batch_loop" => batch_loop(args.first().ok_or("batch_loop(size) needs a size")?, root)?,
+        "upsert_on" => upsert_on(
+            args.first().ok_or("upsert_on(columns) needs columns")?,
+            root,
+        )?,
+        "ts_value" => ts_value(args.first().ok_or("ts_value(column) needs a column")?)?,
+        _ => return Err(format!("unknown helper '{}'", name)),
+    };
+    Ok(Value::String(text))
+}
+
+fn arity(name: &str, args: &[Value], n: usize) -> Result<(), String> {
+    if args.len() == n {
+        Ok(())
+    } else {
+        Err(format!(
+            "{}() takes {} argument(s), got {}",
+            name,
+            n,
+            args.len()
+        ))
+    }
+}
+
+fn str_arg<'a>(name: &str, args: &'a [Value], i: usize) -> Result<&'a str, String> {
+    match args.get(i) {
+        Some(Value::String(s)) => Ok(s),
+        Some(_) => Err(format!("{}(): argument {} must be a string", name, i + 1)),
+        None => Err(format!("{}(): missing argument {}", name, i + 1)),
+    }
+}
+
+fn list_arg<'a>(name: &str, args: &'a [Value], i: usize) -> Result<&'a [Value], String> {
+    match args.get(i) {
+        Some(Value::Array(items)) => Ok(items),
+        Some(_) => Err(format!("{}(): argument {} must be a list", name, i + 1)),
+        None => Err(format!("{}(): missing argument {}", name, i + 1)),
+    }
+}
+
+fn root_str<'a>(root: &'a Value, key: &str) -> Result<&'a str, String> {
+    root.get(key)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("context has no '{}'", key))
+}
+
+fn words(name: &str) -> impl Iterator<Item = &str> {
+    name.split(['_', '-', ' ']).filter(|w| !w.is_empty())
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn js_string(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// `legacy_fk(entity, column)`: the UUID for `row.<column>` resolved through
+/// the `<entity>` mappings, or `null` when the mapping is missing.
+fn legacy_fk(entity: &str, column: &str) -> String {
+    format!(
+        "lookups[{}].get(String(row.{})) ?? null",
+        js_string(entity),
+        column
+    )
+}
+
+/// `mapped_id(entity, column)`: the UUID `row.<column>` is already mapped to,
+/// else the UUIDv5 `mapping::deterministic_id` gives it, so scaffolded scripts
+/// and `run` assign the same keys.
+fn mapped_id(entity: &str, column: &str) -> String {
+    format!(
+        "lookups[{}].get(String(row.{1})) ?? uuidv5(String(row.{1}), '{2}')",
+        js_string(entity),
+        column,
+        mapping::entity_namespace(entity)
+    )
+}
+
+/// `uuid_lookup(entity)`: one bulk `migration_mappings` query for every legacy
+/// ID collected in `wanted[entity]`, in place of a round trip per row.
+fn uuid_lookup(entity: &str) -> String {
+    let key = js_string(entity);
+    format!(
+        "lookups[{key}] = new Map();
+if ((wanted[{key}]?.size ?? 0) > 0) {{
+  const result = await this.targetPool.query(
+    'SELECT legacy_id::text AS legacy_id, new_id FROM migration_mappings WHERE entity_type = $1 AND legacy_id::text = ANY($2)',
+    [{key}, Array.from(wanted[{key}])]
+  );
+  for (const mapping of result.rows) {{
+    lookups[{key}].set(mapping.legacy_id, mapping.new_id);
+  }}
+}}"
+    )
+}
+
+/// `batch_loop(size)`: keyset pagination over the source key, handing each
+/// batch to `processBatch` and logging progress.
+fn batch_loop(size: &Value, root: &Value) -> Result<String, String> {
+    let size = match size {
+        Value::Number(n) => n.to_string(),
+        Value::String(s) if !s.is_empty() => s.clone(),
+        _ => return Err("batch_loop(size): size must be a number or expression".into()),
+    };
+    let key = root_str(root, "source_key")?;
+    Ok(format!(
+        "let afterId: number | null = null;
+for (;;) {{
+  const rows = await this.fetchBatch(afterId, {size});
+  if (rows.length === 0) break;
+  await this.processBatch(rows);
+  afterId = rows[rows.length - 1].{key};
+  console.log(`📈 Processed ${{this.stats.totalProcessed}} rows (last {key}: ${{afterId}})`);
+}}"
+    ))
+}
+
+/// `upsert_on(columns)`: an `ON CONFLICT … DO UPDATE` clause that refreshes
+/// every target column except the conflict columns.
+fn upsert_on(columns: &Value, root: &Value) -> Result<String, String> {
+    let conflict: Vec<String> = match columns {
+        Value::String(s) => vec![s.clone()],
+        Value::Array(items) => items.iter().map(super::display).collect(),
+        _ => return Err("upsert_on(columns): expected a column or list of columns".into()),
+    };
+    if conflict.is_empty() {
+        return Err("upsert_on(columns): at least one conflict column is required".into());
+    }
+    let targets = root
+        .get("target_columns")
+        .and_then(Value::as_array)
+        .ok_or("context has no 'target_columns'")?;
+    let mut updates: Vec<String> = targets
+        .iter()
+        .map(super::display)
+        .filter(|c| !conflict.contains(c))
+        .map(|c| format!("{0} = EXCLUDED.{0}", c))
+        .collect();
+    if updates.is_empty() {
+        // Still touch the row so `RETURNING` reports it.
+        updates.push(format!("{0} = EXCLUDED.{0}", conflict[0]));
+    }
+    Ok(format!(
+        "ON CONFLICT ({}) DO UPDATE SET {}",
+        conflict.join(", "),
+        updates.join(", ")
+    ))
+}
+
+/// `ts_value(column)`: the TypeScript expression for a mapped column, applying
+/// its transform and default.
+fn ts_value(column: &Value) -> Result<String, String> {
+    let source = column
+        .get("source")
+        .and_then(Value::as_str)
+        .ok_or("ts_value(column): column has no 'source'")?;
+    let field = format!("row.{}", source);
+    let default = match column.get("default") {
+        Some(Value::Null) | None => None,
+        Some(value) => Some(value),
+    };
+    let string_op = |op: &str| format!("typeof {0} === 'string' ? {0}.{1}() : {0}", field, op);
+    let expr = match column.get("transform").and_then(Value::as_str) {
+        None => field.clone(),
+        Some("trim") => string_op("trim"),
+        Some("lowercase") => string_op("toLowerCase"),
+        Some("uppercase") => string_op("toUpperCase"),
+        Some("null_if_empty") => format!("{0} === '' ? null : {0}", field),
+        // JSON.stringify never yields null, so the default goes inside.
+        Some("json") => {
+            return Ok(format!(
+                "JSON.stringify({} ?? {})",
+                field,
+                default.map_or_else(|| "null".to_string(), Value::to_string)
+            ));
+        }
+        Some(other) => return Err(format!("ts_value(column): unknown transform '{}'", other)),
+    };
+    Ok(match default {
+        None => expr,
+        Some(value) => format!("({}) ?? {}", expr, value),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn helper(name: &str, args: Value, root: Value) -> Result<String, String> {
+        let args = args.as_array().cloned().unwrap_or_default();
+        call(name, &args, &root).map(|v| v.as_str().unwrap_or_default().to_string())
+    }
+
+    #[test]
+    fn legacy_fk() {
+        assert_eq!(
+            helper("legacy_fk", json!(["office", "office_id"]), json!({})).unwrap(),
+            "lookups['office'].get(String(row.office_id)) ?? null"
+        );
+        assert_eq!(
+            helper("legacy_fk", json!(["o'brien", "id"]), json!({})).unwrap(),
+            "lookups['o\\'brien'].get(String(row.id)) ?? null"
+        );
+        assert_eq!(
+            helper("legacy_fk", json!(["office"]), json!({})).unwrap_err(),
+            "legacy_fk(): missing argument 2"
+        );
+    }
+
+    #[test]
+    fn mapped_id() {
+        assert_eq!(
+            helper("mapped_id", json!(["office", "id"]), json!({})).unwrap(),
+            "lookups['office'].get(String(row.id)) ?? \
+             uuidv5(String(row.id), 'f0afffd3-d1b6-5847-a68b-26e242209bc7')"
+        );
+    }
+
+    #[test]
+    fn uuid_lookup() {
+        assert_eq!(
+            helper("uuid_lookup", json!(["patient"]), json!({})).unwrap(),
+            "lookups['patient'] = new Map();
+if ((wanted['patient']?.size ?? 0) > 0) {
+  const result = await this.targetPool.query(
+    'SELECT legacy_id::text AS legacy_id, new_id FROM migration_mappings WHERE entity_type = $1 AND legacy_id::text = ANY($2)',
+    ['patient', Array.from(wanted['patient'])]
+  );
+  for (const mapping of result.rows) {
+    lookups['patient'].set(mapping.legacy_id, mapping.new_id);
+  }
+}"
+        );
+        assert_eq!(
+            helper("uuid_lookup", json!([1]), json!({})).unwrap_err(),
+            "uuid_lookup(): argument 1 must be a string"
+        );
+    }
+
+    #[test]
+    fn batch_loop() {
+        let root = json!({"source_key": "id"});
+        let expected = |size: &str| {
+            format!(
+                "let afterId: number | null = null;
+for (;;) {{
+  const rows = await this.fetchBatch(afterId, {size});
+  if (rows.length === 0) break;
+  await this.processBatch(rows);
+  afterId = rows[rows.length - 1].id;
+  console.log(`📈 Processed ${{this.stats.totalProcessed}} rows (last id: ${{afterId}})`);
+}}"
+            )
+        };
+        assert_eq!(
+            helper("batch_loop", json!(["BATCH_SIZE"]), root.clone()),
+            Ok(expected("BATCH_SIZE"))
+        );
+        assert_eq!(
+            helper("batch_loop", json!([500]), root.clone()),
+            Ok(expected("500"))
+        );
+        let errors = [
+            (
+                json!([true]),
+                root.clone(),
+                "batch_loop(size): size must be a number or expression",
+            ),
+            (
+                json!([""]),
+                root,
+                "batch_loop(size): size must be a number or expression",
+            ),
+            (json!([]), json!({}), "batch_loop(size) needs a size"),
+            (json!([10]), json!({}), "context has no 'source_key'"),
+        ];
+        for (args, root, error) in errors {
+            assert_eq!(helper("batch_loop", args, root), Err(error.to_string()));
+        }
+    }
+
+    #[test]
+    fn upsert_on() {
+        let root = json!({"target_columns": ["legacy_id", "name", "office_id"]});
+        let cases = [
+            (
+                json!(["legacy_id"]),
+                Ok(
+                    "ON CONFLICT (legacy_id) DO UPDATE SET name = EXCLUDED.name, office_id = EXCLUDED.office_id",
+                ),
+            ),
+            (
+                json!([["legacy_id", "office_id"]]),
+                Ok("ON CONFLICT (legacy_id, office_id) DO UPDATE SET name = EXCLUDED.name"),
+            ),
+            (
+                json!([["legacy_id", "name", "office_id"]]),
+                Ok(
+                    "ON CONFLICT (legacy_id, name, office_id) DO UPDATE SET legacy_id = EXCLUDED.legacy_id",
+                ),
+            ),
+            (
+                json!([[]]),
+                Err("upsert_on(columns): at least one conflict column is required"),
+            ),
+            (
+                json!([1]),
+                Err("upsert_on(columns): expected a column or list of columns"),
+            ),
+        ];
+        for (args, expected) in cases {
+            assert_eq!(
+                helper("upsert_on", args.clone(), root.clone()),
+                expected.map(String::from).map_err(String::from),
+                "{}",
+                args
+            );
+        }
+        assert_eq!(
+            helper("upsert_on", json!(["legacy_id"]), json!({})),
+            Err("context has no 'target_columns'".to_string())
+        );
+    }
+
+    #[test]
+    fn ts_value() {
+        let cases = [
+            (json!({"source": "name"}), Ok("row.name")),
+            (
+                json!({"source": "name", "transform": "trim"}),
+                Ok("typeof row.name === 'string' ? row.name.trim() : row.name"),
+            ),
+            (
+                json!({"source": "email", "transform": "lowercase"}),
+                Ok("typeof row.email === 'string' ? row.email.toLowerCase() : row.email"),
+            ),
+            (
+                json!({"source": "code", "transform": "uppercase", "default": "X"}),
+                Ok("(typeof row.code === 'string' ? row.code.toUpperCase() : row.code) ?? \"X\""),
+            ),
+            (
+                json!({"source": "apt", "transform": "null_if_empty"}),
+                Ok("row.apt === '' ? null : row.apt"),
+            ),
+            (
+                json!({"source": "rate", "default": 0}),
+                Ok("(row.rate) ?? 0"),
+            ),
+            (
+                json!({"source": "active", "default": false}),
+                Ok("(row.active) ?? false"),
+            ),
+            (json!({"source": "note", "default": null}), Ok("row.note")),
+            (
+                json!({"source": "settings", "transform": "json"}),
+                Ok("JSON.stringify(row.settings ?? null)"),
+            ),
+            (
+                json!({"source": "settings", "transform": "json", "default": {"a": [1]}}),
+                Ok("JSON.stringify(row.settings ?? {\"a\":[1]})"),
+            ),
+            (
+                json!({"source": "x", "transform": "reverse"}),
+                Err("ts_value(column): unknown transform 'reverse'"),
+            ),
+            (
+                json!({"target": "x"}),
+                Err("ts_value(column): column has no 'source'"),
+            ),
+        ];
+        for (column, expected) in cases {
+            assert_eq!(
+                helper("ts_value", json!([column.clone()]), json!({})),
+                expected.map(String::from).map_err(String::from),
+                "{}",
+                column
+            );
+        }
+    }
+
+    #[test]
+    fn names() {
+        let cases = [
+            ("pascal", "case_files", "CaseFiles"),
+            ("pascal", "doctor-notes v2", "DoctorNotesV2"),
+            ("title", "case_files", "Case Files"),
+            ("kebab", "Case_Files", "case-files"),
+        ];
+        for (name, arg, expected) in cases {
+            assert_eq!(helper(name, json!([arg]), json!({})).unwrap(), expected);
+        }
+        assert_eq!(
+            helper("shout", json!([]), json!({})).unwrap_err(),
+            "unknown helper 'shout'"
+        );
+    }
+}
@@ -0,0 +1,902 @@
+//! A small template engine for scaffolded migration artifacts.
+//!
+//! Syntax:
+//! - `{{ expr }}` inserts a value. Expressions are literals (`"text"`, `42`,
+//!   `[a, b]`), dotted context paths (`spec.name`), helper calls
+//!   (`upsert_on(legacy_id_column)`) and pipes (`name | pascal`), where
+//!   `x | f(a)` is shorthand for `f(x, a)`.
+//! - `{% for item in expr %}…{% endfor %}` iterates arrays and exposes
+//!   `loop.index`, `loop.first` and `loop.last`.
+//! - `{% if expr %}…{% else %}…{% endif %}`, with `not expr` for negation.
+//!   A path missing from the context is falsy there, so `not missing` holds.
+//!
+//! A tag alone on its line leaves no blank line behind, nor does a `{{ }}`
+//! alone on its line that renders to nothing. Multi-line values are indented
+//! to the column of their placeholder.
+//!
+//! Built-in templates can be overridden by files of the same name in a
+//! project `templates/` directory; see [`Loader`].
+
+mod helpers;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json::{Map, Value};
+
+#[derive(Debug)]
+pub struct TemplateError {
+    /// Template name, or the override file path when loaded from disk.
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// Resolves template names to sources, preferring overrides on disk.
+pub struct Loader {
+    builtins: BTreeMap<&'static str, &'static str>,
+    override_dir: Option<PathBuf>,
+}
+
+impl Loader {
+    pub fn new(builtins: &[(&'static str, &'static str)]) -> Self {
+        Loader {
+            builtins: builtins.iter().copied().collect(),
+            override_dir: None,
+        }
+    }
+
+    /// Look for overrides in `dir`; a missing directory is not an error.
+    pub fn with_override_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.override_dir = Some(dir.into());
+        self
+    }
+
+    pub fn load(&self, name: &str) -> Result<Template, TemplateError> {
+        if let Some(dir) = &self.override_dir {
+            let path = dir.join(name);
+            match fs::read_to_string(&path) {
+                Ok(source) => return Template::parse(&path.display().to_string(), &source),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(TemplateError {
+                        file: path.display().to_string(),
+                        line: 0,
+                        message: e.to_string(),
+                    });
+                }
+            }
+        }
+        match self.builtins.get(name) {
+            Some(source) => Template::parse(&format!("<builtin>/{}", name), source),
+            None => Err(TemplateError {
+                file: name.to_string(),
+                line: 0,
+                message: "no such template".into(),
+            }),
+        }
+    }
+
+    /// Whether `name` would be read from the override directory.
+    pub fn is_overridden(&self, name: &str) -> bool {
+        self.override_dir
+            .as_deref()
+            .is_some_and(|dir| Path::new(dir).join(name).is_file())
+    }
+}
+
+#[derive(Debug)]
+pub struct Template {
+    file: String,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(Value),
+    Path(Vec<String>),
+    List(Vec<Expr>),
+    Call(String, Vec<Expr>),
+    Not(Box<Expr>),
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Output {
+        expr: Expr,
+        line: usize,
+        /// Leading whitespace of the placeholder's line, used to indent
+        /// continuation lines of multi-line values.
+        indent: String,
+        /// Alone on its line: the newline is owned by the node and dropped
+        /// together with the indentation when the value is empty.
+        standalone: bool,
+    },
+    For {
+        var: String,
+        iter: Expr,
+        body: Vec<Node>,
+        line: usize,
+    },
+    If {
+        cond: Expr,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+        line: usize,
+    },
+}
+
+enum Token {
+    Text(String),
+    Output {
+        src: String,
+        line: usize,
+        indent: String,
+        standalone: bool,
+    },
+    Tag {
+        src: String,
+        line: usize,
+    },
+}
+
+impl Template {
+    pub fn parse(file: &str, source: &str) -> Result<Template, TemplateError> {
+        let tokens = tokenize(file, source)?;
+        let mut iter = tokens.into_iter().peekable();
+        let nodes = parse_nodes(file, &mut iter, &[])?.0;
+        if let Some(Token::Tag { src, line }) = iter.next() {
+            return Err(TemplateError {
+                file: file.to_string(),
+                line,
+                message: format!("unexpected '{{% {} %}}'", src),
+            });
+        }
+        Ok(Template {
+            file: file.to_string(),
+            nodes,
+        })
+    }
+
+    pub fn render(&self, context: &Value) -> Result<String, TemplateError> {
+        let mut scope = Scope {
+            root: context,
+            locals: Vec::new(),
+        };
+        let mut out = String::new();
+        render_nodes(&self.file, &self.nodes, &mut scope, &mut out)?;
+        Ok(out)
+    }
+}
+
+fn tokenize(file: &str, source: &str) -> Result<Vec<Token>, TemplateError> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut rest = source;
+    let mut line = 1;
+    // Whether `text` starts at the beginning of a line (no token precedes it
+    // on the same line).
+    let mut at_line_start = true;
+
+    loop {
+        let next = match (rest.find("{{"), rest.find("{%")) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        let Some(start) = next else {
+            text.push_str(rest);
+            break;
+        };
+        text.push_str(&rest[..start]);
+        line += rest[..start].matches('\n').count();
+        let is_tag = rest[start..].starts_with("{%");
+        let close = if is_tag { "%}" } else { "}}" };
+        let body = &rest[start + 2..];
+        let end = body.find(close).ok_or_else(|| TemplateError {
+            file: file.to_string(),
+            line,
+            message: format!("unterminated '{}'", &rest[start..start + 2]),
+        })?;
+        let src = body[..end].trim().to_string();
+        let after = &body[end + 2..];
+
+        let line_start = text.rfind('\n').map_or(0, |i| i + 1);
+        let at_start = line_start > 0 || at_line_start;
+        let prefix_blank = at_start && text[line_start..].chars().all(|c| c == ' ' || c == '\t');
+        let rest_of_line = after.split('\n').next().unwrap_or("");
+        // A placeholder only stands alone when it has a newline to own.
+        let standalone = prefix_blank
+            && rest_of_line.trim().is_empty()
+            && (after.contains('\n') || (is_tag && after.trim().is_empty()));
+        let leading: String = if at_start {
+            text[line_start..]
+                .chars()
+                .take_while(|c| *c == ' ' || *c == '\t')
+                .collect()
+        } else {
+            String::new()
+        };
+
+        let tag_line = line;
+        line += body[..end].matches('\n').count();
+        rest = after;
+        if standalone {
+            text.truncate(line_start);
+            match rest.find('\n') {
+                Some(i) => {
+                    rest = &rest[i + 1..];
+                    line += 1;
+                }
+                None => rest = "",
+            }
+        }
+        at_line_start = standalone;
+        if !text.is_empty() {
+            tokens.push(Token::Text(std::mem::take(&mut text)));
+        }
+        tokens.push(if is_tag {
+            Token::Tag {
+                src,
+                line: tag_line,
+            }
+        } else {
+            Token::Output {
+                src,
+                line: tag_line,
+                indent: leading,
+                standalone,
+            }
+        });
+    }
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+    Ok(tokens)
+}
+
+type Tokens = std::iter::Peekable<std::vec::IntoIter<Token>>;
+
+/// Parse nodes until one of `terminators` (e.g. `endfor`) is reached; the
+/// terminator keyword is returned and consumed.
+fn parse_nodes(
+    file: &str,
+    tokens: &mut Tokens,
+    terminators: &[&str],
+) -> Result<(Vec<Node>, Option<String>), TemplateError> {
+    let mut nodes = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Text(text) => nodes.push(Node::Text(text)),
+            Token::Output {
+                src,
+                line,
+                indent,
+                standalone,
+            } => nodes.push(Node::Output {
+                expr: parse_expr(file, line, &src)?,
+                line,
+                indent,
+                standalone,
+            }),
+            Token::Tag { src, line } => {
+                let keyword = src.split_whitespace().next().unwrap_or("");
+                if terminators.contains(&keyword) {
+                    return Ok((nodes, Some(keyword.to_string())));
+                }
+                let err = |message: String| TemplateError {
+                    file: file.to_string(),
+                    line,
+                    message,
+                };
+                match keyword {
+                    "for" => {
+                        let rest = src["for".len()..].trim();
+                        let (var, iter) = rest
+                            .split_once(" in ")
+                            .ok_or_else(|| err("expected '{% for <name> in <expr> %}'".into()))?;
+                        let var = var.trim();
+                        if !is_ident(var) {
+                            return Err(err(format!("invalid loop variable '{}'", var)));
+                        }
+                        let iter = parse_expr(file, line, iter)?;
+                        let (body, end) = parse_nodes(file, tokens, &["endfor"])?;
+                        if end.is_none() {
+                            return Err(err("'for' without matching 'endfor'".into()));
+                        }
+                        nodes.push(Node::For {
+                            var: var.to_string(),
+                            iter,
+                            body,
+                            line,
+                        });
+                    }
+                    "if" => {
+                        let cond = parse_expr(file, line, src["if".len()..].trim())?;
+                        let (then, end) = parse_nodes(file, tokens, &["else", "endif"])?;
+                        let otherwise = match end.as_deref() {
+                            Some("else") => {
+                                let (otherwise, end) = parse_nodes(file, tokens, &["endif"])?;
+                                if end.is_none() {
+                                    return Err(err("'if' without matching 'endif'".into()));
+                                }
+                                otherwise
+                            }
+                            Some(_) => Vec::new(),
+                            None => return Err(err("'if' without matching 'endif'".into())),
+                        };
+                        nodes.push(Node::If {
+                            cond,
+                            then,
+                            otherwise,
+                            line,
+                        });
+                    }
+                    _ => return Err(err(format!("unexpected '{{% {} %}}'", src))),
+                }
+            }
+        }
+    }
+    Ok((nodes, None))
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+fn parse_expr(file: &str, line: usize, src: &str) -> Result<Expr, TemplateError> {
+    let mut parser = ExprParser {
+        chars: src.chars().collect(),
+        pos: 0,
+    };
+    let expr = parser.pipeline().and_then(|expr| {
+        parser.skip_ws();
+        if parser.pos < parser.chars.len() {
+            Err(format!("unexpected '{}'", parser.chars[parser.pos]))
+        } else {
+            Ok(expr)
+        }
+    });
+    expr.map_err(|message| TemplateError {
+        file: file.to_string(),
+        line,
+        message: format!("in '{}': {}", src, message),
+    })
+}
+
+struct ExprParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl ExprParser {
+    fn skip_ws(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_ws();
+        if self.chars.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn pipeline(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.eat('|') {
+            self.skip_ws();
+            let name = self.ident()?;
+            let mut args = vec![expr];
+            if self.eat('(') {
+                args.extend(self.args(')')?);
+            }
+            expr = Expr::Call(name, args);
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        self.skip_ws();
+        let rest: String = self.chars[self.pos..].iter().take(4).collect();
+        if rest == "not " {
+            self.pos += 4;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        self.skip_ws();
+        match self.chars.get(self.pos).copied() {
+            None => Err("expected an expression".into()),
+            Some(q @ ('"' | '\'')) => {
+                self.pos += 1;
+                let mut s = String::new();
+                loop {
+                    match self.chars.get(self.pos).copied() {
+                        None => return Err("unterminated string literal".into()),
+                        Some('\\') => {
+                            let escaped = self.chars.get(self.pos + 1).copied();
+                            s.push(match escaped {
+                                Some('n') => '\n',
+                                Some('t') => '\t',
+                                Some(c) => c,
+                                None => return Err("unterminated string literal".into()),
+                            });
+                            self.pos += 2;
+                        }
+                        Some(c) if c == q => {
+                            self.pos += 1;
+                            break;
+                        }
+                        Some(c) => {
+                            s.push(c);
+                            self.pos += 1;
+                        }
+                    }
+                }
+                Ok(Expr::Literal(Value::String(s)))
+            }
+            Some('[') => {
+                self.pos += 1;
+                Ok(Expr::List(self.args(']')?))
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' => {
+                let start = self.pos;
+                self.pos += 1;
+                while self.chars.get(self.pos).is_some_and(|c| c.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                text.parse::<i64>()
+                    .map(|n| Expr::Literal(Value::from(n)))
+                    .map_err(|_| format!("invalid number '{}'", text))
+            }
+            Some(_) => {
+                let name = self.ident()?;
+                match name.as_str() {
+                    "true" => return Ok(Expr::Literal(Value::Bool(true))),
+                    "false" => return Ok(Expr::Literal(Value::Bool(false))),
+                    "null" => return Ok(Expr::Literal(Value::Null)),
+                    _ => {}
+                }
+                if self.eat('(') {
+                    return Ok(Expr::Call(name, self.args(')')?));
+                }
+                let mut path = vec![name];
+                while self.chars.get(self.pos) == Some(&'.') {
+                    self.pos += 1;
+                    path.push(self.ident()?);
+                }
+                Ok(Expr::Path(path))
+            }
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|c| *c == '_' || c.is_ascii_alphanumeric())
+        {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(match self.chars.get(self.pos) {
+                Some(c) => format!("unexpected '{}'", c),
+                None => "unexpected end of expression".into(),
+            });
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn args(&mut self, close: char) -> Result<Vec<Expr>, String> {
+        let mut args = Vec::new();
+        if self.eat(close) {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.pipeline()?);
+            if self.eat(close) {
+                return Ok(args);
+            }
+            if !self.eat(',') {
+                return Err(format!("expected ',' or '{}'", close));
+            }
+        }
+    }
+}
+
+struct Scope<'a> {
+    root: &'a Value,
+    locals: Vec<(String, Value)>,
+}
+
+impl Scope<'_> {
+    fn lookup(&self, path: &[String]) -> Option<Value> {
+        let (head, tail) = path.split_first()?;
+        let mut value = match self.locals.iter().rev().find(|(name, _)| name == head) {
+            Some((_, v)) => v.clone(),
+            None => self.root.get(head)?.clone(),
+        };
+        for key in tail {
+            value = value.get(key)?.clone();
+        }
+        Some(value)
+    }
+}
+
+fn render_nodes(
+    file: &str,
+    nodes: &[Node],
+    scope: &mut Scope,
+    out: &mut String,
+) -> Result<(), TemplateError> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Output {
+                expr,
+                line,
+                indent,
+                standalone,
+            } => {
+                let value = eval(expr, scope).map_err(|message| TemplateError {
+                    file: file.to_string(),
+                    line: *line,
+                    message,
+                })?;
+                let text = display(&value);
+                if *standalone {
+                    if text.is_empty() {
+                        continue;
+                    }
+                    out.push_str(indent);
+                }
+                let mut lines = text.split('\n');
+                if let Some(first) = lines.next() {
+                    out.push_str(first);
+                }
+                for next in lines {
+                    out.push('\n');
+                    if !next.is_empty() {
+                        out.push_str(indent);
+                    }
+                    out.push_str(next);
+                }
+                if *standalone {
+                    out.push('\n');
+                }
+            }
+            Node::For {
+                var,
+                iter,
+                body,
+                line,
+            } => {
+                let items = match eval(iter, scope) {
+                    Ok(Value::Array(items)) => items,
+                    Ok(Value::Null) => Vec::new(),
+                    Ok(other) => {
+                        return Err(TemplateError {
+                            file: file.to_string(),
+                            line: *line,
+                            message: format!("cannot iterate over {}", type_name(&other)),
+                        });
+                    }
+                    Err(message) => {
+                        return Err(TemplateError {
+                            file: file.to_string(),
+                            line: *line,
+                            message,
+                        });
+                    }
+                };
+                let len = items.len();
+                for (index, item) in items.into_iter().enumerate() {
+                    let mut meta = Map::new();
+                    meta.insert("index".into(), Value::from(index));
+                    meta.insert("first".into(), Value::Bool(index == 0));
+                    meta.insert("last".into(), Value::Bool(index + 1 == len));
+                    scope.locals.push(("loop".into(), Value::Object(meta)));
+                    scope.locals.push((var.clone(), item));
+                    let result = render_nodes(file, body, scope, out);
+                    scope.locals.truncate(scope.locals.len() - 2);
+                    result?;
+                }
+            }
+            Node::If {
+                cond,
+                then,
+                otherwise,
+                line,
+            } => {
+                let truthy = condition(cond, scope).map_err(|message| TemplateError {
+                    file: file.to_string(),
+                    line: *line,
+                    message,
+                })?;
+                render_nodes(file, if truthy { then } else { otherwise }, scope, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn eval(expr: &Expr, scope: &Scope) -> Result<Value, String> {
+    match expr {
+        Expr::Literal(v) => Ok(v.clone()),
+        Expr::Path(path) => scope
+            .lookup(path)
+            .ok_or_else(|| format!("unknown variable '{}'", path.join("."))),
+        Expr::List(items) => items
+            .iter()
+            .map(|e| eval(e, scope))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array),
+        Expr::Not(inner) => Ok(Value::Bool(!is_truthy(&eval(inner, scope)?))),
+        Expr::Call(name, args) => {
+            let args = args
+                .iter()
+                .map(|e| eval(e, scope))
+                .collect::<Result<Vec<_>, _>>()?;
+            helpers::call(name, &args, scope.root)
+        }
+    }
+}
+
+/// An `if` condition; only a missing path is forgiven.
+fn condition(expr: &Expr, scope: &Scope) -> Result<bool, String> {
+    match expr {
+        Expr::Path(path) => Ok(scope.lookup(path).is_some_and(|v| is_truthy(&v))),
+        Expr::Not(inner) => condition(inner, scope).map(|truthy| !truthy),
+        other => eval(other, scope).map(|v| is_truthy(&v)),
+    }
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(a) => !a.is_empty(),
+        Value::Object(o) => !o.is_empty(),
+    }
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "a list",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn render(source: &str, context: &Value) -> Result<String, String> {
+        Template::parse("t.tmpl", source)
+            .and_then(|t| t.render(context))
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn renders() {
+        let ctx = json!({
+            "name": "case_files",
+            "flag": true,
+            "off": false,
+            "empty": "",
+            "none": null,
+            "xs": ["a", "b", "c"],
+            "block": "first\nsecond",
+            "gappy": "a\n\nb",
+            "spec": {"table": "dispatch_file"},
+        });
+        let cases = [
+            ("Hello {{ name }}!", "Hello case_files!"),
+            ("{{ spec.table }}", "dispatch_file"),
+            (
+                "{{ 42 }} {{ null }}|{{ [1, \"x\"] | json }}",
+                "42 |[1,\"x\"]",
+            ),
+            // Tags alone on their line leave no line behind.
+            ("a\n{% if flag %}\nb\n{% endif %}\nc\n", "a\nb\nc\n"),
+            ("a\n{% if off %}\nb\n{% endif %}\nc\n", "a\nc\n"),
+            (
+                "  {% for x in xs %}\n  - {{ x }}\n  {% endfor %}\n",
+                "  - a\n  - b\n  - c\n",
+            ),
+            ("{% if flag %}\nlast\n{% endif %}", "last\n"),
+            ("{{ name }}", "case_files"),
+            // Tags sharing their line with text keep it.
+            ("a {% if flag %}b{% endif %} c\n", "a b c\n"),
+            ("x{% if off %}y{% endif %}\nz", "x\nz"),
+            // A standalone placeholder that renders nothing leaves nothing.
+            ("a\n    {{ empty }}\nb\n", "a\nb\n"),
+            ("a\n    {{ [] | join(\",\") }}\nb\n", "a\nb\n"),
+            // Multi-line values follow the placeholder's indentation.
+            ("  x = {{ block }};\n", "  x = first\n  second;\n"),
+            ("    {{ gappy }}\n", "    a\n\n    b\n"),
+            ("\t{{ block }}\n", "\tfirst\n\tsecond\n"),
+            ("text {{ block }}\n", "text first\nsecond\n"),
+            // Loops, conditions and pipes.
+            (
+                "{% for x in xs %}{{ loop.index }}{{ x }}{% if not loop.last %},{% endif %}{% endfor %}",
+                "0a,1b,2c",
+            ),
+            ("{% for x in none %}x{% endfor %}.", "."),
+            ("{% if missing %}y{% else %}n{% endif %}", "n"),
+            ("{% if not missing %}y{% endif %}", "y"),
+            ("{% if not not flag %}y{% endif %}", "y"),
+            ("{% if xs %}{{ xs | join(\", \") }}{% endif %}", "a, b, c"),
+            (
+                "{{ name | pascal }} {{ name | title }} {{ name | kebab }}",
+                "CaseFiles Case Files case-files",
+            ),
+            ("{{ join(xs, \"-\") | title }}", "A B C"),
+            (
+                "{{ [\"it's\", \"a\\\\b\"] | js_array }}",
+                "['it\\'s', 'a\\\\b']",
+            ),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(
+                render(source, &ctx).as_deref(),
+                Ok(expected),
+                "{:?}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn errors_name_file_and_line() {
+        let ctx = json!({"name": "x", "xs": [1]});
+        let cases = [
+            ("a\nb {{ nope }}", "t.tmpl:2: unknown variable 'nope'"),
+            ("\n\n{{ name | shout }}", "t.tmpl:3: unknown helper 'shout'"),
+            (
+                "{{ name.first.second }}",
+                "t.tmpl:1: unknown variable 'name.first.second'",
+            ),
+            (
+                "x\n{% if nope | pascal %}{% endif %}",
+                "t.tmpl:2: unknown variable 'nope'",
+            ),
+            (
+                "\n{% for x in name %}{% endfor %}",
+                "t.tmpl:2: cannot iterate over a string",
+            ),
+            (
+                "{% for x in nothing %}{% endfor %}",
+                "t.tmpl:1: unknown variable 'nothing'",
+            ),
+            (
+                "{% for x in xs %}\n{{ x.y }}\n{% endfor %}",
+                "t.tmpl:2: unknown variable 'x.y'",
+            ),
+            (
+                "a\n\n{% for x in xs %}\n",
+                "t.tmpl:3: 'for' without matching 'endfor'",
+            ),
+            (
+                "{% if name %}\n{% else %}\n",
+                "t.tmpl:1: 'if' without matching 'endif'",
+            ),
+            ("x\n{% endif %}", "t.tmpl:2: unexpected '{% endif %}'"),
+            ("x\n{% while x %}", "t.tmpl:2: unexpected '{% while x %}'"),
+            (
+                "{% for 1x in xs %}{% endfor %}",
+                "t.tmpl:1: invalid loop variable '1x'",
+            ),
+            ("a\n{{ name", "t.tmpl:2: unterminated '{{'"),
+            (
+                "{{ name name }}",
+                "t.tmpl:1: in 'name name': unexpected 'n'",
+            ),
+            (
+                "{{ \"open }}",
+                "t.tmpl:1: in '\"open': unterminated string literal",
+            ),
+            (
+                "{{ join(xs) }}",
+                "t.tmpl:1: join() takes 2 argument(s), got 1",
+            ),
+            // Continuation lines of a multi-line tag count.
+            (
+                "{{\nname\n}}{{ nope }}",
+                "t.tmpl:3: unknown variable 'nope'",
+            ),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(
+                render(source, &ctx),
+                Err(expected.to_string()),
+                "{:?}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn overrides_win_over_builtins() {
+        let dir = std::env::temp_dir().join(format!("template-overrides-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.tmpl"), "override {{ x }}\n{{ nope }}\n").unwrap();
+        let builtins = [("a.tmpl", "builtin {{ x }}"), ("b.tmpl", "builtin b")];
+        let ctx = json!({"x": 1});
+
+        let loader = Loader::new(&builtins).with_override_dir(&dir);
+        assert!(loader.is_overridden("a.tmpl"));
+        assert!(!loader.is_overridden("b.tmpl"));
+        let error = loader.load("a.tmpl").unwrap().render(&ctx).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{}:2: unknown variable 'nope'",
+                dir.join("a.tmpl").display()
+            )
+        );
+        assert_eq!(
+            loader.load("b.tmpl").unwrap().render(&ctx).unwrap(),
+            "builtin b"
+        );
+        let missing = loader.load("c.tmpl").unwrap_err().to_string();
+        assert_eq!(missing, "c.tmpl:0: no such template");
+
+        fs::write(dir.join("a.tmpl"), "override {{ x }}").unwrap();
+        assert_eq!(
+            loader.load("a.tmpl").unwrap().render(&ctx).unwrap(),
+            "override 1"
+        );
+
+        // Without the directory the built-ins are used.
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!loader.is_overridden("a.tmpl"));
+        let error = loader
+            .load("a.tmpl")
+            .unwrap()
+            .render(&json!({}))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "<builtin>/a.tmpl:1: unknown variable 'x'"
+        );
+    }
+}
@@ -0,0 +1,1124 @@
+//! Declarative post-migration checks, replacing the `validate-*-migration.ts`
+//! scripts and the `COMMON_INTEGRITY_CHECKS` of `ValidationFramework`.
+//!
+//! A check file holds `[[check]]` tables, each naming an entity spec and a
+//! `type`:
+//!
+//! - `row_count`: source rows (after the spec's `filter`) against target rows
+//!   with a legacy ID;
+//! - `foreign_keys`: target rows whose FK column points nowhere, for the
+//!   given `columns` and `references` or else for every lookup of the spec;
+//! - `not_null`: NULLs in `columns`;
+//! - `unique`: duplicate values of `columns` taken together, by default the
+//!   legacy ID column;
+//! - `sample`: randomly drawn rows compared field by field through the spec
+//!   (see [`sample`]);
+//! - `sql`: a query selecting the violating rows, run read-only on the
+//!   `database` (target by default).
+//!
+//! Discrepancies within the check's `tolerance` (rows, or a percentage like
+//! `"0.5%"`) pass; beyond it the check fails, or warns when its `severity` is
+//! `warn`. A check that cannot run fails. Without a check file every spec
+//! gets [`default_checks`].
+//!
+//! Results are written to `migration_validation_reports`, in the layout of
+//! `MigrationValidationReportModel`, one row per check with the run in
+//! `metadata`.
+
+pub mod sample;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use postgres::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::engine::{self, describe};
+use crate::mapping::MappingStore;
+use crate::spec::{self, EntitySpec, is_identifier};
+
+use sample::{SampleOptions, Strategy};
+
+/// Rows drawn by a `sample` check without a `size`.
+pub const DEFAULT_SAMPLE_SIZE: u64 = 100;
+
+/// Offending keys or values kept in a result's details.
+const EXAMPLES: usize = 5;
+
+const CREATE_SQL: &str = "CREATE TABLE IF NOT EXISTS migration_validation_reports (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    validation_type VARCHAR(50) NOT NULL CHECK (validation_type IN
+        ('data_integrity', 'relationship_integrity', 'completeness_check', 'performance_check')),
+    source_entity VARCHAR(100) NOT NULL,
+    target_entity VARCHAR(100) NOT NULL,
+    records_validated INTEGER NOT NULL DEFAULT 0,
+    validation_passed BOOLEAN NOT NULL DEFAULT FALSE,
+    discrepancies_found INTEGER NOT NULL DEFAULT 0,
+    discrepancy_details JSONB DEFAULT '{}',
+    validation_criteria JSONB DEFAULT '{}',
+    execution_time_ms INTEGER NOT NULL DEFAULT 0,
+    generated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    metadata JSONB DEFAULT '{}'
+)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckType {
+    RowCount,
+    ForeignKeys,
+    NotNull,
+    Unique,
+    Sample,
+    Sql,
+}
+
+impl CheckType {
+    pub const ALL: [CheckType; 6] = [
+        CheckType::RowCount,
+        CheckType::ForeignKeys,
+        CheckType::NotNull,
+        CheckType::Unique,
+        CheckType::Sample,
+        CheckType::Sql,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CheckType::RowCount => "row_count",
+            CheckType::ForeignKeys => "foreign_keys",
+            CheckType::NotNull => "not_null",
+            CheckType::Unique => "unique",
+            CheckType::Sample => "sample",
+            CheckType::Sql => "sql",
+        }
+    }
+
+    /// `migration_validation_reports.validation_type`.
+    fn validation_type(self) -> &'static str {
+        match self {
+            CheckType::RowCount => "completeness_check",
+            CheckType::ForeignKeys => "relationship_integrity",
+            _ => "data_integrity",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    #[default]
+    Fail,
+    Warn,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Database {
+    Source,
+    #[default]
+    Target,
+}
+
+/// Ordered from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Pass => "✓ pass",
+            Status::Warn => "⚠ warn",
+            Status::Fail => "✗ fail",
+        })
+    }
+}
+
+/// Discrepancies a check accepts: a number of rows or a percentage of the
+/// rows it looked at.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawTolerance", into = "RawTolerance")]
+pub enum Tolerance {
+    Rows(u64),
+    Percent(f64),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawTolerance {
+    Rows(u64),
+    Text(String),
+}
+
+impl TryFrom<RawTolerance> for Tolerance {
+    type Error = String;
+
+    fn try_from(raw: RawTolerance) -> Result<Self, String> {
+        match raw {
+            RawTolerance::Rows(n) => Ok(Tolerance::Rows(n)),
+            RawTolerance::Text(text) => text
+                .trim()
+                .strip_suffix('%')
+                .and_then(|p| p.trim().parse::<f64>().ok())
+                .filter(|p| (0.0..=100.0).contains(p))
+                .map(Tolerance::Percent)
+                .ok_or_else(|| {
+                    format!(
+                        "tolerance '{}' is neither a row count nor a percentage like \"0.5%\"",
+                        text
+                    )
+                }),
+        }
+    }
+}
+
+impl From<Tolerance> for RawTolerance {
+    fn from(tolerance: Tolerance) -> Self {
+        match tolerance {
+            Tolerance::Rows(n) => RawTolerance::Rows(n),
+            Tolerance::Percent(p) => RawTolerance::Text(format!("{}%", p)),
+        }
+    }
+}
+
+impl Tolerance {
+    fn allows(self, discrepancies: u64, of: u64) -> bool {
+        match self {
+            Tolerance::Rows(n) => discrepancies <= n,
+            Tolerance::Percent(p) => (discrepancies as f64) <= of as f64 * p / 100.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Check {
+    /// The spec's `name`; any label for `sql` checks.
+    pub entity: String,
+    #[serde(rename = "type")]
+    pub kind: CheckType,
+    /// Shown in the results; defaults to the type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub severity: Severity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<Tolerance>,
+    /// Target columns: FK columns, required columns, the unique key or the
+    /// sampled columns.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub columns: Vec<String>,
+    /// `table` or `table.column` the FK `columns` point to; the column
+    /// defaults to `id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub references: Option<String>,
+    /// Rows drawn by a `sample` check.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Query of a `sql` check; every row it returns is a violation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sql: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database: Option<Database>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CheckFile {
+    #[serde(default)]
+    check: Vec<Check>,
+}
+
+impl Check {
+    fn new(entity: &str, kind: CheckType) -> Check {
+        Check {
+            entity: entity.to_string(),
+            kind,
+            name: None,
+            severity: Severity::Fail,
+            tolerance: None,
+            columns: Vec::new(),
+            references: None,
+            size: None,
+            sql: None,
+            database: None,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(self.kind.as_str())
+    }
+
+    fn needs_source(&self) -> bool {
+        match self.kind {
+            CheckType::RowCount | CheckType::Sample => true,
+            CheckType::Sql => self.database == Some(Database::Source),
+            _ => false,
+        }
+    }
+
+    fn validate(&self, specs: &[EntitySpec]) -> Result<(), String> {
+        let kind = self.kind;
+        let only = |field: &str, set: bool, allowed: &[CheckType]| {
+            if set && !allowed.contains(&kind) {
+                Err(format!(
+                    "`{}` does not apply to {} checks",
+                    field,
+                    kind.as_str()
+                ))
+            } else {
+                Ok(())
+            }
+        };
+        only("sql", self.sql.is_some(), &[CheckType::Sql])?;
+        only("database", self.database.is_some(), &[CheckType::Sql])?;
+        only(
+            "references",
+            self.references.is_some(),
+            &[CheckType::ForeignKeys],
+        )?;
+        only("size", self.size.is_some(), &[CheckType::Sample])?;
+        only(
+            "columns",
+            !self.columns.is_empty(),
+            &[
+                CheckType::ForeignKeys,
+                CheckType::NotNull,
+                CheckType::Unique,
+                CheckType::Sample,
+            ],
+        )?;
+        if kind != CheckType::Sql {
+            spec::find(specs, &self.entity)?;
+        }
+        if let Some(bad) = self.columns.iter().find(|c| !is_identifier(c)) {
+            return Err(format!("'{}' is not a valid column name", bad));
+        }
+        match kind {
+            CheckType::NotNull if self.columns.is_empty() => {
+                Err("`columns` lists the columns that must not be NULL".into())
+            }
+            CheckType::ForeignKeys if self.references.is_some() && self.columns.is_empty() => {
+                Err("`references` needs the FK `columns`".into())
+            }
+            CheckType::ForeignKeys
+                if self.references.as_deref().is_some_and(|r| {
+                    !r.split('.').all(is_identifier) || r.split('.').count() > 2
+                }) =>
+            {
+                Err("`references` must be `table` or `table.column`".into())
+            }
+            CheckType::Sample if self.size == Some(0) => {
+                Err("`size` must be greater than zero".into())
+            }
+            CheckType::Sql if self.sql.as_deref().is_none_or(|q| q.trim().is_empty()) => {
+                Err("`sql` selecting the violating rows is required".into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checks of a `.toml`, `.yaml` or `.yml` check file, validated against
+/// `specs`.
+pub fn load(path: &Path, specs: &[EntitySpec]) -> Result<Vec<Check>, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let invalid = |e: String| format!("invalid check file {}: {}", path.display(), e);
+    let file: CheckFile = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str(&text).map_err(|e| invalid(e.to_string()))?,
+        Some("yaml" | "yml") => serde_yaml::from_str(&text).map_err(|e| invalid(e.to_string()))?,
+        _ => return Err(invalid("expected a .toml, .yaml or .yml file".into())),
+    };
+    for (i, check) in file.check.iter().enumerate() {
+        check.validate(specs).map_err(|e| {
+            invalid(format!(
+                "check {} ({} {}): {}",
+                i + 1,
+                check.entity,
+                check.label(),
+                e
+            ))
+        })?;
+    }
+    Ok(file.check)
+}
+
+/// What every entity is checked for without a check file: row counts, the
+/// spec's FK lookups, unique legacy IDs and a sample.
+pub fn default_checks(spec: &EntitySpec) -> Vec<Check> {
+    let mut checks = vec![Check::new(&spec.name, CheckType::RowCount)];
+    if !spec.lookups.is_empty() {
+        checks.push(Check::new(&spec.name, CheckType::ForeignKeys));
+    }
+    checks.push(Check::new(&spec.name, CheckType::Unique));
+    checks.push(Check::new(&spec.name, CheckType::Sample));
+    checks
+}
+
+/// Whether any of `checks` reads the source database.
+pub fn needs_source(checks: &[Check]) -> bool {
+    checks.iter().any(Check::needs_source)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub entity: String,
+    pub check: String,
+    #[serde(rename = "type")]
+    pub kind: CheckType,
+    pub status: Status,
+    pub records_validated: u64,
+    pub discrepancies: u64,
+    pub message: String,
+    pub details: Value,
+    pub duration_ms: u64,
+    #[serde(skip)]
+    source_table: Option<String>,
+    #[serde(skip)]
+    target_table: Option<String>,
+    #[serde(skip)]
+    criteria: Value,
+}
+
+/// What a check measured, before its tolerance is applied.
+struct Measured {
+    records: u64,
+    discrepancies: u64,
+    /// The number a percentage tolerance is taken of.
+    of: u64,
+    message: String,
+    details: Value,
+}
+
+/// Runs checks against one source and target.
+pub struct Validator<'a> {
+    pub specs: &'a [EntitySpec],
+    pub source: Option<&'a mut Client>,
+    pub target: &'a mut Client,
+    mappings: MappingStore,
+    types: HashMap<String, HashMap<String, String>>,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(
+        specs: &'a [EntitySpec],
+        source: Option<&'a mut Client>,
+        target: &'a mut Client,
+    ) -> Self {
+        Validator {
+            specs,
+            source,
+            target,
+            mappings: MappingStore::new(),
+            types: HashMap::new(),
+        }
+    }
+
+    pub fn run(&mut self, check: &Check) -> CheckResult {
+        let started = Instant::now();
+        let spec = self.specs.iter().find(|s| s.name == check.entity);
+        let measured = match (check.kind, spec) {
+            (CheckType::Sql, _) => self.sql(check),
+            (_, None) => Err(format!("no entity spec named '{}'", check.entity)),
+            (CheckType::RowCount, Some(spec)) => self.row_count(spec),
+            (CheckType::ForeignKeys, Some(spec)) => self.foreign_keys(spec, check),
+            (CheckType::NotNull, Some(spec)) => self.not_null(spec, check),
+            (CheckType::Unique, Some(spec)) => self.unique(spec, check),
+            (CheckType::Sample, Some(spec)) => self.sample(spec, check),
+        };
+        let (status, measured) = match measured {
+            Ok(m) if m.discrepancies == 0 => (Status::Pass, m),
+            Ok(mut m)
+                if check
+                    .tolerance
+                    .is_some_and(|t| t.allows(m.discrepancies, m.of)) =>
+            {
+                m.message.push_str(" (within tolerance)");
+                (Status::Pass, m)
+            }
+            Ok(m) => (
+                match check.severity {
+                    Severity::Fail => Status::Fail,
+                    Severity::Warn => Status::Warn,
+                },
+                m,
+            ),
+            Err(e) => (
+                Status::Fail,
+                Measured {
+                    records: 0,
+                    discrepancies: 0,
+                    of: 0,
+                    message: format!("could not run: {}", e),
+                    details: json!({ "error": e }),
+                },
+            ),
+        };
+        CheckResult {
+            entity: check.entity.clone(),
+            check: check.label().to_string(),
+            kind: check.kind,
+            status,
+            records_validated: measured.records,
+            discrepancies: measured.discrepancies,
+            message: measured.message,
+            details: measured.details,
+            duration_ms: started.elapsed().as_millis() as u64,
+            source_table: spec.map(|s| s.source_table.clone()),
+            target_table: spec.map(|s| s.target_table.clone()),
+            criteria: serde_json::to_value(check).unwrap_or(Value::Null),
+        }
+    }
+
+    fn source(&mut self) -> Result<&mut Client, String> {
+        self.source
+            .as_deref_mut()
+            .ok_or_else(|| "the source database is not connected".to_string())
+    }
+
+    fn count(client: &mut Client, sql: &str) -> Result<u64, String> {
+        let row = client.query_one(sql, &[]).map_err(|e| describe(&e))?;
+        let n: i64 = row.get(0);
+        Ok(n as u64)
+    }
+
+    fn row_count(&mut self, spec: &EntitySpec) -> Result<Measured, String> {
+        let source = Self::count(
+            self.source()?,
+            &format!(
+                "SELECT count(*) FROM {} s{}",
+                spec.source_table,
+                spec.filter
+                    .as_ref()
+                    .map(|f| format!(" WHERE ({})", f))
+                    .unwrap_or_default()
+            ),
+        )?;
+        let target = Self::count(
+            self.target,
+            &format!(
+                "SELECT count(*) FROM {} WHERE {} IS NOT NULL",
+                spec.target_table, spec.legacy_id_column
+            ),
+        )?;
+        Ok(Measured {
+            records: source,
+            discrepancies: source.abs_diff(target),
+            of: source,
+            message: format!("source {}, target {}", source, target),
+            details: json!({
+                "source_rows": source,
+                "target_rows": target,
+                "difference": target as i64 - source as i64,
+            }),
+        })
+    }
+
+    /// `(column, table, referenced column)` of every FK the check covers.
+    fn references(&self, spec: &EntitySpec, check: &Check) -> Vec<(String, String, String)> {
+        if let Some(references) = &check.references {
+            let (table, column) = references.split_once('.').unwrap_or((references, "id"));
+            return check
+                .columns
+                .iter()
+                .map(|c| (c.clone(), table.to_string(), column.to_string()))
+                .collect();
+        }
+        spec.lookups
+            .iter()
+            .filter(|l| check.columns.is_empty() || check.columns.contains(&l.target))
+            .filter_map(|l| {
+                let referenced = self
+                    .specs
+                    .iter()
+                    .find(|s| s.name == l.entity || s.entity_type() == l.entity)?;
+                Some((
+                    l.target.clone(),
+                    referenced.target_table.clone(),
+                    referenced.target_key.clone(),
+                ))
+            })
+            .collect()
+    }
+
+    fn foreign_keys(&mut self, spec: &EntitySpec, check: &Check) -> Result<Measured, String> {
+        let references = self.references(spec, check);
+        if references.is_empty() {
+            return Err(format!(
+                "no lookup of {} references a known spec; set `columns` and `references`",
+                spec.name
+            ));
+        }
+        let records = Self::count(
+            self.target,
+            &format!("SELECT count(*) FROM {}", spec.target_table),
+        )?;
+        let mut orphans = 0;
+        let mut details = serde_json::Map::new();
+        let mut worst = Vec::new();
+        for (column, table, key) in &references {
+            let row = self
+                .target
+                .query_one(
+                    &format!(
+                        "SELECT count(*), (array_agg(t.{legacy}::bigint ORDER BY t.{legacy}))[1:{n}]
+                         FROM {target} t
+                         WHERE t.{column} IS NOT NULL
+                           AND NOT EXISTS (SELECT 1 FROM {table} r WHERE r.{key} = t.{column})",
+                        legacy = spec.legacy_id_column,
+                        n = EXAMPLES,
+                        target = spec.target_table,
+                        column = column,
+                        table = table,
+                        key = key,
+                    ),
+                    &[],
+                )
+                .map_err(|e| format!("{} → {}.{}: {}", column, table, key, describe(&e)))?;
+            let count: i64 = row.get(0);
+            let examples: Option<Vec<Option<i64>>> = row.get(1);
+            orphans += count as u64;
+            if count > 0 {
+                worst.push(format!("{} → {}: {}", column, table, count));
+            }
+            details.insert(
+                column.clone(),
+                json!({
+                    "references": format!("{}.{}", table, key),
+                    "orphans": count,
+                    "legacy_ids": examples.unwrap_or_default(),
+                }),
+            );
+        }
+        Ok(Measured {
+            records,
+            discrepancies: orphans,
+            of: records,
+            message: if worst.is_empty() {
+                format!("{} FK column(s), no orphans", references.len())
+            } else {
+                format!("orphaned rows: {}", worst.join(", "))
+            },
+            details: Value::Object(details),
+        })
+    }
+
+    fn not_null(&mut self, spec: &EntitySpec, check: &Check) -> Result<Measured, String> {
+        let counts: Vec<String> = check
+            .columns
+            .iter()
+            .map(|c| format!("count(*) FILTER (WHERE {} IS NULL)", c))
+            .collect();
+        let row = self
+            .target
+            .query_one(
+                &format!(
+                    "SELECT count(*), {} FROM {}",
+                    counts.join(", "),
+                    spec.target_table
+                ),
+                &[],
+            )
+            .map_err(|e| describe(&e))?;
+        let records: i64 = row.get(0);
+        let mut nulls = 0;
+        let mut details = serde_json::Map::new();
+        let mut worst = Vec::new();
+        for (i, column) in check.columns.iter().enumerate() {
+            let n: i64 = row.get(i + 1);
+            nulls += n as u64;
+            if n > 0 {
+                worst.push(format!("{} ({})", column, n));
+            }
+            details.insert(column.clone(), json!(n));
+        }
+        Ok(Measured {
+            records: records as u64,
+            discrepancies: nulls,
+            of: records as u64,
+            message: if worst.is_empty() {
+                format!("{} column(s) fully populated", check.columns.len())
+            } else {
+                format!("NULLs in {}", worst.join(", "))
+            },
+            details: Value::Object(details),
+        })
+    }
+
+    fn unique(&mut self, spec: &EntitySpec, check: &Check) -> Result<Measured, String> {
+        let columns = if check.columns.is_empty() {
+            vec![spec.legacy_id_column.clone()]
+        } else {
+            check.columns.clone()
+        };
+        let key = columns.join(", ");
+        let present: Vec<String> = columns
+            .iter()
+            .map(|c| format!("{} IS NOT NULL", c))
+            .collect();
+        let texts: Vec<String> = columns.iter().map(|c| format!("{}::text", c)).collect();
+        let rows = self
+            .target
+            .query(
+                &format!(
+                    "SELECT concat_ws(', ', {texts}), count(*), (sum(count(*)) OVER ())::bigint,
+                            count(*) OVER (), (SELECT count(*) FROM {table})
+                     FROM {table}
+                     WHERE {present}
+                     GROUP BY {key}
+                     HAVING count(*) > 1
+                     ORDER BY count(*) DESC, 1
+                     LIMIT {n}",
+                    texts = texts.join(", "),
+                    table = spec.target_table,
+                    present = present.join(" AND "),
+                    key = key,
+                    n = EXAMPLES,
+                ),
+                &[],
+            )
+            .map_err(|e| describe(&e))?;
+        let records = match rows.first() {
+            Some(row) => row.get::<_, i64>(4) as u64,
+            None => Self::count(
+                self.target,
+                &format!("SELECT count(*) FROM {}", spec.target_table),
+            )?,
+        };
+        let (rows_in_groups, groups) = rows.first().map_or((0, 0), |row| {
+            (row.get::<_, i64>(2) as u64, row.get::<_, i64>(3) as u64)
+        });
+        let duplicates = rows_in_groups - groups;
+        let examples: Vec<Value> = rows
+            .iter()
+            .map(|row| json!({ "value": row.get::<_, String>(0), "rows": row.get::<_, i64>(1) }))
+            .collect();
+        Ok(Measured {
+            records,
+            discrepancies: duplicates,
+            of: records,
+            message: if duplicates == 0 {
+                format!("({}) is unique", key)
+            } else {
+                format!(
+                    "{} duplicate row(s) in {} group(s) of ({})",
+                    duplicates, groups, key
+                )
+            },
+            details: json!({ "columns": columns, "groups": groups, "examples": examples }),
+        })
+    }
+
+    fn sample(&mut self, spec: &EntitySpec, check: &Check) -> Result<Measured, String> {
+        if !self.types.contains_key(&spec.name) {
+            let types = engine::target_types(self.target, spec)?;
+            self.types.insert(spec.name.clone(), types);
+        }
+        let mut referenced: Vec<&str> = spec.lookups.iter().map(|l| l.entity.as_str()).collect();
+        referenced.sort_unstable();
+        referenced.dedup();
+        self.mappings.preload(self.target, &referenced)?;
+        let source = self
+            .source
+            .as_deref_mut()
+            .ok_or_else(|| "the source database is not connected".to_string())?;
+        let sample = sample::compare(
+            spec,
+            source,
+            self.target,
+            &self.types[&spec.name],
+            &self.mappings,
+            &SampleOptions {
+                size: check.size.unwrap_or(DEFAULT_SAMPLE_SIZE),
+                strategy: Strategy::Random,
+                strata: 1,
+                columns: &check.columns,
+                examples: 3,
+            },
+        )?;
+        let discrepancies = sample.discrepancies();
+        let mut worst: Vec<&sample::ColumnSample> =
+            sample.columns.iter().filter(|c| c.mismatched > 0).collect();
+        worst.sort_by_key(|c| std::cmp::Reverse(c.mismatched));
+        let mut message = format!(
+            "{} of {} sampled row(s) differ",
+            sample.mismatched_rows, sample.sampled
+        );
+        if !sample.unmapped.is_empty() {
+            message.push_str(&format!(", {} unmapped", sample.unmapped.len()));
+        }
+        if !sample.missing.is_empty() {
+            message.push_str(&format!(", {} missing", sample.missing.len()));
+        }
+        if !worst.is_empty() {
+            let columns: Vec<String> = worst
+                .iter()
+                .take(3)
+                .map(|c| format!("{} ({})", c.column, c.mismatched))
+                .collect();
+            message.push_str(&format!("; {}", columns.join(", ")));
+        }
+        Ok(Measured {
+            records: sample.sampled,
+            discrepancies,
+            of: sample.sampled,
+            message,
+            details: serde_json::to_value(&sample).unwrap_or(Value::Null),
+        })
+    }
+
+    fn sql(&mut self, check: &Check) -> Result<Measured, String> {
+        let query = check.sql.as_deref().unwrap_or_default().trim();
+        let query = query.strip_suffix(';').unwrap_or(query);
+        let client = match check.database.unwrap_or_default() {
+            Database::Source => self.source()?,
+            Database::Target => &mut *self.target,
+        };
+        let mut tx = client
+            .build_transaction()
+            .read_only(true)
+            .start()
+            .map_err(|e| describe(&e))?;
+        let rows = tx
+            .query(
+                &format!(
+                    "SELECT count(*) OVER (), to_jsonb(q)::text FROM ({}) q LIMIT {}",
+                    query, EXAMPLES
+                ),
+                &[],
+            )
+            .map_err(|e| describe(&e))?;
+        tx.rollback().map_err(|e| describe(&e))?;
+        let violations = rows.first().map_or(0, |r| r.get::<_, i64>(0) as u64);
+        let examples: Vec<Value> = rows
+            .iter()
+            .filter_map(|r| serde_json::from_str(&r.get::<_, String>(1)).ok())
+            .collect();
+        Ok(Measured {
+            records: violations,
+            discrepancies: violations,
+            of: violations,
+            message: format!("{} violating row(s)", violations),
+            details: json!({ "examples": examples }),
+        })
+    }
+}
+
+/// Worst status per entity and check type, in the order the entities were
+/// first checked.
+pub fn matrix(results: &[CheckResult]) -> Vec<(String, HashMap<CheckType, Status>)> {
+    let mut rows: Vec<(String, HashMap<CheckType, Status>)> = Vec::new();
+    for result in results {
+        let index = match rows.iter().position(|(e, _)| *e == result.entity) {
+            Some(i) => i,
+            None => {
+                rows.push((result.entity.clone(), HashMap::new()));
+                rows.len() - 1
+            }
+        };
+        let cell = rows[index].1.entry(result.kind).or_insert(Status::Pass);
+        *cell = (*cell).max(result.status);
+    }
+    rows
+}
+
+pub fn ensure_table(target: &mut Client) -> Result<(), String> {
+    target.batch_execute(CREATE_SQL).map_err(|e| {
+        format!(
+            "cannot create migration_validation_reports: {}",
+            describe(&e)
+        )
+    })
+}
+
+/// Insert one `migration_validation_reports` row per result, kept for
+/// `keep_days`.
+pub fn record(
+    target: &mut Client,
+    run: &str,
+    results: &[CheckResult],
+    keep_days: u32,
+) -> Result<(), String> {
+    let failed = |e: postgres::Error| {
+        format!(
+            "cannot write migration_validation_reports: {}",
+            describe(&e)
+        )
+    };
+    let keep_days = keep_days.max(1) as i32;
+    let mut tx = target.transaction().map_err(failed)?;
+    let insert = tx
+        .prepare(
+            "INSERT INTO migration_validation_reports (
+                 validation_type, source_entity, target_entity, records_validated,
+                 validation_passed, discrepancies_found, discrepancy_details,
+                 validation_criteria, execution_time_ms, expires_at, metadata)
+             VALUES ($1, $2, $3, $4, $5, $6, $7::text::jsonb, $8::text::jsonb, $9,
+                     NOW() + make_interval(days => $10), $11::text::jsonb)",
+        )
+        .map_err(failed)?;
+    for result in results {
+        let clamp = |n: u64| n.min(i32::MAX as u64) as i32;
+        let mut details = match &result.details {
+            Value::Object(map) => map.clone(),
+            other => {
+                let mut map = serde_json::Map::new();
+                map.insert("details".into(), other.clone());
+                map
+            }
+        };
+        details.insert("message".into(), json!(result.message));
+        let metadata = json!({
+            "run": run,
+            "entity": result.entity,
+            "check": result.check,
+            "type": result.kind,
+            "status": result.status,
+        });
+        tx.execute(
+            &insert,
+            &[
+                &result.kind.validation_type(),
+                &result.source_table.as_deref().unwrap_or(&result.entity),
+                &result.target_table.as_deref().unwrap_or(&result.entity),
+                &clamp(result.records_validated),
+                &(result.status != Status::Fail),
+                &clamp(result.discrepancies),
+                &Value::Object(details).to_string(),
+                &result.criteria.to_string(),
+                &clamp(result.duration_ms.max(1)),
+                &keep_days,
+                &metadata.to_string(),
+            ],
+        )
+        .map_err(failed)?;
+    }
+    tx.commit().map_err(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(tolerance: &str) -> Result<Tolerance, String> {
+        Tolerance::try_from(RawTolerance::Text(tolerance.to_string()))
+    }
+
+    #[test]
+    fn tolerances_parse() {
+        let cases = [
+            ("0.5%", Some(Tolerance::Percent(0.5))),
+            (" 2 % ", Some(Tolerance::Percent(2.0))),
+            ("0%", Some(Tolerance::Percent(0.0))),
+            ("100%", Some(Tolerance::Percent(100.0))),
+            ("100.5%", None),
+            ("-1%", None),
+            ("NaN%", None),
+            ("5", None),
+            ("%", None),
+            ("half%", None),
+            ("", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(text(input).ok(), expected, "{:?}", input);
+        }
+        let error = text("lots").unwrap_err();
+        assert!(error.contains("'lots' is neither"), "{}", error);
+
+        // TOML gives a row count as an integer and a percentage as text.
+        let file: CheckFile = toml::from_str(
+            r#"
+            check = [
+              { entity = "offices", type = "unique", tolerance = 3 },
+              { entity = "offices", type = "unique", tolerance = "1.5%" },
+            ]
+            "#,
+        )
+        .unwrap();
+        let tolerances: Vec<_> = file.check.iter().map(|c| c.tolerance).collect();
+        assert_eq!(
+            tolerances,
+            [Some(Tolerance::Rows(3)), Some(Tolerance::Percent(1.5))]
+        );
+        let error = toml::from_str::<CheckFile>(
+            r#"check = [{ entity = "offices", type = "unique", tolerance = "101%" }]"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("'101%' is neither"), "{}", error);
+        assert_eq!(
+            serde_json::to_value(Tolerance::Percent(0.5)).unwrap(),
+            json!("0.5%")
+        );
+    }
+
+    #[test]
+    fn tolerances_allow() {
+        // (tolerance, discrepancies, of, allowed)
+        let cases = [
+            (Tolerance::Rows(0), 0, 10, true),
+            (Tolerance::Rows(0), 1, 10, false),
+            (Tolerance::Rows(2), 2, 0, true),
+            (Tolerance::Rows(2), 3, 1000, false),
+            (Tolerance::Percent(0.5), 5, 1000, true),
+            (Tolerance::Percent(0.5), 6, 1000, false),
+            (Tolerance::Percent(10.0), 1, 10, true),
+            (Tolerance::Percent(10.0), 1, 9, false),
+            (Tolerance::Percent(0.0), 1, 1000, false),
+            (Tolerance::Percent(100.0), 7, 7, true),
+            (Tolerance::Percent(50.0), 1, 0, false),
+        ];
+        for (tolerance, discrepancies, of, allowed) in cases {
+            assert_eq!(
+                tolerance.allows(discrepancies, of),
+                allowed,
+                "{:?} of {} with {:?}",
+                discrepancies,
+                of,
+                tolerance
+            );
+        }
+    }
+
+    fn result(entity: &str, kind: CheckType, status: Status) -> CheckResult {
+        CheckResult {
+            entity: entity.to_string(),
+            check: kind.as_str().to_string(),
+            kind,
+            status,
+            records_validated: 0,
+            discrepancies: 0,
+            message: String::new(),
+            details: Value::Null,
+            duration_ms: 0,
+            source_table: None,
+            target_table: None,
+            criteria: Value::Null,
+        }
+    }
+
+    #[test]
+    fn matrix_keeps_the_worst_status_per_cell() {
+        use CheckType::*;
+        use Status::*;
+
+        let results = [
+            result("patients", Unique, Pass),
+            result("offices", Sql, Warn),
+            result("offices", Sql, Pass),
+            result("patients", Unique, Fail),
+            result("patients", Unique, Warn),
+            result("offices", NotNull, Pass),
+            result("offices", Sql, Fail),
+            result("offices", Sample, Warn),
+        ];
+        let rows = matrix(&results);
+        let entities: Vec<&str> = rows.iter().map(|(e, _)| e.as_str()).collect();
+        assert_eq!(entities, ["patients", "offices"], "first seen first");
+        assert_eq!(rows[0].1, HashMap::from([(Unique, Fail)]));
+        assert_eq!(
+            rows[1].1,
+            HashMap::from([(Sql, Fail), (NotNull, Pass), (Sample, Warn)])
+        );
+        assert!(matrix(&[]).is_empty());
+    }
+
+    #[test]
+    fn checks_are_validated() {
+        let spec: EntitySpec = toml::from_str(
+            r#"
+            name = "offices"
+            source_table = "dispatch_office"
+            target_table = "offices"
+            legacy_id_column = "legacy_office_id"
+            dependency_order = 1
+            "#,
+        )
+        .unwrap();
+        let specs = [spec];
+        let cases = [
+            (r#"type = "unique""#, None),
+            (r#"type = "unique", columns = ["name", "apt"]"#, None),
+            (
+                r#"type = "foreign_keys", columns = ["office_id"], references = "offices.id""#,
+                None,
+            ),
+            (r#"type = "sample", size = 10"#, None),
+            (
+                r#"type = "sql", entity = "anything", sql = "SELECT 1""#,
+                None,
+            ),
+            (
+                r#"type = "foreign_keys", references = "offices""#,
+                Some("`references` needs the FK `columns`"),
+            ),
+            (
+                r#"type = "foreign_keys", columns = ["office_id"], references = "a.b.c""#,
+                Some("`references` must be `table` or `table.column`"),
+            ),
+            (
+                r#"type = "foreign_keys", columns = ["office_id"], references = "offices;drop""#,
+                Some("`references` must be `table` or `table.column`"),
+            ),
+            (
+                r#"type = "sample", size = 0"#,
+                Some("`size` must be greater than zero"),
+            ),
+            (
+                r#"type = "not_null""#,
+                Some("`columns` lists the columns that must not be NULL"),
+            ),
+            (
+                r#"type = "sql", sql = "  ""#,
+                Some("`sql` selecting the violating rows is required"),
+            ),
+            (
+                r#"type = "unique", size = 5"#,
+                Some("`size` does not apply to unique checks"),
+            ),
+            (
+                r#"type = "row_count", columns = ["name"]"#,
+                Some("`columns` does not apply to row_count checks"),
+            ),
+            (
+                r#"type = "not_null", sql = "SELECT 1""#,
+                Some("`sql` does not apply to not_null checks"),
+            ),
+            (
+                r#"type = "unique", database = "source""#,
+                Some("`database` does not apply to unique checks"),
+            ),
+            (
+                r#"type = "not_null", columns = ["bad name"]"#,
+                Some("'bad name' is not a valid column name"),
+            ),
+            (r#"type = "unique", entity = "patients""#, Some("patients")),
+        ];
+        for (fields, expected) in cases {
+            let entity = if fields.contains("entity") {
+                ""
+            } else {
+                r#"entity = "offices", "#
+            };
+            let check: Check =
+                toml::from_str::<CheckFile>(&format!("check = [{{ {}{} }}]", entity, fields))
+                    .unwrap_or_else(|e| panic!("{}: {}", fields, e))
+                    .check
+                    .remove(0);
+            match (check.validate(&specs), expected) {
+                (Ok(()), None) => {}
+                (Err(e), Some(expected)) => assert!(e.contains(expected), "{}: {}", fields, e),
+                (got, _) => panic!("{}: {:?}", fields, got),
+            }
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! Field-level comparison of sampled rows.
+//!
+//! The sampled source rows go through the spec's transforms and FK lookups,
+//! exactly as the executor would write them, and are compared column by
+//! column with the target row their legacy ID is mapped to in
+//! `migration_mappings`. Both sides are brought to the canonical form of the
+//! target column type first (see [`crate::differential::hash`]), so `0.50`
+//! and `0.5` agree.
+//!
+//! Rows are drawn at random, or stratified: the source keys are cut into
+//! equally sized ranges and each range gives the same share of the sample,
+//! so old and recent rows are both represented.
+
+use std::collections::HashMap;
+
+use postgres::Client;
+use serde::Serialize;
+
+use crate::differential::hash;
+use crate::engine::{column_value, describe};
+use crate::mapping::MappingStore;
+use crate::spec::EntitySpec;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Mismatch {
+    pub legacy_id: i64,
+    /// The source value after the transformation; None is NULL.
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnSample {
+    pub column: String,
+    pub compared: u64,
+    pub mismatched: u64,
+    /// `mismatched / compared`, between 0 and 1.
+    pub mismatch_rate: f64,
+    pub examples: Vec<Mismatch>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Sample {
+    pub strategy: Strategy,
+    /// Source rows drawn.
+    pub sampled: u64,
+    /// Sampled rows without a `migration_mappings` row.
+    pub unmapped: Vec<i64>,
+    /// Sampled rows whose mapped UUID has no target row.
+    pub missing: Vec<i64>,
+    /// Rows with at least one differing column, unmapped and missing rows
+    /// excluded.
+    pub mismatched_rows: u64,
+    pub columns: Vec<ColumnSample>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    #[default]
+    Random,
+    /// The same number of rows from each of `strata` ranges of source keys.
+    Stratified,
+}
+
+/// What to sample.
+pub struct SampleOptions<'a> {
+    pub size: u64,
+    pub strategy: Strategy,
+    /// Ranges of a stratified sample.
+    pub strata: u64,
+    /// Target columns to compare; empty compares every mapped column.
+    pub columns: &'a [String],
+    /// Differing values kept per column.
+    pub examples: usize,
+}
+
+impl Sample {
+    /// Rows that are unmapped, missing or differ.
+    pub fn discrepancies(&self) -> u64 {
+        self.mismatched_rows + self.unmapped.len() as u64 + self.missing.len() as u64
+    }
+}
+
+/// Draw `options.size` source rows of `spec` and compare them with the
+/// target. `types` are the target column types and `mappings` must hold the
+/// entities of the spec's lookups.
+pub fn compare(
+    spec: &EntitySpec,
+    source: &mut Client,
+    target: &mut Client,
+    types: &HashMap<String, String>,
+    mappings: &MappingStore,
+    options: &SampleOptions<'_>,
+) -> Result<Sample, String> {
+    let compared: Vec<usize> = spec
+        .columns
+        .iter()
+        .map(|c| c.target.as_str())
+        .chain(spec.lookups.iter().map(|l| l.target.as_str()))
+        .enumerate()
+        .filter(|(_, c)| options.columns.is_empty() || options.columns.iter().any(|o| o == c))
+        .map(|(i, _)| i)
+        .collect();
+    if let Some(unknown) = options.columns.iter().find(|o| {
+        !spec.columns.iter().any(|c| &c.target == *o)
+            && !spec.lookups.iter().any(|l| &l.target == *o)
+    }) {
+        return Err(format!(
+            "{} is not a mapped column of {}",
+            unknown, spec.target_table
+        ));
+    }
+
+    let mut select = vec![format!("s.{}::bigint", spec.source_key)];
+    select.extend(
+        spec.columns
+            .iter()
+            .map(|c| hash::timestamp_sql(&format!("s.{}", c.source), &types[&c.target])),
+    );
+    select.extend(
+        spec.lookups
+            .iter()
+            .map(|l| format!("s.{}::bigint", l.source)),
+    );
+    let filter = spec
+        .filter
+        .as_ref()
+        .map(|f| format!("\nWHERE ({})", f))
+        .unwrap_or_default();
+    let source_sql = match options.strategy {
+        Strategy::Random => format!(
+            "SELECT {}\nFROM {} s{}\nORDER BY random()\nLIMIT {}",
+            select.join(", "),
+            spec.source_table,
+            filter,
+            options.size
+        ),
+        Strategy::Stratified => {
+            let strata = options.strata.clamp(1, options.size.max(1));
+            format!(
+                "SELECT {select}\nFROM (\n  SELECT s.*, row_number() OVER (PARTITION BY stratum ORDER BY random()) AS pick\n  \
+                 FROM (SELECT s.*, ntile({strata}) OVER (ORDER BY s.{key}) AS stratum FROM {table} s{filter}) s\n) s\n\
+                 WHERE s.pick <= {per}\nORDER BY s.{key}",
+                select = select.join(", "),
+                strata = strata,
+                key = spec.source_key,
+                table = spec.source_table,
+                filter = filter,
+                per = options.size.div_ceil(strata),
+            )
+        }
+    };
+    let bad_source =
+        |e: postgres::Error| format!("cannot sample {}: {}", spec.source_table, describe(&e));
+    let rows = source.query(&source_sql, &[]).map_err(bad_source)?;
+
+    let mut expected: Vec<(i64, Vec<Option<String>>)> = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let key: Option<i64> = row.try_get(0).map_err(bad_source)?;
+        let Some(key) = key else { continue };
+        let mut values = Vec::with_capacity(spec.columns.len() + spec.lookups.len());
+        for (i, column) in spec.columns.iter().enumerate() {
+            let raw: Option<String> = row.try_get(1 + i).map_err(bad_source)?;
+            values.push(hash::canonical(
+                &types[&column.target],
+                column_value(column, raw),
+            ));
+        }
+        let offset = 1 + spec.columns.len();
+        for (i, lookup) in spec.lookups.iter().enumerate() {
+            let legacy: Option<i64> = row.try_get(offset + i).map_err(bad_source)?;
+            let resolved = legacy
+                .and_then(|id| mappings.get(&lookup.entity, id))
+                .map(|id| id.to_string());
+            values.push(hash::canonical(&types[&lookup.target], resolved));
+        }
+        expected.push((key, values));
+    }
+
+    let columns = crate::scaffold::target_columns(spec);
+    let mut select = vec![
+        "m.legacy_id::bigint".to_string(),
+        "t.ctid IS NOT NULL".to_string(),
+    ];
+    select.extend(
+        columns
+            .iter()
+            .skip(1)
+            .map(|c| hash::timestamp_sql(&format!("t.{}", c), &types[*c])),
+    );
+    let target_sql = format!(
+        "SELECT DISTINCT ON (1) {}\nFROM migration_mappings m\nLEFT JOIN {} t ON t.{} = m.new_id\n\
+         WHERE m.entity_type = $1 AND m.legacy_id = ANY($2::bigint[])\nORDER BY 1",
+        select.join(", "),
+        spec.target_table,
+        spec.target_key
+    );
+    let keys: Vec<i64> = expected.iter().map(|(k, _)| *k).collect();
+    let bad_target =
+        |e: postgres::Error| format!("cannot read {}: {}", spec.target_table, describe(&e));
+    // None: mapped, but the target row is gone.
+    let mut actual: HashMap<i64, Option<Vec<Option<String>>>> = HashMap::new();
+    for row in target
+        .query(&target_sql, &[&spec.entity_type(), &keys])
+        .map_err(bad_target)?
+    {
+        let key: i64 = row.try_get(0).map_err(bad_target)?;
+        let found: bool = row.try_get(1).map_err(bad_target)?;
+        if !found {
+            actual.insert(key, None);
+            continue;
+        }
+        let mut values = Vec::with_capacity(columns.len() - 1);
+        for (i, column) in columns.iter().enumerate().skip(1) {
+            let value: Option<String> = row.try_get(i + 1).map_err(bad_target)?;
+            values.push(hash::canonical(&types[*column], value));
+        }
+        actual.insert(key, Some(values));
+    }
+
+    let mut sample = Sample {
+        strategy: options.strategy,
+        sampled: expected.len() as u64,
+        columns: compared
+            .iter()
+            .map(|&i| ColumnSample {
+                column: columns[i + 1].to_string(),
+                compared: 0,
+                mismatched: 0,
+                mismatch_rate: 0.0,
+                examples: Vec::new(),
+            })
+            .collect(),
+        ..Sample::default()
+    };
+    for (key, values) in expected {
+        let stored = match actual.get(&key) {
+            Some(Some(stored)) => stored,
+            Some(None) => {
+                sample.missing.push(key);
+                continue;
+            }
+            None => {
+                sample.unmapped.push(key);
+                continue;
+            }
+        };
+        let mut differs = false;
+        for (stats, &i) in sample.columns.iter_mut().zip(&compared) {
+            stats.compared += 1;
+            if values[i] != stored[i] {
+                differs = true;
+                stats.mismatched += 1;
+                if stats.examples.len() < options.examples {
+                    stats.examples.push(Mismatch {
+                        legacy_id: key,
+                        expected: values[i].clone(),
+                        actual: stored[i].clone(),
+                    });
+                }
+            }
+        }
+        if differs {
+            sample.mismatched_rows += 1;
+        }
+    }
+    for stats in &mut sample.columns {
+        if stats.compared > 0 {
+            stats.mismatch_rate = stats.mismatched as f64 / stats.compared as f64;
+        }
+    }
+    sample.unmapped.sort_unstable();
+    sample.missing.sort_unstable();
+    Ok(sample)
+}
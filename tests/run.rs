@@ -0,0 +1,728 @@
+//! `run` against a real Postgres: both load modes, the row-by-row fallback,
+//! lineage and checkpoints committed with their batch, `--resume`, and the
+//! conflict policies with `resolve apply`.
+
+mod support;
+
+use std::process::Output;
+
+use postgres::Client;
+use support::Cluster;
+
+const SOURCE: &str = "
+CREATE TABLE dispatch_office (
+    id serial PRIMARY KEY,
+    name varchar(100),
+    apt varchar(10),
+    tax_rate numeric(6,4),
+    valid boolean,
+    emails boolean,
+    settings text,
+    created_at timestamptz DEFAULT '2024-01-02 03:04:05+00'
+);
+INSERT INTO dispatch_office (id, name, apt, tax_rate, valid, emails, settings) VALUES
+    (1, '  Alpha ', '', 0.0825, true, true, '{\"theme\": \"dark\"}'),
+    (2, 'Beta', '2B', NULL, true, NULL, NULL),
+    (3, 'Gamma', NULL, 0.05, false, true, NULL),
+    (4, 'Delta', '4D', 0.07, true, false, '[]'),
+    (5, 'Epsilon', '5A', 0.06, true, true, NULL),
+    (6, 'Zeta', NULL, 0.06, true, true, NULL),
+    (7, 'Eta', NULL, 0.06, true, true, NULL),
+    (8, 'Theta', NULL, 0.06, true, true, NULL);
+CREATE TABLE dispatch_patient (
+    id serial PRIMARY KEY,
+    name varchar(100),
+    office_id integer
+);
+INSERT INTO dispatch_patient (id, name, office_id) VALUES
+    (1, 'ann', 1),
+    (2, 'bob', 2),
+    (3, 'cid', 3),
+    (4, 'dee', 8);
+";
+
+const TARGET: &str = "
+CREATE TABLE migration_mappings (
+    entity_type varchar NOT NULL,
+    legacy_id integer NOT NULL,
+    new_id uuid,
+    migrated_at timestamp NOT NULL DEFAULT now(),
+    migration_batch varchar NOT NULL,
+    PRIMARY KEY (entity_type, legacy_id)
+);
+CREATE TABLE offices (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    legacy_office_id integer UNIQUE,
+    name text NOT NULL,
+    apartment text,
+    tax_rate numeric(6,4) NOT NULL,
+    is_active boolean,
+    email_notifications boolean NOT NULL,
+    settings jsonb,
+    created_at timestamptz
+);
+CREATE TABLE patients (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    legacy_patient_id integer UNIQUE,
+    name text NOT NULL,
+    office_id uuid NOT NULL REFERENCES offices (id)
+);
+";
+
+const OFFICES: &str = r#"
+name = "offices"
+entity_type = "office"
+source_table = "dispatch_office"
+target_table = "offices"
+legacy_id_column = "legacy_office_id"
+dependency_order = 1
+batch_size = 3
+filter = "valid = true"
+columns = [
+  { source = "name", target = "name", transform = "trim" },
+  { source = "apt", target = "apartment", transform = "null_if_empty" },
+  { source = "tax_rate", target = "tax_rate", default = 0 },
+  { source = "valid", target = "is_active" },
+  { source = "emails", target = "email_notifications", default = false },
+  { source = "settings", target = "settings", transform = "json" },
+  { source = "created_at", target = "created_at" },
+]
+"#;
+
+const PATIENTS: &str = r#"
+name = "patients"
+entity_type = "patient"
+source_table = "dispatch_patient"
+target_table = "patients"
+legacy_id_column = "legacy_patient_id"
+dependency_order = 2
+columns = [{ source = "name", target = "name", transform = "uppercase" }]
+lookups = [{ source = "office_id", target = "office_id", entity = "office", required = true }]
+"#;
+
+/// Offices the `valid = true` filter lets through, in key order.
+const MIGRATED: [i32; 7] = [1, 2, 4, 5, 6, 7, 8];
+
+const OFFICE_ROWS: &str = "
+SELECT legacy_office_id, name, apartment, tax_rate::text, is_active,
+       email_notifications, settings::text, created_at::text
+FROM offices ORDER BY legacy_office_id";
+
+type OfficeRow = (
+    i32,
+    String,
+    Option<String>,
+    String,
+    Option<bool>,
+    bool,
+    Option<String>,
+    Option<String>,
+);
+
+struct Fixture {
+    cluster: Cluster,
+    specs: String,
+}
+
+impl Fixture {
+    /// A cluster with the source database and one target per name.
+    fn new(targets: &[&str]) -> Option<Fixture> {
+        let cluster = Cluster::start()?;
+        cluster.create("source", SOURCE);
+        for target in targets {
+            cluster.create(target, TARGET);
+        }
+        cluster.write("specs/offices.toml", OFFICES);
+        let specs = cluster.write("specs/patients.toml", PATIENTS);
+        let specs = specs.parent().unwrap().display().to_string();
+        Some(Fixture { cluster, specs })
+    }
+
+    fn run(&self, entity: &str, target: &str, extra: &[&str]) -> Output {
+        let source = self.cluster.url("source");
+        let target = self.cluster.url(target);
+        let mut args = vec![
+            "run",
+            entity,
+            "--specs",
+            &self.specs,
+            "--source-url",
+            &source,
+            "--target-url",
+            &target,
+        ];
+        args.extend(extra);
+        self.cluster.migrate(&args)
+    }
+}
+
+fn succeeded(output: &Output) {
+    assert!(
+        output.status.success(),
+        "run failed:\n{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+fn failed(output: &Output) -> String {
+    assert!(
+        !output.status.success(),
+        "run succeeded:\n{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+fn ids(client: &mut Client, sql: &str) -> Vec<i32> {
+    client
+        .query(sql, &[])
+        .expect(sql)
+        .iter()
+        .map(|row| row.get(0))
+        .collect()
+}
+
+fn offices(client: &mut Client) -> Vec<OfficeRow> {
+    client
+        .query(OFFICE_ROWS, &[])
+        .expect("read offices")
+        .iter()
+        .map(|r| {
+            (
+                r.get(0),
+                r.get(1),
+                r.get(2),
+                r.get(3),
+                r.get(4),
+                r.get(5),
+                r.get(6),
+                r.get(7),
+            )
+        })
+        .collect()
+}
+
+/// `(last_source_key, batches, records_processed, status)`.
+fn checkpoint(client: &mut Client, entity: &str) -> (Option<i64>, i64, i64, String) {
+    let row = client
+        .query_one(
+            "SELECT last_source_key, batches, records_processed, status
+             FROM migration_executor_checkpoints WHERE entity = $1",
+            &[&entity],
+        )
+        .expect("read checkpoint");
+    (row.get(0), row.get(1), row.get(2), row.get(3))
+}
+
+/// Every office has exactly one mapping, pointing at it, and nothing else is
+/// mapped.
+fn assert_lineage(client: &mut Client) {
+    let orphans = ids(
+        client,
+        "SELECT coalesce(o.legacy_office_id, m.legacy_id)
+         FROM offices o
+         FULL JOIN (SELECT * FROM migration_mappings WHERE entity_type = 'office') m
+           ON m.legacy_id = o.legacy_office_id AND m.new_id = o.id
+         WHERE o.id IS NULL OR m.legacy_id IS NULL",
+    );
+    assert_eq!(orphans, Vec::<i32>::new(), "offices without lineage");
+}
+
+#[test]
+fn upsert_writes_rows_lineage_and_checkpoint() {
+    let Some(fixture) = Fixture::new(&["target"]) else {
+        return;
+    };
+    succeeded(&fixture.run("offices", "target", &[]));
+
+    let mut target = fixture.cluster.connect("target");
+    let rows = offices(&mut target);
+    let created = Some("2024-01-02 03:04:05+00".to_string());
+    assert_eq!(
+        rows[..2],
+        [
+            (
+                1,
+                "Alpha".into(),
+                None,
+                "0.0825".into(),
+                Some(true),
+                true,
+                Some(r#"{"theme": "dark"}"#.into()),
+                created.clone()
+            ),
+            (
+                2,
+                "Beta".into(),
+                Some("2B".into()),
+                "0.0000".into(),
+                Some(true),
+                false,
+                None,
+                created
+            ),
+        ]
+    );
+    assert_eq!(rows.iter().map(|r| r.0).collect::<Vec<_>>(), MIGRATED);
+    assert_lineage(&mut target);
+    assert_eq!(
+        checkpoint(&mut target, "offices"),
+        (Some(8), 3, 7, "completed".into())
+    );
+
+    // A second run updates in place and keeps every row's ID.
+    let before = ids(
+        &mut target,
+        "SELECT legacy_office_id FROM offices ORDER BY id",
+    );
+    target
+        .batch_execute("UPDATE offices SET name = 'changed'")
+        .unwrap();
+    succeeded(&fixture.run("offices", "target", &["--batch-size", "100"]));
+    assert_eq!(offices(&mut target), rows);
+    assert_eq!(
+        ids(
+            &mut target,
+            "SELECT legacy_office_id FROM offices ORDER BY id"
+        ),
+        before
+    );
+    assert_lineage(&mut target);
+}
+
+#[test]
+fn copy_loads_what_upsert_loads() {
+    let Some(fixture) = Fixture::new(&["upserted", "copied"]) else {
+        return;
+    };
+    for (target, load) in [("upserted", "upsert"), ("copied", "copy")] {
+        succeeded(&fixture.run("offices", target, &["--load", load]));
+        succeeded(&fixture.run("patients", target, &["--load", load]));
+    }
+
+    let mut upserted = fixture.cluster.connect("upserted");
+    let mut copied = fixture.cluster.connect("copied");
+    assert_eq!(offices(&mut copied), offices(&mut upserted));
+    assert_lineage(&mut copied);
+    assert_eq!(
+        checkpoint(&mut copied, "offices"),
+        (Some(8), 3, 7, "completed".into())
+    );
+
+    // Patient 3's office was filtered out, so the required lookup drops it.
+    let patients = "SELECT p.legacy_patient_id, p.name, o.legacy_office_id
+                    FROM patients p JOIN offices o ON o.id = p.office_id
+                    ORDER BY 1";
+    let read = |client: &mut Client| -> Vec<(i32, String, Option<i32>)> {
+        client
+            .query(patients, &[])
+            .unwrap()
+            .iter()
+            .map(|r| (r.get(0), r.get(1), r.get(2)))
+            .collect()
+    };
+    let expected = vec![
+        (1, "ANN".to_string(), Some(1)),
+        (2, "BOB".to_string(), Some(2)),
+        (4, "DEE".to_string(), Some(8)),
+    ];
+    assert_eq!(read(&mut copied), expected);
+    assert_eq!(read(&mut upserted), expected);
+    assert_eq!(
+        ids(
+            &mut copied,
+            "SELECT legacy_id FROM migration_mappings
+             WHERE entity_type = 'patient' ORDER BY 1"
+        ),
+        [1, 2, 4]
+    );
+}
+
+#[test]
+fn rejected_rows_fall_back_to_savepoints() {
+    let Some(fixture) = Fixture::new(&["upserted", "copied"]) else {
+        return;
+    };
+    for (target, load) in [("upserted", "upsert"), ("copied", "copy")] {
+        let mut client = fixture.cluster.connect(target);
+        client
+            .batch_execute(
+                "ALTER TABLE offices ADD CONSTRAINT no_5a
+                   CHECK (apartment IS DISTINCT FROM '5A')",
+            )
+            .unwrap();
+
+        let stderr = failed(&fixture.run("offices", target, &["--load", load]));
+        assert!(stderr.contains("no_5a"), "{}", stderr);
+        assert!(
+            stderr
+                .contains("1 row(s) were rejected by the target (1 batch(es) retried row by row)"),
+            "{}",
+            stderr
+        );
+
+        // Office 5 shared its batch with 6 and 7, which were still written.
+        let written: Vec<i32> = MIGRATED.into_iter().filter(|id| *id != 5).collect();
+        assert_eq!(
+            ids(
+                &mut client,
+                "SELECT legacy_office_id FROM offices ORDER BY 1"
+            ),
+            written
+        );
+        assert_lineage(&mut client);
+        // The checkpoint moves past the rejected row: resume does not retry it.
+        assert_eq!(
+            checkpoint(&mut client, "offices"),
+            (Some(8), 3, 7, "completed".into())
+        );
+    }
+}
+
+#[test]
+fn a_failed_batch_keeps_no_lineage_or_checkpoint_and_resume_continues() {
+    let Some(fixture) = Fixture::new(&["target"]) else {
+        return;
+    };
+    let mut target = fixture.cluster.connect("target");
+    // Office 8's lineage cannot be written, so its whole batch must fail.
+    target
+        .batch_execute(
+            "CREATE FUNCTION refuse() RETURNS trigger LANGUAGE plpgsql AS $$
+             BEGIN
+               IF NEW.entity_type = 'office' AND NEW.legacy_id = 8 THEN
+                 RAISE EXCEPTION 'lineage refused';
+               END IF;
+               RETURN NEW;
+             END $$;
+             CREATE TRIGGER refuse BEFORE INSERT ON migration_mappings
+               FOR EACH ROW EXECUTE FUNCTION refuse();",
+        )
+        .unwrap();
+
+    let stderr = failed(&fixture.run("offices", "target", &[]));
+    assert!(stderr.contains("lineage refused"), "{}", stderr);
+    // Office 8 was upserted, then rolled back with its batch's checkpoint.
+    assert_eq!(
+        ids(
+            &mut target,
+            "SELECT legacy_office_id FROM offices ORDER BY 1"
+        ),
+        [1, 2, 4, 5, 6, 7]
+    );
+    assert_lineage(&mut target);
+    assert_eq!(
+        checkpoint(&mut target, "offices"),
+        (Some(7), 2, 6, "failed".into())
+    );
+
+    target
+        .batch_execute("DROP TRIGGER refuse ON migration_mappings")
+        .unwrap();
+    target
+        .batch_execute("UPDATE offices SET name = 'kept'")
+        .unwrap();
+    let output = fixture.run("offices", "target", &["--resume"]);
+    succeeded(&output);
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("Resuming after id 7"),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    // Only office 8 was read; the rest were not written twice.
+    assert_eq!(
+        ids(
+            &mut target,
+            "SELECT legacy_office_id FROM offices WHERE name = 'kept' ORDER BY 1"
+        ),
+        [1, 2, 4, 5, 6, 7]
+    );
+    assert_eq!(
+        ids(
+            &mut target,
+            "SELECT legacy_office_id FROM offices ORDER BY 1"
+        ),
+        MIGRATED
+    );
+    assert_lineage(&mut target);
+    let batches: i64 = target
+        .query_one(
+            "SELECT count(DISTINCT migration_batch) FROM migration_mappings
+             WHERE entity_type = 'office'",
+            &[],
+        )
+        .unwrap()
+        .get(0);
+    assert_eq!(batches, 1, "the resumed run keeps the migration_batch");
+    assert_eq!(
+        checkpoint(&mut target, "offices"),
+        (Some(8), 3, 7, "completed".into())
+    );
+}
+
+#[test]
+fn a_failed_run_reports_its_cause_when_recording_it_fails_too() {
+    let Some(fixture) = Fixture::new(&["target"]) else {
+        return;
+    };
+    let mut target = fixture.cluster.connect("target");
+    succeeded(&fixture.run("offices", "target", &["--limit", "1"]));
+    target
+        .batch_execute(
+            "CREATE FUNCTION refuse() RETURNS trigger LANGUAGE plpgsql AS $$
+             BEGIN
+               RAISE EXCEPTION '% refused', TG_TABLE_NAME;
+             END $$;
+             CREATE TRIGGER refuse BEFORE INSERT ON migration_mappings
+               FOR EACH ROW WHEN (NEW.legacy_id = 8) EXECUTE FUNCTION refuse();
+             CREATE TRIGGER refuse BEFORE UPDATE ON migration_executor_checkpoints
+               FOR EACH ROW WHEN (NEW.status = 'failed') EXECUTE FUNCTION refuse();",
+        )
+        .unwrap();
+
+    let stderr = failed(&fixture.run("offices", "target", &[]));
+    assert!(
+        stderr.contains("migration_mappings refused (recording the failure also failed:"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("migration_executor_checkpoints refused"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn target_wins_keeps_an_edit_made_after_cutover() {
+    let Some(fixture) = Fixture::new(&["target"]) else {
+        return;
+    };
+    let conflicts = "\n[conflicts]\ncolumns = { name = \"target-wins\" }\n";
+    fixture
+        .cluster
+        .write("specs/offices.toml", &format!("{}{}", OFFICES, conflicts));
+    succeeded(&fixture.run("offices", "target", &[]));
+
+    let mut target = fixture.cluster.connect("target");
+    target
+        .batch_execute("UPDATE offices SET name = 'edited' WHERE legacy_office_id = 1")
+        .unwrap();
+    let mut source = fixture.cluster.connect("source");
+    source
+        .batch_execute(
+            "UPDATE dispatch_office SET name = 'Beta 2', tax_rate = 0.09 WHERE id IN (1, 2)",
+        )
+        .unwrap();
+    succeeded(&fixture.run("offices", "target", &[]));
+
+    // The other columns still follow the source.
+    let names: Vec<(i32, String, String)> = target
+        .query(
+            "SELECT legacy_office_id, name, tax_rate::text FROM offices
+             WHERE legacy_office_id IN (1, 2) ORDER BY 1",
+            &[],
+        )
+        .unwrap()
+        .iter()
+        .map(|r| (r.get(0), r.get(1), r.get(2)))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            (1, "edited".into(), "0.0900".into()),
+            (2, "Beta".into(), "0.0900".into())
+        ]
+    );
+    let logged: Vec<(i64, String, String, String, String)> = target
+        .query(
+            "SELECT legacy_id, column_name, policy, outcome, resolved_value
+             FROM migration_conflict_log WHERE column_name = 'name' ORDER BY legacy_id",
+            &[],
+        )
+        .unwrap()
+        .iter()
+        .map(|r| (r.get(0), r.get(1), r.get(2), r.get(3), r.get(4)))
+        .collect();
+    assert_eq!(
+        logged,
+        [
+            (
+                1,
+                "name".into(),
+                "target-wins".into(),
+                "target".into(),
+                "edited".into()
+            ),
+            (
+                2,
+                "name".into(),
+                "target-wins".into(),
+                "target".into(),
+                "Beta".into()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn manual_conflicts_are_queued_until_applied() {
+    let Some(fixture) = Fixture::new(&["target"]) else {
+        return;
+    };
+    let conflicts = "\n[conflicts]\ncolumns = { name = \"manual\" }\n";
+    fixture
+        .cluster
+        .write("specs/offices.toml", &format!("{}{}", OFFICES, conflicts));
+    succeeded(&fixture.run("offices", "target", &[]));
+
+    let mut target = fixture.cluster.connect("target");
+    target
+        .batch_execute(
+            "UPDATE offices SET name = 'edited', tax_rate = 1 WHERE legacy_office_id = 1",
+        )
+        .unwrap();
+    succeeded(&fixture.run("offices", "target", &[]));
+
+    // The queued column keeps the target's value; the rest is decided.
+    let office = "SELECT name, tax_rate::text FROM offices WHERE legacy_office_id = 1";
+    let row = target.query_one(office, &[]).unwrap();
+    assert_eq!(
+        (row.get::<_, String>(0), row.get::<_, String>(1)),
+        ("edited".into(), "0.0825".into())
+    );
+    let queued = target
+        .query_one(
+            "SELECT id, legacy_id, columns, source_values->>'name', target_values->>'name', status
+             FROM migration_conflict_queue",
+            &[],
+        )
+        .unwrap();
+    let id: i64 = queued.get(0);
+    assert_eq!(
+        (
+            queued.get::<_, i64>(1),
+            queued.get::<_, Vec<String>>(2),
+            queued.get::<_, String>(3),
+            queued.get::<_, String>(4),
+            queued.get::<_, String>(5),
+        ),
+        (
+            1,
+            vec!["name".to_string()],
+            "Alpha".into(),
+            "edited".into(),
+            "pending".into()
+        )
+    );
+
+    let url = fixture.cluster.url("target");
+    let id = id.to_string();
+    let output = fixture.cluster.migrate(&[
+        "resolve",
+        "apply",
+        &id,
+        "--take",
+        "source",
+        "--by",
+        "reviewer",
+        "--target-url",
+        &url,
+    ]);
+    succeeded(&output);
+    let row = target.query_one(office, &[]).unwrap();
+    assert_eq!(row.get::<_, String>(0), "Alpha");
+    let status: String = target
+        .query_one("SELECT status FROM migration_conflict_queue", &[])
+        .unwrap()
+        .get(0);
+    assert_eq!(status, "resolved");
+    let logged = target
+        .query_one(
+            "SELECT policy, outcome, resolved_value, decided_by FROM migration_conflict_log
+             WHERE queue_id IS NOT NULL",
+            &[],
+        )
+        .unwrap();
+    assert_eq!(
+        (
+            logged.get::<_, String>(0),
+            logged.get::<_, String>(1),
+            logged.get::<_, String>(2),
+            logged.get::<_, String>(3),
+        ),
+        (
+            "manual".into(),
+            "source".into(),
+            "Alpha".into(),
+            "reviewer".into()
+        )
+    );
+
+    // A settled conflict cannot be applied again.
+    let stderr = failed(&fixture.cluster.migrate(&[
+        "resolve",
+        "apply",
+        &id,
+        "--take",
+        "target",
+        "--target-url",
+        &url,
+    ]));
+    assert!(stderr.contains("is already resolved"), "{}", stderr);
+}
+
+#[test]
+fn newest_updated_at_keeps_the_later_edit() {
+    let Some(fixture) = Fixture::new(&["target"]) else {
+        return;
+    };
+    let conflicts = "\n[conflicts]\nupdated_at = \"created_at\"\n\
+                     columns = { name = \"newest-updated-at\" }\n";
+    fixture
+        .cluster
+        .write("specs/offices.toml", &format!("{}{}", OFFICES, conflicts));
+    succeeded(&fixture.run("offices", "target", &[]));
+
+    // 1: only the source moved on. 2: the target is newer. 4: the target has
+    // no timestamp. 5: the source has none.
+    let mut source = fixture.cluster.connect("source");
+    source
+        .batch_execute(
+            "UPDATE dispatch_office SET name = trim(name) || ' (source)';
+             UPDATE dispatch_office SET created_at = '2025-01-01' WHERE id IN (1, 2, 4);
+             UPDATE dispatch_office SET created_at = NULL WHERE id = 5;",
+        )
+        .unwrap();
+    let mut target = fixture.cluster.connect("target");
+    target
+        .batch_execute(
+            "UPDATE offices SET name = 'target' WHERE legacy_office_id IN (2, 4, 5);
+             UPDATE offices SET created_at = '2026-01-01' WHERE legacy_office_id = 2;
+             UPDATE offices SET created_at = NULL WHERE legacy_office_id = 4;",
+        )
+        .unwrap();
+    succeeded(&fixture.run("offices", "target", &[]));
+
+    let names: Vec<(i32, String)> = target
+        .query(
+            "SELECT legacy_office_id, name FROM offices
+             WHERE legacy_office_id IN (1, 2, 4, 5) ORDER BY 1",
+            &[],
+        )
+        .unwrap()
+        .iter()
+        .map(|r| (r.get(0), r.get(1)))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            (1, "Alpha (source)".into()),
+            (2, "target".into()),
+            (4, "Delta (source)".into()),
+            (5, "target".into()),
+        ]
+    );
+}
@@ -0,0 +1,202 @@
+//! A throwaway Postgres cluster for the integration tests.
+//!
+//! `initdb` and `pg_ctl` are looked up in `PG_BIN`, then on `PATH`; without
+//! them the tests are skipped. The cluster listens on a Unix socket in its
+//! own temporary directory only, and is stopped and removed on drop. Postgres
+//! refuses to run as root, so under root the server runs as `PG_TEST_USER`
+//! (default `postgres`).
+
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use postgres::{Client, NoTls};
+
+const PORT: u16 = 5432;
+
+static CLUSTERS: AtomicU32 = AtomicU32::new(0);
+
+pub struct Cluster {
+    dir: PathBuf,
+    bin: PathBuf,
+    user: Option<String>,
+}
+
+impl Cluster {
+    /// Start a cluster, or `None` (with a note on stderr) when Postgres is
+    /// not installed.
+    pub fn start() -> Option<Cluster> {
+        let Some(bin) = find_bin() else {
+            eprintln!("skipped: initdb/pg_ctl not found (set PG_BIN)");
+            return None;
+        };
+        let dir = env::temp_dir().join(format!(
+            "migration_generator-{}-{}",
+            std::process::id(),
+            CLUSTERS.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("create cluster directory");
+        let user = if unsafe { libc::geteuid() } == 0 {
+            let name = env::var("PG_TEST_USER").unwrap_or_else(|_| "postgres".into());
+            let (uid, gid) = account(&name).expect("PG_TEST_USER must exist when running as root");
+            std::os::unix::fs::chown(&dir, Some(uid), Some(gid)).expect("chown cluster directory");
+            Some(name)
+        } else {
+            None
+        };
+        let cluster = Cluster { dir, bin, user };
+        let data = cluster.dir.join("data");
+        cluster.pg(
+            "initdb",
+            &[
+                "-D".as_ref(),
+                data.as_os_str(),
+                "-U".as_ref(),
+                "postgres".as_ref(),
+                "-A".as_ref(),
+                "trust".as_ref(),
+                "-E".as_ref(),
+                "UTF8".as_ref(),
+                "--no-sync".as_ref(),
+            ],
+        );
+        let options = format!(
+            "-k {} -p {} -c listen_addresses='' -c fsync=off",
+            cluster.dir.display(),
+            PORT
+        );
+        cluster.pg(
+            "pg_ctl",
+            &[
+                "-D".as_ref(),
+                data.as_os_str(),
+                "-o".as_ref(),
+                options.as_ref(),
+                "-l".as_ref(),
+                cluster.dir.join("server.log").as_os_str(),
+                "-w".as_ref(),
+                "start".as_ref(),
+            ],
+        );
+        Some(cluster)
+    }
+
+    /// A keyword/value connection string for `database`.
+    pub fn url(&self, database: &str) -> String {
+        format!(
+            "host={} port={} user=postgres dbname={}",
+            self.dir.display(),
+            PORT,
+            database
+        )
+    }
+
+    pub fn connect(&self, database: &str) -> Client {
+        Client::connect(&self.url(database), NoTls)
+            .unwrap_or_else(|e| panic!("connect to {}: {}", database, e))
+    }
+
+    /// Create `database` and run `sql` in it.
+    pub fn create(&self, database: &str, sql: &str) -> Client {
+        self.connect("postgres")
+            .batch_execute(&format!("CREATE DATABASE {}", database))
+            .expect("create database");
+        let mut client = self.connect(database);
+        client.batch_execute(sql).expect("load fixture");
+        client
+    }
+
+    /// Write a file into the cluster's directory and return its path.
+    pub fn write(&self, name: &str, contents: &str) -> PathBuf {
+        let path = self.dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).expect("create directory");
+        fs::write(&path, contents).expect("write file");
+        path
+    }
+
+    /// Run the binary from the cluster's directory, without the caller's
+    /// database settings.
+    pub fn migrate(&self, args: &[&str]) -> Output {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_migration_generator"));
+        command.args(args).current_dir(&self.dir);
+        for (key, _) in env::vars() {
+            if key.starts_with("PG")
+                || key.starts_with("SOURCE_DB_")
+                || key.starts_with("TARGET_DB_")
+                || key.starts_with("MIGRATION_")
+                || key == "BATCH_SIZE"
+            {
+                command.env_remove(key);
+            }
+        }
+        command.output().expect("run migration_generator")
+    }
+
+    fn pg(&self, program: &str, args: &[&std::ffi::OsStr]) {
+        let output = self
+            .command(program)
+            .args(args)
+            .output()
+            .unwrap_or_else(|e| panic!("run {}: {}", program, e));
+        assert!(
+            output.status.success(),
+            "{} failed: {}{}",
+            program,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    fn command(&self, program: &str) -> Command {
+        let path = self.bin.join(program);
+        match &self.user {
+            Some(user) => {
+                let mut command = Command::new("runuser");
+                command.args(["-u", user, "--"]).arg(path);
+                command
+            }
+            None => Command::new(path),
+        }
+    }
+}
+
+impl Drop for Cluster {
+    fn drop(&mut self) {
+        let data = self.dir.join("data");
+        if data.join("postmaster.pid").exists() {
+            let _ = self
+                .command("pg_ctl")
+                .arg("-D")
+                .arg(&data)
+                .args(["-m", "immediate", "-w", "stop"])
+                .output();
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn find_bin() -> Option<PathBuf> {
+    let dirs = env::var_os("PG_BIN")
+        .map(|dir| vec![PathBuf::from(dir)])
+        .or_else(|| env::var_os("PATH").map(|path| env::split_paths(&path).collect()))?;
+    dirs.into_iter()
+        .find(|dir| has(dir, "initdb") && has(dir, "pg_ctl"))
+}
+
+fn has(dir: &Path, program: &str) -> bool {
+    dir.join(program).is_file()
+}
+
+fn account(name: &str) -> Option<(u32, u32)> {
+    let name = CString::new(name).ok()?;
+    let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+    if entry.is_null() {
+        return None;
+    }
+    let entry = unsafe { &*entry };
+    Some((entry.pw_uid, entry.pw_gid))
+}
@@ -0,0 +1,307 @@
+//! `tombstones` against a real Postgres: rows still referenced through the
+//! specs' lookups are blocked under `hard` and `archive` but marked under
+//! `soft`, a preview changes nothing, and duplicated legacy IDs are left
+//! alone.
+
+mod support;
+
+use std::process::Output;
+
+use postgres::Client;
+use serde_json::{Value, json};
+use support::Cluster;
+
+/// Office 1 is referenced by patient 10, which visit 100 references in turn;
+/// office 2 is referenced by nothing; two rows claim office 3. No foreign
+/// keys, so only the walk keeps a referenced row in place.
+const TARGET: &str = "
+CREATE TABLE offices (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    legacy_office_id integer,
+    name text NOT NULL,
+    deleted_at timestamptz,
+    is_active boolean NOT NULL DEFAULT true
+);
+CREATE TABLE patients (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    legacy_patient_id integer,
+    office_id uuid
+);
+CREATE TABLE visits (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    legacy_visit_id integer,
+    patient_id uuid
+);
+INSERT INTO offices (legacy_office_id, name) VALUES
+    (1, 'Alpha'), (2, 'Beta'), (3, 'Gamma'), (3, 'Gamma again'), (4, 'Delta');
+INSERT INTO patients (legacy_patient_id, office_id)
+    SELECT 10, id FROM offices WHERE legacy_office_id = 1;
+INSERT INTO visits (legacy_visit_id, patient_id)
+    SELECT 100, id FROM patients WHERE legacy_patient_id = 10;
+";
+
+const OFFICES: &str = r#"
+name = "offices"
+entity_type = "office"
+source_table = "dispatch_office"
+target_table = "offices"
+legacy_id_column = "legacy_office_id"
+dependency_order = 1
+columns = [{ source = "name", target = "name" }]
+deletes = { policy = "report", deleted_at = "deleted_at", is_active = "is_active" }
+"#;
+
+const PATIENTS: &str = r#"
+name = "patients"
+entity_type = "patient"
+source_table = "dispatch_patient"
+target_table = "patients"
+legacy_id_column = "legacy_patient_id"
+dependency_order = 2
+columns = []
+lookups = [{ source = "office_id", target = "office_id", entity = "office" }]
+"#;
+
+const VISITS: &str = r#"
+name = "visits"
+entity_type = "visit"
+source_table = "dispatch_visit"
+target_table = "visits"
+legacy_id_column = "legacy_visit_id"
+dependency_order = 3
+columns = []
+lookups = [{ source = "patient_id", target = "patient_id", entity = "patient" }]
+"#;
+
+/// Office 9 has no target row.
+const DELETED: &str = r#"{"entity_type": "offices", "deleted_records": [1, 2, 3, 9]}"#;
+
+const OFFICE_ROWS: &str = "
+SELECT legacy_office_id, name, deleted_at IS NOT NULL, is_active
+FROM offices ORDER BY legacy_office_id, name";
+
+struct Fixture {
+    cluster: Cluster,
+    specs: String,
+    changes: String,
+}
+
+impl Fixture {
+    fn new(targets: &[&str]) -> Option<Fixture> {
+        let cluster = Cluster::start()?;
+        for target in targets {
+            cluster.create(target, TARGET);
+        }
+        cluster.write("specs/offices.toml", OFFICES);
+        cluster.write("specs/patients.toml", PATIENTS);
+        let specs = cluster.write("specs/visits.toml", VISITS);
+        let specs = specs.parent().unwrap().display().to_string();
+        let changes = cluster.write("changes.json", DELETED).display().to_string();
+        Some(Fixture {
+            cluster,
+            specs,
+            changes,
+        })
+    }
+
+    /// `tombstones offices` under `policy`, returning the JSON result.
+    fn tombstones(&self, target: &str, policy: &str, extra: &[&str]) -> Value {
+        let target = self.cluster.url(target);
+        let mut args = vec![
+            "--json",
+            "tombstones",
+            "offices",
+            "--specs",
+            &self.specs,
+            "--target-url",
+            &target,
+            "--changes",
+            &self.changes,
+            "--policy",
+            policy,
+        ];
+        args.extend(extra);
+        let output = self.cluster.migrate(&args);
+        succeeded(&output);
+        serde_json::from_slice(&output.stdout).expect("tombstones --json")
+    }
+}
+
+fn succeeded(output: &Output) {
+    assert!(
+        output.status.success(),
+        "tombstones failed:\n{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+fn offices(client: &mut Client) -> Vec<(i32, String, bool, bool)> {
+    client
+        .query(OFFICE_ROWS, &[])
+        .expect("read offices")
+        .iter()
+        .map(|r| (r.get(0), r.get(1), r.get(2), r.get(3)))
+        .collect()
+}
+
+fn untouched() -> Vec<(i32, String, bool, bool)> {
+    vec![
+        (1, "Alpha".into(), false, true),
+        (2, "Beta".into(), false, true),
+        (3, "Gamma".into(), false, true),
+        (3, "Gamma again".into(), false, true),
+        (4, "Delta".into(), false, true),
+    ]
+}
+
+/// The decisions recorded in `migration_tombstones`, by legacy ID.
+fn logged(client: &mut Client) -> Vec<(i64, String, String)> {
+    client
+        .query(
+            "SELECT legacy_id, policy, action FROM migration_tombstones ORDER BY 1, 3",
+            &[],
+        )
+        .expect("read migration_tombstones")
+        .iter()
+        .map(|r| (r.get(0), r.get(1), r.get(2)))
+        .collect()
+}
+
+#[test]
+fn a_preview_walks_the_dependents_and_changes_nothing() {
+    let Some(fixture) = Fixture::new(&["target"]) else {
+        return;
+    };
+    for policy in ["hard", "archive", "soft"] {
+        let result = fixture.tombstones("target", policy, &["--preview"]);
+        let blocked = if policy == "soft" {
+            json!([])
+        } else {
+            json!([1])
+        };
+        let applied = if policy == "soft" {
+            json!([1, 2])
+        } else {
+            json!([2])
+        };
+        assert_eq!(result["preview"], true, "{}", policy);
+        assert_eq!(result["applied"], applied, "{}", policy);
+        assert_eq!(result["blocked"], blocked, "{}", policy);
+        assert_eq!(result["duplicated"], json!([3]), "{}", policy);
+        assert_eq!(result["absent"], 1, "{}", policy);
+        assert_eq!(result["dependent_rows"], 2, "{}", policy);
+        let groups: Vec<(u64, &str, &str)> = result["dependent_groups"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|g| {
+                (
+                    g["depth"].as_u64().unwrap(),
+                    g["target_table"].as_str().unwrap(),
+                    g["column"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            [(1, "patients", "office_id"), (2, "visits", "patient_id")],
+            "{}",
+            policy
+        );
+        assert_eq!(result["dependents"][1]["legacy_id"], 100, "{}", policy);
+    }
+
+    let mut target = fixture.cluster.connect("target");
+    assert_eq!(offices(&mut target), untouched());
+    let tables: i64 = target
+        .query_one(
+            "SELECT count(*) FROM pg_tables
+             WHERE tablename IN ('migration_tombstones', 'offices_archive')",
+            &[],
+        )
+        .unwrap()
+        .get(0);
+    assert_eq!(tables, 0, "a preview leaves no log or archive behind");
+}
+
+#[test]
+fn hard_and_archive_keep_rows_that_are_still_referenced() {
+    let Some(fixture) = Fixture::new(&["hard", "archive"]) else {
+        return;
+    };
+    for policy in ["hard", "archive"] {
+        let result = fixture.tombstones(policy, policy, &[]);
+        assert_eq!(result["applied"], json!([2]), "{}", policy);
+        assert_eq!(result["blocked"], json!([1]), "{}", policy);
+        assert_eq!(result["duplicated"], json!([3]), "{}", policy);
+
+        let mut target = fixture.cluster.connect(policy);
+        let mut kept = untouched();
+        kept.remove(1);
+        assert_eq!(offices(&mut target), kept, "{}", policy);
+        let action = if policy == "hard" {
+            "deleted"
+        } else {
+            "archived"
+        };
+        assert_eq!(
+            logged(&mut target),
+            [
+                (1, policy.into(), "blocked".into()),
+                (2, policy.into(), action.into()),
+                (3, policy.into(), "duplicate".into()),
+                (3, policy.into(), "duplicate".into()),
+            ]
+        );
+    }
+
+    let mut archive = fixture.cluster.connect("archive");
+    let archived = archive
+        .query_one(
+            "SELECT legacy_office_id, name, archive_batch IS NOT NULL FROM offices_archive",
+            &[],
+        )
+        .unwrap();
+    assert_eq!(
+        (
+            archived.get::<_, i32>(0),
+            archived.get::<_, String>(1),
+            archived.get::<_, bool>(2)
+        ),
+        (2, "Beta".into(), true)
+    );
+}
+
+#[test]
+fn soft_marks_referenced_rows_and_leaves_duplicates_alone() {
+    let Some(fixture) = Fixture::new(&["target"]) else {
+        return;
+    };
+    let result = fixture.tombstones("target", "soft", &[]);
+    assert_eq!(result["applied"], json!([1, 2]));
+    assert_eq!(result["blocked"], json!([]));
+    assert_eq!(result["duplicated"], json!([3]));
+
+    let mut target = fixture.cluster.connect("target");
+    let mut expected = untouched();
+    for row in &mut expected[..2] {
+        (row.2, row.3) = (true, false);
+    }
+    assert_eq!(offices(&mut target), expected);
+    assert_eq!(
+        logged(&mut target),
+        [
+            (1, "soft".into(), "soft_deleted".into()),
+            (2, "soft".into(), "soft_deleted".into()),
+            (3, "soft".into(), "duplicate".into()),
+            (3, "soft".into(), "duplicate".into()),
+        ]
+    );
+
+    // A second run finds them marked and changes nothing.
+    let result = fixture.tombstones("target", "soft", &[]);
+    assert_eq!(result["applied"], json!([]));
+    assert_eq!(result["already_marked"], json!([1, 2]));
+    assert_eq!(offices(&mut target), expected);
+}